@@ -35,14 +35,21 @@ import('monaco-editor-core').then(monaco => {
       'and',
       'cut',
       'debug',
+      'distinct',
+      'else',
+      'for',
       'forall',
       'if',
+      'import',
       'in',
       'matches',
+      'module',
       'new',
       'not',
+      'once',
       'or',
-      'print'
+      'print',
+      'then'
     ],
     operators: [
       '=',
@@ -327,6 +327,8 @@ type FormattedPolarError struct {
 	Kind ErrorKind `json:"kind"`
 	// Formatted
 	Formatted string `json:"formatted"`
+	// Span
+	Span *Span `json:"span"`
 }
 
 // InstanceLiteral struct
@@ -892,6 +894,30 @@ type OperatorAssign struct{}
 
 func (OperatorAssign) isOperator() {}
 
+type OperatorConditional struct{}
+
+func (OperatorConditional) isOperator() {}
+
+type OperatorRange struct{}
+
+func (OperatorRange) isOperator() {}
+
+type OperatorCollect struct{}
+
+func (OperatorCollect) isOperator() {}
+
+type OperatorAggregate struct{}
+
+func (OperatorAggregate) isOperator() {}
+
+type OperatorDistinct struct{}
+
+func (OperatorDistinct) isOperator() {}
+
+type OperatorOnce struct{}
+
+func (OperatorOnce) isOperator() {}
+
 // Operator enum
 type OperatorVariant interface {
 	isOperator()
@@ -1199,6 +1225,72 @@ func (result *Operator) UnmarshalJSON(b []byte) error {
 		*result = Operator{variant}
 		return nil
 
+	case "Conditional":
+		var variant OperatorConditional
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = Operator{variant}
+		return nil
+
+	case "Range":
+		var variant OperatorRange
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = Operator{variant}
+		return nil
+
+	case "Collect":
+		var variant OperatorCollect
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = Operator{variant}
+		return nil
+
+	case "Aggregate":
+		var variant OperatorAggregate
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = Operator{variant}
+		return nil
+
+	case "Distinct":
+		var variant OperatorDistinct
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = Operator{variant}
+		return nil
+
+	case "Once":
+		var variant OperatorOnce
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = Operator{variant}
+		return nil
+
 	}
 
 	return fmt.Errorf("Cannot deserialize Operator: %s", string(b))
@@ -1282,6 +1374,24 @@ func (variant Operator) MarshalJSON() ([]byte, error) {
 	case OperatorAssign:
 		return json.Marshal("Assign")
 
+	case OperatorConditional:
+		return json.Marshal("Conditional")
+
+	case OperatorRange:
+		return json.Marshal("Range")
+
+	case OperatorCollect:
+		return json.Marshal("Collect")
+
+	case OperatorAggregate:
+		return json.Marshal("Aggregate")
+
+	case OperatorDistinct:
+		return json.Marshal("Distinct")
+
+	case OperatorOnce:
+		return json.Marshal("Once")
+
 	}
 
 	return nil, fmt.Errorf("unexpected variant of %v", variant)
@@ -1329,6 +1439,8 @@ func (ParseErrorKindInvalidToken) isParseErrorKind() {}
 type ParseErrorKindUnrecognizedEOF struct {
 	// Loc
 	Loc uint64 `json:"loc"`
+	// Expected
+	Expected *string `json:"expected"`
 }
 
 func (ParseErrorKindUnrecognizedEOF) isParseErrorKind() {}
@@ -2122,6 +2234,10 @@ func (RuntimeErrorTypeError) isRuntimeError() {}
 type RuntimeErrorStackOverflow struct {
 	// Msg
 	Msg string `json:"msg"`
+	// Rule
+	Rule *string `json:"rule"`
+	// Depth
+	Depth uint64 `json:"depth"`
 }
 
 func (RuntimeErrorStackOverflow) isRuntimeError() {}
@@ -2136,6 +2252,27 @@ type RuntimeErrorQueryTimeout struct {
 
 func (RuntimeErrorQueryTimeout) isRuntimeError() {}
 
+// RuntimeErrorStepLimit struct
+type RuntimeErrorStepLimit struct {
+	// Limit
+	Limit uint64 `json:"limit"`
+}
+
+func (RuntimeErrorStepLimit) isRuntimeError() {}
+
+// RuntimeErrorMemoryLimit struct
+type RuntimeErrorMemoryLimit struct {
+	// Limit
+	Limit uint64 `json:"limit"`
+}
+
+func (RuntimeErrorMemoryLimit) isRuntimeError() {}
+
+// RuntimeErrorCancelled struct
+type RuntimeErrorCancelled struct{}
+
+func (RuntimeErrorCancelled) isRuntimeError() {}
+
 // RuntimeErrorApplication struct
 type RuntimeErrorApplication struct {
 	// Msg
@@ -2293,6 +2430,39 @@ func (result *RuntimeError) UnmarshalJSON(b []byte) error {
 		*result = RuntimeError{variant}
 		return nil
 
+	case "StepLimit":
+		var variant RuntimeErrorStepLimit
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = RuntimeError{variant}
+		return nil
+
+	case "MemoryLimit":
+		var variant RuntimeErrorMemoryLimit
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = RuntimeError{variant}
+		return nil
+
+	case "Cancelled":
+		var variant RuntimeErrorCancelled
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = RuntimeError{variant}
+		return nil
+
 	case "Application":
 		var variant RuntimeErrorApplication
 		if variantValue != nil {
@@ -2414,6 +2584,21 @@ func (variant RuntimeError) MarshalJSON() ([]byte, error) {
 			"QueryTimeout": inner,
 		})
 
+	case RuntimeErrorStepLimit:
+		return json.Marshal(map[string]RuntimeErrorStepLimit{
+			"StepLimit": inner,
+		})
+
+	case RuntimeErrorMemoryLimit:
+		return json.Marshal(map[string]RuntimeErrorMemoryLimit{
+			"MemoryLimit": inner,
+		})
+
+	case RuntimeErrorCancelled:
+		return json.Marshal(map[string]RuntimeErrorCancelled{
+			"Cancelled": inner,
+		})
+
 	case RuntimeErrorApplication:
 		return json.Marshal(map[string]RuntimeErrorApplication{
 			"Application": inner,
@@ -2467,6 +2652,22 @@ type Source struct {
 	Src string `json:"src"`
 }
 
+// Span struct
+type Span struct {
+	// Filename
+	Filename *string `json:"filename"`
+	// Line
+	Line uint64 `json:"line"`
+	// Column
+	Column uint64 `json:"column"`
+	// EndLine
+	EndLine uint64 `json:"end_line"`
+	// EndColumn
+	EndColumn uint64 `json:"end_column"`
+	// Text
+	Text string `json:"text"`
+}
+
 // Symbol newtype
 type Symbol string
 
@@ -2577,6 +2778,26 @@ type ValidationErrorUnregisteredClass struct {
 
 func (ValidationErrorUnregisteredClass) isValidationError() {}
 
+// ValidationErrorInvalidConstant struct
+type ValidationErrorInvalidConstant struct {
+	// Term
+	Term Term `json:"term"`
+	// Msg
+	Msg string `json:"msg"`
+}
+
+func (ValidationErrorInvalidConstant) isValidationError() {}
+
+// ValidationErrorInvalidImport struct
+type ValidationErrorInvalidImport struct {
+	// Term
+	Term Term `json:"term"`
+	// Msg
+	Msg string `json:"msg"`
+}
+
+func (ValidationErrorInvalidImport) isValidationError() {}
+
 // ValidationErrorDuplicateResourceBlockDeclaration struct
 type ValidationErrorDuplicateResourceBlockDeclaration struct {
 	// Resource
@@ -2711,6 +2932,28 @@ func (result *ValidationError) UnmarshalJSON(b []byte) error {
 		*result = ValidationError{variant}
 		return nil
 
+	case "InvalidConstant":
+		var variant ValidationErrorInvalidConstant
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = ValidationError{variant}
+		return nil
+
+	case "InvalidImport":
+		var variant ValidationErrorInvalidImport
+		if variantValue != nil {
+			err := json.Unmarshal(*variantValue, &variant)
+			if err != nil {
+				return err
+			}
+		}
+		*result = ValidationError{variant}
+		return nil
+
 	case "DuplicateResourceBlockDeclaration":
 		var variant ValidationErrorDuplicateResourceBlockDeclaration
 		if variantValue != nil {
@@ -2770,6 +3013,16 @@ func (variant ValidationError) MarshalJSON() ([]byte, error) {
 			"UnregisteredClass": inner,
 		})
 
+	case ValidationErrorInvalidConstant:
+		return json.Marshal(map[string]ValidationErrorInvalidConstant{
+			"InvalidConstant": inner,
+		})
+
+	case ValidationErrorInvalidImport:
+		return json.Marshal(map[string]ValidationErrorInvalidImport{
+			"InvalidImport": inner,
+		})
+
 	case ValidationErrorDuplicateResourceBlockDeclaration:
 		return json.Marshal(map[string]ValidationErrorDuplicateResourceBlockDeclaration{
 			"DuplicateResourceBlockDeclaration": inner,
@@ -734,9 +734,9 @@ describe('errors', () => {
 
     test('raises on UnrecognizedEOF errors', async () => {
       const p = new Polar();
-      const rule = 'f(a)';
+      const rule = 'f(a) if';
       await expect(p.loadStr(rule)).rejects.toThrow(
-        'hit the end of the file unexpectedly. Did you forget a semi-colon at line 1, column 5'
+        'hit the end of the file unexpectedly. Expected a rule body after `if` at line 1, column 8'
       );
     });
 
@@ -50,11 +50,11 @@ void (async function () {
   // Test that a custom error type is thrown.
   let exceptionThrown = false;
   try {
-    await oso.loadStr('missingSemicolon()');
+    await oso.loadStr('unfinishedRule() if');
   } catch (e) {
     const expectedName = 'ParseError::UnrecognizedEOF';
     const expectedMessage =
-      'hit the end of the file unexpectedly. Did you forget a semi-colon at line 1, column 19';
+      'hit the end of the file unexpectedly. Expected a rule body after `if` at line 1, column 20';
     const { name, message } = e as Error;
     if (name === expectedName && message.startsWith(expectedMessage))
       exceptionThrown = true;
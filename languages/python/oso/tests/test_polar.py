@@ -401,12 +401,12 @@ def test_parser_errors(polar):
 
     # UnrecognizedEOF
     rules = """
-    f(a)
+    f(a) if
     """
     with pytest.raises(exceptions.UnrecognizedEOF) as e:
         polar.load_str(rules)
     assert str(e.value).startswith(
-        "hit the end of the file unexpectedly. Did you forget a semi-colon at line 2, column 9"
+        "hit the end of the file unexpectedly. Expected a rule body after `if` at line 2, column 12"
     )
 
     # UnrecognizedToken
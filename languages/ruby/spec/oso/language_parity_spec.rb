@@ -7,10 +7,10 @@ oso = Oso.new
 # Test that a custom error type is thrown.
 exception_thrown = false
 begin
-  oso.load_str 'missingSemicolon()'
+  oso.load_str 'unfinishedRule() if'
 rescue Oso::Polar::ParseError::UnrecognizedEOF => e
   exception_thrown = true
-  raise unless e.message.start_with? 'hit the end of the file unexpectedly. Did you forget a semi-colon at line 1'
+  raise unless e.message.start_with? 'hit the end of the file unexpectedly. Expected a rule body after `if` at line 1'
 end
 raise unless exception_thrown
 
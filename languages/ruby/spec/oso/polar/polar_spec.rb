@@ -659,11 +659,11 @@ RSpec.describe Oso::Polar::Polar do # rubocop:disable Metrics/BlockLength
 
     it 'raises on UnrecognizedEOF errors' do
       rule = <<~POLAR
-        f(a)
+        f(a) if
       POLAR
       expect { subject.load_str(rule) }.to raise_error do |e|
         expect(e).to be_an Oso::Polar::ParseError::UnrecognizedEOF
-        expect(e.message).to start_with 'hit the end of the file unexpectedly. Did you forget a semi-colon at line 1'
+        expect(e.message).to start_with 'hit the end of the file unexpectedly. Expected a rule body after `if` at line 1'
       end
     end
 
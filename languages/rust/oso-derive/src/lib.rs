@@ -1,13 +1,28 @@
 use proc_macro::TokenStream;
 use quote::{quote, quote_spanned};
 use syn::{
-    Attribute, Data, DataEnum, DataStruct, Fields, Lit, Meta, MetaNameValue, NestedMeta, Path,
+    Attribute, Data, DataEnum, DataStruct, Fields, Ident, Lit, Meta, MetaNameValue, NestedMeta,
+    Path, Visibility,
 };
 
 #[derive(Debug, PartialEq)]
 enum OsoAttribute {
-    ClassName { name: String },
+    ClassName {
+        name: String,
+    },
     Attribute,
+    /// Add getters for the public fields of the type.
+    PublicFields,
+    /// Don't add a getter for a public field.
+    Skip,
+    /// Add the method of the type with this name.
+    Method {
+        name: Ident,
+    },
+    /// Add the associated function of the type with this name as a class method.
+    ClassMethod {
+        name: Ident,
+    },
 }
 
 fn get_single_segment(path: &Path) -> Option<String> {
@@ -32,6 +47,12 @@ fn get_nested_attr(nested: NestedMeta, oso_attrs: &mut Vec<OsoAttribute>) {
                         Some(ref seg) if seg == "attribute" => {
                             oso_attrs.push(OsoAttribute::Attribute);
                         }
+                        Some(ref seg) if seg == "public_fields" => {
+                            oso_attrs.push(OsoAttribute::PublicFields);
+                        }
+                        Some(ref seg) if seg == "skip" => {
+                            oso_attrs.push(OsoAttribute::Skip);
+                        }
                         _ => (),
                     };
                 }
@@ -44,12 +65,19 @@ fn get_nested_attr(nested: NestedMeta, oso_attrs: &mut Vec<OsoAttribute>) {
                 }
                 Meta::NameValue(MetaNameValue { path, lit, .. }) => {
                     if let Some(ref seg) = get_single_segment(&path) {
-                        if seg == "class_name" {
-                            // @TODO: Type error if it's not a string.
-                            if let Lit::Str(class_name) = lit {
-                                oso_attrs.push(OsoAttribute::ClassName {
-                                    name: class_name.value(),
-                                });
+                        // @TODO: Type error if it's not a string.
+                        if let Lit::Str(value) = lit {
+                            match seg.as_str() {
+                                "class_name" => oso_attrs.push(OsoAttribute::ClassName {
+                                    name: value.value(),
+                                }),
+                                "method" => oso_attrs.push(OsoAttribute::Method {
+                                    name: Ident::new(&value.value(), value.span()),
+                                }),
+                                "class_method" => oso_attrs.push(OsoAttribute::ClassMethod {
+                                    name: Ident::new(&value.value(), value.span()),
+                                }),
+                                _ => (),
                             }
                         }
                     }
@@ -85,9 +113,25 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
     for attr in attrs {
         get_oso_attrs(attr, &mut oso_attrs);
     }
+    let mut methods = vec![];
+    let mut public_fields = false;
     for oso_attr in oso_attrs {
-        if let OsoAttribute::ClassName { name } = oso_attr {
-            class_name = name;
+        match oso_attr {
+            OsoAttribute::ClassName { name } => class_name = name,
+            OsoAttribute::PublicFields => public_fields = true,
+            OsoAttribute::Method { name } => {
+                let method = name.to_string();
+                methods.push(quote! {
+                    .add_method(#method, #type_name::#name)
+                });
+            }
+            OsoAttribute::ClassMethod { name } => {
+                let method = name.to_string();
+                methods.push(quote! {
+                    .add_class_method(#method, #type_name::#name)
+                });
+            }
+            _ => (),
         }
     }
 
@@ -102,7 +146,12 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
                     for attr in field.attrs {
                         get_oso_attrs(attr, &mut oso_attrs);
                     }
-                    if oso_attrs.contains(&OsoAttribute::Attribute) {
+                    // Fields are attributes if they ask to be, and public fields are too if the
+                    // type asks for them and they aren't skipped.
+                    let public = public_fields
+                        && matches!(field.vis, Visibility::Public(_))
+                        && !oso_attrs.contains(&OsoAttribute::Skip);
+                    if public || oso_attrs.contains(&OsoAttribute::Attribute) {
                         let attr = field.ident.unwrap();
                         let name = attr.to_string();
                         getters.push(quote! {
@@ -141,6 +190,7 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
                 oso::Class::builder()
                     .name(#class_name)
                     #(#getters)*
+                    #(#methods)*
                     #(#constants)*
             }
 
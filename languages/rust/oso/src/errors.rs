@@ -58,6 +58,10 @@ pub enum OsoError {
     #[error("Tried to find an instance that doesn't exist -- internal error")]
     MissingInstanceError,
 
+    /// A rule or variable name passed to a query that Polar can't use.
+    #[error("{name:?} is not a valid rule or variable name")]
+    InvalidName { name: String },
+
     /// TODO: replace all these with proper variants
     #[error("{message}")]
     Custom { message: String },
@@ -19,11 +19,13 @@ use super::PolarValue;
 type Attributes = HashMap<&'static str, AttributeGetter>;
 type RegisterHooks = Vec<RegisterHook>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
+type ClassConstants = HashMap<&'static str, PolarValue>;
 type InstanceMethods = HashMap<&'static str, InstanceMethod>;
 
 type EqualityMethod = Arc<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync>;
 type IteratorMethod =
     Arc<dyn Fn(&Host, &Instance) -> crate::Result<crate::host::PolarIterator> + Send + Sync>;
+type InstanceCheck = Arc<dyn Fn(&Instance) -> bool + Send + Sync>;
 
 fn equality_not_supported() -> EqualityMethod {
     let eq = move |host: &Host, lhs: &Instance, _: &Instance| -> crate::Result<bool> {
@@ -60,6 +62,8 @@ pub struct Class {
     instance_methods: InstanceMethods,
     /// Class methods on `T`
     class_methods: ClassMethods,
+    /// Constants looked up on the class itself, like `Role.ADMIN`
+    class_constants: ClassConstants,
 
     /// A function that accepts arguments of this class and compares them for equality.
     /// Limitation: Only works on comparisons of the same type.
@@ -67,6 +71,10 @@ pub struct Class {
 
     into_iter: IteratorMethod,
 
+    /// A function that checks whether an instance matches the class in an `isa` check. Without
+    /// one, only instances of the class's own type do.
+    instance_check: Option<InstanceCheck>,
+
     // Hooks to be called on the class once it's been registered with host.
     pub register_hooks: RegisterHooks,
 }
@@ -101,6 +109,17 @@ impl Class {
         attr.clone().invoke(args)
     }
 
+    /// Look up the class constant `name`, as in `Role.ADMIN`.
+    fn get_constant(&self, name: &str) -> crate::Result<PolarValue> {
+        self.class_constants.get(name).cloned().ok_or_else(|| {
+            InvalidCallError::AttributeNotFound {
+                attribute_name: name.to_owned(),
+                type_name: self.name.clone(),
+            }
+            .into()
+        })
+    }
+
     fn get_method(&self, name: &str) -> Option<InstanceMethod> {
         tracing::trace!({class=%self.name, name}, "get_method");
         if self.type_id == TypeId::of::<Class>() {
@@ -144,8 +163,10 @@ where
                 attributes: HashMap::new(),
                 instance_methods: InstanceMethods::new(),
                 class_methods: ClassMethods::new(),
+                class_constants: ClassConstants::new(),
                 equality_check: equality_not_supported(),
                 into_iter: iterator_not_supported(),
+                instance_check: None,
                 type_id: TypeId::of::<T>(),
                 register_hooks: RegisterHooks::new(),
             },
@@ -230,6 +251,16 @@ where
         self.set_into_iter(|t| t.clone().into_iter())
     }
 
+    /// Set a function to check whether an instance matches the class in polar `isa` checks, for
+    /// classes that match instances of other types too.
+    pub fn set_instance_check<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Instance) -> bool + Send + Sync + 'static,
+    {
+        self.class.instance_check = Some(Arc::new(f));
+        self
+    }
+
     /// Use PartialEq::eq as the equality check for polar `==` statements.
     pub fn with_equality_check(self) -> Self
     where
@@ -313,6 +344,13 @@ where
         self
     }
 
+    /// A constant that's looked up on the type, unlike one added with `add_constant`.
+    /// eg `Role.ADMIN`
+    pub fn add_class_constant<V: crate::ToPolar>(mut self, value: V, name: &'static str) -> Self {
+        self.class.class_constants.insert(name, value.to_polar());
+        self
+    }
+
     /// Finish building a build the class
     pub fn build(self) -> Class {
         self.class
@@ -354,7 +392,10 @@ impl Instance {
 
     /// Check whether this is an instance of `class`
     pub fn instance_of(&self, class: &Class) -> bool {
-        self.type_id() == class.type_id
+        match &class.instance_check {
+            Some(check) => check(self),
+            None => self.type_id() == class.type_id,
+        }
     }
 
     pub fn type_id(&self) -> std::any::TypeId {
@@ -382,6 +423,10 @@ impl Instance {
     /// Lookup an attribute on the instance via the registered `Class`
     pub fn get_attr(&self, name: &str, host: &mut Host) -> crate::Result<PolarValue> {
         tracing::trace!({ method = %name }, "get_attr");
+        // The attributes of a class are its class constants.
+        if let Ok(class) = self.downcast::<Class>(None) {
+            return class.get_constant(name);
+        }
         let attr = self
             .class(host)
             .and_then(|c| {
@@ -474,5 +519,16 @@ mod test {
 
         assert!(foo_instance.instance_of(&foo_class));
         assert!(!foo_instance.instance_of(&bar_class));
+
+        // Bars match instances of Foo too.
+        let bar_class = Class::builder::<Bar>()
+            .set_instance_check(|instance| {
+                instance.type_id() == TypeId::of::<Foo>()
+                    || instance.type_id() == TypeId::of::<Bar>()
+            })
+            .build();
+        assert!(foo_instance.instance_of(&bar_class));
+        assert!(Instance::new(Bar {}).instance_of(&bar_class));
+        assert!(!Instance::new(1).instance_of(&bar_class));
     }
 }
@@ -6,6 +6,7 @@ use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedL
 use std::hash::Hash;
 
 use impl_trait_for_tuples::*;
+use polar_core::terms::Decimal;
 
 use super::class::Instance;
 use super::PolarValue;
@@ -63,6 +64,27 @@ polar_to_int!(u32);
 polar_to_int!(i32);
 polar_to_int!(i64);
 
+macro_rules! polar_to_wide_int {
+    ($i:ty) => {
+        impl FromPolar for $i {
+            fn from_polar(val: PolarValue) -> crate::Result<Self> {
+                match val {
+                    PolarValue::Integer(i) => {
+                        <$i>::try_from(i).map_err(|_| crate::OsoError::FromPolar)
+                    }
+                    PolarValue::BigInteger(i) => {
+                        <$i>::try_from(i).map_err(|_| crate::OsoError::FromPolar)
+                    }
+                    _ => Err(TypeError::expected("Integer").user()),
+                }
+            }
+        }
+    };
+}
+
+polar_to_wide_int!(u64);
+polar_to_wide_int!(i128);
+
 impl<T> FromPolar for T
 where
     T: 'static + Clone + PolarClass,
@@ -86,6 +108,17 @@ impl FromPolar for f64 {
     }
 }
 
+impl FromPolar for Decimal {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        match val {
+            PolarValue::Decimal(d) => Ok(d),
+            PolarValue::Integer(i) => Ok(Decimal::new(i.into(), 0)),
+            PolarValue::BigInteger(i) => Ok(Decimal::new(i, 0)),
+            _ => Err(TypeError::expected("Decimal").user()),
+        }
+    }
+}
+
 impl FromPolar for String {
     fn from_polar(val: PolarValue) -> crate::Result<Self> {
         if let PolarValue::String(s) = val {
@@ -261,7 +294,10 @@ try_from_polar!(i16);
 try_from_polar!(u32);
 try_from_polar!(i32);
 try_from_polar!(i64);
+try_from_polar!(u64);
+try_from_polar!(i128);
 try_from_polar!(f64);
+try_from_polar!(Decimal);
 try_from_polar!(String);
 try_from_polar!(bool);
 
@@ -301,6 +337,8 @@ impl FromPolar for Instance {
             PolarValue::Boolean(b) => Instance::new(b),
             PolarValue::Integer(i) => Instance::new(i),
             PolarValue::Float(f) => Instance::new(f),
+            PolarValue::BigInteger(i) => Instance::new(i),
+            PolarValue::Decimal(d) => Instance::new(d),
             PolarValue::List(v) => Instance::new(v),
             PolarValue::String(s) => Instance::new(s),
             PolarValue::Map(d) => Instance::new(d),
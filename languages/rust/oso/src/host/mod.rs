@@ -15,6 +15,7 @@ pub use class::{Class, ClassBuilder, Instance};
 pub use from_polar::{FromPolar, FromPolarList};
 use polar_core::terms::{Operator, Symbol};
 pub use to_polar::{PolarIterator, ToPolar, ToPolarList};
+pub(crate) use value::is_name;
 pub use value::PolarValue;
 
 lazy_static::lazy_static! {
@@ -117,7 +118,7 @@ impl Host {
     pub fn register_mros(&self) -> crate::Result<()> {
         for name in self.classes.keys() {
             if name != "oso::host::Class" {
-                self.polar.register_mro(Symbol(name.clone()), vec![])?;
+                self.polar.register_mro(Symbol::new(name), vec![])?;
             }
         }
         Ok(())
@@ -177,7 +178,7 @@ impl Host {
             PolarValue::Boolean(_) => class_tag == "Boolean",
             PolarValue::Map(_) => class_tag == "Dictionary",
             PolarValue::List(_) => class_tag == "List",
-            PolarValue::Integer(_) => class_tag == "Integer",
+            PolarValue::Integer(_) | PolarValue::BigInteger(_) => class_tag == "Integer",
             PolarValue::Float(_) => class_tag == "Float",
             PolarValue::String(_) => class_tag == "String",
             _ => false,
@@ -4,6 +4,7 @@
 //! Rust types back to Polar types.
 
 use impl_trait_for_tuples::*;
+use polar_core::terms::Decimal;
 
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
 
@@ -134,6 +135,22 @@ int_to_polar!(u32);
 int_to_polar!(i32);
 int_to_polar!(i64);
 
+macro_rules! wide_int_to_polar {
+    ($i:ty) => {
+        impl ToPolar for $i {
+            fn to_polar(self) -> PolarValue {
+                match i64::try_from(self) {
+                    Ok(i) => PolarValue::Integer(i),
+                    Err(_) => PolarValue::BigInteger(self.into()),
+                }
+            }
+        }
+    };
+}
+
+wide_int_to_polar!(u64);
+wide_int_to_polar!(i128);
+
 macro_rules! float_to_polar {
     ($i:ty) => {
         impl ToPolar for $i {
@@ -147,6 +164,12 @@ macro_rules! float_to_polar {
 float_to_polar!(f32);
 float_to_polar!(f64);
 
+impl ToPolar for Decimal {
+    fn to_polar(self) -> PolarValue {
+        PolarValue::Decimal(self)
+    }
+}
+
 impl ToPolar for String {
     fn to_polar(self) -> PolarValue {
         PolarValue::String(self)
@@ -15,6 +15,10 @@ use crate::host::{Host, Instance};
 pub enum PolarValue {
     Integer(i64),
     Float(f64),
+    /// An integer too big for an `i64`, like a `u64` database id.
+    BigInteger(i128),
+    /// An exact decimal, like an amount of money.
+    Decimal(Decimal),
     String(String),
     Boolean(bool),
     Map(HashMap<String, PolarValue>),
@@ -29,6 +33,8 @@ impl PartialEq for PolarValue {
             (PolarValue::Boolean(b1), PolarValue::Boolean(b2)) => b1 == b2,
             (PolarValue::Float(f1), PolarValue::Float(f2)) => f1 == f2,
             (PolarValue::Integer(i1), PolarValue::Integer(i2)) => i1 == i2,
+            (PolarValue::BigInteger(i1), PolarValue::BigInteger(i2)) => i1 == i2,
+            (PolarValue::Decimal(d1), PolarValue::Decimal(d2)) => d1 == d2,
             (PolarValue::List(l1), PolarValue::List(l2)) => l1 == l2,
             (PolarValue::Map(m1), PolarValue::Map(m2)) => m1 == m2,
             (PolarValue::String(s1), PolarValue::String(s2)) => s1 == s2,
@@ -50,12 +56,14 @@ impl PolarValue {
         let val = match term.value() {
             Value::Number(Numeric::Integer(i)) => PolarValue::Integer(*i),
             Value::Number(Numeric::Float(f)) => PolarValue::Float(*f),
+            Value::Number(Numeric::BigInteger(i)) => PolarValue::BigInteger(*i),
+            Value::Number(Numeric::Decimal(d)) => PolarValue::Decimal(*d),
             Value::String(s) => PolarValue::String(s.clone()),
             Value::Boolean(b) => PolarValue::Boolean(*b),
             Value::Dictionary(dict) => {
                 let mut map = HashMap::new();
                 for (k, v) in &dict.fields {
-                    let key = k.0.clone();
+                    let key = k.to_string();
                     let value = PolarValue::from_term(v, host)?;
                     map.insert(key, value);
                 }
@@ -71,7 +79,7 @@ impl PolarValue {
                 }
                 PolarValue::List(list)
             }
-            Value::Variable(Symbol(sym)) => PolarValue::Variable(sym.clone()),
+            Value::Variable(sym) => PolarValue::Variable(sym.to_string()),
             Value::Expression(_) => {
                 return Err(crate::OsoError::Custom {
                     message: r#"
@@ -91,17 +99,21 @@ This may mean you performed an operation in your policy over an unbound variable
         Ok(val)
     }
 
-    pub(crate) fn to_term(&self, host: &mut Host) -> Term {
+    /// The term for this value. Fails for a variable whose name Polar can't use, which would
+    /// otherwise make a query that silently never binds it.
+    pub(crate) fn to_term(&self, host: &mut Host) -> crate::Result<Term> {
         let value = match self {
             PolarValue::Integer(i) => Value::Number(Numeric::Integer(*i)),
             PolarValue::Float(f) => Value::Number(Numeric::Float(*f)),
+            PolarValue::BigInteger(i) => Value::Number(Numeric::from_i128(*i)),
+            PolarValue::Decimal(d) => Value::Number(Numeric::Decimal(*d)),
             PolarValue::String(s) => Value::String(s.clone()),
             PolarValue::Boolean(b) => Value::Boolean(*b),
             PolarValue::Map(map) => {
                 let mut dict = Dictionary::new();
                 for (k, v) in map {
-                    let key = Symbol(k.clone());
-                    let value = v.to_term(host);
+                    let key = Symbol::new(k);
+                    let value = v.to_term(host)?;
                     dict.fields.insert(key, value);
                 }
                 Value::Dictionary(dict)
@@ -119,12 +131,24 @@ This may mean you performed an operation in your policy over an unbound variable
             PolarValue::List(l) => {
                 let mut list = vec![];
                 for v in l {
-                    list.push(v.to_term(host))
+                    list.push(v.to_term(host)?)
                 }
                 Value::List(list)
             }
-            PolarValue::Variable(s) => Value::Variable(Symbol(s.clone())),
+            PolarValue::Variable(s) if is_name(s) => Value::Variable(Symbol::new(s)),
+            PolarValue::Variable(s) => {
+                return Err(crate::OsoError::InvalidName { name: s.clone() })
+            }
         };
-        Term::new_from_ffi(value)
+        Ok(Term::new_from_ffi(value))
     }
 }
+
+/// Whether `name` can be written as a rule or variable name in a policy, like `allow` or
+/// `_resource`.
+pub(crate) fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+        && !matches!(name, "true" | "false")
+}
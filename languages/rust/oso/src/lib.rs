@@ -33,7 +33,10 @@
 //!
 //! You can register classes with oso, which makes it possible to use them for type checking,
 //! as well as accessing attributes in policies.
-//! The `PolarClass` derive macro can handle some of this
+//! The `PolarClass` derive macro can handle some of this. Fields marked `#[polar(attribute)]`
+//! are attributes. `#[polar(public_fields)]` on the type makes its public fields attributes too,
+//! except the ones marked `#[polar(skip)]`, and `#[polar(method = "...")]` and
+//! `#[polar(class_method = "...")]` on the type add its methods.
 //! ```
 //! # fn main() -> anyhow::Result<()> {
 //! use oso::{Oso, PolarClass};
@@ -41,22 +44,18 @@
 //! let mut oso = Oso::new();
 //!
 //! #[derive(Clone, PolarClass)]
+//! #[polar(public_fields, class_method = "superusers")]
 //! struct User {
-//!     #[polar(attribute)]
 //!     pub username: String,
 //! }
 //!
 //! impl User {
-//!     fn superuser() -> Vec<String> {
+//!     fn superusers() -> Vec<String> {
 //!         return vec!["alice".to_string(), "charlie".to_string()]
 //!     }
 //! }
 //!
-//! oso.register_class(
-//!    User::get_polar_class_builder()
-//!         .add_class_method("superusers", User::superuser)
-//!         .build()
-//! )?;
+//! oso.register_class(User::get_polar_class())?;
 //!
 //! oso.load_str(r#"allow(actor: User, _action, _resource) if
 //!                     actor.username.ends_with("example.com");"#)?;
@@ -84,7 +83,7 @@ mod query;
 pub use crate::oso::{Action, Oso};
 pub use errors::{OsoError, Result};
 pub use host::{Class, ClassBuilder, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList};
-pub use query::{Query, ResultSet};
+pub use query::{CancelToken, Query, QueryStats, ResultSet};
 
 use polar_core::polar::Polar;
 
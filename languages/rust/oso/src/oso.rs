@@ -8,7 +8,7 @@ use std::hash::Hash;
 use std::io::Read;
 use std::sync::Arc;
 
-use crate::host::Host;
+use crate::host::{is_name, Host};
 use crate::query::Query;
 use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
 
@@ -64,6 +64,9 @@ impl Oso {
     }
 
     /// High level interface for authorization decisions. Makes an allow query with the given actor, action and resource and returns true or false.
+    ///
+    /// The query stops at its first result. Errors from the policy or the application, such as
+    /// a type error in a rule, are returned rather than treated as a denial.
     pub fn is_allowed<Actor, Action, Resource>(
         &self,
         actor: Actor,
@@ -75,7 +78,7 @@ impl Oso {
         Action: ToPolar,
         Resource: ToPolar,
     {
-        let mut query = self.query_rule("allow", (actor, action, resource)).unwrap();
+        let mut query = self.query_rule("allow", (actor, action, resource))?;
         match query.next() {
             Some(Ok(_)) => Ok(true),
             Some(Err(e)) => Err(e),
@@ -222,14 +225,19 @@ impl Oso {
     /// ```
     #[must_use = "Query that is not consumed does nothing."]
     pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
+        if !is_name(name) {
+            return Err(OsoError::InvalidName {
+                name: name.to_owned(),
+            });
+        }
         let mut query_host = self.host.clone();
         let args = args
             .to_polar_list()
             .iter()
             .map(|value| value.to_term(&mut query_host))
-            .collect();
+            .collect::<crate::Result<_>>()?;
         let query_value = Value::Call(Call {
-            name: Symbol(name.to_string()),
+            name: Symbol::new(name),
             args,
             kwargs: None,
         });
@@ -259,9 +267,32 @@ impl Oso {
         value: V,
         name: &str,
     ) -> crate::Result<()> {
-        self.inner.register_constant(
-            Symbol(name.to_string()),
-            value.to_polar().to_term(&mut self.host),
+        let value = value.to_polar().to_term(&mut self.host)?;
+        self.inner.register_constant(Symbol::new(name), value)?;
+        Ok(())
+    }
+
+    /// Register a union specializer that matches instances of any of the
+    /// already registered classes in `members`.
+    ///
+    /// ```
+    /// # use oso::{Oso, PolarClass};
+    /// # #[derive(Clone, PolarClass)]
+    /// # struct Issue;
+    /// # #[derive(Clone, PolarClass)]
+    /// # struct PullRequest;
+    /// let mut oso = Oso::new();
+    /// oso.register_class(Issue::get_polar_class())?;
+    /// oso.register_class(PullRequest::get_polar_class())?;
+    /// oso.register_union("Ticket", &["Issue", "PullRequest"])?;
+    /// oso.load_str(r#"allow(_, "comment", _: Ticket);"#)?;
+    /// assert!(oso.is_allowed("alice", "comment", Issue)?);
+    /// # Ok::<(), oso::OsoError>(())
+    /// ```
+    pub fn register_union(&mut self, name: &str, members: &[&str]) -> crate::Result<()> {
+        self.inner.register_union(
+            Symbol::new(name),
+            members.iter().map(|m| Symbol::new(m)).collect(),
         )?;
         Ok(())
     }
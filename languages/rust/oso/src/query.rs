@@ -6,6 +6,8 @@ use crate::host::{Host, Instance, PolarIterator};
 use crate::{FromPolar, PolarValue};
 
 use polar_core::events::*;
+pub use polar_core::query::CancelToken;
+pub use polar_core::stats::QueryStats;
 use polar_core::terms::*;
 
 impl Iterator for Query {
@@ -35,6 +37,17 @@ impl Query {
         self.inner.source_info()
     }
 
+    /// Counters of the work the query has done so far, such as goals run and calls to the host.
+    pub fn stats(&self) -> QueryStats {
+        self.inner.stats()
+    }
+
+    /// A token that makes the query fail with a `Cancelled` error when it's cancelled, from this
+    /// thread or another one.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.inner.cancel_token()
+    }
+
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
         loop {
             let event = self.inner.next()?;
@@ -85,7 +98,7 @@ impl Query {
                     left_class_tag,
                     right_class_tag,
                 ),
-                QueryEvent::Debug { message } => self.handle_debug(message),
+                QueryEvent::Debug { message, .. } => self.handle_debug(message),
                 QueryEvent::ExternalIsSubclass {
                     call_id,
                     left_class_tag,
@@ -115,9 +128,8 @@ impl Query {
     }
 
     fn call_result(&mut self, call_id: u64, result: PolarValue) -> crate::Result<()> {
-        Ok(self
-            .inner
-            .call_result(call_id, Some(result.to_term(&mut self.host)))?)
+        let result = result.to_term(&mut self.host)?;
+        Ok(self.inner.call_result(call_id, Some(result))?)
     }
 
     fn call_result_none(&mut self, call_id: u64) -> crate::Result<()> {
@@ -144,7 +156,7 @@ impl Query {
                         .iter()
                         .map(|term| PolarValue::from_term(term, &self.host))
                         .collect::<crate::Result<Vec<PolarValue>>>()?;
-                    self.host.make_instance(&name.0, args, instance_id)
+                    self.host.make_instance(&name.as_str(), args, instance_id)
                 }
             }
             _ => lazy_error!("invalid type for constructing an instance -- internal error"),
@@ -191,9 +203,9 @@ impl Query {
                 .iter()
                 .map(|v| PolarValue::from_term(v, &self.host))
                 .collect::<crate::Result<Vec<PolarValue>>>()?;
-            instance.call(&name.0, args, &mut self.host)
+            instance.call(&name.as_str(), args, &mut self.host)
         } else {
-            instance.get_attr(&name.0, &mut self.host)
+            instance.get_attr(&name.as_str(), &mut self.host)
         };
         match result {
             Ok(t) => self.call_result(call_id, t),
@@ -229,9 +241,10 @@ impl Query {
         class_tag: Symbol,
     ) -> crate::Result<()> {
         tracing::debug!(instance = ?instance, class = %class_tag, "isa");
-        let res = self
-            .host
-            .isa(PolarValue::from_term(&instance, &self.host)?, &class_tag.0)?;
+        let res = self.host.isa(
+            PolarValue::from_term(&instance, &self.host)?,
+            &class_tag.as_str(),
+        )?;
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -243,9 +256,11 @@ impl Query {
         left_class_tag: Symbol,
         right_class_tag: Symbol,
     ) -> crate::Result<()> {
-        let res = self
-            .host
-            .is_subspecializer(instance_id, &left_class_tag.0, &right_class_tag.0);
+        let res = self.host.is_subspecializer(
+            instance_id,
+            &left_class_tag.as_str(),
+            &right_class_tag.as_str(),
+        );
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -271,7 +286,8 @@ impl Query {
 
 #[derive(Clone)]
 pub struct ResultSet {
-    bindings: polar_core::kb::Bindings,
+    /// The bindings by the names of their variables, which are only resolved once.
+    bindings: HashMap<String, Term>,
     host: crate::host::Host,
 }
 
@@ -294,16 +310,20 @@ This may mean you performed an operation in your policy over an unbound variable
             }
         }
 
+        let bindings = bindings
+            .into_iter()
+            .map(|(var, value)| (var.to_string(), value))
+            .collect();
         Ok(Self { bindings, host })
     }
 
     /// Return the keys in bindings.
     pub fn keys(&self) -> Box<dyn std::iter::Iterator<Item = &str> + '_> {
-        Box::new(self.bindings.keys().map(|sym| sym.0.as_ref()))
+        Box::new(self.bindings.keys().map(String::as_str))
     }
 
     pub fn iter_bindings(&self) -> Box<dyn std::iter::Iterator<Item = (&str, &Value)> + '_> {
-        Box::new(self.bindings.iter().map(|(k, v)| (k.0.as_ref(), v.value())))
+        Box::new(self.bindings.iter().map(|(k, v)| (k.as_str(), v.value())))
     }
 
     pub fn is_empty(&self) -> bool {
@@ -312,7 +332,7 @@ This may mean you performed an operation in your policy over an unbound variable
 
     pub fn get(&self, name: &str) -> Option<crate::PolarValue> {
         self.bindings
-            .get(&Symbol(name.to_string()))
+            .get(name)
             .map(|t| PolarValue::from_term(t, &self.host).unwrap())
     }
 
@@ -323,7 +343,12 @@ This may mean you performed an operation in your policy over an unbound variable
     }
 
     pub fn into_event(self) -> ResultEvent {
-        ResultEvent::new(self.bindings)
+        let bindings = self
+            .bindings
+            .into_iter()
+            .map(|(var, value)| (Symbol::new(&var), value))
+            .collect();
+        ResultEvent::new(bindings)
     }
 }
 
@@ -81,8 +81,8 @@ fn test() {
     .unwrap();
 
     assert_eq!(
-        oso.load_str("missingSemicolon()").unwrap_err().to_string(),
-        "hit the end of the file unexpectedly. Did you forget a semi-colon at line 1, column 19:\n\t001: missingSemicolon()\n\t                       ^\n"
+        oso.load_str("unfinishedRule() if").unwrap_err().to_string(),
+        "hit the end of the file unexpectedly. Expected a rule body after `if` at line 1, column 20:\n\t001: unfinishedRule() if\n\t                        ^\n"
     );
 
     // Test that a built in string method can be called.
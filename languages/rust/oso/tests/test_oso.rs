@@ -1,4 +1,4 @@
-use oso::{Action, Oso, PolarClass};
+use oso::{Action, Oso, OsoError, PolarClass, PolarValue};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
@@ -117,6 +117,29 @@ fn test_query_rule() -> oso::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_is_allowed_error() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+    oso.load_str(r#"allow(_actor, "read", resource) if resource + 1 = 2;"#)?;
+
+    assert!(oso.is_allowed("alice", "read", "doc").is_err());
+    assert!(!oso.is_allowed("alice", "write", "doc")?);
+
+    // The query can't be made for an argument that isn't a Polar value.
+    let action = PolarValue::Variable("an action".to_owned());
+    assert!(matches!(
+        oso.is_allowed("alice", action, "doc"),
+        Err(OsoError::InvalidName { name }) if name == "an action"
+    ));
+    assert!(matches!(
+        oso.query_rule("allow!", ("alice", "read", "doc")),
+        Err(OsoError::InvalidName { .. })
+    ));
+
+    Ok(())
+}
+
 #[test]
 fn test_fail() -> oso::Result<()> {
     common::setup();
@@ -131,6 +154,42 @@ fn test_fail() -> oso::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_class_specializers() -> oso::Result<()> {
+    common::setup();
+    let oso = test_oso();
+
+    // `allow(_actor: User, "get", _resource: Widget)` only matches instances
+    // of the registered classes.
+    let actor = User::new(String::from("guest"));
+    assert!(oso.is_allowed(actor.clone(), "get", Widget::new(1))?);
+    assert!(!oso.is_allowed(actor, "get", Company::new(1))?);
+    assert!(!oso.is_allowed(Widget::new(1), "get", Widget::new(1))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_class_and_field_specializers() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+    oso.load_str(r#"allow(_: User{name: "admin"}, _action, _: Widget{id: 1});"#)?;
+
+    let admin = User::new(String::from("admin"));
+    assert!(oso.is_allowed(admin.clone(), "read", Widget::new(1))?);
+    assert!(!oso.is_allowed(admin, "read", Widget::new(2))?);
+    assert!(!oso.is_allowed(User::new(String::from("guest")), "read", Widget::new(1))?);
+
+    // A dictionary with matching fields is not an instance of the class.
+    let mut dict = HashMap::new();
+    dict.insert("name", "admin".to_string());
+    assert!(!oso.is_allowed(dict, "read", Widget::new(1))?);
+
+    Ok(())
+}
+
 #[test]
 fn test_instance_from_external_call() -> oso::Result<()> {
     common::setup();
@@ -375,6 +375,15 @@ fn test_register_constant() -> oso::Result<()> {
 
     assert_eq!(oso.qvar::<i64>("d.a = x", "x"), vec![1]);
 
+    // Constants can be referenced by bare name in rules, and outlive them.
+    oso.oso.register_constant("production", "ENV")?;
+    oso.oso.register_constant(vec![7, 8], "TENANT_IDS")?;
+    oso.load_str(r#"can_debug(tenant) if ENV != "production" or tenant in TENANT_IDS;"#);
+    oso.qeval("can_debug(7)");
+    oso.qnull("can_debug(1)");
+    oso.oso.clear_rules()?;
+    oso.qvar_one("ENV = x", "x", String::from("production"));
+
     Ok(())
 }
 
@@ -518,6 +527,8 @@ fn test_register_class() -> oso::Result<()> {
     oso.query_err("new Foo().b = x");
     oso.qvar_one("new Foo().b() = x", "x", vec!["b".to_owned()]);
     oso.qvar_one("new Foo().c() = x", "x", "c".to_owned());
+    oso.qvar_one(r#"new Foo().d("d") = x"#, "x", "d".to_owned());
+    oso.qvar_one(r#"y = "z" and new Foo().d(y) = x"#, "x", "z".to_owned());
     oso.qvar_one("new Foo() = f and f.a = x", "x", "A".to_owned());
     oso.qvar_one("new Foo().bar().y() = x", "x", "y".to_owned());
     oso.qvar_one("new Foo().e() = x", "x", vec![1, 2, 3]);
@@ -967,6 +978,40 @@ fn test_rule_types() {
     ));
 }
 
+#[test]
+fn test_declared_allow_rule_type() {
+    common::setup();
+    let mut oso = test_oso();
+    let rule_type = "type allow(actor: User, action: String, resource: Widget);";
+    oso.load_str(&format!(
+        "{} allow(_actor: User, \"read\", _resource: Widget);",
+        rule_type
+    ));
+    oso.clear_rules();
+
+    // A declared `allow` rule type replaces the default, which accepts any three arguments, so
+    // misordered arguments and the wrong arity are caught at load time.
+    for rule in [
+        "allow(_resource: Widget, \"read\", _actor: User);",
+        "allow(_actor: User, \"read\");",
+    ] {
+        let err = oso
+            .oso
+            .load_str(&format!("{} {}", rule_type, rule))
+            .expect_err("Expected validation error");
+        assert!(matches!(
+            &err,
+            OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Validation(
+                polar_error::ValidationError::InvalidRule { .. }
+            )))
+        ));
+        oso.clear_rules();
+    }
+
+    // Clearing the rules restores the default.
+    oso.load_str("allow(_resource: Widget, \"read\", _actor: User);");
+}
+
 #[test]
 fn test_exclusive_isa() {
     common::setup();
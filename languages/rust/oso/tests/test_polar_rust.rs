@@ -67,6 +67,34 @@ fn test_data_conversions() {
     assert!(bool::try_from(x.remove(0)).unwrap());
 }
 
+#[test]
+fn test_wide_number_conversions() {
+    common::setup();
+
+    use polar_core::terms::Decimal;
+
+    let mut test = OsoTest::new();
+    test.oso.register_constant(u64::MAX, "MAX_ID").unwrap();
+    test.oso.register_constant(i128::MIN, "MIN_I128").unwrap();
+    let price = Decimal::parse("19.99").unwrap();
+    test.oso.register_constant(price, "PRICE").unwrap();
+
+    test.qvar_one("x = MAX_ID", "x", u64::MAX);
+    test.qvar_one("x = MIN_I128", "x", i128::MIN);
+    test.qvar_one("x = PRICE", "x", price);
+    test.qvar_one("x = 7", "x", 7u64);
+    test.qvar_one("x = 7", "x", Decimal::new(7, 0));
+    test.qeval("MAX_ID > 9223372036854775807");
+    test.qeval("MIN_I128 < MAX_ID");
+    test.qeval("PRICE > 19.98 and PRICE < 20");
+    test.qeval("x = MAX_ID and x matches Integer");
+
+    let results = test.query("x = MAX_ID");
+    assert!(results[0].get_typed::<i64>("x").is_err());
+    let results = test.query("x = MIN_I128");
+    assert!(results[0].get_typed::<u64>("x").is_err());
+}
+
 // This logic is changing. Updated when fixed
 #[ignore]
 #[test]
@@ -197,6 +225,7 @@ fn test_external() {
         .add_attribute_getter("a", |receiver: &Foo| receiver.a)
         // .add_method("b", |receiver: &Foo| oso::host::PolarResultIter(receiver.b()))
         .add_class_method("c", Foo::c)
+        .add_class_constant("C", "C")
         .add_method::<_, _, u32>("d", Foo::d)
         .add_method("e", Foo::e)
         // .add_method("f", |receiver: &Foo| oso::host::PolarResultIter(receiver.f()))
@@ -212,6 +241,8 @@ fn test_external() {
     // test.qvar_one("new Foo().b() = x", "x", vec!["b".to_string()]);
 
     test.qvar_one("Foo.c() = x", "x", "c".to_string());
+    test.qvar_one("Foo.C = x", "x", "C".to_string());
+    test.query_err("Foo.D = x");
     test.qvar_one("new Foo().d(1) = x", "x", 1);
     test.query_err("new Foo().d(\"1\") = x");
     test.qvar_one("new Foo() = f and f.a = x", "x", "A".to_string());
@@ -442,6 +473,80 @@ fn test_enums_and_structs() {
     assert!(!test.oso.is_allowed(member, "read", "resource").unwrap());
 }
 
+#[test]
+fn test_derive_public_fields_and_methods() {
+    common::setup();
+
+    #[allow(dead_code)]
+    #[derive(Clone, PolarClass)]
+    #[polar(public_fields, method = "greeting", class_method = "anonymous")]
+    struct User {
+        pub name: String,
+        #[polar(skip)]
+        pub password: String,
+        #[polar(attribute)]
+        age: u32,
+        secret: u32,
+    }
+
+    impl User {
+        fn greeting(&self, greeting: String) -> String {
+            format!("{}, {}!", greeting, self.name)
+        }
+
+        fn anonymous() -> String {
+            "anonymous".to_owned()
+        }
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class()).unwrap();
+    test.oso
+        .register_constant(
+            User {
+                name: "alice".to_owned(),
+                password: "hunter2".to_owned(),
+                age: 30,
+                secret: 0,
+            },
+            "alice",
+        )
+        .unwrap();
+
+    test.qvar_one("x = alice.name", "x", "alice".to_string());
+    test.qvar_one("x = alice.age", "x", 30u32);
+    test.qvar_one(
+        r#"x = alice.greeting("Hello")"#,
+        "x",
+        "Hello, alice!".to_string(),
+    );
+    test.qvar_one("x = User.anonymous()", "x", "anonymous".to_string());
+    assert!(test.query_err("x = alice.password").contains("password"));
+    assert!(test.query_err("x = alice.secret").contains("secret"));
+
+    // Without `public_fields`, only the fields marked as attributes are.
+    #[allow(dead_code)]
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        pub name: String,
+        #[polar(attribute)]
+        pub public: bool,
+    }
+
+    test.oso.register_class(Repo::get_polar_class()).unwrap();
+    test.oso
+        .register_constant(
+            Repo {
+                name: "oso".to_owned(),
+                public: true,
+            },
+            "repo",
+        )
+        .unwrap();
+    test.qvar_one("x = repo.public", "x", true);
+    assert!(test.query_err("x = repo.name").contains("name"));
+}
+
 #[test]
 fn test_results_and_options() {
     common::setup();
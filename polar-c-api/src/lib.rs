@@ -2,8 +2,9 @@
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
 use polar_core::error::PolarError;
-pub use polar_core::polar::Polar;
+pub use polar_core::polar::{Polar, Snapshot};
 pub use polar_core::query::Query;
+use polar_core::query::{CancelToken, Order};
 use polar_core::{error, terms};
 
 use std::ffi::{c_void, CStr, CString};
@@ -110,6 +111,18 @@ pub extern "C" fn polar_load(
     })
 }
 
+/// Replace the loaded policy with `sources`, keeping registered classes and constants.
+#[no_mangle]
+pub extern "C" fn polar_reload_policy(
+    polar_ptr: *mut Polar,
+    sources: *const c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        from_json(sources).and_then(|sources| polar.reload_policy(sources))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_clear_rules(polar_ptr: *mut Polar) -> *mut CResult<c_void> {
     ffi_try!({
@@ -119,6 +132,129 @@ pub extern "C" fn polar_clear_rules(polar_ptr: *mut Polar) -> *mut CResult<c_voi
     })
 }
 
+/// Returns a copy of the knowledge base, which `polar_restore` puts back and `snapshot_free`
+/// frees.
+#[no_mangle]
+pub extern "C" fn polar_snapshot(polar_ptr: *mut Polar) -> *mut Snapshot {
+    let polar = unsafe { ffi_ref!(polar_ptr) };
+    box_ptr!(polar.snapshot())
+}
+
+#[no_mangle]
+pub extern "C" fn polar_restore(
+    polar_ptr: *mut Polar,
+    snapshot_ptr: *mut Snapshot,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let snapshot = unsafe { ffi_ref!(snapshot_ptr) };
+        polar.restore(snapshot);
+        Ok(())
+    })
+}
+
+/// Set the timeout in milliseconds of queries made after this call. 0 disables it.
+#[no_mangle]
+pub extern "C" fn polar_set_query_timeout(
+    polar_ptr: *mut Polar,
+    timeout_ms: u64,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_query_timeout(timeout_ms);
+        Ok(())
+    })
+}
+
+/// Set the step limit of queries made after this call. 0 removes it.
+#[no_mangle]
+pub extern "C" fn polar_set_step_limit(polar_ptr: *mut Polar, limit: u64) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_step_limit(limit);
+        Ok(())
+    })
+}
+
+/// Set the memory limit in bytes of queries made after this call. 0 removes it.
+#[no_mangle]
+pub extern "C" fn polar_set_memory_limit(
+    polar_ptr: *mut Polar,
+    limit: u64,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_memory_limit(limit);
+        Ok(())
+    })
+}
+
+/// Set the limit on how deeply rule calls are nested in queries made after this call. 0 removes
+/// it.
+#[no_mangle]
+pub extern "C" fn polar_set_depth_limit(
+    polar_ptr: *mut Polar,
+    limit: usize,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_depth_limit(limit);
+        Ok(())
+    })
+}
+
+/// Table every rule in queries made after this call if `tabling` is nonzero.
+#[no_mangle]
+pub extern "C" fn polar_set_tabling(polar_ptr: *mut Polar, tabling: u32) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_tabling(tabling != 0);
+        Ok(())
+    })
+}
+
+/// Evaluate independent top-level disjuncts of queries made after this call in parallel if
+/// `parallel` is nonzero.
+#[no_mangle]
+pub extern "C" fn polar_set_parallel_disjuncts(
+    polar_ptr: *mut Polar,
+    parallel: u32,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_parallel_disjuncts(parallel != 0);
+        Ok(())
+    })
+}
+
+/// Send the host the calls that don't depend on each other in one batch, in queries made after
+/// this call, if `batch` is nonzero.
+#[no_mangle]
+pub extern "C" fn polar_set_batch_external_calls(
+    polar_ptr: *mut Polar,
+    batch: u32,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_batch_external_calls(batch != 0);
+        Ok(())
+    })
+}
+
+/// Reuse the first answer to each external lookup for the rest of the query, in queries made
+/// after this call, if `cache` is nonzero.
+#[no_mangle]
+pub extern "C" fn polar_set_cache_lookups(
+    polar_ptr: *mut Polar,
+    cache: u32,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.set_cache_lookups(cache != 0);
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_register_constant(
     polar_ptr: *mut Polar,
@@ -253,6 +389,18 @@ pub extern "C" fn polar_call_result(
     })
 }
 
+/// Answer the calls of an external batch with a JSON list of answers, in the same order.
+#[no_mangle]
+pub extern "C" fn polar_batch_result(
+    query_ptr: *mut Query,
+    answers: *const c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        from_json(answers).and_then(|answers| query.batch_result(answers))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_question_result(
     query_ptr: *mut Query,
@@ -279,6 +427,20 @@ pub extern "C" fn polar_application_error(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_external_call_error(
+    query_ptr: *mut Query,
+    call_id: u64,
+    message: *mut c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        let s = unsafe { ffi_string!(message) }.to_string();
+
+        query.external_call_error(call_id, s)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_next_query_message(query_ptr: *mut Query) -> *mut CResult<c_char> {
     ffi_try!({
@@ -304,6 +466,30 @@ pub extern "C" fn polar_query_source_info(query_ptr: *mut Query) -> *mut CResult
     })
 }
 
+/// What the query has done so far, as JSON.
+#[no_mangle]
+pub extern "C" fn polar_query_stats(query_ptr: *mut Query) -> *mut CResult<c_char> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        let stats_json = serde_json::to_string(&query.stats()).unwrap();
+        Ok(CString::new(stats_json)
+            .expect("JSON should not contain any 0 bytes")
+            .into_raw())
+    })
+}
+
+/// The ids of the instances the host can free once the query is done, as a JSON list.
+#[no_mangle]
+pub extern "C" fn polar_query_released_instances(query_ptr: *mut Query) -> *mut CResult<c_char> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        let ids_json = serde_json::to_string(&query.released_instances()).unwrap();
+        Ok(CString::new(ids_json)
+            .expect("JSON should not contain any 0 bytes")
+            .into_raw())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_bind(
     query_ptr: *mut Query,
@@ -317,6 +503,92 @@ pub extern "C" fn polar_bind(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_query_set_timeout(
+    query_ptr: *mut Query,
+    timeout_ms: u64,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        query.set_timeout(timeout_ms);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_query_set_step_limit(
+    query_ptr: *mut Query,
+    limit: u64,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        query.set_step_limit(limit);
+        Ok(())
+    })
+}
+
+/// Order the results of the query by the value of the variable `name`, in descending order if
+/// `descending` is nonzero.
+#[no_mangle]
+pub extern "C" fn polar_query_set_result_order(
+    query_ptr: *mut Query,
+    name: *const c_char,
+    descending: u32,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        let name = unsafe { ffi_string!(name) };
+        let order = if descending != 0 {
+            Order::Descending
+        } else {
+            Order::Ascending
+        };
+        query.set_result_order(terms::Symbol::new(name.as_ref()), order);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_query_set_memory_limit(
+    query_ptr: *mut Query,
+    limit: u64,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        query.set_memory_limit(limit);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_query_set_depth_limit(
+    query_ptr: *mut Query,
+    limit: usize,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        query.set_depth_limit(limit);
+        Ok(())
+    })
+}
+
+/// Returns a token that cancels the query from any thread with `cancel_token_cancel`, and which
+/// `cancel_token_free` frees.
+#[no_mangle]
+pub extern "C" fn polar_query_cancel_token(query_ptr: *mut Query) -> *mut CancelToken {
+    let query = unsafe { ffi_ref!(query_ptr) };
+    box_ptr!(query.cancel_token())
+}
+
+#[no_mangle]
+pub extern "C" fn cancel_token_cancel(token_ptr: *mut CancelToken) -> i32 {
+    // Other threads may hold the same token, so only take a shared reference to it.
+    assert!(!token_ptr.is_null());
+    let token = unsafe { &*token_ptr };
+    token.cancel();
+    POLAR_SUCCESS
+}
+
 #[no_mangle]
 pub extern "C" fn polar_get_external_id(polar_ptr: *mut Polar) -> u64 {
     let polar = unsafe { ffi_ref!(polar_ptr) };
@@ -349,6 +621,22 @@ pub extern "C" fn query_free(query: *mut Query) -> i32 {
     POLAR_SUCCESS
 }
 
+/// Recovers the original boxed version of `snapshot` so that
+/// it can be properly freed
+#[no_mangle]
+pub extern "C" fn snapshot_free(snapshot: *mut Snapshot) -> i32 {
+    std::mem::drop(unsafe { Box::from_raw(snapshot) });
+    POLAR_SUCCESS
+}
+
+/// Recovers the original boxed version of `token` so that
+/// it can be properly freed
+#[no_mangle]
+pub extern "C" fn cancel_token_free(token: *mut CancelToken) -> i32 {
+    std::mem::drop(unsafe { Box::from_raw(token) });
+    POLAR_SUCCESS
+}
+
 /// Recovers the original boxed version of `result` so that
 /// it can be properly freed
 #[no_mangle]
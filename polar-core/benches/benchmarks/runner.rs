@@ -40,7 +40,7 @@ impl Runner {
                     panic!("Result expected")
                 }
                 QueryEvent::Done { .. } => break,
-                QueryEvent::Debug { message } => self.handle_debug(message),
+                QueryEvent::Debug { message, .. } => self.handle_debug(message),
                 event => todo!("{:?}", event),
             }
         }
@@ -2,10 +2,11 @@
 ///
 /// Bindings associate variables in the VM with constraints or values.
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use crate::{
     error::{PolarResult, RuntimeError},
-    folder::{fold_list, fold_term, Folder},
+    folder::{fold_term, Folder},
     terms::{has_rest_var, Operation, Operator, Symbol, Term, Value},
     vm::Goal,
 };
@@ -57,22 +58,50 @@ impl<'a> Derefer<'a> {
 }
 
 impl<'a> Folder for Derefer<'a> {
-    fn fold_list(&mut self, list: Vec<Term>) -> Vec<Term> {
-        let has_rest = has_rest_var(&list);
-        let mut list = fold_list(list, self);
-        if has_rest {
-            let last = list.pop().unwrap();
-            if let Value::List(rest) = last.value() {
-                list.append(&mut rest.clone());
+    /// Splice bound rest variables into the list. A list built up by a recursive rule can
+    /// be a long chain of them, so follow the chain in a loop rather than by recursion.
+    fn fold_list(&mut self, mut list: Vec<Term>) -> Vec<Term> {
+        let mut folded = vec![];
+        let mut rests = vec![];
+        loop {
+            let rest = if has_rest_var(&list) {
+                list.pop()
             } else {
-                list.push(last);
+                None
+            };
+            folded.extend(list.into_iter().map(|t| self.fold_term(t)));
+            let rest = match rest {
+                Some(rest) => rest,
+                None => break,
+            };
+            let hash = rest.hash_value();
+            let value = match rest.value() {
+                Value::RestVariable(v) if !self.seen.contains(&hash) => {
+                    self.binding_manager.lookup(v)
+                }
+                _ => None,
+            };
+            match value.as_ref().map(Term::value) {
+                Some(Value::List(tail)) => {
+                    self.seen.insert(hash);
+                    rests.push(hash);
+                    list = tail.clone();
+                }
+                _ => {
+                    folded.push(self.fold_term(rest));
+                    break;
+                }
             }
         }
-        list
+        for hash in rests {
+            self.seen.remove(&hash);
+        }
+        folded
     }
 
     fn fold_term(&mut self, t: Term) -> Term {
         match t.value() {
+            _ if t.is_variable_free() => t,
             Value::Expression(_) => t,
             Value::Variable(v) | Value::RestVariable(v) => {
                 let hash = t.hash_value();
@@ -143,13 +172,73 @@ enum BindingManagerVariableState<'a> {
 /// A binding is created with the `bind` method.
 ///
 /// The constraints or value associated with a variable is retrieved with `variable_state`.
+///
+/// Bindings are only ever pushed, and backtracking pops the ones made since the point it
+/// returns to. The bindings made before a manager was last forked are kept in a store that it
+/// shares with its forks, so the sub-VMs that run negations and `findall`s don't copy them.
 #[derive(Clone, Debug, Default)]
 pub struct BindingManager {
-    bindings: BindingStack,
+    /// The bindings made before the last fork, of which only the first `shared_len` are
+    /// this manager's. The rest were made by a fork or have since been backtracked over.
+    shared: Rc<BindingStore>,
+    shared_len: usize,
+    /// The bindings made since the last fork, starting at index `shared_len`.
+    local: BindingStore,
     followers: HashMap<FollowerId, BindingManager>,
     next_follower_id: FollowerId,
 }
 
+/// A stack of bindings, indexed by variable so that looking one up doesn't scan it.
+#[derive(Clone, Debug, Default)]
+struct BindingStore {
+    /// The index of the first binding in the bindings of the manager as a whole.
+    start: usize,
+    bindings: BindingStack,
+    /// The index of the latest binding of each variable.
+    latest: HashMap<Symbol, usize>,
+    /// For each binding, the index of the earlier binding of the same variable, if any.
+    shadowed: Vec<Option<usize>>,
+}
+
+impl BindingStore {
+    fn new(start: usize) -> Self {
+        Self {
+            start,
+            ..Default::default()
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.start + self.bindings.len()
+    }
+
+    fn push(&mut self, binding: Binding) {
+        let shadowed = self.latest.insert(binding.0.clone(), self.len());
+        self.shadowed.push(shadowed);
+        self.bindings.push(binding);
+    }
+
+    /// Pop the bindings from index `len` on.
+    fn truncate(&mut self, len: usize) {
+        while self.len() > len {
+            let Binding(var, _) = self.bindings.pop().unwrap();
+            match self.shadowed.pop().unwrap() {
+                Some(index) => self.latest.insert(var, index),
+                None => self.latest.remove(&var),
+            };
+        }
+    }
+
+    /// The latest binding of `variable` before index `bsp`.
+    fn value(&self, variable: &Symbol, bsp: usize) -> Option<&Term> {
+        let mut index = *self.latest.get(variable)?;
+        while index >= bsp {
+            index = self.shadowed[index - self.start]?;
+        }
+        Some(&self.bindings[index - self.start].1)
+    }
+}
+
 // Public interface.
 impl BindingManager {
     pub fn new() -> Self {
@@ -312,7 +401,44 @@ impl BindingManager {
         })
         .unwrap();
 
-        self.bindings.truncate(to.bindings_index)
+        let to = to.bindings_index;
+        if to >= self.shared_len {
+            self.local.truncate(to);
+        } else {
+            // Bindings in the shared store that a fork may still use are left there, past the
+            // end of this manager's part of it.
+            if let Some(shared) = Rc::get_mut(&mut self.shared) {
+                shared.truncate(to);
+            }
+            self.shared_len = to;
+            self.local = BindingStore::new(to);
+        }
+    }
+
+    /// A copy of this manager for a sub-VM, which shares the bindings made so far with it
+    /// instead of copying them.
+    pub fn fork(&mut self) -> Self {
+        if self.shared_len < self.shared.len() || !self.local.bindings.is_empty() {
+            // Only copies the shared store if another fork still uses it.
+            let shared = Rc::make_mut(&mut self.shared);
+            shared.truncate(self.shared_len);
+            for binding in std::mem::take(&mut self.local.bindings) {
+                shared.push(binding);
+            }
+            self.shared_len = shared.len();
+            self.local = BindingStore::new(self.shared_len);
+        }
+        Self {
+            shared: self.shared.clone(),
+            shared_len: self.shared_len,
+            local: BindingStore::new(self.shared_len),
+            followers: self
+                .followers
+                .iter_mut()
+                .map(|(id, follower)| (*id, follower.fork()))
+                .collect(),
+            next_follower_id: self.next_follower_id,
+        }
     }
 
     // *** Binding Inspection ***
@@ -359,12 +485,16 @@ impl BindingManager {
 
     /// Return all variables used in this binding manager.
     pub fn variables(&self) -> HashSet<Symbol> {
-        self.bindings
-            .iter()
+        self.bindings_from(0)
             .map(|Binding(v, _)| v.clone())
             .collect()
     }
 
+    /// The number of bindings, including the ones shared with the manager this was forked from.
+    pub fn len(&self) -> usize {
+        self.local.len()
+    }
+
     /// Retrieve an opaque value representing the current state of `BindingManager`.
     /// Can be used to reset state with `backtrack`.
     pub fn bsp(&self) -> Bsp {
@@ -375,7 +505,7 @@ impl BindingManager {
             .collect::<HashMap<_, _>>();
 
         Bsps {
-            bindings_index: self.bindings.len(),
+            bindings_index: self.local.len(),
             followers: follower_bsps,
         }
     }
@@ -386,7 +516,7 @@ impl BindingManager {
 
     pub fn bindings_after(&self, include_temps: bool, after: &Bsp) -> Bindings {
         let mut bindings = HashMap::new();
-        for Binding(var, value) in &self.bindings[after.bindings_index..] {
+        for Binding(var, value) in self.bindings_from(after.bindings_index) {
             if !include_temps && var.is_temporary_var() {
                 continue;
             }
@@ -395,6 +525,22 @@ impl BindingManager {
         bindings
     }
 
+    /// The bindings after `after` to return in a query result, which leave out bound temporary
+    /// variables. Simplifying the result only needs the temporaries that are unbound or partial,
+    /// and copying out the values of the rest can cost more than the query: a rule that builds
+    /// up a list binds a temporary to every suffix of it.
+    pub fn result_bindings(&self, after: &Bsp) -> Bindings {
+        let mut bindings = HashMap::new();
+        for Binding(var, value) in self.bindings_from(after.bindings_index) {
+            if var.is_temporary_var() && matches!(self.variable_state(var), VariableState::Bound(_))
+            {
+                continue;
+            }
+            bindings.insert(var.clone(), self.deep_deref(value));
+        }
+        bindings
+    }
+
     pub fn variable_bindings(&self, variables: &HashSet<Symbol>) -> Bindings {
         let mut bindings = HashMap::new();
         for var in variables.iter() {
@@ -407,8 +553,8 @@ impl BindingManager {
     }
 
     /// Get the bindings stack *for debugging purposes only*.
-    pub fn bindings_debug(&self) -> &BindingStack {
-        &self.bindings
+    pub fn bindings_debug(&self) -> BindingStack {
+        self.bindings_from(0).cloned().collect()
     }
 
     // *** Followers ***
@@ -545,7 +691,14 @@ impl BindingManager {
     }
 
     fn add_binding(&mut self, var: &Symbol, val: Term) {
-        self.bindings.push(Binding(var.clone(), val));
+        self.local.push(Binding(var.clone(), val));
+    }
+
+    /// The bindings from index `index` on, oldest first.
+    fn bindings_from(&self, index: usize) -> impl Iterator<Item = &Binding> {
+        let shared = &self.shared.bindings[index.min(self.shared_len)..self.shared_len];
+        let local = &self.local.bindings[index.saturating_sub(self.shared_len)..];
+        shared.iter().chain(local)
     }
 
     fn lookup(&self, var: &Symbol) -> Option<Term> {
@@ -558,11 +711,12 @@ impl BindingManager {
     /// Look up a variable in the bindings stack and return
     /// a reference to its value if it's bound.
     fn value(&self, variable: &Symbol, bsp: usize) -> Option<&Term> {
-        self.bindings[..bsp]
-            .iter()
-            .rev()
-            .find(|Binding(var, _)| var == variable)
-            .map(|Binding(_, val)| val)
+        if bsp > self.shared_len {
+            if let Some(value) = self.local.value(variable, bsp) {
+                return Some(value);
+            }
+        }
+        self.shared.value(variable, bsp.min(self.shared_len))
     }
 
     fn _variable_state(&self, variable: &Symbol) -> BindingManagerVariableState {
@@ -663,7 +817,7 @@ mod test {
         );
         assert_eq!(
             bindings._variable_state(&z),
-            BindingManagerVariableState::Cycle(vec![z.clone(), x.clone(), y])
+            BindingManagerVariableState::Cycle(vec![z, x.clone(), y])
         );
 
         // Expression.
@@ -869,6 +1023,39 @@ mod test {
         assert_eq!(bm.variable_state(&y), VariableState::Unbound);
     }
 
+    #[test]
+    fn test_fork() {
+        let (x, y, z) = (sym!("x"), sym!("y"), sym!("z"));
+        let mut parent = BindingManager::new();
+        parent.bind(&x, term!(1)).unwrap();
+        let start = parent.bsp();
+        parent.bind(&y, term!(2)).unwrap();
+
+        // The fork sees the parent's bindings, and they don't see each other's new ones.
+        let mut fork = parent.fork();
+        assert!(Rc::ptr_eq(&parent.shared, &fork.shared));
+        fork.bind(&z, term!(3)).unwrap();
+        parent.bind(&z, term!(4)).unwrap();
+        assert_eq!(fork.variable_state(&y), VariableState::Bound(term!(2)));
+        assert_eq!(fork.variable_state(&z), VariableState::Bound(term!(3)));
+        assert_eq!(parent.variable_state(&z), VariableState::Bound(term!(4)));
+
+        // Backtracking past the fork leaves the shared bindings to the fork.
+        parent.backtrack(&start);
+        assert_eq!(parent.variable_state(&y), VariableState::Unbound);
+        assert_eq!(parent.variable_state(&z), VariableState::Unbound);
+        assert_eq!(fork.variable_state(&y), VariableState::Bound(term!(2)));
+        parent.bind(&y, term!(5)).unwrap();
+        assert_eq!(parent.bindings(true).len(), 2);
+
+        let mut fork = parent.fork();
+        assert_eq!(fork.variable_state(&y), VariableState::Bound(term!(5)));
+        assert_eq!(fork.variable_state(&x), VariableState::Bound(term!(1)));
+        fork.backtrack(&Bsp::default());
+        assert_eq!(fork.variable_state(&x), VariableState::Unbound);
+        assert_eq!(parent.variable_state(&x), VariableState::Bound(term!(1)));
+    }
+
     #[test]
     fn test_backtrack_followers() {
         // Regular bindings
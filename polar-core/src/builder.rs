@@ -0,0 +1,107 @@
+//! Building rules in Rust, for code that generates a policy and would otherwise have to write
+//! Polar source for `Polar::load_str` to parse:
+//!
+//! ```
+//! use polar_core::builder::{call, class, var, RuleBuilder};
+//! # use polar_core::polar::Polar;
+//!
+//! // allow(actor, "read", resource: Repo) if has_role(actor, "reader", resource);
+//! let rule = RuleBuilder::new("allow")
+//!     .param(var("actor"))
+//!     .param("read".to_owned().into())
+//!     .param_with_specializer(var("resource"), class("Repo"))
+//!     .body(call("has_role", vec![var("actor"), "reader".to_owned().into(), var("resource")]))
+//!     .build();
+//! let polar = Polar::new();
+//! polar.add_rule(rule).unwrap();
+//! ```
+//!
+//! Strings, numbers and booleans convert to terms with `Term::from`.
+
+use crate::rules::{Parameter, Rule};
+use crate::sources::SourceInfo;
+use crate::terms::{Call, Dictionary, InstanceLiteral, Operation, Operator, Pattern, Symbol, Term};
+
+/// The variable `name`.
+pub fn var(name: &str) -> Term {
+    Term::from(Symbol::new(name))
+}
+
+/// A call to the rule `name`, like `has_role(actor, "reader", resource)`.
+pub fn call(name: &str, args: Vec<Term>) -> Term {
+    Term::from(Call {
+        name: Symbol::new(name),
+        args,
+        kwargs: None,
+    })
+}
+
+/// An operation, like `x = 1` or `x.name`, with the operator's arguments in the order they're
+/// written.
+pub fn op(operator: Operator, args: Vec<Term>) -> Term {
+    Term::from(Operation { operator, args })
+}
+
+/// A specializer for instances of the class `tag`, like `Repo` in `resource: Repo`.
+pub fn class(tag: &str) -> Term {
+    Term::from(Pattern::Instance(InstanceLiteral {
+        tag: Symbol::new(tag),
+        fields: Dictionary::default(),
+    }))
+}
+
+/// Builds a rule from its parameters and the conditions of its body.
+pub struct RuleBuilder {
+    name: Symbol,
+    params: Vec<Parameter>,
+    conditions: Vec<Term>,
+}
+
+impl RuleBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Symbol::new(name),
+            params: vec![],
+            conditions: vec![],
+        }
+    }
+
+    /// Add a parameter, which is a variable or a value the argument must match.
+    pub fn param(mut self, parameter: Term) -> Self {
+        self.params.push(Parameter {
+            parameter,
+            specializer: None,
+        });
+        self
+    }
+
+    /// Add a parameter that only matches arguments that match `specializer`, like `class("Repo")`
+    /// or a dictionary of fields.
+    pub fn param_with_specializer(mut self, parameter: Term, specializer: Term) -> Self {
+        self.params.push(Parameter {
+            parameter,
+            specializer: Some(specializer),
+        });
+        self
+    }
+
+    /// Add a condition to the body. The rule holds if all of them do, and a rule without any is
+    /// a fact.
+    pub fn body(mut self, condition: Term) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn build(self) -> Rule {
+        Rule {
+            name: self.name,
+            params: self.params,
+            body: op(Operator::And, self.conditions),
+            source_info: SourceInfo::ffi(),
+            required: false,
+            doc: None,
+            annotations: vec![],
+            program: Default::default(),
+        }
+    }
+}
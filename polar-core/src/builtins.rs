@@ -0,0 +1,179 @@
+//! Built-in predicates, which are called like rules but evaluated by the VM.
+//!
+//! A rule defined in the KB with the same name as a built-in takes precedence over it. Hosts can
+//! add built-ins of their own, implemented in Rust, with `Polar::register_builtin`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use super::datetime::parse_datetime;
+use super::numerics::Numeric;
+use super::terms::*;
+
+/// Regexes compiled by `matches_regex`, by pattern.
+pub type Regexes = HashMap<String, Regex>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    IsBound,
+    IsUnbound,
+    IsString,
+    IsInteger,
+    IsList,
+    IsDict,
+    MatchesRegex,
+    Now,
+    TimeBefore,
+    TimeAfter,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "is_bound" => Some(Self::IsBound),
+            "is_unbound" => Some(Self::IsUnbound),
+            "is_string" => Some(Self::IsString),
+            "is_integer" => Some(Self::IsInteger),
+            "is_list" => Some(Self::IsList),
+            "is_dict" => Some(Self::IsDict),
+            "matches_regex" => Some(Self::MatchesRegex),
+            "now" => Some(Self::Now),
+            "time_before" => Some(Self::TimeBefore),
+            "time_after" => Some(Self::TimeAfter),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::IsBound => "is_bound",
+            Self::IsUnbound => "is_unbound",
+            Self::IsString => "is_string",
+            Self::IsInteger => "is_integer",
+            Self::IsList => "is_list",
+            Self::IsDict => "is_dict",
+            Self::MatchesRegex => "matches_regex",
+            Self::Now => "now",
+            Self::TimeBefore => "time_before",
+            Self::TimeAfter => "time_after",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Self::IsBound
+            | Self::IsUnbound
+            | Self::IsString
+            | Self::IsInteger
+            | Self::IsList
+            | Self::IsDict
+            | Self::Now => 1,
+            Self::MatchesRegex | Self::TimeBefore | Self::TimeAfter => 2,
+        }
+    }
+
+    /// Whether the built-in holds for `args`, which must already be dereferenced. Returns a
+    /// message describing the type error if the arguments aren't valid.
+    ///
+    /// `now`, which binds its argument rather than testing it, is evaluated by the VM.
+    pub fn holds(self, args: &[Term], regexes: &mut Regexes) -> Result<bool, String> {
+        Ok(match self {
+            Self::IsBound => !is_unbound(&args[0]),
+            Self::IsUnbound => is_unbound(&args[0]),
+            Self::IsString => matches!(args[0].value(), Value::String(_)),
+            Self::IsInteger => matches!(
+                args[0].value(),
+                Value::Number(Numeric::Integer(_) | Numeric::BigInteger(_))
+            ),
+            Self::IsList => matches!(args[0].value(), Value::List(_)),
+            Self::IsDict => matches!(args[0].value(), Value::Dictionary(_)),
+            Self::MatchesRegex => match (args[0].value(), args[1].value()) {
+                (Value::String(string), Value::String(pattern)) => {
+                    if !regexes.contains_key(pattern) {
+                        let regex = Regex::new(pattern)
+                            .map_err(|e| format!("invalid regex {}: {}", args[1], e))?;
+                        regexes.insert(pattern.clone(), regex);
+                    }
+                    regexes[pattern].is_match(string)
+                }
+                _ => {
+                    return Err(format!(
+                        "matches_regex expects a string and a pattern string, got {} and {}",
+                        args[0], args[1]
+                    ))
+                }
+            },
+            Self::Now => return Err("now binds its argument, so it can't be tested".to_owned()),
+            Self::TimeBefore | Self::TimeAfter => {
+                let (left, right) = match (args[0].value(), args[1].value()) {
+                    (Value::String(left), Value::String(right)) => {
+                        (parse_datetime(left), parse_datetime(right))
+                    }
+                    _ => (None, None),
+                };
+                match (left, right) {
+                    (Some(left), Some(right)) if self == Self::TimeBefore => left < right,
+                    (Some(left), Some(right)) => left > right,
+                    _ => {
+                        return Err(format!(
+                            "{} expects two RFC 3339 datetime strings, got {} and {}",
+                            self.name(),
+                            args[0],
+                            args[1]
+                        ))
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// The answer of a built-in registered with `Polar::register_builtin` to a call.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuiltinAnswer {
+    /// The call fails.
+    No,
+    /// The call holds once, without binding its arguments.
+    Yes,
+    /// The call holds once, unifying its arguments with these terms, one for each argument.
+    Unify(Vec<Term>),
+    /// The call holds once for each of these lists of terms to unify its arguments with, in
+    /// order. It fails if there are none.
+    Choices(Vec<Vec<Term>>),
+}
+
+type NativeFn = dyn Fn(&[Term]) -> Result<BuiltinAnswer, String> + Send + Sync;
+
+/// A built-in predicate implemented in Rust, which the VM calls with the dereferenced arguments
+/// of a call instead of asking the host.
+#[derive(Clone)]
+pub struct NativeBuiltin {
+    pub name: Symbol,
+    pub arity: usize,
+    f: Arc<NativeFn>,
+}
+
+impl NativeBuiltin {
+    pub fn new<F>(name: &str, arity: usize, f: F) -> Self
+    where
+        F: Fn(&[Term]) -> Result<BuiltinAnswer, String> + Send + Sync + 'static,
+    {
+        Self {
+            name: Symbol::new(name),
+            arity,
+            f: Arc::new(f),
+        }
+    }
+
+    /// Answer a call with `args`, or return a message describing why they aren't valid.
+    pub(crate) fn call(&self, args: &[Term]) -> Result<BuiltinAnswer, String> {
+        (self.f)(args)
+    }
+}
+
+/// A variable that is unbound or only constrained by partial evaluation has no value yet.
+fn is_unbound(term: &Term) -> bool {
+    matches!(term.value(), Value::Variable(_) | Value::RestVariable(_))
+}
@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::{PolarError, PolarResult};
+use crate::events::{ExternalAnswer, QueryEvent};
+use crate::runnable::Runnable;
+use crate::terms::Term;
+use crate::vm::{Goals, PolarVirtualMachine};
+
+/// An aggregate function applied to the values collected by a `Collector`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+}
+
+/// The collector implements list comprehensions and aggregates in Polar.
+///
+/// It is a `Runnable` that runs `goals` to exhaustion using `vm`, and collects the value of
+/// `template` in each result. The collected values are passed back to the parent VM in
+/// `results`, in the order in which they were found.
+#[derive(Clone)]
+pub struct Collector {
+    vm: PolarVirtualMachine,
+    template: Term,
+    results: Rc<RefCell<Vec<Term>>>,
+}
+
+impl Collector {
+    pub fn new(
+        vm: &mut PolarVirtualMachine,
+        goals: Goals,
+        template: Term,
+        results: Rc<RefCell<Vec<Term>>>,
+    ) -> Self {
+        Self {
+            vm: vm.clone_with_goals(goals),
+            template,
+            results,
+        }
+    }
+}
+
+impl Runnable for Collector {
+    fn run(&mut self, _: Option<&mut Counter>) -> PolarResult<QueryEvent> {
+        loop {
+            match self.vm.run(None)? {
+                QueryEvent::Done { .. } => return Ok(QueryEvent::Done { result: true }),
+                QueryEvent::Result { .. } => {
+                    let value = self.vm.deref(&self.template);
+                    self.vm.hold(&value);
+                    self.results.borrow_mut().push(value);
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
+        self.vm.external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> PolarResult<()> {
+        self.vm.external_call_result(call_id, term)
+    }
+
+    fn external_call_error(&mut self, call_id: u64, message: String) -> PolarResult<()> {
+        self.vm.external_call_error(call_id, message)
+    }
+
+    fn external_batch_result(&mut self, answers: Vec<ExternalAnswer>) -> PolarResult<()> {
+        self.vm.external_batch_result(answers)
+    }
+
+    fn debug_command(&mut self, command: &str) -> PolarResult<()> {
+        self.vm.debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
+        self.vm.handle_error(error)
+    }
+}
@@ -1,7 +1,7 @@
 use crate::terms::{Symbol, Term};
 use std::collections::HashMap;
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub(crate) struct Constants {
     // Symbol -> Term (populated by *all* constants)
     pub symbol_to_term: HashMap<Symbol, Term>,
@@ -22,6 +22,13 @@ impl Constants {
         self.class_id_to_symbol.insert(class_id, name);
     }
 
+    pub(crate) fn remove(&mut self, name: &Symbol) {
+        self.symbol_to_term.remove(name);
+        if let Some(class_id) = self.class_symbol_to_id.remove(name) {
+            self.class_id_to_symbol.remove(&class_id);
+        }
+    }
+
     pub(crate) fn contains_key(&self, name: &Symbol) -> bool {
         self.symbol_to_term.contains_key(name)
     }
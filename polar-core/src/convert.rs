@@ -0,0 +1,450 @@
+//! Conversions between Rust values and terms, for passing values to queries and reading their
+//! results without building `Value`s by hand. They're the standard `From` and `TryFrom` traits,
+//! so a value converts with `Term::from(value)`, or `Term::try_from(value)` if it can be null,
+//! and reads back with `T::try_from(&term)`.
+//!
+//! Numbers, strings, booleans, `Option`s, `Vec`s, maps with string keys and JSON values convert
+//! both ways. Integers up to `i128` and `u64` convert exactly, as do `numerics::Decimal`s. In
+//! JSON, an integer that fits in neither an `i64` nor a `u64`, or a decimal, is a string.
+//! Polar has no null, and the `nil` that hosts register for theirs is only a variable without
+//! them, so `None` and JSON `null` are errors. An unbound variable in a result converts to
+//! `None`, or to JSON `null`. The host keeps its own instances, so any external instance in a
+//! result converts to an `InstanceRef`, or to a JSON object with its `instance_id` and
+//! `class_tag`, and an `InstanceRef` converts back, so an external call can return the host's
+//! objects.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{unexpected_value, OperationalError, PolarError, PolarResult};
+use crate::numerics::{Decimal, Numeric};
+use crate::terms::{has_rest_var, Dictionary, ExternalInstance, InstanceRef, Symbol, Term, Value};
+
+impl TryFrom<&Term> for bool {
+    type Error = PolarError;
+
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::Boolean(b) => Ok(*b),
+            _ => unexpected_value("a boolean", term.clone()),
+        }
+    }
+}
+
+macro_rules! small_integer {
+    ($($t:ty),*) => {$(
+        impl From<$t> for Numeric {
+            fn from(other: $t) -> Self {
+                Self::Integer(i64::from(other))
+            }
+        }
+    )*};
+}
+
+small_integer!(i8, i16, i32, u8, u16, u32);
+
+impl From<u64> for Numeric {
+    fn from(other: u64) -> Self {
+        Self::from_i128(i128::from(other))
+    }
+}
+
+impl From<i128> for Numeric {
+    fn from(other: i128) -> Self {
+        Self::from_i128(other)
+    }
+}
+
+macro_rules! integer {
+    ($($t:ty),*) => {$(
+        impl TryFrom<&Term> for $t {
+            type Error = PolarError;
+
+            fn try_from(term: &Term) -> PolarResult<Self> {
+                match term.value() {
+                    Value::Number(n) => match n.to_i128().map(<$t>::try_from) {
+                        Some(Ok(i)) => Ok(i),
+                        Some(Err(_)) => unexpected_value(
+                            concat!("an integer that fits in ", stringify!($t)),
+                            term.clone(),
+                        ),
+                        None => unexpected_value("an integer", term.clone()),
+                    },
+                    _ => unexpected_value("an integer", term.clone()),
+                }
+            }
+        }
+    )*};
+}
+
+integer!(i8, i16, i32, i64, i128, u8, u16, u32, u64);
+
+impl From<f32> for Numeric {
+    fn from(other: f32) -> Self {
+        Self::Float(f64::from(other))
+    }
+}
+
+impl TryFrom<&Term> for f64 {
+    type Error = PolarError;
+
+    /// Integers and decimals are read as the nearest float too.
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::Number(n) => Ok(n.to_f64()),
+            _ => unexpected_value("a number", term.clone()),
+        }
+    }
+}
+
+impl TryFrom<&Term> for Decimal {
+    type Error = PolarError;
+
+    /// Integers are read as decimals too, but floats aren't, since they're rarely exact.
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::Number(n) => match n.to_decimal() {
+                Some(d) => Ok(d),
+                None => unexpected_value("a decimal or an integer", term.clone()),
+            },
+            _ => unexpected_value("a decimal or an integer", term.clone()),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(other: &str) -> Self {
+        Self::String(other.to_owned())
+    }
+}
+
+impl TryFrom<&Term> for String {
+    type Error = PolarError;
+
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::String(s) => Ok(s.clone()),
+            _ => unexpected_value("a string", term.clone()),
+        }
+    }
+}
+
+impl<T: Into<Term>> From<Vec<T>> for Value {
+    fn from(other: Vec<T>) -> Self {
+        Self::List(other.into_iter().map(T::into).collect())
+    }
+}
+
+impl<T> TryFrom<&Term> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Term, Error = PolarError>,
+{
+    type Error = PolarError;
+
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::List(list) if !has_rest_var(list) => list.iter().map(T::try_from).collect(),
+            _ => unexpected_value("a list", term.clone()),
+        }
+    }
+}
+
+/// The error for a null value, which has no term.
+fn null_error(null: &str) -> PolarError {
+    OperationalError::Serialization {
+        msg: format!("{} has no Polar value", null),
+    }
+    .into()
+}
+
+impl<T: Into<Term>> TryFrom<Option<T>> for Term {
+    type Error = PolarError;
+
+    fn try_from(other: Option<T>) -> PolarResult<Self> {
+        other.map(T::into).ok_or_else(|| null_error("None"))
+    }
+}
+
+impl<T> TryFrom<&Term> for Option<T>
+where
+    T: for<'a> TryFrom<&'a Term, Error = PolarError>,
+{
+    type Error = PolarError;
+
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::Variable(_) => Ok(None),
+            _ => T::try_from(term).map(Some),
+        }
+    }
+}
+
+macro_rules! dictionary {
+    ($($map:ident),*) => {$(
+        impl<T: Into<Term>> From<$map<String, T>> for Value {
+            fn from(other: $map<String, T>) -> Self {
+                let fields = other
+                    .into_iter()
+                    .map(|(k, v)| (Symbol::new(&k), v.into()))
+                    .collect();
+                Self::Dictionary(Dictionary { fields })
+            }
+        }
+
+        impl<T> TryFrom<&Term> for $map<String, T>
+        where
+            T: for<'a> TryFrom<&'a Term, Error = PolarError>,
+        {
+            type Error = PolarError;
+
+            fn try_from(term: &Term) -> PolarResult<Self> {
+                let Value::Dictionary(Dictionary { fields }) = term.value() else {
+                    return unexpected_value("a dictionary", term.clone());
+                };
+                let mut map = $map::new();
+                for (k, v) in fields {
+                    map.insert(k.to_string(), T::try_from(v)?);
+                }
+                Ok(map)
+            }
+        }
+    )*};
+}
+
+dictionary!(HashMap, BTreeMap);
+
+/// An instance the host made for a result, which it has mapped to `instance_id`. Its class
+/// tag lets the VM check it against a registered class without asking the host.
+impl From<InstanceRef> for Value {
+    fn from(other: InstanceRef) -> Self {
+        Self::ExternalInstance(ExternalInstance {
+            instance_id: other.instance_id,
+            constructor: None,
+            repr: None,
+            class_repr: other.class_tag,
+            class_id: None,
+        })
+    }
+}
+
+impl TryFrom<&Term> for InstanceRef {
+    type Error = PolarError;
+
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::ExternalInstance(instance) => Ok(instance.to_ref()),
+            _ => unexpected_value("an external instance", term.clone()),
+        }
+    }
+}
+
+/// JSON `null` anywhere in the value is an error, like `None`.
+impl TryFrom<serde_json::Value> for Term {
+    type Error = PolarError;
+
+    fn try_from(other: serde_json::Value) -> PolarResult<Self> {
+        use serde_json::Value as Json;
+        Ok(match other {
+            Json::Null => return Err(null_error("JSON null")),
+            Json::Bool(b) => Self::from(b),
+            Json::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => Self::from(i),
+                (_, Some(u)) => Self::from(u),
+                _ => Self::from(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(s) => Self::from(s),
+            Json::Array(elements) => Self::from(
+                elements
+                    .into_iter()
+                    .map(Self::try_from)
+                    .collect::<PolarResult<Vec<_>>>()?,
+            ),
+            Json::Object(entries) => {
+                let mut fields = BTreeMap::new();
+                for (k, v) in entries {
+                    fields.insert(Symbol::new(&k), Self::try_from(v)?);
+                }
+                Self::from(Value::Dictionary(Dictionary { fields }))
+            }
+        })
+    }
+}
+
+impl TryFrom<&Term> for serde_json::Value {
+    type Error = PolarError;
+
+    fn try_from(term: &Term) -> PolarResult<Self> {
+        use serde_json::Value as Json;
+        Ok(match term.value() {
+            Value::Variable(_) => Json::Null,
+            Value::Boolean(b) => Json::Bool(*b),
+            Value::Number(Numeric::Integer(i)) => Json::from(*i),
+            Value::Number(Numeric::Float(f)) => match serde_json::Number::from_f64(*f) {
+                Some(n) => Json::Number(n),
+                None => return unexpected_value("a finite number", term.clone()),
+            },
+            Value::Number(Numeric::BigInteger(i)) => match u64::try_from(*i) {
+                Ok(u) => Json::from(u),
+                Err(_) => Json::String(i.to_string()),
+            },
+            Value::Number(Numeric::Decimal(d)) => Json::String(d.to_string()),
+            Value::String(s) => Json::String(s.clone()),
+            Value::List(list) if !has_rest_var(list) => Json::Array(
+                list.iter()
+                    .map(Self::try_from)
+                    .collect::<PolarResult<_>>()?,
+            ),
+            Value::Dictionary(Dictionary { fields }) => {
+                let mut entries = serde_json::Map::new();
+                for (k, v) in fields {
+                    entries.insert(k.to_string(), Self::try_from(v)?);
+                }
+                Json::Object(entries)
+            }
+            Value::ExternalInstance(instance) => {
+                serde_json::to_value(instance.to_ref()).expect("an instance ref is valid JSON")
+            }
+            _ => return unexpected_value("a JSON value", term.clone()),
+        })
+    }
+}
+
+/// The arguments of a rule call, as a tuple of values that convert to terms.
+pub trait ToPolarArgs {
+    fn to_polar_args(self) -> Vec<Term>;
+}
+
+macro_rules! args {
+    ($(($($t:ident),*)),*) => {$(
+        impl<$($t: Into<Term>),*> ToPolarArgs for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn to_polar_args(self) -> Vec<Term> {
+                let ($($t,)*) = self;
+                vec![$($t.into()),*]
+            }
+        }
+    )*};
+}
+
+args!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+);
+
+impl ToPolarArgs for () {
+    fn to_polar_args(self) -> Vec<Term> {
+        vec![]
+    }
+}
+
+impl<T: Into<Term>> ToPolarArgs for Vec<T> {
+    fn to_polar_args(self) -> Vec<Term> {
+        self.into_iter().map(T::into).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(value: T)
+    where
+        T: Into<Term> + for<'a> TryFrom<&'a Term, Error = PolarError>,
+        T: Clone + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(T::try_from(&value.clone().into()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(true);
+        round_trip(-3i8);
+        round_trip(70_000u32);
+        round_trip(1.5f64);
+        round_trip(u64::MAX);
+        round_trip(i128::MIN);
+        round_trip(Decimal::parse("-12.50").unwrap());
+        round_trip("text".to_owned());
+        round_trip(vec![vec![1i64, 2], vec![]]);
+        round_trip(HashMap::from([("a".to_owned(), 1i32), ("b".to_owned(), 2)]));
+        round_trip(BTreeMap::from([("a".to_owned(), vec!["x".to_owned()])]));
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        assert!(u8::try_from(&Term::from(256)).is_err());
+        assert!(String::try_from(&Term::from(1)).is_err());
+        assert!(Vec::<i64>::try_from(&Term::from(vec![Term::from("a".to_owned())])).is_err());
+        assert_eq!(f64::try_from(&Term::from(2)).unwrap(), 2.0);
+        assert!(u64::try_from(&Term::from(-1i64)).is_err());
+        assert!(i64::try_from(&Term::from(u64::MAX)).is_err());
+        assert!(Decimal::try_from(&Term::from(0.5)).is_err());
+        assert_eq!(
+            Decimal::try_from(&Term::from(2)).unwrap(),
+            Decimal::new(2, 0)
+        );
+        assert_eq!(
+            Option::<i64>::try_from(&Term::from(Symbol::new("x"))).unwrap(),
+            None
+        );
+        assert_eq!(Option::<i64>::try_from(&Term::from(1)).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_options() {
+        let term = Term::try_from(Some(vec![2u8])).unwrap();
+        assert_eq!(Option::<Vec<u8>>::try_from(&term).unwrap(), Some(vec![2]));
+        assert!(Term::try_from(None::<u8>).is_err());
+        // An unbound variable in a result is `None`.
+        assert_eq!(
+            Option::<u8>::try_from(&Term::from(Symbol::new("x"))).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::json!({
+            "sub": "alice",
+            "exp": 1646137800,
+            "scope": ["read", "write"],
+            "ratio": 0.5,
+            "verified": true,
+            "id": u64::MAX,
+            "claims": {"org": "acme"},
+        });
+        let term = Term::try_from(json.clone()).unwrap();
+        assert_eq!(serde_json::Value::try_from(&term).unwrap(), json);
+        let Value::Dictionary(Dictionary { fields }) = term.value() else {
+            panic!("expected a dictionary, got {}", term);
+        };
+        assert_eq!(fields[&Symbol::new("exp")], Term::from(1646137800));
+        assert!(Term::try_from(serde_json::json!([1, null])).is_err());
+        assert!(Term::try_from(serde_json::json!({"a": null, "b": null})).is_err());
+        assert_eq!(
+            serde_json::Value::try_from(&Term::from(Symbol::new("x"))).unwrap(),
+            serde_json::Value::Null
+        );
+        assert!(serde_json::Value::try_from(&Term::from(f64::INFINITY)).is_err());
+        assert_eq!(
+            serde_json::Value::try_from(&Term::from(Decimal::new(1250, 3))).unwrap(),
+            serde_json::json!("1.25")
+        );
+    }
+
+    #[test]
+    fn test_to_polar_args() {
+        assert_eq!(().to_polar_args(), vec![]);
+        assert_eq!(
+            ("alice", 1i64, vec![true]).to_polar_args(),
+            vec![
+                Term::from("alice".to_owned()),
+                Term::from(1),
+                Term::from(vec![Term::from(true)])
+            ]
+        );
+    }
+}
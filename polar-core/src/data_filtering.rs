@@ -185,7 +185,7 @@ impl VarInfo {
             _ => {
                 let new_var = sym!(&format!(
                     "_{}_dot_{}_{}",
-                    sym.0,
+                    sym,
                     field_str,
                     self.counter.next()
                 ));
@@ -225,7 +225,7 @@ impl VarInfo {
         match rhs.as_pattern() {
             Ok(Pattern::Instance(i)) if i.fields.fields.is_empty() => {
                 let lhs = self.symbolize(lhs);
-                self.types.push((lhs, i.tag.0.clone()));
+                self.types.push((lhs, i.tag.to_string()));
                 Ok(self)
             }
             _ => df_unsupported_op(Operation {
@@ -775,10 +775,11 @@ impl<'a> ResultSetBuilder<'a> {
         } else {
             invalid_state(format!(
                 "Unsupported field access: {}.{} = {}",
-                self.var_name(id).unwrap_or_else(|| Symbol(id.to_string())),
+                self.var_name(id)
+                    .unwrap_or_else(|| Symbol::new(&id.to_string())),
                 field,
                 self.var_name(child)
-                    .unwrap_or_else(|| Symbol(child.to_string())),
+                    .unwrap_or_else(|| Symbol::new(&child.to_string())),
             ))
         }
     }
@@ -904,7 +905,7 @@ impl Vars {
         for (id, set) in &self.variables {
             let values = set
                 .iter()
-                .map(|sym| sym.0.clone())
+                .map(|sym| sym.to_string())
                 .collect::<Vec<String>>()
                 .join(", ");
             eprintln!("      {}:  vars: {{{}}}", id, values);
@@ -0,0 +1,198 @@
+//! Datetimes, which Polar represents as RFC 3339 strings like `"2022-03-01T12:00:00Z"`.
+//!
+//! The `time_before` and `time_after` built-ins compare two datetime strings as the instants they
+//! denote, so strings with different offsets or fractional digits are ordered correctly. Other
+//! comparisons, like `<` and `==`, compare the strings themselves.
+
+use std::sync::Arc;
+
+/// The current time in milliseconds since the Unix epoch, as used by the `now` built-in.
+///
+/// Hosts can replace the system clock with `Polar::set_clock`, e.g. to evaluate a policy as of
+/// some other time.
+pub type Clock = Arc<dyn Fn() -> i64 + Send + Sync>;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn system_clock() -> Clock {
+    Arc::new(|| {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system time is before the Unix epoch");
+        now.as_millis() as i64
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn system_clock() -> Clock {
+    Arc::new(|| js_sys::Date::now() as i64)
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, month and day that is `days` days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse a run of exactly `len` ASCII digits.
+fn digits(s: &[u8], len: usize) -> Option<i64> {
+    if s.len() < len || !s[..len].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(s[..len].iter().fold(0, |n, d| n * 10 + i64::from(d - b'0')))
+}
+
+/// The instant denoted by an RFC 3339 datetime, as seconds and nanoseconds since the Unix epoch,
+/// or `None` if `s` isn't one.
+pub fn parse_datetime(s: &str) -> Option<(i64, u32)> {
+    let s = s.as_bytes();
+    let year = digits(s, 4)?;
+    let month = digits(s.get(5..)?, 2)?;
+    let day = digits(s.get(8..)?, 2)?;
+    let hour = digits(s.get(11..)?, 2)?;
+    let minute = digits(s.get(14..)?, 2)?;
+    let second = digits(s.get(17..)?, 2)?;
+    if s[4] != b'-'
+        || s[7] != b'-'
+        || !matches!(s[10], b'T' | b't' | b' ')
+        || s[13] != b':'
+        || s[16] != b':'
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    let mut nanos = 0;
+    if rest.first() == Some(&b'.') {
+        let len = rest[1..].iter().take_while(|d| d.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+        // Digits past nanosecond precision are dropped.
+        let frac = &rest[1..1 + len.min(9)];
+        nanos = frac.iter().fold(0, |n, d| n * 10 + u32::from(d - b'0'))
+            * 10u32.pow(9 - frac.len() as u32);
+        rest = &rest[1 + len..];
+    }
+    let offset = match rest {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (hours, minutes) = (digits(&rest[1..], 2)?, digits(&rest[4..], 2)?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some((seconds, nanos))
+}
+
+/// Format `millis` since the Unix epoch as an RFC 3339 datetime in UTC.
+pub fn format_datetime(millis: i64) -> String {
+    let (days, millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(parse_datetime("1970-01-01T00:00:00Z"), Some((0, 0)));
+        assert_eq!(
+            parse_datetime("2022-03-01T12:30:05.25Z"),
+            Some((1646137805, 250_000_000))
+        );
+        assert_eq!(
+            parse_datetime("2022-03-01T13:30:05.25+01:00"),
+            parse_datetime("2022-03-01T12:30:05.25Z")
+        );
+        assert_eq!(
+            parse_datetime("2022-02-28T23:00:00-01:00"),
+            parse_datetime("2022-03-01T00:00:00Z")
+        );
+        assert_eq!(
+            parse_datetime("1969-12-31T23:59:59.5Z"),
+            Some((-1, 500_000_000))
+        );
+        assert_eq!(
+            parse_datetime("2024-02-29t00:00:00z"),
+            Some((1709164800, 0))
+        );
+
+        assert_eq!(parse_datetime("2023-02-29T00:00:00Z"), None);
+        assert_eq!(parse_datetime("2022-03-01"), None);
+        assert_eq!(parse_datetime("2022-03-01T12:00:00"), None);
+        assert_eq!(parse_datetime("2022-03-01T24:00:00Z"), None);
+        assert_eq!(parse_datetime("2022-03-01T12:00:00.Z"), None);
+        assert_eq!(parse_datetime("2022-03-01T12:00:00+0100"), None);
+        assert_eq!(parse_datetime("alice"), None);
+    }
+
+    #[test]
+    fn test_format_datetime() {
+        assert_eq!(format_datetime(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_datetime(1646137805250), "2022-03-01T12:30:05.250Z");
+        assert_eq!(format_datetime(-500), "1969-12-31T23:59:59.500Z");
+        let now = system_clock()();
+        assert_eq!(
+            parse_datetime(&format_datetime(now)),
+            Some((
+                now.div_euclid(1000),
+                (now.rem_euclid(1000) * 1_000_000) as u32
+            ))
+        );
+    }
+}
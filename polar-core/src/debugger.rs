@@ -14,7 +14,7 @@ impl PolarVirtualMachine {
         let relevant_bindings = self.relevant_bindings(&[query]);
         let bindings_str = relevant_bindings
             .iter()
-            .map(|(var, val)| format!("{} = {}", var.0, val))
+            .map(|(var, val)| format!("{} = {}", var, val))
             .collect::<Vec<_>>()
             .join(", ");
         format!("QUERY: {}, BINDINGS: {{{}}}", query, bindings_str)
@@ -84,6 +84,11 @@ pub struct Debugger {
 }
 
 impl Debugger {
+    /// Whether the user is stepping through the query, so that evaluation may pause.
+    pub fn is_stepping(&self) -> bool {
+        self.step.is_some()
+    }
+
     /// Retrieve the original source line (and, optionally, additional lines of context) for the
     /// current query.
     fn query_source(&self, query: &Term, num_lines: usize) -> String {
@@ -252,7 +257,11 @@ impl Debugger {
                     message: vm.stack_trace()
                 })
             }
-            "goals" => return Some(show(&vm.goals)),
+            "goals" => {
+                let mut goals = vm.goals.iter().cloned().collect::<Vec<_>>();
+                goals.reverse();
+                return Some(show(&goals))
+            }
             "bindings" => {
                 return Some(show(vm.bindings_debug().as_slice()))
             }
@@ -269,7 +278,7 @@ impl Debugger {
                     let mut vars = vm
                         .bindings(true)
                         .keys()
-                        .map(|k| k.0.as_ref())
+                        .map(|k| k.to_string())
                         .collect::<Vec<_>>()
                         .join(", ");
                     if vars.is_empty() {
@@ -319,7 +328,8 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
             bindings
                 .keys()
                 .filter_map(|k| {
-                    k.0.strip_prefix(&prefix)
+                    k.as_str()
+                        .strip_prefix(&prefix)
                         .and_then(|i| i.parse::<i64>().map_or(None, |i| Some((k, i))))
                 })
                 .max_by(|a, b| a.1.cmp(&b.1))
@@ -327,7 +337,7 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
                     || Binding(sym!(name), Term::from(sym!("<unbound>"))),
                     |b| {
                         Binding(
-                            sym!(format!("{}@{}", name, b.0 .0).as_str()),
+                            sym!(format!("{}@{}", name, b.0).as_str()),
                             bindings.get(b.0).unwrap().clone(),
                         )
                     },
@@ -0,0 +1,250 @@
+//! Deserialization of terms and query results into Rust types with serde.
+//!
+//! A result's bindings deserialize like a map from variable names to values, so a struct with a
+//! field for each variable of a query gets its values:
+//!
+//! ```
+//! # use polar_core::{deserialize::from_bindings, events::QueryEvent, polar::Polar};
+//! #[derive(serde::Deserialize)]
+//! struct Pair {
+//!     x: i64,
+//!     names: Vec<String>,
+//! }
+//!
+//! let polar = Polar::new();
+//! let mut query = polar.new_query(r#"x = 1 and names = ["a", "b"]"#, false).unwrap();
+//! if let QueryEvent::Result { bindings, .. } = query.next_event().unwrap() {
+//!     let pair: Pair = from_bindings(&bindings).unwrap();
+//!     assert_eq!((pair.x, pair.names.len()), (1, 2));
+//! }
+//! ```
+//!
+//! Lists deserialize as sequences, dictionaries as maps and strings as unit enum variants.
+//! Integers too big for an `i64` deserialize into any integer type they fit in, and decimals
+//! deserialize as strings, so they can be parsed without rounding.
+//! An unbound variable deserializes as `None` or `()`. An external instance deserializes as a
+//! map with its `instance_id` and `class_tag`, like `terms::InstanceRef`. Other terms, like
+//! expressions, can't be deserialized.
+
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::bindings::Bindings;
+use crate::error::{OperationalError, PolarError, PolarResult};
+use crate::numerics::Numeric;
+use crate::terms::{has_rest_var, Symbol, Term, Value};
+
+impl de::Error for PolarError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        OperationalError::Serialization {
+            msg: msg.to_string(),
+        }
+        .into()
+    }
+}
+
+/// Deserialize `term` into a `T`.
+#[allow(clippy::result_large_err)]
+pub fn from_term<T: DeserializeOwned>(term: &Term) -> PolarResult<T> {
+    T::deserialize(TermDeserializer(term))
+}
+
+/// Deserialize the bindings of a query result into a `T`, whose fields are named after the
+/// variables of the query.
+#[allow(clippy::result_large_err)]
+pub fn from_bindings<T: DeserializeOwned>(bindings: &Bindings) -> PolarResult<T> {
+    T::deserialize(MapDeserializer(Entries::new(
+        bindings.iter().map(|(k, v)| (k.to_string(), v)),
+    )))
+}
+
+struct TermDeserializer<'a>(&'a Term);
+
+impl<'de, 'a> de::Deserializer<'de> for TermDeserializer<'a> {
+    type Error = PolarError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> PolarResult<V::Value> {
+        match self.0.value() {
+            Value::Boolean(b) => visitor.visit_bool(*b),
+            Value::Number(Numeric::Integer(i)) => visitor.visit_i64(*i),
+            Value::Number(Numeric::Float(f)) => visitor.visit_f64(*f),
+            Value::Number(Numeric::BigInteger(i)) => match u64::try_from(*i) {
+                Ok(u) => visitor.visit_u64(u),
+                Err(_) => visitor.visit_i128(*i),
+            },
+            Value::Number(Numeric::Decimal(d)) => visitor.visit_string(d.to_string()),
+            Value::String(s) => visitor.visit_str(s),
+            Value::List(list) if !has_rest_var(list) => visitor.visit_seq(Elements(list.iter())),
+            Value::Dictionary(dict) => visitor.visit_map(Entries::new(
+                dict.fields.iter().map(|(k, v)| (k.to_string(), v)),
+            )),
+            Value::Variable(_) => visitor.visit_unit(),
+            Value::ExternalInstance(instance) => {
+                let instance = instance.to_ref();
+                let class_tag = match instance.class_tag {
+                    Some(tag) => Term::from(tag),
+                    None => Term::from(Symbol::new("_")),
+                };
+                let fields = [
+                    ("instance_id", Term::from(instance.instance_id as i64)),
+                    ("class_tag", class_tag),
+                ];
+                visitor.visit_map(Entries::new(fields.iter().map(|(k, v)| (k.to_string(), v))))
+            }
+            _ => Err(de::Error::custom(format!("can't deserialize {}", self.0))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> PolarResult<V::Value> {
+        match self.0.value() {
+            Value::Variable(_) => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> PolarResult<V::Value> {
+        match self.0.value() {
+            Value::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            _ => Err(de::Error::custom(format!(
+                "can't deserialize {} as {}, which is one of {:?}",
+                self.0, name, variants
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Elements<'a>(std::slice::Iter<'a, Term>);
+
+impl<'de, 'a> de::SeqAccess<'de> for Elements<'a> {
+    type Error = PolarError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> PolarResult<Option<T::Value>> {
+        match self.0.next() {
+            Some(term) => seed.deserialize(TermDeserializer(term)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The entries of a dictionary or of bindings, and the value of the last key that was read.
+struct Entries<'a, I> {
+    entries: I,
+    value: Option<&'a Term>,
+}
+
+impl<'a, I> Entries<'a, I> {
+    fn new(entries: I) -> Self {
+        Self {
+            entries,
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'a, I: Iterator<Item = (String, &'a Term)>> de::MapAccess<'de> for Entries<'a, I> {
+    type Error = PolarError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> PolarResult<Option<K::Value>> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.value = Some(value);
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> PolarResult<V::Value> {
+        let value = self.value.take().expect("value read before its key");
+        seed.deserialize(TermDeserializer(value))
+    }
+}
+
+/// Bindings, which are always a map.
+struct MapDeserializer<'a, I>(Entries<'a, I>);
+
+impl<'de, 'a, I: Iterator<Item = (String, &'a Term)>> de::Deserializer<'de>
+    for MapDeserializer<'a, I>
+{
+    type Error = PolarError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> PolarResult<V::Value> {
+        visitor.visit_map(self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::terms::Symbol;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Role {
+        Admin,
+        Member,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        name: String,
+        age: Option<u8>,
+        roles: Vec<Role>,
+        scores: HashMap<String, f64>,
+    }
+
+    #[test]
+    fn test_from_bindings() {
+        let bindings: Bindings = hashmap! {
+            sym!("name") => term!("alice"),
+            sym!("age") => term!(Symbol::new("_age")),
+            sym!("roles") => term!(["Admin", "Member"]),
+            sym!("scores") => term!(btreemap! { sym!("a") => term!(1), sym!("b") => term!(1.5) }),
+            sym!("ignored") => term!(true),
+        };
+        let user: User = from_bindings(&bindings).unwrap();
+        assert_eq!(
+            user,
+            User {
+                name: "alice".to_owned(),
+                age: None,
+                roles: vec![Role::Admin, Role::Member],
+                scores: HashMap::from([("a".to_owned(), 1.0), ("b".to_owned(), 1.5)]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_term_errors() {
+        assert!(from_term::<u8>(&term!(300)).is_err());
+        assert!(from_term::<Role>(&term!("Owner")).is_err());
+        let error = from_term::<String>(&term!(1)).unwrap_err();
+        assert!(matches!(
+            error.0,
+            crate::error::ErrorKind::Operational(OperationalError::Serialization { .. })
+        ));
+    }
+}
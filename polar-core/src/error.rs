@@ -7,7 +7,7 @@ use strum_macros::AsRefStr;
 use super::{
     resource_block::Declaration,
     rules::Rule,
-    sources::{Context, Source},
+    sources::{Context, Source, Span},
     terms::{Operation, Symbol, Term},
 };
 
@@ -44,7 +44,11 @@ pub struct PolarError(pub ErrorKind);
 impl std::error::Error for PolarError {}
 
 impl fmt::Display for PolarError {
+    /// The alternate form, `{:#}`, is the same as `PolarError::to_formatted`.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return write!(f, "{}", self.to_formatted());
+        }
         write!(f, "{}", self.0)?;
         if let Some(context) = self.get_context() {
             write!(f, "{}", context)?;
@@ -64,6 +68,63 @@ impl PolarError {
         }
     }
 
+    /// Where in the policy the error is, if it's about a part of the policy.
+    pub fn span(&self) -> Option<Span> {
+        self.get_context().map(|context| context.span())
+    }
+
+    /// The error with the line of the policy it occurred on and the part of the line it's about
+    /// underlined, followed by a hint at how to fix it if there is one:
+    ///
+    /// ```text
+    /// error: did not expect to find the token ';'
+    ///  --> policy.polar:2:7
+    ///   |
+    /// 2 |   x = ;
+    ///   |       ^
+    /// ```
+    pub fn to_formatted(&self) -> String {
+        let mut f = format!("error: {}", self.0);
+        if let Some(context) = self.get_context() {
+            f.push('\n');
+            f.push_str(&context.snippet());
+        }
+        if let Some(hint) = self.hint() {
+            f.push_str("\n  = hint: ");
+            f.push_str(hint);
+        }
+        f
+    }
+
+    fn hint(&self) -> Option<&'static str> {
+        use ErrorKind::*;
+        use ParseErrorKind::*;
+        use RuntimeError::*;
+        use ValidationError::*;
+
+        match &self.0 {
+            Parse(e) => match &e.kind {
+                UnrecognizedEOF {
+                    expected: Some(expected),
+                    ..
+                } if expected == "`;`" => Some("every rule and rule type ends with a `;`"),
+                IntegerOverflow { .. } => Some("integers must fit in 64 bits; try a float instead"),
+                ReservedWord { .. } => Some("reserved words can't name variables, rules or fields"),
+                _ => None,
+            },
+            Runtime(ArithmeticError { .. }) => {
+                Some("arithmetic fails on overflow and on division by zero")
+            }
+            Validation(UnregisteredClass { .. }) => {
+                Some("register the class with the host before loading the policy")
+            }
+            Validation(UndefinedRuleCall { .. }) => {
+                Some("check the spelling of the rule's name, or define the rule")
+            }
+            _ => None,
+        }
+    }
+
     pub fn get_context(&self) -> Option<Context> {
         use ErrorKind::*;
         use OperationalError::*;
@@ -88,7 +149,7 @@ impl PolarError {
                 // of context is also `loc`.
                 InvalidTokenCharacter { loc, .. }
                 | InvalidToken { loc }
-                | UnrecognizedEOF { loc } => Some(Context::new(e.source.clone(), *loc, *loc)),
+                | UnrecognizedEOF { loc, .. } => Some(Context::new(e.source.clone(), *loc, *loc)),
 
                 // These errors track `term`, from which we calculate the context.
                 WrongValueType { term, .. } => term.parsed_context().cloned(),
@@ -107,6 +168,9 @@ impl PolarError {
                 // These errors never have context.
                 StackOverflow { .. }
                 | QueryTimeout { .. }
+                | StepLimit { .. }
+                | MemoryLimit { .. }
+                | Cancelled
                 | IncompatibleBindings { .. }
                 | DataFilteringFieldMissing { .. }
                 | DataFilteringUnsupportedOp { .. }
@@ -123,7 +187,9 @@ impl PolarError {
                 | DuplicateResourceBlockDeclaration {
                     declaration: term, ..
                 }
-                | UnregisteredClass { term, .. } => term.parsed_context().cloned(),
+                | UnregisteredClass { term, .. }
+                | InvalidConstant { term, .. }
+                | InvalidImport { term, .. } => term.parsed_context().cloned(),
 
                 // These errors track `rule`, from which we calculate the context.
                 InvalidRule { rule, .. }
@@ -133,7 +199,7 @@ impl PolarError {
 
                 // These errors track `rule_type`, from which we sometimes calculate the context.
                 MissingRequiredRule { rule_type } => {
-                    if rule_type.name.0 == "has_relation" {
+                    if rule_type.name.as_str() == "has_relation" {
                         rule_type.parsed_context().cloned()
                     } else {
                         // TODO(gj): copy source info from the appropriate resource block term for
@@ -189,12 +255,14 @@ impl PolarError {
 pub struct FormattedPolarError {
     pub kind: ErrorKind,
     pub formatted: String,
+    pub span: Option<Span>,
 }
 
 impl From<PolarError> for FormattedPolarError {
     fn from(other: PolarError) -> Self {
         Self {
             formatted: other.to_string(),
+            span: other.span(),
             kind: other.0,
         }
     }
@@ -246,6 +314,8 @@ pub enum ParseErrorKind {
     #[allow(clippy::upper_case_acronyms)]
     UnrecognizedEOF {
         loc: usize,
+        /// What could have come next, like "`;`", if it's one thing.
+        expected: Option<String>,
     },
     UnrecognizedToken {
         token: String,
@@ -287,10 +357,17 @@ impl fmt::Display for ParseErrorKind {
                 token.escape_debug()
             ),
             Self::InvalidToken { .. } => write!(f, "found an unexpected sequence of characters"),
-            Self::UnrecognizedEOF { .. } => write!(
+            Self::UnrecognizedEOF {
+                expected: Some(expected),
+                ..
+            } => write!(
                 f,
-                "hit the end of the file unexpectedly. Did you forget a semi-colon"
+                "hit the end of the file unexpectedly. Expected {}",
+                expected
             ),
+            Self::UnrecognizedEOF { expected: None, .. } => {
+                write!(f, "hit the end of the file unexpectedly")
+            }
             Self::UnrecognizedToken { token, .. } => write!(
                 f,
                 "did not expect to find the token '{}'",
@@ -340,11 +417,23 @@ pub enum RuntimeError {
     },
     StackOverflow {
         msg: String,
+        /// The innermost rule that was running, if any.
+        rule: Option<String>,
+        /// The number of nested rule calls.
+        depth: usize,
     },
     QueryTimeout {
         elapsed: u64,
         timeout: u64,
     },
+    StepLimit {
+        limit: u64,
+    },
+    MemoryLimit {
+        limit: u64,
+    },
+    /// The host cancelled the query with its `CancelToken`.
+    Cancelled,
     Application {
         msg: String,
         stack_trace: String,
@@ -396,10 +485,25 @@ impl fmt::Display for RuntimeError {
                 writeln!(f, "{}", stack_trace)?;
                 write!(f, "Type error: {}", msg)
             }
-            Self::StackOverflow { msg } => {
-                write!(f, "{}", msg)
+            Self::StackOverflow { msg, rule, depth } => {
+                write!(f, "{}", msg)?;
+                if let Some(rule) = rule {
+                    write!(f, " The innermost rule running was `{}`, {} calls deep.", rule, depth)?;
+                }
+                Ok(())
             }
             Self::QueryTimeout { elapsed, timeout } => write!(f, "Query timeout: Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the POLAR_TIMEOUT_MS environment variable to 0.", elapsed, timeout),
+            Self::StepLimit { limit } => write!(
+                f,
+                "Step limit: Query ran more than {} goals, which exceeds its step limit.",
+                limit
+            ),
+            Self::MemoryLimit { limit } => write!(
+                f,
+                "Memory limit: Query used more than an estimated {} bytes, which exceeds its memory limit.",
+                limit
+            ),
+            Self::Cancelled => write!(f, "Query cancelled: The query was cancelled by its host."),
             Self::Application {
                 msg, stack_trace, ..
             } => {
@@ -525,16 +629,16 @@ pub enum ValidationError {
         msg: String,
     },
     MissingRequiredRule {
-        rule_type: Rule,
+        rule_type: Box<Rule>,
     },
     InvalidRule {
         /// Rule where the error arose, tracked for lexical context.
-        rule: Rule,
+        rule: Box<Rule>,
         msg: String,
     },
     InvalidRuleType {
         /// Rule type where the error arose, tracked for lexical context.
-        rule_type: Rule,
+        rule_type: Box<Rule>,
         msg: String,
     },
     /// The policy contains a call to an undefined rule. This is the validation analogue of
@@ -560,6 +664,16 @@ pub enum ValidationError {
         /// Term<Symbol> where the error arose, tracked for lexical context.
         term: Term,
     },
+    InvalidConstant {
+        /// Term<Symbol> naming the constant, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
+    InvalidImport {
+        /// Term<String> naming the imported file, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
     DuplicateResourceBlockDeclaration {
         /// Term<Symbol> where the error arose.
         resource: Term,
@@ -601,6 +715,12 @@ impl fmt::Display for ValidationError {
             Self::UnregisteredClass { term } => {
                 write!(f, "Unregistered class: {}", term)
             }
+            Self::InvalidConstant { term, msg } => {
+                write!(f, "Invalid constant {}: {}", term, msg)
+            }
+            Self::InvalidImport { term, msg } => {
+                write!(f, "Invalid import {}: {}", term, msg)
+            }
             Self::DuplicateResourceBlockDeclaration {
                 resource,
                 declaration,
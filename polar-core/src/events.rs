@@ -26,8 +26,13 @@ pub enum QueryEvent {
         runnable: Box<dyn Runnable>,
     },
 
+    /// Evaluation paused at a `debug()` call or a debugger breakpoint.
     Debug {
         message: String,
+        /// The goal stack at the breakpoint, most recent goal first.
+        goals: Vec<String>,
+        /// Non-temporary variable bindings at the breakpoint.
+        bindings: Bindings,
     },
 
     MakeExternal {
@@ -38,7 +43,8 @@ pub enum QueryEvent {
     ExternalCall {
         /// Persistent id across all requests for results from the same external call.
         call_id: u64,
-        /// The external instance to make this call on.
+        /// The external instance to make this call on, or the class for a class constant or
+        /// static method, like `Role.ADMIN`, which `ExternalInstance::is_class` tells apart.
         instance: Term,
         /// Field name to lookup or method name to call. A class name indicates a constructor
         /// should be called.
@@ -49,7 +55,8 @@ pub enum QueryEvent {
         kwargs: Option<BTreeMap<Symbol, Term>>,
     },
 
-    /// Checks if the instance is an instance of (a subclass of) the class_tag.
+    /// Checks if the instance is an instance of (a subclass of) the class_tag. The host answers
+    /// with `Query::question_result`.
     ExternalIsa {
         call_id: u64,
         instance: Term,
@@ -65,7 +72,8 @@ pub enum QueryEvent {
         class_tag: Symbol,
     },
 
-    /// Checks if the left is more specific than right with respect to instance.
+    /// Checks if the left is more specific than right with respect to instance, to sort the
+    /// applicable rules of a call. The host answers with `Query::question_result`.
     ExternalIsSubSpecializer {
         call_id: u64,
         instance_id: u64,
@@ -95,6 +103,25 @@ pub enum QueryEvent {
         call_id: u64,
         iterable: Term,
     },
+
+    /// Calls that don't depend on each other's answers, sent together in place of separate
+    /// events when `Polar::set_batch_external_calls` is on: the `ExternalCall`s that look up the
+    /// fields of a pattern on the same instance, or the `ExternalIsSubSpecializer` checks that
+    /// sort the rules of a call. The host answers them all at once with `Query::batch_result`.
+    ExternalBatch {
+        calls: Vec<QueryEvent>,
+    },
+}
+
+/// The host's answer to one of the calls of an `ExternalBatch`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ExternalAnswer {
+    /// The result of an `ExternalCall`, or `None` if it has none.
+    Result(Option<Term>),
+    /// The answer to a question like `ExternalIsSubSpecializer`.
+    Question(bool),
+    /// The error the host raised answering an `ExternalCall`.
+    Error(String),
 }
 
 // A struct for just Result Events. Used to pass data back into
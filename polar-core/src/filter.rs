@@ -123,7 +123,7 @@ impl PathVar {
                 pv.path.push(dot);
                 Ok(pv)
             }
-            Variable(Symbol(var)) => Ok(var.clone().into()),
+            Variable(var) => Ok(var.to_string().into()),
             _ => invalid_state(format!("PathVar::from_term({})", t)),
         }
     }
@@ -143,7 +143,7 @@ impl Filter {
             eprintln!("\n==Bindings==")
         }
 
-        let sym = Symbol(var.to_string());
+        let sym = Symbol::new(var);
         let filter = partials
             .into_iter()
             .filter_map(|opt| opt.bindings.get(&sym).cloned())
@@ -93,6 +93,9 @@ pub fn fold_rule<T: Folder>(
         body,
         source_info,
         required,
+        doc,
+        annotations,
+        ..
     }: Rule,
     fld: &mut T,
 ) -> Rule {
@@ -102,6 +105,9 @@ pub fn fold_rule<T: Folder>(
         body: fld.fold_term(body),
         source_info,
         required,
+        doc,
+        annotations,
+        program: Default::default(),
     }
 }
 
@@ -115,11 +115,16 @@ fn format_params(args: &[Parameter], sep: &str) -> String {
 fn precedence(o: &Operator) -> i32 {
     match o {
         Operator::Print => 11,
+        Operator::Collect => 11,
+        Operator::Aggregate => 11,
         Operator::Debug => 11,
         Operator::New => 10,
         Operator::Cut => 10,
         Operator::ForAll => 10,
+        Operator::Distinct => 10,
+        Operator::Once => 10,
         Operator::Dot => 9,
+        Operator::Range => 9,
         Operator::In => 8,
         Operator::Isa => 8,
         Operator::Mul => 7,
@@ -139,6 +144,7 @@ fn precedence(o: &Operator) -> i32 {
         Operator::Not => 3,
         Operator::And => 2,
         Operator::Or => 1,
+        Operator::Conditional => 0,
     }
 }
 
@@ -161,6 +167,62 @@ fn to_polar_parens(op: Operator, t: &Term) -> String {
     }
 }
 
+/// Binary operators associate to the left, so the right operand also needs
+/// parentheses if it has the same precedence as `op`, as in `a - (b - c)`.
+fn to_polar_right_parens(op: Operator, t: &Term) -> String {
+    match t.value() {
+        Value::Expression(Operation {
+            operator: other, ..
+        }) if precedence(&op) == precedence(other) => format!("({})", t),
+        _ => to_polar_parens(op, t),
+    }
+}
+
+/// Whether a dictionary key or field name can be written bare, like `name`, rather than as
+/// a string, like `"content-type"`.
+fn is_bare_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c == '_' || !(c.is_ascii_punctuation() || c.is_ascii_digit() || c.is_ascii_whitespace()))
+        && chars.all(|c| c == '_' || !(c.is_ascii_punctuation() || c.is_ascii_whitespace()))
+        && !matches!(key, "true" | "false")
+}
+
+/// `s` as a string literal, with the characters the lexer unescapes escaped.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats the body of a list comprehension or aggregate, `template for x in xs if condition`.
+fn format_comprehension(template: &Term, query: &Term) -> String {
+    let query = match query.value() {
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }) => &args[..],
+        _ => std::slice::from_ref(query),
+    };
+    let condition = if query.len() > 1 {
+        format!(" if {}", format_args(Operator::And, &query[1..], " and "))
+    } else {
+        "".to_owned()
+    };
+    format!("{} for {}{}", template, query[0], condition)
+}
+
 mod display {
     use std::fmt;
     use std::sync::Arc;
@@ -181,7 +243,7 @@ mod display {
 
     impl fmt::Display for Symbol {
         fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(fmt, "{}", self.0)
+            self.write_name(fmt)
         }
     }
 
@@ -211,23 +273,19 @@ mod display {
 
     impl fmt::Display for Choice {
         fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // Goal stacks iterate from the top; print them from the bottom.
+            fn goals(stack: &GoalStack, sep: &str) -> String {
+                let mut goals = stack.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+                goals.reverse();
+                goals.join(sep)
+            }
             write!(
                 fmt,
                 "[{}] ++ [{}]",
-                self.goals
-                    .iter()
-                    .map(|g| g.to_string())
-                    .collect::<Vec<String>>()
-                    .join(", "),
+                goals(&self.goals, ", "),
                 self.alternatives
                     .iter()
-                    .map(|alt| format!(
-                        "[{}]",
-                        alt.iter()
-                            .map(|g| g.to_string())
-                            .collect::<Vec<String>>()
-                            .join(",")
-                    ))
+                    .map(|alt| format!("[{}]", goals(alt, ",")))
                     .collect::<Vec<String>>()
                     .join(", ")
             )
@@ -285,6 +343,21 @@ mod display {
                 Goal::PopQuery { term } => write!(fmt, "PopQuery({})", term.to_polar()),
                 Goal::Query { term } => write!(fmt, "Query({})", term.to_polar()),
                 Goal::Run { .. } => write!(fmt, "Run(...)"),
+                Goal::UnifyAnswers { call, args } => {
+                    write!(
+                        fmt,
+                        "UnifyAnswers({}, {})",
+                        args.to_polar(),
+                        call.to_polar()
+                    )
+                }
+                Goal::UnifyStreamed { disjunct, .. } => {
+                    write!(fmt, "UnifyStreamed({})", disjunct.to_polar())
+                }
+                Goal::SkipStreamed { skip } => write!(fmt, "SkipStreamed({})", skip.get()),
+                Goal::CheckDistinct { vars, .. } => {
+                    write!(fmt, "CheckDistinct({})", vars.to_polar())
+                }
                 Goal::FilterRules {
                     applicable_rules,
                     unfiltered_rules,
@@ -336,6 +409,8 @@ mod display {
             match self {
                 Self::Integer(i) => write!(f, "{}", i),
                 Self::Float(float) => write!(f, "{}", float),
+                Self::BigInteger(i) => write!(f, "{}", i),
+                Self::Decimal(d) => write!(f, "{}", d),
             }
         }
     }
@@ -376,7 +451,10 @@ mod display {
 mod to_polar {
     use std::fmt::Write;
 
-    use crate::formatting::{format_args, format_params, to_polar_parens};
+    use crate::formatting::{
+        format_args, format_comprehension, format_params, is_bare_key, quote, to_polar_parens,
+        to_polar_right_parens,
+    };
     use crate::resource_block::{BlockType, ResourceBlock, ShorthandRule};
     use crate::rules::*;
     use crate::terms::*;
@@ -392,7 +470,13 @@ mod to_polar {
             let fields = self
                 .fields
                 .iter()
-                .map(|(k, v)| format!("{}: {}", k.to_polar(), v.to_polar()))
+                .map(|(k, v)| {
+                    if is_bare_key(&k.as_str()) {
+                        format!("{}: {}", k.to_polar(), v.to_polar())
+                    } else {
+                        format!("{}: {}", quote(&k.as_str()), v.to_polar())
+                    }
+                })
                 .collect::<Vec<String>>()
                 .join(", ");
             format!("{{{}}}", fields)
@@ -452,9 +536,15 @@ mod to_polar {
                 In => "in",
                 Cut => "cut",
                 ForAll => "forall",
+                Distinct => "distinct",
+                Once => "once",
                 Debug => "debug",
                 Print => "print",
                 Isa => "matches",
+                Conditional => "if",
+                Range => "..",
+                Collect => "for",
+                Aggregate => "for",
             }
             .to_string()
         }
@@ -474,6 +564,15 @@ mod to_polar {
                     self.args[0].to_polar(),
                     self.args[1].to_polar()
                 ),
+                // The rewritten form also carries the list of variables to deduplicate on.
+                Distinct => format!("distinct({})", self.args.last().unwrap().to_polar()),
+                Once => format!("once({})", self.args[0].to_polar()),
+                Conditional => format!(
+                    "if {} then {} else {}",
+                    self.args[0].to_polar(),
+                    self.args[1].to_polar(),
+                    self.args[2].to_polar()
+                ),
                 New => {
                     if self.args.len() == 1 {
                         format!("new {}", to_polar_parens(self.operator, &self.args[0]))
@@ -487,10 +586,12 @@ mod to_polar {
                 }
                 // Lookup operator
                 Dot => {
-                    let call_term = if let Value::String(s) = self.args[1].value() {
-                        s.to_string()
-                    } else {
-                        self.args[1].to_polar()
+                    let call_term = match self.args[1].value() {
+                        Value::String(s) if is_bare_key(s) => s.to_string(),
+                        Value::String(_) | Value::Variable(_) => {
+                            format!("({})", self.args[1].to_polar())
+                        }
+                        _ => self.args[1].to_polar(),
                     };
                     match self.args.len() {
                         2 => format!("{}.{}", self.args[0].to_polar(), call_term),
@@ -504,6 +605,35 @@ mod to_polar {
                         _ => format!(".({})", format_args(self.operator, &self.args, ", ")),
                     }
                 }
+                // List comprehension
+                Collect => {
+                    let collect =
+                        format!("[{}]", format_comprehension(&self.args[0], &self.args[1]));
+                    match self.args.get(2) {
+                        Some(result) => format!("{} = {}", result.to_polar(), collect),
+                        None => collect,
+                    }
+                }
+                Aggregate => {
+                    let function = match self.args[0].value() {
+                        Value::String(s) => s.clone(),
+                        _ => self.args[0].to_polar(),
+                    };
+                    let aggregate = format!(
+                        "{}({})",
+                        function,
+                        format_comprehension(&self.args[1], &self.args[2])
+                    );
+                    match self.args.get(3) {
+                        Some(result) => format!("{} = {}", result.to_polar(), aggregate),
+                        None => aggregate,
+                    }
+                }
+                Range => format!(
+                    "{}..{}",
+                    to_polar_parens(self.operator, &self.args[0]),
+                    to_polar_parens(self.operator, &self.args[1])
+                ),
                 // Unary operators
                 Not => format!(
                     "{} {}",
@@ -517,7 +647,7 @@ mod to_polar {
                         "{} {} {}",
                         to_polar_parens(self.operator, &self.args[0]),
                         self.operator.to_polar(),
-                        to_polar_parens(self.operator, &self.args[1]),
+                        to_polar_right_parens(self.operator, &self.args[1]),
                     ),
                     3 => format!(
                         "{} {} {} = {}",
@@ -613,7 +743,7 @@ mod to_polar {
 
     impl ToPolarString for Symbol {
         fn to_polar(&self) -> String {
-            self.0.to_string()
+            self.to_string()
         }
     }
 
@@ -636,7 +766,7 @@ mod to_polar {
         fn to_polar(&self) -> String {
             match self {
                 Value::Number(i) => format!("{}", i),
-                Value::String(s) => format!("\"{}\"", s),
+                Value::String(s) => quote(s),
                 Value::Boolean(b) => {
                     if *b {
                         "true".to_string()
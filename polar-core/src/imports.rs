@@ -0,0 +1,159 @@
+//! Resolve `import "file.polar";` directives against the sources of a load and the files loaded
+//! before it.
+
+use std::collections::HashSet;
+
+use super::error::{PolarError, ValidationError};
+use super::parser::Line;
+use super::terms::*;
+
+/// A source file that has been parsed but not yet loaded into the KB.
+pub(crate) struct ParsedSource {
+    pub filename: Option<String>,
+    pub lines: Vec<Line>,
+}
+
+/// Normalize `path` relative to the directory of `importer`, if any.
+fn resolve(importer: Option<&str>, path: &str) -> String {
+    let mut parts: Vec<&str> = match importer.and_then(|f| f.rfind('/').map(|i| &f[..i])) {
+        Some(dir) if !path.starts_with('/') => dir.split('/').collect(),
+        _ => vec![],
+    };
+    for part in path.split('/') {
+        match part {
+            "." => (),
+            ".." if matches!(parts.last(), Some(p) if !p.is_empty() && *p != "..") => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+struct ImportSorter {
+    /// Normalized filename of each source.
+    names: Vec<Option<String>>,
+    /// Normalized filenames of the files loaded into the KB before.
+    loaded: HashSet<String>,
+    /// `import` terms in each source, along with the normalized path of the imported file.
+    imports: Vec<Vec<(Term, String)>>,
+    visited: Vec<bool>,
+    stack: Vec<usize>,
+    order: Vec<usize>,
+    errors: Vec<PolarError>,
+}
+
+impl ImportSorter {
+    fn visit(&mut self, i: usize) {
+        if self.visited[i] {
+            return;
+        }
+        self.stack.push(i);
+        for (term, path) in self.imports[i].clone() {
+            match self.names.iter().position(|n| n.as_ref() == Some(&path)) {
+                Some(j) if self.stack.contains(&j) => {
+                    let start = self.stack.iter().position(|&k| k == j).unwrap();
+                    let cycle = self.stack[start..]
+                        .iter()
+                        .chain(std::iter::once(&j))
+                        .filter_map(|&k| self.names[k].as_deref())
+                        .collect::<Vec<_>>()
+                        .join(" -> ");
+                    self.errors
+                        .push(invalid_import(term, format!("import cycle {}", cycle)));
+                }
+                Some(j) => self.visit(j),
+                None if self.loaded.contains(&path) => (),
+                None => self.errors.push(invalid_import(
+                    term,
+                    format!("no file named {} is being loaded or has been loaded", path),
+                )),
+            }
+        }
+        self.stack.pop();
+        self.visited[i] = true;
+        self.order.push(i);
+    }
+}
+
+fn invalid_import(term: Term, msg: String) -> PolarError {
+    ValidationError::InvalidImport { term, msg }.into()
+}
+
+/// Order `sources` so that each file is loaded after the files it imports.
+///
+/// Imports are resolved relative to the directory of the importing file and must name another
+/// source in the same load or one of the `loaded` files, which are already in the KB. Unresolved
+/// imports & import cycles are reported on the `import` line where they were found.
+pub(crate) fn order_by_imports(
+    sources: Vec<ParsedSource>,
+    loaded: &[String],
+) -> (Vec<ParsedSource>, Vec<PolarError>) {
+    let names = sources
+        .iter()
+        .map(|s| s.filename.as_deref().map(|f| resolve(None, f)))
+        .collect::<Vec<_>>();
+    let loaded = loaded.iter().map(|f| resolve(None, f)).collect();
+    let imports = sources
+        .iter()
+        .map(|s| {
+            s.lines
+                .iter()
+                .filter_map(|line| match line {
+                    Line::Import(term) => {
+                        let path = term.as_string().ok()?;
+                        Some((term.clone(), resolve(s.filename.as_deref(), path)))
+                    }
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect();
+    let mut sorter = ImportSorter {
+        visited: vec![false; sources.len()],
+        names,
+        loaded,
+        imports,
+        stack: vec![],
+        order: vec![],
+        errors: vec![],
+    };
+    for i in 0..sources.len() {
+        sorter.visit(i);
+    }
+
+    let mut sources = sources.into_iter().map(Some).collect::<Vec<_>>();
+    let ordered = sorter
+        .order
+        .into_iter()
+        .filter_map(|i| sources[i].take())
+        .collect();
+    (ordered, sorter.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve(None, "rbac.polar"), "rbac.polar");
+        assert_eq!(resolve(None, "./rbac.polar"), "rbac.polar");
+        assert_eq!(resolve(Some("main.polar"), "rbac.polar"), "rbac.polar");
+        assert_eq!(
+            resolve(Some("policies/main.polar"), "rbac.polar"),
+            "policies/rbac.polar"
+        );
+        assert_eq!(
+            resolve(Some("policies/main.polar"), "../shared/rbac.polar"),
+            "shared/rbac.polar"
+        );
+        assert_eq!(resolve(Some("a/main.polar"), "../../x.polar"), "../x.polar");
+        assert_eq!(
+            resolve(Some("/etc/main.polar"), "rbac.polar"),
+            "/etc/rbac.polar"
+        );
+        assert_eq!(resolve(Some("a/main.polar"), "/x.polar"), "/x.polar");
+    }
+}
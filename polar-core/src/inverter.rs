@@ -5,13 +5,13 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::bindings::{BindingManager, Bsp, FollowerId, VariableState};
 use crate::counter::Counter;
-use crate::error::{PolarError, PolarResult};
-use crate::events::QueryEvent;
+use crate::error::{unsupported, PolarError, PolarResult};
+use crate::events::{ExternalAnswer, QueryEvent};
 use crate::kb::Bindings;
 use crate::partial::simplify_bindings;
 use crate::runnable::Runnable;
-use crate::terms::{Operation, Operator, Term, Value};
-use crate::vm::{Goals, PolarVirtualMachine};
+use crate::terms::{Operation, Operator, Symbol, Term, Value};
+use crate::vm::{Goals, PolarVirtualMachine, Queries};
 
 /// The inverter implements the `not` operation in Polar.
 ///
@@ -34,6 +34,22 @@ pub struct Inverter {
     /// Constraints to return to parent VM.
     add_constraints: Rc<RefCell<Bindings>>,
 
+    /// The `not` operation, for error context.
+    term: Term,
+
+    /// Unbound variables of the negation. It is an error for a result to bind one of them that is
+    /// also used outside of the negation, because the negation only fails for that value of the
+    /// variable.
+    unbound: Vec<Symbol>,
+
+    /// The queries that were running in the parent VM, to find out which of `unbound` they use.
+    queries: Queries,
+    subquery: bool,
+
+    /// The variables of `unbound` that are used outside of the negation, once a result has bound
+    /// one of `unbound`.
+    shared: Option<Vec<Symbol>>,
+
     /// The ID of the current binding manager follower. Initialized in `run`.
     follower: Option<FollowerId>,
 
@@ -46,22 +62,54 @@ static ID: AtomicU64 = AtomicU64::new(0);
 
 impl Inverter {
     pub fn new(
-        vm: &PolarVirtualMachine,
+        vm: &mut PolarVirtualMachine,
         goals: Goals,
         add_constraints: Rc<RefCell<Bindings>>,
         bsp: Bsp,
+        term: Term,
+        unbound: Vec<Symbol>,
     ) -> Self {
+        let queries = vm.queries.clone();
+        let subquery = vm.subquery;
         let mut vm = vm.clone_with_goals(goals);
         vm.inverting = true;
         Self {
             vm,
             bsp,
             add_constraints,
+            term,
+            unbound,
+            queries,
+            subquery,
+            shared: None,
             results: vec![],
             follower: None,
             _debug_id: ID.fetch_add(1, Ordering::AcqRel),
         }
     }
+
+    /// A shared variable that is bound or constrained in the current result of the inverted
+    /// query.
+    fn bound_shared_variable(&mut self) -> Option<Symbol> {
+        let vm = &self.vm;
+        let is_bound = |var: &Symbol| match vm.deref(&term!(var.clone())).value() {
+            Value::Variable(v) => matches!(vm.variable_state(v), VariableState::Partial),
+            _ => true,
+        };
+        if !self.unbound.iter().any(is_bound) {
+            return None;
+        }
+        let shared = self.shared.get_or_insert_with(|| {
+            vm.shared_variables(
+                &self.term,
+                &self.unbound,
+                &self.queries,
+                self.subquery,
+                &self.bsp,
+            )
+        });
+        shared.iter().find(|var| is_bound(var)).cloned()
+    }
 }
 
 /// Convert a list of new bindings into inverted constraints.
@@ -102,7 +150,7 @@ fn invert_partials(bindings: BindingManager) -> Bindings {
 
     for var in bindings.variables() {
         let constraint = bindings.get_constraints(&var);
-        new_bindings.insert(var.clone(), term!(constraint));
+        new_bindings.insert(var, term!(constraint));
     }
 
     let simplified = simplify_bindings(new_bindings).unwrap_or_default();
@@ -220,6 +268,14 @@ impl Runnable for Inverter {
                     return Ok(QueryEvent::Done { result });
                 }
                 QueryEvent::Result { .. } => {
+                    if let Some(var) = self.bound_shared_variable() {
+                        let msg = format!(
+                            "cannot negate a query that binds the unbound variable {}; bind it before the negation",
+                            var
+                        );
+                        return unsupported(msg, &self.term);
+                    }
+
                     // Retrieve new bindings made when running inverted query.
                     let binding_follower = self
                         .vm
@@ -241,6 +297,14 @@ impl Runnable for Inverter {
         self.vm.external_call_result(call_id, term)
     }
 
+    fn external_call_error(&mut self, call_id: u64, message: String) -> PolarResult<()> {
+        self.vm.external_call_error(call_id, message)
+    }
+
+    fn external_batch_result(&mut self, answers: Vec<ExternalAnswer>) -> PolarResult<()> {
+        self.vm.external_batch_result(answers)
+    }
+
     fn debug_command(&mut self, command: &str) -> PolarResult<()> {
         self.vm.debug_command(command)
     }
@@ -1,16 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Write;
 use std::sync::Arc;
 
 pub use super::bindings::Bindings;
+use super::builtins::{Builtin, NativeBuiltin};
 use super::constants::Constants;
 use super::counter::Counter;
 use super::diagnostic::Diagnostic;
 use super::error::{invalid_state, PolarError, PolarResult, RuntimeError, ValidationError};
 use super::resource_block::{ResourceBlocks, ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
 use super::rules::*;
+use super::sources::Source;
 use super::terms::*;
-use super::validations::check_undefined_rule_calls;
+use super::validations::{check_undefined_rule_calls, check_unguarded_recursion};
 
 enum RuleParamMatch {
     True,
@@ -24,19 +26,32 @@ impl RuleParamMatch {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct KnowledgeBase {
     /// A map of bindings: variable name → value. The VM uses a stack internally,
     /// but can translate to and from this type.
     constants: Constants,
     /// Map of class name -> MRO list where the MRO list is a list of class instance IDs
     pub mro: HashMap<Symbol, Vec<u64>>,
+    /// Map of union name -> member classes for unions registered by the host.
+    unions: HashMap<Symbol, HashSet<Term>>,
+    /// Built-in predicates implemented in Rust, by name.
+    native_builtins: HashMap<Symbol, NativeBuiltin>,
+    /// Names of constants defined in the policy, which are cleared along with its rules.
+    policy_constants: HashSet<Symbol>,
 
     /// Map from contents to filename for files loaded into the KB.
     loaded_content: HashMap<String, String>,
+    /// The sources loaded into the KB, in the order they were loaded, to reload it from when
+    /// one of them is replaced or removed.
+    sources: Vec<Source>,
+    /// The facts added with `add_fact`, which are added again when the KB is reloaded.
+    added_facts: Vec<Rule>,
 
     rules: HashMap<Symbol, GenericRule>,
     rule_types: RuleTypes,
+    /// Names of the rules declared with `table`, whose calls are tabled.
+    tabled: HashSet<Symbol>,
     /// For symbols returned from gensym.
     gensym_counter: Counter,
     /// For call IDs, instance IDs, symbols, etc.
@@ -75,7 +90,17 @@ impl KnowledgeBase {
     /// Generate a new symbol.
     pub fn gensym(&self, prefix: &str) -> Symbol {
         let next = self.gensym_counter.next();
-        Symbol(format!("{}{}", Self::temp_prefix(prefix), next))
+        Symbol::numbered(&Self::temp_prefix(prefix), next)
+    }
+
+    /// Generate a new symbol to rename `var` to.
+    pub fn rename(&self, var: &Symbol) -> Symbol {
+        var.temporary(self.gensym_counter.next())
+    }
+
+    /// The number of the next temporary variable that a variable is renamed to.
+    pub fn next_temporary(&self) -> u64 {
+        self.gensym_counter.next()
     }
 
     /// Add a generic rule to the knowledge base.
@@ -92,6 +117,41 @@ impl KnowledgeBase {
         generic_rule.add_rule(Arc::new(rule));
     }
 
+    /// Add a fact, or a rule the host made, to the loaded policy. It must match the rule types
+    /// with its name, if there are any.
+    pub fn add_fact(&mut self, fact: Rule) -> PolarResult<()> {
+        self.validate_rule_type(&fact)?;
+        self.added_facts.push(fact.clone());
+        self.add_rule(fact);
+        Ok(())
+    }
+
+    /// Remove a fact that was added or loaded. Returns whether there was one.
+    pub fn remove_fact(&mut self, fact: &Rule) -> bool {
+        let removed = self
+            .rules
+            .get_mut(&fact.name)
+            .is_some_and(|generic_rule| generic_rule.remove_rule(fact));
+        if removed {
+            if let Some(i) = self.added_facts.iter().position(|f| f == fact) {
+                self.added_facts.remove(i);
+            }
+        }
+        removed
+    }
+
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    pub(crate) fn record_source(&mut self, source: Source) {
+        self.sources.push(source);
+    }
+
+    pub fn added_facts(&self) -> &[Rule] {
+        &self.added_facts
+    }
+
     pub fn validate_rules(&self) -> Vec<Diagnostic> {
         // Prior to #1310 these validations were not order dependent due to the
         // use of static default rule types.
@@ -106,6 +166,7 @@ impl KnowledgeBase {
         }
 
         diagnostics.append(&mut check_undefined_rule_calls(self));
+        diagnostics.append(&mut check_unguarded_recursion(self));
 
         diagnostics
     }
@@ -113,36 +174,9 @@ impl KnowledgeBase {
     /// Validate that all rules loaded into the knowledge base are valid based on rule types.
     fn validate_rule_types(&self) -> PolarResult<()> {
         // For every rule, if there *is* a rule type, check that the rule matches the rule type.
-        for (rule_name, generic_rule) in &self.rules {
-            if let Some(types) = self.rule_types.get(rule_name) {
-                // If a type with the same name exists, then the parameters must match for each rule
-                for rule in generic_rule.rules.values() {
-                    let mut msg = "Must match one of the following rule types:\n".to_owned();
-
-                    let results = types
-                        .iter()
-                        .map(|rule_type| {
-                            self.rule_params_match(rule.as_ref(), rule_type)
-                                .map(|result| (result, rule_type))
-                        })
-                        .collect::<PolarResult<Vec<_>>>()?;
-                    let found_match = results.iter().any(|(result, rule_type)| match result {
-                        RuleParamMatch::True => true,
-                        RuleParamMatch::False(message) => {
-                            write!(
-                                msg,
-                                "\n{}\n\tFailed to match because: {}\n",
-                                rule_type, message
-                            )
-                            .unwrap();
-                            false
-                        }
-                    });
-                    if !found_match {
-                        let rule = Rule::clone(rule);
-                        return Err(ValidationError::InvalidRule { rule, msg }.into());
-                    }
-                }
+        for generic_rule in self.rules.values() {
+            for rule in generic_rule.rules.values() {
+                self.validate_rule_type(rule)?;
             }
         }
 
@@ -160,11 +194,11 @@ impl KnowledgeBase {
                     }
                 }
                 if !found_match {
-                    let rule_type = rule_type.clone();
+                    let rule_type = Box::new(rule_type.clone());
                     return Err(ValidationError::MissingRequiredRule { rule_type }.into());
                 }
             } else {
-                let rule_type = rule_type.clone();
+                let rule_type = Box::new(rule_type.clone());
                 return Err(ValidationError::MissingRequiredRule { rule_type }.into());
             }
         }
@@ -172,6 +206,41 @@ impl KnowledgeBase {
         Ok(())
     }
 
+    /// If a rule type with the same name as `rule` exists, check that `rule` matches one of the
+    /// rule types with that name.
+    fn validate_rule_type(&self, rule: &Rule) -> PolarResult<()> {
+        let types = match self.rule_types.get(&rule.name) {
+            Some(types) => types,
+            None => return Ok(()),
+        };
+        let mut msg = "Must match one of the following rule types:\n".to_owned();
+
+        let results = types
+            .iter()
+            .map(|rule_type| {
+                self.rule_params_match(rule, rule_type)
+                    .map(|result| (result, rule_type))
+            })
+            .collect::<PolarResult<Vec<_>>>()?;
+        let found_match = results.iter().any(|(result, rule_type)| match result {
+            RuleParamMatch::True => true,
+            RuleParamMatch::False(message) => {
+                write!(
+                    msg,
+                    "\n{}\n\tFailed to match because: {}\n",
+                    rule_type, message
+                )
+                .unwrap();
+                false
+            }
+        });
+        if !found_match {
+            let rule = Box::new(rule.clone());
+            return Err(ValidationError::InvalidRule { rule, msg }.into());
+        }
+        Ok(())
+    }
+
     /// Determine whether the fields of a rule parameter specializer match the fields of a type parameter specializer.
     /// Rule fields match if they are a superset of type fields and all field values are equal.
     // TODO: once field-level specializers are working this should be updated so
@@ -251,8 +320,8 @@ impl KnowledgeBase {
                     } else {
                         RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance, index, rule_type_instance))
                     }
-                } else if self.is_union(&term!(sym!(&rule_type_instance.tag.0))) {
-                    if self.is_union(&term!(sym!(&rule_instance.tag.0))) {
+                } else if self.is_union(&term!(rule_type_instance.tag.clone())) {
+                    if self.is_union(&term!(rule_instance.tag.clone())) {
                         // If both specializers are the same union, check fields.
                         if rule_instance.tag == rule_type_instance.tag {
                             if self.param_fields_match(
@@ -270,10 +339,10 @@ impl KnowledgeBase {
                         }
                     }
 
-                    let members = self.get_union_members(&term!(sym!(&rule_type_instance.tag.0)));
+                    let members = self.get_union_members(&term!(rule_type_instance.tag.clone()));
                     // If the rule specializer is not a direct member of the union, we still need
                     // to check if it's a subclass of any member of the union.
-                    if !members.contains(&term!(sym!(&rule_instance.tag.0))) {
+                    if !members.contains(&term!(rule_instance.tag.clone())) {
                         let mut success = false;
                         for member in members {
                             // Turn `member` into an `InstanceLiteral` by copying fields from
@@ -290,13 +359,13 @@ impl KnowledgeBase {
                         }
                         if !success {
                             let mut err = format!("Rule specializer {} on parameter {} must be a member of rule type specializer {}", rule_instance.tag,index, rule_type_instance.tag);
-                            if rule_type_instance.tag.0 == ACTOR_UNION_NAME {
+                            if rule_type_instance.tag.as_str() == ACTOR_UNION_NAME {
                                 write!(err, "
 
 \tPerhaps you meant to add an actor block to the top of your policy, like this:
 
 \t  actor {} {{}}", rule_instance.tag).unwrap();
-                            } else if rule_type_instance.tag.0 == RESOURCE_UNION_NAME {
+                            } else if rule_type_instance.tag.as_str() == RESOURCE_UNION_NAME {
                                 write!(err, "
 
 \tPerhaps you meant to add a resource block to your policy, like this:
@@ -358,7 +427,7 @@ impl KnowledgeBase {
             (Value::List(rule_type_list), Value::List(rule_list)) => {
                 if has_rest_var(rule_type_list) {
                     return Err(ValidationError::InvalidRuleType {
-                        rule_type: rule_type.clone(),
+                        rule_type: Box::new(rule_type.clone()),
                         msg: "Rule types cannot contain *rest variables.".to_string(),
                     }
                     .into());
@@ -447,7 +516,9 @@ impl KnowledgeBase {
                         Pattern::Instance(InstanceLiteral { .. }) => {
                             let rule_spec = match rule_value {
                                 Value::String(_) => instance!(sym!("String")),
-                                Value::Number(Numeric::Integer(_)) => instance!(sym!("Integer")),
+                                Value::Number(Numeric::Integer(_) | Numeric::BigInteger(_)) => {
+                                    instance!(sym!("Integer"))
+                                }
                                 Value::Number(Numeric::Float(_)) => instance!(sym!("Float")),
                                 Value::Boolean(_) => instance!(sym!("Boolean")),
                                 Value::List(_) => instance!(sym!("List")),
@@ -563,6 +634,15 @@ impl KnowledgeBase {
         self.rules.get(name)
     }
 
+    /// Table calls to the rules named `name`.
+    pub fn table(&mut self, name: Symbol) {
+        self.tabled.insert(name);
+    }
+
+    pub fn is_tabled(&self, name: &Symbol) -> bool {
+        self.tabled.contains(name)
+    }
+
     pub fn add_rule_type(&mut self, rule_type: Rule) {
         self.rule_types.add(rule_type);
     }
@@ -572,7 +652,7 @@ impl KnowledgeBase {
     /// Error on attempts to register the "union" types (Actor & Resource) since those types have
     /// special meaning in policies that use resource blocks.
     pub fn register_constant(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
-        if name.0 == ACTOR_UNION_NAME || name.0 == RESOURCE_UNION_NAME {
+        if matches!(&*name.as_str(), ACTOR_UNION_NAME | RESOURCE_UNION_NAME) {
             return Err(RuntimeError::InvalidRegistration {
                 msg: format!("'{}' is a built-in specializer.", name),
                 sym: name,
@@ -580,6 +660,14 @@ impl KnowledgeBase {
             .into());
         }
 
+        if self.unions.contains_key(&name) {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is already registered as a union.", name),
+                sym: name,
+            }
+            .into());
+        }
+
         if let Value::ExternalInstance(ExternalInstance {
             class_id,
             instance_id,
@@ -602,6 +690,71 @@ impl KnowledgeBase {
         Ok(())
     }
 
+    /// Define a union specializer that matches instances of any of `members`.
+    ///
+    /// Each member must already be registered as a class.
+    pub fn register_union(&mut self, name: Symbol, members: Vec<Symbol>) -> PolarResult<()> {
+        if matches!(&*name.as_str(), ACTOR_UNION_NAME | RESOURCE_UNION_NAME) {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is a built-in specializer.", name),
+                sym: name,
+            }
+            .into());
+        }
+        if self.is_constant(&name) {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is already registered as a constant.", name),
+                sym: name,
+            }
+            .into());
+        }
+        if members.is_empty() {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: "a union must have at least one member.".to_owned(),
+                sym: name,
+            }
+            .into());
+        }
+
+        let members = members
+            .into_iter()
+            .map(|member| {
+                let member = term!(member);
+                self.get_registered_class(&member)?;
+                Ok(member)
+            })
+            .collect::<PolarResult<_>>()?;
+        self.unions.insert(name, members);
+        Ok(())
+    }
+
+    /// Define a constant declared in a policy, e.g. `ADMIN_ROLES = ["admin", "owner"];`.
+    ///
+    /// Unlike constants registered by the host, these are removed by `clear_rules`.
+    pub fn add_policy_constant(&mut self, name: &Term, value: Term) -> PolarResult<()> {
+        let invalid = |msg: &str| -> PolarError {
+            ValidationError::InvalidConstant {
+                term: name.clone(),
+                msg: msg.to_owned(),
+            }
+            .into()
+        };
+
+        let symbol = name.as_symbol()?;
+        if self.is_constant(symbol) || self.is_union(name) {
+            return Err(invalid("a constant with this name is already defined."));
+        }
+        if !is_literal(&value) {
+            return Err(invalid(
+                "the value must be a number, string, boolean, list or dictionary literal.",
+            ));
+        }
+
+        self.register_constant(symbol.clone(), value)?;
+        self.policy_constants.insert(symbol.clone());
+        Ok(())
+    }
+
     /// Return true if a constant with the given name has been defined.
     pub fn is_constant(&self, name: &Symbol) -> bool {
         self.constants.contains_key(name)
@@ -632,6 +785,49 @@ impl KnowledgeBase {
         })
     }
 
+    /// Register a built-in predicate implemented in Rust. Its name can't be the name of one of
+    /// the VM's own built-ins.
+    pub fn register_builtin(&mut self, builtin: NativeBuiltin) -> PolarResult<()> {
+        let name = builtin.name.clone();
+        if Builtin::from_name(&name.as_str()).is_some() || self.native_builtins.contains_key(&name)
+        {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is already a built-in predicate.", name),
+                sym: name,
+            }
+            .into());
+        }
+        self.native_builtins.insert(name, builtin);
+        Ok(())
+    }
+
+    pub(crate) fn native_builtin(&self, name: &Symbol) -> Option<&NativeBuiltin> {
+        self.native_builtins.get(name)
+    }
+
+    pub(crate) fn native_builtin_names(&self) -> impl Iterator<Item = &Symbol> {
+        self.native_builtins.keys()
+    }
+
+    /// The ids of the external instances that constants and added facts and rules refer to,
+    /// which outlive the queries they're passed to.
+    pub fn instance_ids(&self) -> BTreeSet<u64> {
+        let mut ids = BTreeSet::new();
+        for constant in self.constants.symbol_to_term.values() {
+            constant.instance_ids(&mut ids);
+        }
+        for fact in &self.added_facts {
+            for param in &fact.params {
+                param.parameter.instance_ids(&mut ids);
+                if let Some(specializer) = &param.specializer {
+                    specializer.instance_ids(&mut ids);
+                }
+            }
+            fact.body.instance_ids(&mut ids);
+        }
+        ids
+    }
+
     /// Add the Method Resolution Order (MRO) list for a registered class.
     /// The `mro` argument is a list of the `instance_id` associated with a registered class.
     pub fn add_mro(&mut self, name: Symbol, mro: Vec<u64>) -> PolarResult<()> {
@@ -644,10 +840,16 @@ impl KnowledgeBase {
     }
 
     pub fn clear_rules(&mut self) {
+        for name in self.policy_constants.drain() {
+            self.constants.remove(&name);
+        }
         self.rules.clear();
         self.rule_types.reset();
+        self.tabled.clear();
         self.inline_queries.clear();
         self.loaded_content.clear();
+        self.sources.clear();
+        self.added_facts.clear();
         self.resource_blocks.clear();
     }
 
@@ -804,9 +1006,9 @@ impl KnowledgeBase {
         }
 
         let mut rule_types = rule_types_to_create.into_iter().map(|((subject, relation, object), required)| {
-            let subject_specializer = pattern!(instance!(&subject.as_symbol()?.0));
+            let subject_specializer = pattern!(instance!(subject.as_symbol()?.clone()));
             let relation_name = relation.as_string()?;
-            let object_specializer = pattern!(instance!(&object.as_symbol()?.0));
+            let object_specializer = pattern!(instance!(object.as_symbol()?.clone()));
 
             let name = sym!("has_relation");
             let mut params = args!("subject"; subject_specializer, relation_name, "object"; object_specializer);
@@ -814,7 +1016,7 @@ impl KnowledgeBase {
             let body = term!(op!(And));
             // Copy SourceInfo from implier or relation in shorthand rule.
             let source_info = relation.source_info().clone();
-            Ok(Rule { name, params, body, source_info, required })
+            Ok(Rule { name, params, body, source_info, required, doc: None, annotations: vec![], program: Default::default() })
         }).collect::<PolarResult<Vec<_>>>()?;
 
         // If there are any Relation::Role declarations in *any* of our resource
@@ -836,7 +1038,9 @@ impl KnowledgeBase {
     }
 
     pub fn is_union(&self, maybe_union: &Term) -> bool {
-        (maybe_union.is_actor_union()) || (maybe_union.is_resource_union())
+        (maybe_union.is_actor_union())
+            || (maybe_union.is_resource_union())
+            || union_tag(maybe_union).is_some_and(|tag| self.unions.contains_key(tag))
     }
 
     pub fn get_union_members(&self, union: &Term) -> &HashSet<Term> {
@@ -844,6 +1048,8 @@ impl KnowledgeBase {
             &self.resource_blocks.actors
         } else if union.is_resource_union() {
             &self.resource_blocks.resources
+        } else if let Some(members) = union_tag(union).and_then(|tag| self.unions.get(tag)) {
+            members
         } else {
             unreachable!()
         }
@@ -854,6 +1060,27 @@ impl KnowledgeBase {
     }
 }
 
+/// Whether `term` is built only from numbers, strings, booleans, lists and dictionaries.
+fn is_literal(term: &Term) -> bool {
+    match term.value() {
+        Value::Number(_) | Value::String(_) | Value::Boolean(_) => true,
+        Value::List(terms) => terms.iter().all(is_literal),
+        Value::Dictionary(Dictionary { fields }) => fields.values().all(is_literal),
+        _ => false,
+    }
+}
+
+/// The name of a union specializer, which may appear as a bare symbol or as
+/// an instance pattern.
+pub(crate) fn union_tag(term: &Term) -> Option<&Symbol> {
+    match term.value() {
+        Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) => {
+            Some(tag)
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
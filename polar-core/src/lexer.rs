@@ -28,6 +28,27 @@ pub fn loc_to_pos(src: &str, loc: usize) -> SrcPos {
     (row, col)
 }
 
+/// The text of a doc string without the indentation of the lines after the first, which line up
+/// with the rule rather than with the opening quotes.
+fn doc_text(text: &str) -> String {
+    let indent = text
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => line,
+            _ => line.get(indent..).unwrap_or_else(|| line.trim_start()),
+        })
+        .collect::<Vec<_>>();
+    lines.join("\n").trim().to_owned()
+}
+
 pub struct Lexer<'input> {
     c: Option<(usize, char)>,
     chars: Peekable<CharIndices<'input>>,
@@ -50,6 +71,8 @@ pub enum Token {
     Integer(i64),
     Float(f64),
     String(String),
+    /// The text of a `"""` string, which documents the rule after it.
+    DocString(String),
     Boolean(bool),
     Symbol(Symbol),
     Colon,     // :
@@ -61,6 +84,7 @@ pub enum Token {
     LCB,       // {
     RCB,       // }
     Dot,       // .
+    DotDot,    // ..
     New,       // new
     Bang,      // !
     Mul,       // *
@@ -78,6 +102,7 @@ pub enum Token {
     Unify,     // =
     Assign,    // :=
     Pipe,      // |
+    At,        // @
     SemiColon, // ;
     Query,     // ?=
     In,        // in
@@ -86,12 +111,19 @@ pub enum Token {
     Print,     // print()
     Isa,       // isa
     ForAll,    // forall
+    For,       // for
+    Distinct,  // distinct
+    Once,      // once
     If,        // if
+    Then,      // then
+    Else,      // else
     And,       // and
     Or,        // or
     Not,       // not
     Matches,   // matches
     Type,      // type
+    Module,    // module
+    Import,    // import
 }
 
 impl ToString for Token {
@@ -100,48 +132,58 @@ impl ToString for Token {
             Token::Integer(i) => i.to_string(),
             Token::Float(f) => f.to_string(),
             Token::String(s) => s.clone(),
+            Token::DocString(s) => format!(r#""""{}""""#, s),
             Token::Boolean(b) => b.to_string(),
-            Token::Symbol(sym) => sym.0.clone(),
-            Token::Colon => ":".to_owned(),         // :
-            Token::Comma => ",".to_owned(),         // ,
-            Token::LB => "[".to_owned(),            // [
-            Token::RB => "]".to_owned(),            // ]
-            Token::LP => "(".to_owned(),            // (
-            Token::RP => ")".to_owned(),            // )
-            Token::LCB => "{".to_owned(),           // {
-            Token::RCB => "}".to_owned(),           // }
-            Token::Dot => ".".to_owned(),           // .
-            Token::New => "new".to_owned(),         // new
-            Token::Bang => "!".to_owned(),          // !
-            Token::Mul => "*".to_owned(),           // *
-            Token::Div => "/".to_owned(),           // /
-            Token::Mod => "mod".to_owned(),         // mod
-            Token::Rem => "rem".to_owned(),         // rem
-            Token::Add => "+".to_owned(),           // +
-            Token::Sub => "-".to_owned(),           // -
-            Token::Eq => "==".to_owned(),           // ==
-            Token::Neq => "!=".to_owned(),          // !=
-            Token::Leq => "<=".to_owned(),          // <=
-            Token::Geq => ">=".to_owned(),          // >=
-            Token::Lt => "<".to_owned(),            // <
-            Token::Gt => ">".to_owned(),            // >
-            Token::Unify => "=".to_owned(),         // =
-            Token::Assign => ":=".to_owned(),       // :=
-            Token::Pipe => "|".to_owned(),          // |
-            Token::SemiColon => ";".to_owned(),     // ;
-            Token::Query => "?=".to_owned(),        // ?=
-            Token::In => "in".to_owned(),           // in
-            Token::Cut => "cut".to_owned(),         // cut
-            Token::Debug => "debug".to_owned(),     // debug
-            Token::Print => "print".to_owned(),     // print
-            Token::Isa => "isa".to_owned(),         // isa
-            Token::ForAll => "forall".to_owned(),   // forall
-            Token::If => "if".to_owned(),           // if
-            Token::And => "and".to_owned(),         // and
-            Token::Or => "or".to_owned(),           // or
-            Token::Not => "not".to_owned(),         // not
-            Token::Matches => "matches".to_owned(), // matches
-            Token::Type => "type".to_owned(),       // type
+            Token::Symbol(sym) => sym.to_string(),
+            Token::Colon => ":".to_owned(),           // :
+            Token::Comma => ",".to_owned(),           // ,
+            Token::LB => "[".to_owned(),              // [
+            Token::RB => "]".to_owned(),              // ]
+            Token::LP => "(".to_owned(),              // (
+            Token::RP => ")".to_owned(),              // )
+            Token::LCB => "{".to_owned(),             // {
+            Token::RCB => "}".to_owned(),             // }
+            Token::Dot => ".".to_owned(),             // .
+            Token::DotDot => "..".to_owned(),         // ..
+            Token::New => "new".to_owned(),           // new
+            Token::Bang => "!".to_owned(),            // !
+            Token::Mul => "*".to_owned(),             // *
+            Token::Div => "/".to_owned(),             // /
+            Token::Mod => "mod".to_owned(),           // mod
+            Token::Rem => "rem".to_owned(),           // rem
+            Token::Add => "+".to_owned(),             // +
+            Token::Sub => "-".to_owned(),             // -
+            Token::Eq => "==".to_owned(),             // ==
+            Token::Neq => "!=".to_owned(),            // !=
+            Token::Leq => "<=".to_owned(),            // <=
+            Token::Geq => ">=".to_owned(),            // >=
+            Token::Lt => "<".to_owned(),              // <
+            Token::Gt => ">".to_owned(),              // >
+            Token::Unify => "=".to_owned(),           // =
+            Token::Assign => ":=".to_owned(),         // :=
+            Token::Pipe => "|".to_owned(),            // |
+            Token::At => "@".to_owned(),              // @
+            Token::SemiColon => ";".to_owned(),       // ;
+            Token::Query => "?=".to_owned(),          // ?=
+            Token::In => "in".to_owned(),             // in
+            Token::Cut => "cut".to_owned(),           // cut
+            Token::Debug => "debug".to_owned(),       // debug
+            Token::Print => "print".to_owned(),       // print
+            Token::Isa => "isa".to_owned(),           // isa
+            Token::ForAll => "forall".to_owned(),     // forall
+            Token::For => "for".to_owned(),           // for
+            Token::Distinct => "distinct".to_owned(), // distinct
+            Token::Once => "once".to_owned(),         // once
+            Token::If => "if".to_owned(),             // if
+            Token::Then => "then".to_owned(),         // then
+            Token::Else => "else".to_owned(),         // else
+            Token::And => "and".to_owned(),           // and
+            Token::Or => "or".to_owned(),             // or
+            Token::Not => "not".to_owned(),           // not
+            Token::Matches => "matches".to_owned(),   // matches
+            Token::Type => "type".to_owned(),         // type
+            Token::Module => "module".to_owned(),     // module
+            Token::Import => "import".to_owned(),     // import
         }
     }
 }
@@ -233,12 +275,19 @@ impl<'input> Lexer<'input> {
             "print" => Token::Print,
             "isa" => Token::Isa,
             "forall" => Token::ForAll,
+            "for" => Token::For,
+            "distinct" => Token::Distinct,
+            "once" => Token::Once,
             "if" => Token::If,
+            "then" => Token::Then,
+            "else" => Token::Else,
             "and" => Token::And,
             "or" => Token::Or,
             "not" => Token::Not,
             "matches" => Token::Matches,
             "type" => Token::Type,
+            "module" => Token::Module,
+            "import" => Token::Import,
             "mod" => Token::Mod,
             "rem" => Token::Rem,
             _ => Token::Symbol(Symbol::new(&self.buf)),
@@ -253,6 +302,9 @@ impl<'input> Lexer<'input> {
         let last;
         self.buf.clear();
         self.c = self.chars.next();
+        if matches!(self.c, Some((_, '"'))) && matches!(self.chars.peek(), Some((_, '"'))) {
+            return self.scan_doc_string(start);
+        }
         loop {
             if let Some((i, char)) = self.c {
                 match char {
@@ -304,6 +356,34 @@ impl<'input> Lexer<'input> {
         Some(Ok((start, Token::String(self.buf.clone()), last + 1)))
     }
 
+    /// Scan a `"""` string, whose opening quotes start at `start`. Doc strings can span lines
+    /// and have no escapes. Their text is trimmed, and the indentation its lines share is
+    /// removed.
+    fn scan_doc_string(&mut self, start: usize) -> Option<Spanned<Token, usize, ParseErrorKind>> {
+        // Skip the rest of the opening quotes.
+        self.chars.next();
+        self.c = self.chars.next();
+        let mut quotes = 0;
+        while let Some((i, char)) = self.c {
+            self.c = self.chars.next();
+            if char == '"' {
+                quotes += 1;
+                if quotes == 3 {
+                    let text = &self.buf[..self.buf.len() - 2];
+                    return Some(Ok((start, Token::DocString(doc_text(text)), i + 1)));
+                }
+            } else {
+                quotes = 0;
+            }
+            self.buf.push(char);
+        }
+        Some(Err(ParseErrorKind::InvalidTokenCharacter {
+            token: self.buf.clone(),
+            c: '\0',
+            loc: start,
+        }))
+    }
+
     #[inline]
     fn push_char(&mut self, c: char) {
         self.buf.push(c);
@@ -340,7 +420,9 @@ impl<'input> Lexer<'input> {
 
         last = self.match_digits(last);
 
-        if let Some((i, '.')) = self.c {
+        // `1..10` is a range, not the float `1.` followed by `.`.
+        let is_range = matches!(self.chars.peek(), Some((_, '.')));
+        if let (Some((i, '.')), false) = (self.c, is_range) {
             self.push_char('.');
             last = i;
             parse_as_float = true;
@@ -475,6 +557,7 @@ impl<'input> Iterator for Lexer<'input> {
                 '!' => self.scan_1c_or_2c_op(i, Token::Bang, '=', Token::Neq),
                 '?' => self.scan_2c_op(i, '=', Token::Query),
                 '|' => self.scan_1c_op(i, Token::Pipe),
+                '@' => self.scan_1c_op(i, Token::At),
                 ',' => self.scan_1c_op(i, Token::Comma),
                 '[' => self.scan_1c_op(i, Token::LB),
                 ']' => self.scan_1c_op(i, Token::RB),
@@ -482,7 +565,7 @@ impl<'input> Iterator for Lexer<'input> {
                 '}' => self.scan_1c_op(i, Token::RCB),
                 '(' => self.scan_1c_op(i, Token::LP),
                 ')' => self.scan_1c_op(i, Token::RP),
-                '.' => self.scan_1c_op(i, Token::Dot),
+                '.' => self.scan_1c_or_2c_op(i, Token::Dot, '.', Token::DotDot),
                 '+' => self.scan_1c_op(i, Token::Add),
                 '-' => self.scan_1c_op(i, Token::Sub),
                 '*' => self.scan_1c_op(i, Token::Mul),
@@ -692,5 +775,11 @@ mod tests {
         let f = "1.1e-1";
         let mut lexer = Lexer::new(f);
         assert!(matches!(lexer.next(), Some(Ok((_, Token::Float(f), _))) if f == 1.1e-1));
+
+        let f = "1..10";
+        let mut lexer = Lexer::new(f);
+        assert!(matches!(lexer.next(), Some(Ok((0, Token::Integer(1), 1)))));
+        assert!(matches!(lexer.next(), Some(Ok((1, Token::DotDot, 3)))));
+        assert!(matches!(lexer.next(), Some(Ok((3, Token::Integer(10), 5)))));
     }
 }
@@ -8,31 +8,43 @@ extern crate maplit;
 pub mod macros;
 
 mod bindings;
+pub mod builder;
+pub mod builtins;
+mod collector;
 mod constants;
+pub mod convert;
 mod counter;
 pub mod data_filtering;
+pub mod datetime;
 mod debugger;
+pub mod deserialize;
 pub mod diagnostic;
 pub mod error;
 pub mod events;
 pub mod filter;
 mod folder;
 mod formatting;
+mod imports;
 mod inverter;
 pub mod kb;
 mod lexer;
 pub mod messages;
 pub mod normalize;
 mod numerics;
+mod parallel;
 pub mod parser;
 mod partial;
 pub mod polar;
+mod program;
 pub mod query;
 pub mod resource_block;
 mod rewrites;
 pub mod rules;
 mod runnable;
 pub mod sources;
+mod stack;
+pub mod stats;
+mod tabling;
 pub mod terms;
 pub mod traces;
 mod validations;
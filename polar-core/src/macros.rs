@@ -4,7 +4,7 @@
 
 /// Helper macros to create AST types
 ///
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::rules::*;
@@ -17,11 +17,11 @@ pub static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 macro_rules! value {
     ([$($args:expr),*]) => {
         $crate::terms::Value::List(vec![
-            $(term!(value!($args))),*
+            $($crate::term!($crate::value!($args))),*
         ])
     };
     ($arg:expr) => {
-        $crate::macros::TestHelper::<Value>::from($arg).0
+        $crate::macros::TestHelper::<$crate::terms::Value>::from($arg).0
     };
 }
 
@@ -38,7 +38,7 @@ macro_rules! values {
 #[macro_export]
 macro_rules! term {
     ($($expr:tt)*) => {
-        $crate::macros::TestHelper::<Term>::from(value!($($expr)*)).0
+        $crate::macros::TestHelper::<$crate::terms::Term>::from($crate::value!($($expr)*)).0
     };
 }
 
@@ -75,7 +75,7 @@ macro_rules! instance {
 #[macro_export]
 macro_rules! sym {
     ($arg:expr) => {
-        $crate::macros::TestHelper::<Symbol>::from($arg).0
+        $crate::macros::TestHelper::<$crate::terms::Symbol>::from($arg).0
     };
 }
 
@@ -107,17 +107,17 @@ macro_rules! str {
 #[macro_export]
 macro_rules! call {
     ($name:expr) => {
-        Call {
-            name: sym!($name),
+        $crate::terms::Call {
+            name: $crate::sym!($name),
             args: vec![],
             kwargs: None
         }
     };
     ($name:expr, [$($args:expr),*]) => {
-        Call {
-            name: sym!($name),
+        $crate::terms::Call {
+            name: $crate::sym!($name),
             args: vec![
-                $(term!($args)),*
+                $($crate::term!($args)),*
             ],
             kwargs: None
         }
@@ -133,6 +133,21 @@ macro_rules! call {
     };
 }
 
+/// A query term for the rule `name` with `args`, to pass to `Polar::new_query_from_term`. The
+/// arguments are terms, or values that convert to them, and aren't parsed, so data from a user
+/// can't change what the query means the way it could if it were formatted into Polar source.
+///
+/// ```
+/// # use polar_core::{polar::Polar, pred};
+/// let query = Polar::new().new_query_from_term(pred!("allow", ["alice", "read", 1]), false);
+/// ```
+#[macro_export]
+macro_rules! pred {
+    ($name:expr, [$($args:expr),*]) => {
+        $crate::terms::Term::from($crate::call!($name, [$($args),*]))
+    };
+}
+
 #[macro_export]
 macro_rules! op {
     ($op_type:ident, $($args:expr),+) => {
@@ -189,6 +204,9 @@ macro_rules! rule {
             body: term!(op!(And, $(term!($body)),+)),
             source_info: $crate::sources::SourceInfo::Test,
             required: false,
+            doc: None,
+            annotations: vec![],
+            program: Default::default(),
         }}
     };
     ($name:expr, [$($args:tt)*]) => {{
@@ -200,6 +218,9 @@ macro_rules! rule {
             body: term!(op!(And)),
             source_info: $crate::sources::SourceInfo::Test,
             required: false,
+            doc: None,
+            annotations: vec![],
+            program: Default::default(),
         }
     }};
     // this macro variant is used exclusively to create rule *types*
@@ -213,6 +234,9 @@ macro_rules! rule {
             body: term!(op!(And)),
             source_info: $crate::sources::SourceInfo::Test,
             required: $required,
+            doc: None,
+            annotations: vec![],
+            program: Default::default(),
         }
     }};
 }
@@ -275,7 +299,7 @@ impl From<Value> for TestHelper<Parameter> {
 
 impl<S: AsRef<str>> From<S> for TestHelper<Symbol> {
     fn from(other: S) -> Self {
-        Self(Symbol(other.as_ref().to_string()))
+        Self(Symbol::new(other.as_ref()))
     }
 }
 
@@ -285,6 +309,12 @@ impl From<BTreeMap<Symbol, Term>> for TestHelper<Dictionary> {
     }
 }
 
+impl From<Term> for TestHelper<Value> {
+    fn from(other: Term) -> Self {
+        Self(other.value().clone())
+    }
+}
+
 impl From<i64> for TestHelper<Value> {
     fn from(other: i64) -> Self {
         Self(Value::Number(other.into()))
@@ -329,9 +359,64 @@ impl From<Operation> for TestHelper<Value> {
         Self(Value::Expression(other))
     }
 }
-impl From<TermList> for TestHelper<Value> {
-    fn from(other: TermList) -> Self {
-        Self(Value::List(other))
+
+/// An element of a collection passed to `value!`, which can be any value `value!` takes.
+fn element<T>(other: T) -> Term
+where
+    TestHelper<Value>: From<T>,
+{
+    Term::from(TestHelper::<Value>::from(other).0)
+}
+
+impl<T> From<Vec<T>> for TestHelper<Value>
+where
+    TestHelper<Value>: From<T>,
+{
+    fn from(other: Vec<T>) -> Self {
+        Self(Value::List(other.into_iter().map(element).collect()))
+    }
+}
+
+/// Nested list literals, like the `[2]` in `value!([1, [2]])`.
+impl<T, const N: usize> From<[T; N]> for TestHelper<Value>
+where
+    TestHelper<Value>: From<T>,
+{
+    fn from(other: [T; N]) -> Self {
+        <Self as From<Vec<T>>>::from(Vec::from(other))
+    }
+}
+
+/// Maps with string keys. A `BTreeMap` of symbols to terms is a dictionary too.
+impl<K: AsRef<str>, T> From<HashMap<K, T>> for TestHelper<Value>
+where
+    TestHelper<Value>: From<T>,
+{
+    fn from(other: HashMap<K, T>) -> Self {
+        let fields = other
+            .into_iter()
+            .map(|(k, v)| (Symbol::new(k.as_ref()), element(v)))
+            .collect();
+        Self(Value::Dictionary(Dictionary { fields }))
+    }
+}
+
+/// `None` is `nil`, the constant the hosts register for their null value.
+impl<T> From<Option<T>> for TestHelper<Value>
+where
+    TestHelper<Value>: From<T>,
+{
+    fn from(other: Option<T>) -> Self {
+        match other {
+            Some(other) => Self::from(other),
+            None => Self(Value::Variable(Symbol::new("nil"))),
+        }
+    }
+}
+
+impl From<String> for TestHelper<Value> {
+    fn from(other: String) -> Self {
+        Self(Value::String(other))
     }
 }
 impl From<Symbol> for TestHelper<Value> {
@@ -365,3 +450,25 @@ impl From<Pattern> for TestHelper<Term> {
         Self(Term::from(value!(other)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_collections() {
+        assert_eq!(value!(vec![1, 2]), value!([1, 2]));
+        assert_eq!(value!(vec!["a".to_owned()]), value!(["a"]));
+        assert_eq!(
+            value!([1, [2, 3]]),
+            Value::List(vec![term!(1), term!([2, 3])])
+        );
+        assert_eq!(
+            value!(HashMap::from([("a", vec![true])])),
+            value!(btreemap! { sym!("a") => term!([true]) })
+        );
+        assert_eq!(value!(Some(1)), value!(1));
+        assert_eq!(value!(None::<i64>), value!(sym!("nil")));
+        assert_eq!(value!(vec![Some("a"), None]), value!(["a", sym!("nil")]));
+    }
+}
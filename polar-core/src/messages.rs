@@ -46,6 +46,14 @@ impl MessageQueue {
         }
     }
 
+    /// Take all of the messages in the queue, oldest first.
+    pub fn drain(&self) -> Vec<Message> {
+        match self.messages.lock() {
+            Ok(mut messages) => messages.drain(..).collect(),
+            Err(_) => vec![],
+        }
+    }
+
     pub fn push(&self, kind: MessageKind, msg: String) {
         let mut messages = self.messages.lock().unwrap();
         messages.push_back(Message { kind, msg });
@@ -16,6 +16,173 @@ pub enum Numeric {
         deserialize_with = "deserialize_float"
     )]
     Float(f64),
+
+    /// An integer too big for an `i64`, like a `u64` database id. Policies can't write one, but
+    /// hosts can pass them in, and arithmetic on them is exact. Hosts send and receive them as
+    /// strings of digits, since many JSON parsers read every number as a float.
+    #[serde(
+        serialize_with = "serialize_big_integer",
+        deserialize_with = "deserialize_big_integer"
+    )]
+    BigInteger(i128),
+
+    /// An exact decimal, like an amount of money, which hosts send and receive as a string.
+    Decimal(Decimal),
+}
+
+/// A decimal number, `mantissa * 10^-scale`, with no trailing zeros after the point.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn new(mut mantissa: i128, mut scale: u32) -> Self {
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        Self { mantissa, scale }
+    }
+
+    /// Parse a decimal like `-12.50`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || !whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let magnitude: i128 = format!("0{}{}", whole, fraction).parse().ok()?;
+        let mantissa = if s.starts_with('-') {
+            -magnitude
+        } else {
+            magnitude
+        };
+        Some(Self::new(mantissa, u32::try_from(fraction.len()).ok()?))
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// The nearest float.
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The mantissas of `self` and `other` at the same scale, if they fit.
+    fn align(self, other: Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        let rescale = |d: Self| {
+            10_i128
+                .checked_pow(scale - d.scale)
+                .and_then(|factor| d.mantissa.checked_mul(factor))
+        };
+        Some((rescale(self)?, rescale(other)?, scale))
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self::new(a.checked_add(b)?, scale))
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self::new(a.checked_sub(b)?, scale))
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        Some(Self::new(mantissa, self.scale.checked_add(other.scale)?))
+    }
+
+    fn checked_rem(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self::new(a.checked_rem(b)?, scale))
+    }
+
+    fn checked_modulo(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self::new(modulo_i128(a, b)?, scale))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        if scale == 0 {
+            write!(f, "{}{}", sign, digits)
+        } else if digits.len() > scale {
+            let (whole, fraction) = digits.split_at(digits.len() - scale);
+            write!(f, "{}{}.{}", sign, whole, fraction)
+        } else {
+            write!(f, "{}0.{:0>width$}", sign, digits, width = scale)
+        }
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).ok_or_else(|| de::Error::custom(format!("invalid decimal {:?}", s)))
+    }
+}
+
+fn modulo_i128(a: i128, b: i128) -> Option<i128> {
+    a.checked_rem(b)?.checked_add(b)?.checked_rem(b)
+}
+
+fn serialize_big_integer<S: Serializer>(i: &i128, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(i)
+}
+
+/// Read a big integer from a string of digits, or from a JSON number.
+fn deserialize_big_integer<'de, D>(deserializer: D) -> Result<i128, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct BigIntegerVisitor;
+
+    impl<'de> de::Visitor<'de> for BigIntegerVisitor {
+        type Value = i128;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an integer, or a string of its digits")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            v.parse()
+                .map_err(|_| de::Error::custom(format!("invalid integer {:?}", v)))
+        }
+    }
+
+    deserializer.deserialize_any(BigIntegerVisitor)
 }
 
 /// Since JSON does not support ±∞ or NaN (RFC 8259 §6),
@@ -95,6 +262,7 @@ impl Add for Numeric {
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 + b)),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a + b as f64)),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a + b)),
+            (a, b) => a.wide(b, i128::checked_add, Decimal::checked_add, |a, b| a + b),
         }
     }
 }
@@ -108,6 +276,7 @@ impl Sub for Numeric {
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 - b)),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a - b as f64)),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a - b)),
+            (a, b) => a.wide(b, i128::checked_sub, Decimal::checked_sub, |a, b| a - b),
         }
     }
 }
@@ -125,7 +294,204 @@ impl Numeric {
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(modulo(a as f64, b))),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(modulo(a, b as f64))),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(modulo(a, b))),
+            (a, b) => a.wide(b, modulo_i128, Decimal::checked_modulo, modulo),
+        }
+    }
+
+    /// An integer, as an `Integer` if it fits in one.
+    pub fn from_i128(i: i128) -> Self {
+        match i64::try_from(i) {
+            Ok(i) => Self::Integer(i),
+            Err(_) => Self::BigInteger(i),
+        }
+    }
+
+    /// The value of an integer of either width.
+    pub fn to_i128(self) -> Option<i128> {
+        match self {
+            Self::Integer(i) => Some(i.into()),
+            Self::BigInteger(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer or a decimal as a decimal.
+    pub fn to_decimal(self) -> Option<Decimal> {
+        match self {
+            Self::Decimal(d) => Some(d),
+            _ => self.to_i128().map(|i| Decimal::new(i, 0)),
+        }
+    }
+
+    /// The nearest float.
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Self::Integer(i) => i as f64,
+            Self::Float(f) => f,
+            Self::BigInteger(i) => i as f64,
+            Self::Decimal(d) => d.to_f64(),
+        }
+    }
+
+    /// Apply an operator to two numbers when either is wide: exactly to integers and decimals,
+    /// with a decimal result if either is a decimal, and to the nearest floats otherwise.
+    fn wide(
+        self,
+        other: Self,
+        integer: fn(i128, i128) -> Option<i128>,
+        decimal: fn(Decimal, Decimal) -> Option<Decimal>,
+        float: fn(f64, f64) -> f64,
+    ) -> Option<Self> {
+        if let (Some(a), Some(b)) = (self.to_i128(), other.to_i128()) {
+            integer(a, b).map(Self::from_i128)
+        } else if let (Some(a), Some(b)) = (self.to_decimal(), other.to_decimal()) {
+            decimal(a, b).map(Self::Decimal)
+        } else {
+            Some(Self::Float(float(self.to_f64(), other.to_f64())))
+        }
+    }
+
+    /// Compare two numbers exactly when either is wide, with a float by its exact decimal
+    /// expansion, so that comparisons are transitive.
+    fn wide_cmp(self, other: Self) -> Option<Ordering> {
+        match (self, other) {
+            (n, Self::Float(f)) => match n.to_decimal() {
+                Some(d) if d.scale == 0 => cmp_i128_f64(d.mantissa, f),
+                Some(d) if f.is_finite() => Some(Digits::decimal(d).cmp(&Digits::float(f))),
+                _ => n.to_f64().partial_cmp(&f),
+            },
+            (Self::Float(f), n) => n.wide_cmp(Self::Float(f)).map(Ordering::reverse),
+            (a, b) => {
+                let (a, b) = (a.to_decimal()?, b.to_decimal()?);
+                match a.align(b) {
+                    Some((a, b, _)) => Some(a.cmp(&b)),
+                    None => Some(Digits::decimal(a).cmp(&Digits::decimal(b))),
+                }
+            }
+        }
+    }
+}
+
+/// The decimal digits of a number too long for an `i128`, for comparing it exactly.
+struct Digits {
+    /// -1, 0 or 1.
+    sign: i8,
+    /// The digits of the magnitude, least significant first, with no leading zeros.
+    digits: Vec<u8>,
+    /// How many of the digits are after the point.
+    scale: usize,
+}
+
+impl Digits {
+    fn new(sign: i8, mut digits: Vec<u8>, scale: usize) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        let sign = if digits.is_empty() { 0 } else { sign };
+        Self {
+            sign,
+            digits,
+            scale,
+        }
+    }
+
+    /// The digits of `n`, least significant first.
+    fn digits(n: u128) -> Vec<u8> {
+        n.to_string().bytes().rev().map(|b| b - b'0').collect()
+    }
+
+    fn decimal(d: Decimal) -> Self {
+        let digits = Self::digits(d.mantissa.unsigned_abs());
+        Self::new(d.mantissa.signum() as i8, digits, d.scale as usize)
+    }
+
+    /// The exact decimal expansion of the finite float `f`.
+    fn float(f: f64) -> Self {
+        let bits = f.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32;
+        let fraction = bits & ((1 << 52) - 1);
+        // `f` is `mantissa * 2^power`, which is `mantissa * 5^-power / 10^-power` for a negative
+        // power.
+        let (mantissa, power) = match exponent {
+            0 => (fraction, -1074),
+            _ => (fraction | 1 << 52, exponent - 1075),
+        };
+        let mut digits = Self::digits(mantissa.into());
+        let (factor, times) = if power < 0 { (5, -power) } else { (2, power) };
+        for _ in 0..times {
+            let mut carry = 0;
+            for digit in &mut digits {
+                let product = *digit * factor + carry;
+                *digit = product % 10;
+                carry = product / 10;
+            }
+            if carry > 0 {
+                digits.push(carry);
+            }
         }
+        let sign = if f < 0.0 { -1 } else { 1 };
+        Self::new(sign, digits, (-power).max(0) as usize)
+    }
+
+    /// The digits of the magnitude from the most significant down, to the same scale as `other`.
+    fn aligned<'a>(&'a self, other: &Self) -> impl Iterator<Item = u8> + 'a {
+        let padding = other.scale.saturating_sub(self.scale);
+        let digits = self.digits.iter().rev().copied();
+        digits.chain(std::iter::repeat_n(0, padding))
+    }
+}
+
+impl Ord for Digits {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let magnitude = || {
+            let length = |d: &Self, e: &Self| d.digits.len() + e.scale.saturating_sub(d.scale);
+            length(self, other)
+                .cmp(&length(other, self))
+                .then_with(|| self.aligned(other).cmp(other.aligned(self)))
+        };
+        match self.sign.cmp(&other.sign) {
+            Ordering::Equal if self.sign < 0 => magnitude().reverse(),
+            Ordering::Equal => magnitude(),
+            unequal => unequal,
+        }
+    }
+}
+
+impl PartialOrd for Digits {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Digits {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Digits {}
+
+/// Compare the integer `i` with the float `f` exactly.
+fn cmp_i128_f64(i: i128, f: f64) -> Option<Ordering> {
+    // 2**127, one more than the greatest i128, which is exactly representable as a float.
+    const MOST_POSITIVE_I128_FLOAT: f64 = -(i128::MIN as f64);
+    if f.is_nan() {
+        None
+    } else if f >= MOST_POSITIVE_I128_FLOAT {
+        Some(Ordering::Less)
+    } else if f < -MOST_POSITIVE_I128_FLOAT {
+        Some(Ordering::Greater)
+    } else {
+        // Both the integral part of the float and its fractional part are exact.
+        let whole = f.trunc();
+        let fraction = f - whole;
+        Some(i.cmp(&(whole as i128)).then(if fraction > 0.0 {
+            Ordering::Less
+        } else if fraction < 0.0 {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }))
     }
 }
 
@@ -138,6 +504,7 @@ impl Rem for Numeric {
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float((a as f64) % b)),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a % (b as f64))),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a % b)),
+            (a, b) => a.wide(b, i128::checked_rem, Decimal::checked_rem, |a, b| a % b),
         }
     }
 }
@@ -151,6 +518,7 @@ impl Mul for Numeric {
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 * b)),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a * b as f64)),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a * b)),
+            (a, b) => a.wide(b, i128::checked_mul, Decimal::checked_mul, |a, b| a * b),
         }
     }
 }
@@ -164,6 +532,8 @@ impl Div for Numeric {
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 / b)),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a / b as f64)),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a / b)),
+            // Like integers, wide numbers divide to a float.
+            (a, b) => Some(Numeric::Float(a.to_f64() / b.to_f64())),
         }
     }
 }
@@ -224,6 +594,28 @@ impl Hash for Numeric {
                     }
                 }
             },
+            Numeric::BigInteger(i) => {
+                // Hash big integers the same as numerically equal integers and floats.
+                if let Ok(i) = i64::try_from(*i) {
+                    return Numeric::Integer(i).hash(state);
+                }
+                let f = *i as f64;
+                if f as i128 == *i {
+                    return Numeric::Float(f).hash(state);
+                }
+                discriminant(self).hash(state);
+                (*i as u64) ^ ((*i >> 64) as u64)
+            }
+            Numeric::Decimal(d) => {
+                // Hash decimals the same as numerically equal integers, and otherwise as the
+                // nearest float, which is the float they're equal to if there is one.
+                return if d.scale == 0 {
+                    Numeric::from_i128(d.mantissa)
+                } else {
+                    Numeric::Float(d.to_f64())
+                }
+                .hash(state);
+            }
         }
         .hash(state)
     }
@@ -256,6 +648,7 @@ impl PartialOrd for Numeric {
             (Self::Integer(i), Self::Float(f)) => partial_cmp(i, f),
             (Self::Float(f), Self::Integer(i)) => partial_cmp(i, f).map(Ordering::reverse),
             (Self::Float(left), Self::Float(right)) => left.partial_cmp(&right),
+            (left, right) => left.wide_cmp(right),
         }
     }
 }
@@ -270,6 +663,11 @@ impl From<f64> for Numeric {
         Self::Float(other)
     }
 }
+impl From<Decimal> for Numeric {
+    fn from(other: Decimal) -> Self {
+        Self::Decimal(other)
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -441,6 +839,119 @@ mod tests {
         );
     }
 
+    #[test]
+    fn wide_numbers() {
+        let big = Numeric::BigInteger(u64::MAX.into());
+        let decimal = |s| Numeric::Decimal(Decimal::parse(s).unwrap());
+
+        // Wide numbers compare exactly with each other and with integers.
+        assert!(big > Numeric::Integer(i64::MAX));
+        assert!(big < Numeric::BigInteger(i128::from(u64::MAX) + 1));
+        assert!(decimal("0.10") == decimal("0.1"));
+        assert!(decimal("12.00") == Numeric::Integer(12));
+        assert!(decimal("12.01") > Numeric::Integer(12));
+        assert!(decimal("-0.5") < Numeric::Integer(0));
+
+        // And with floats, by their exact decimal expansion.
+        assert!(big > Numeric::Float(2_f64.powi(64) - 4096.0));
+        assert!(big < Numeric::Float(2_f64.powi(64)));
+        assert!(Numeric::BigInteger(1 << 64) == Numeric::Float(2_f64.powi(64)));
+        assert!(Numeric::BigInteger(1 << 64) < Numeric::Float(f64::INFINITY));
+        assert!(Numeric::BigInteger(-(1 << 64)) > Numeric::Float(f64::NEG_INFINITY));
+        assert!(Numeric::BigInteger(1 << 64) != Numeric::Float(f64::NAN));
+        assert!(decimal("0.5") == Numeric::Float(0.5));
+        assert!(decimal("-0.375") == Numeric::Float(-0.375));
+        assert!(decimal("0.25") < Numeric::Float(0.3));
+        // 0.1 is 0.1000000000000000055511151231257827021181583404541015625 as a float.
+        assert!(decimal("0.1") < Numeric::Float(0.1));
+        assert!(decimal("0.10000000000000000555") < Numeric::Float(0.1));
+        assert!(decimal("0.10000000000000000556") > Numeric::Float(0.1));
+        assert!(decimal("-0.1") > Numeric::Float(-0.1));
+        assert!(decimal("0.1") > Numeric::Float(-0.0));
+        assert!(decimal("0.5") > Numeric::Float(5e-324));
+        assert!(decimal("12345678901234567890.5") < Numeric::Float(1e20));
+        assert!(decimal("0.5") < Numeric::Float(f64::INFINITY));
+        assert!(decimal("0.5") != Numeric::Float(f64::NAN));
+        // Decimals too long to align compare exactly too.
+        let long = decimal("123456789012345678901234567890123456.5");
+        assert!(long > decimal("0.00000000001"));
+        assert!(decimal("-123456789012345678901234567890123456.5") < decimal("-0.00000000001"));
+
+        // Equal numbers hash the same.
+        assert_eq!(
+            hash(&Numeric::BigInteger(1 << 64)),
+            hash(&Numeric::Float(2_f64.powi(64)))
+        );
+        assert_eq!(hash(&Numeric::BigInteger(5)), hash(&Numeric::Integer(5)));
+        assert_eq!(hash(&decimal("12.0")), hash(&Numeric::Integer(12)));
+        assert_eq!(hash(&decimal("0.5")), hash(&Numeric::Float(0.5)));
+        assert_eq!(
+            hash(&decimal("184467440737095516150.0")),
+            hash(&Numeric::BigInteger(i128::from(u64::MAX) * 10))
+        );
+
+        // Arithmetic on wide numbers is exact, and overflows i128 with an error.
+        assert_eq!(
+            big + Numeric::Integer(1),
+            Some(Numeric::BigInteger(1 << 64))
+        );
+        assert!(matches!(big - big, Some(Numeric::Integer(0))));
+        assert_eq!(Numeric::BigInteger(i128::MAX) + Numeric::Integer(1), None);
+        assert!(matches!(
+            decimal("0.1") + decimal("0.2"),
+            Some(Numeric::Decimal(d)) if d == Decimal::new(3, 1)
+        ));
+        assert!(matches!(
+            decimal("19.99") * Numeric::Integer(3),
+            Some(Numeric::Decimal(d)) if d.to_string() == "59.97"
+        ));
+        assert!(matches!(
+            decimal("-7.5").modulo(Numeric::Integer(2)),
+            Some(Numeric::Decimal(d)) if d.to_string() == "0.5"
+        ));
+        assert!(
+            matches!(decimal("0.5") + Numeric::Float(0.25), Some(Numeric::Float(f)) if f == 0.75)
+        );
+        assert!(matches!(big / Numeric::Integer(2), Some(Numeric::Float(_))));
+
+        // Decimals parse and print.
+        for (s, printed) in [
+            ("12.50", "12.5"),
+            ("-0.05", "-0.05"),
+            (".5", "0.5"),
+            ("7.", "7"),
+        ] {
+            assert_eq!(Decimal::parse(s).unwrap().to_string(), printed);
+        }
+        for s in ["", ".", "-", "1.2.3", "1e3", "--1", "0x10"] {
+            assert!(Decimal::parse(s).is_none(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn wide_json() {
+        let big = Numeric::BigInteger(u64::MAX.into());
+        assert_eq!(
+            to_json(&big).unwrap(),
+            r#"{"BigInteger":"18446744073709551615"}"#
+        );
+        assert_eq!(
+            from_json::<Numeric>(r#"{"BigInteger":"18446744073709551615"}"#).unwrap(),
+            big
+        );
+        assert_eq!(
+            from_json::<Numeric>(r#"{"BigInteger":18446744073709551615}"#).unwrap(),
+            big
+        );
+        let decimal = Numeric::Decimal(Decimal::new(-1250, 2));
+        assert_eq!(to_json(&decimal).unwrap(), r#"{"Decimal":"-12.5"}"#);
+        assert_eq!(
+            from_json::<Numeric>(r#"{"Decimal":"-12.50"}"#).unwrap(),
+            decimal
+        );
+        assert!(from_json::<Numeric>(r#"{"Decimal":"twelve"}"#).is_err());
+    }
+
     #[test]
     fn json_serialization() {
         assert_eq!(to_json(&Numeric::Integer(0)).unwrap(), r#"{"Integer":0}"#);
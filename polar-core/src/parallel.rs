@@ -0,0 +1,262 @@
+//! Evaluation of the disjuncts of a query on separate threads, which `Polar::set_parallel_disjuncts`
+//! opts into.
+//!
+//! When a query is a disjunction like `f(x) or g(y)` whose disjuncts share no unbound variables,
+//! each disjunct is run as a query of its own on a pool of up to one thread per core, which sends
+//! its results back to the VM as it finds them. The VM returns the results of each disjunct in
+//! turn, in the same order as if it had evaluated them one after another. A thread only gets a few
+//! results ahead of the VM before it waits for the VM to take them, so a disjunct with no end of
+//! results doesn't keep the query from returning the first ones, and the threads stop when the
+//! query is dropped. The steps and memory the threads use count toward the limits of the query.
+//!
+//! A disjunct that needs the host, fails with an error or has a partial result can't be evaluated
+//! apart from the query, so when its thread gets that far the VM queries it on the host's thread
+//! instead and skips the results the thread already sent.
+
+use std::cell::Cell;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::bindings::Bindings;
+use crate::datetime::Clock;
+use crate::events::QueryEvent;
+use crate::kb::KnowledgeBase;
+use crate::messages::{Message, MessageQueue};
+use crate::query::{CancelToken, Query};
+use crate::stats::QueryStats;
+use crate::terms::{Term, Value};
+use crate::vm::{Goal, PolarVirtualMachine};
+
+/// How many results a disjunct's thread sends before it waits for the VM to take them.
+const BUFFERED_RESULTS: usize = 16;
+
+/// The settings of the VM that a disjunct's query is run with.
+pub struct Config {
+    pub kb: Arc<RwLock<KnowledgeBase>>,
+    pub clock: Clock,
+    /// What's left of the query's timeout.
+    pub query_timeout_ms: u64,
+    pub step_limit: u64,
+    pub memory_limit: u64,
+    pub depth_limit: usize,
+    pub tabling: bool,
+    /// The token of the query the disjuncts are part of.
+    pub cancel: CancelToken,
+    pub usage: Arc<Usage>,
+}
+
+/// The steps and estimated memory used between them by the VMs that evaluate a query and its
+/// disjuncts, which the query's limits apply to.
+#[derive(Debug, Default)]
+pub struct Usage {
+    steps: AtomicU64,
+    memory: AtomicUsize,
+}
+
+impl Usage {
+    /// The usage of a query that has taken `steps` so far.
+    pub fn new(steps: u64) -> Self {
+        Self {
+            steps: AtomicU64::new(steps),
+            memory: AtomicUsize::default(),
+        }
+    }
+}
+
+/// The part of a query's `Usage` that the VMs on one thread count, which a VM shares with the
+/// ones it runs subqueries in.
+#[derive(Clone, Debug)]
+pub struct UsageShare {
+    usage: Arc<Usage>,
+    /// The memory this thread has counted toward `usage`.
+    memory: Rc<Cell<usize>>,
+}
+
+impl UsageShare {
+    pub fn new(usage: Arc<Usage>) -> Self {
+        Self {
+            usage,
+            memory: Rc::default(),
+        }
+    }
+
+    /// Count a step, and return the number of steps taken between them.
+    pub fn step(&self) -> u64 {
+        self.usage.steps.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Record that this thread uses `bytes`, and return the memory used between them.
+    pub fn use_memory(&self, bytes: usize) -> usize {
+        let counted = self.memory.replace(bytes);
+        if bytes >= counted {
+            let more = bytes - counted;
+            self.usage.memory.fetch_add(more, Ordering::Relaxed) + more
+        } else {
+            let less = counted - bytes;
+            self.usage.memory.fetch_sub(less, Ordering::Relaxed) - less
+        }
+    }
+}
+
+/// What a disjunct's thread sends the VM.
+pub enum Update {
+    /// A result, after the messages its query sent before it.
+    Result {
+        bindings: Bindings,
+        messages: Vec<Message>,
+    },
+    /// The thread is done with the disjunct.
+    Done(Done),
+}
+
+/// How a disjunct's thread got on, once it's done.
+#[derive(Default)]
+pub struct Done {
+    /// Whether the disjunct has no more results. If not, the VM has to query it from the start
+    /// and skip the results the thread sent.
+    pub exhausted: bool,
+    /// The messages its query sent after its last result, if it's exhausted.
+    pub messages: Vec<Message>,
+    pub stats: QueryStats,
+    pub instances: BTreeSet<u64>,
+    pub made_instances: BTreeSet<u64>,
+}
+
+/// The updates from the thread a disjunct is evaluated on.
+pub struct Stream {
+    updates: Receiver<Update>,
+    /// The number of results received.
+    results: usize,
+    _stop: Arc<Stop>,
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream")
+            .field("results", &self.results)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stream {
+    /// The next update from the disjunct's thread, or `None` if there's none within `timeout`.
+    pub fn next(&mut self, timeout: Duration) -> Option<Update> {
+        match self.updates.recv_timeout(timeout) {
+            Ok(update) => {
+                if matches!(update, Update::Result { .. }) {
+                    self.results += 1;
+                }
+                Some(update)
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            // The thread went away without a word, so the VM has to pick up where it left off.
+            Err(RecvTimeoutError::Disconnected) => Some(Update::Done(Done::default())),
+        }
+    }
+
+    /// The number of results received.
+    pub fn results(&self) -> usize {
+        self.results
+    }
+}
+
+/// Cancels the threads when the streams of all of their disjuncts are dropped.
+struct Stop(CancelToken);
+
+impl Drop for Stop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Start evaluating each of `disjuncts` on a thread pool, and return the stream of its results.
+pub fn evaluate(config: Config, disjuncts: Vec<Term>) -> Vec<Stream> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(disjuncts.len());
+    let cancel = config.cancel.child();
+    let stop = Arc::new(Stop(cancel.clone()));
+    let config = Arc::new(Config { cancel, ..config });
+
+    let mut streams = vec![];
+    let mut work = VecDeque::new();
+    for disjunct in disjuncts {
+        let (sender, updates) = mpsc::sync_channel(BUFFERED_RESULTS);
+        streams.push(Stream {
+            updates,
+            results: 0,
+            _stop: stop.clone(),
+        });
+        work.push_back((disjunct, sender));
+    }
+
+    // Threads take the disjuncts in order, so the one whose results the VM is waiting for has
+    // always been taken by a thread that isn't waiting for the VM.
+    let work = Arc::new(Mutex::new(work));
+    for _ in 0..threads {
+        let config = config.clone();
+        let work = work.clone();
+        std::thread::spawn(move || loop {
+            let Some((disjunct, sender)) = work.lock().unwrap().pop_front() else {
+                break;
+            };
+            evaluate_disjunct(&config, disjunct, &sender);
+        });
+    }
+    streams
+}
+
+fn evaluate_disjunct(config: &Config, disjunct: Term, sender: &SyncSender<Update>) {
+    let messages = MessageQueue::new();
+    let goals = vec![Goal::Query {
+        term: disjunct.clone(),
+    }];
+    let mut vm = PolarVirtualMachine::new(config.kb.clone(), false, goals, messages.clone());
+    vm.set_clock(config.clock.clone());
+    vm.set_query_timeout(config.query_timeout_ms);
+    vm.set_step_limit(config.step_limit);
+    vm.set_memory_limit(config.memory_limit);
+    vm.set_depth_limit(config.depth_limit);
+    vm.set_tabling(config.tabling);
+    vm.set_cancel_token(config.cancel.clone());
+    let usage = UsageShare::new(config.usage.clone());
+    vm.share_usage(usage.clone());
+
+    let mut query = Query::new(vm, disjunct);
+    let mut exhausted = false;
+    for event in query.by_ref() {
+        match event {
+            Ok(QueryEvent::Result { bindings, .. }) => {
+                let partial = bindings
+                    .values()
+                    .any(|value| matches!(value.value(), Value::Expression(_)));
+                if partial {
+                    break;
+                }
+                let messages = messages.drain();
+                if sender.send(Update::Result { bindings, messages }).is_err() {
+                    // The query was dropped.
+                    break;
+                }
+            }
+            Ok(QueryEvent::Done { .. }) => {
+                exhausted = true;
+                break;
+            }
+            _ => break,
+        }
+    }
+    usage.use_memory(0);
+    let _ = sender.send(Update::Done(Done {
+        exhausted,
+        messages: if exhausted { messages.drain() } else { vec![] },
+        stats: query.stats(),
+        instances: query.instances(),
+        made_instances: query.made_instances(),
+    }));
+}
@@ -28,6 +28,17 @@ pub enum Line {
     Rule(Rule),
     RuleType(Rule),
     Query(Term),
+    Constant {
+        name: Term,
+        value: Term,
+    },
+    Module {
+        name: Term,
+        rules: Vec<Rule>,
+    },
+    Import(Term),
+    /// A `table` declaration, which tables the rules with the given name.
+    Table(Term),
     ResourceBlock {
         keyword: Option<Term>,
         resource: Term,
@@ -41,11 +52,16 @@ fn lalrpop_error_to_polar_error(
 ) -> error::PolarError {
     let kind = match e {
         ParseError::InvalidToken { location: loc } => error::ParseErrorKind::InvalidToken { loc },
-        ParseError::UnrecognizedEOF { location: loc, .. } => {
-            error::ParseErrorKind::UnrecognizedEOF { loc }
-        }
+        ParseError::UnrecognizedEOF {
+            location: loc,
+            expected,
+        } => error::ParseErrorKind::UnrecognizedEOF {
+            loc,
+            expected: expected_at_eof(&source.src, &expected),
+        },
         ParseError::UnrecognizedToken {
-            token: (loc, t, _), ..
+            token: (loc, t, _),
+            expected,
         } => match t {
             Token::Debug | Token::Cut | Token::In | Token::New => {
                 error::ParseErrorKind::ReservedWord {
@@ -53,6 +69,22 @@ fn lalrpop_error_to_polar_error(
                     loc,
                 }
             }
+            // Words that were made keywords later are only reported as reserved where a name
+            // could have been, so they don't mask other syntax errors.
+            Token::Then
+            | Token::Else
+            | Token::Module
+            | Token::Import
+            | Token::For
+            | Token::Distinct
+            | Token::Once
+                if expected.iter().any(|token| token == r#""Symbol""#) =>
+            {
+                error::ParseErrorKind::ReservedWord {
+                    token: t.to_string(),
+                    loc,
+                }
+            }
             _ => error::ParseErrorKind::UnrecognizedToken {
                 token: t.to_string(),
                 loc,
@@ -67,11 +99,62 @@ fn lalrpop_error_to_polar_error(
     error::ParseError { source, kind }.into()
 }
 
+/// What the parser needed next when it hit the end of `src`: a rule body, the right operand of
+/// an operator, a closing bracket or a `;`. `expected` only rules out some of these, since the
+/// parser's lookahead sets are merged between states.
+fn expected_at_eof(src: &str, expected: &[String]) -> Option<String> {
+    use Token::*;
+
+    let expects = |token: &str| expected.iter().any(|e| e.trim_matches('"') == token);
+    let mut brackets = vec![];
+    let mut last = None;
+    for (_, token, _) in Lexer::new(src).flatten() {
+        match token {
+            LP => brackets.push(")"),
+            LB => brackets.push("]"),
+            LCB => brackets.push("}"),
+            RP | RB | RCB => {
+                brackets.pop();
+            }
+            _ => (),
+        }
+        last = Some(token);
+    }
+    match last? {
+        If if brackets.is_empty() => Some("a rule body after `if`".to_owned()),
+        token @ (If | Then | Else | For | And | Or | Not | In | Isa | Matches | New | Bang
+        | Mul | Div | Mod | Rem | Add | Sub | Eq | Neq | Leq | Geq | Lt | Gt | Unify
+        | Assign | Dot | DotDot | Comma | Colon | Query) => {
+            Some(format!("an expression after `{}`", token.to_string()))
+        }
+        _ => match brackets.last() {
+            Some(bracket) if expects(bracket) => Some(format!("`{}`", bracket)),
+            None if expects(";") => Some("`;`".to_owned()),
+            _ => None,
+        },
+    }
+}
+
 pub fn parse_lines(source: Source) -> PolarResult<Vec<Line>> {
     let source = Arc::new(source);
-    polar::LinesParser::new()
-        .parse(&source, Lexer::new(&source.src))
-        .map_err(|e| lalrpop_error_to_polar_error(e, source))
+    let parser = polar::LinesParser::new();
+    match parser.parse(&source, Lexer::new(&source.src)) {
+        // The `;` that ends the last line is optional, so parse it again as if it were there.
+        // If that doesn't parse either, the `;` wasn't what was missing.
+        Err(ParseError::UnrecognizedEOF {
+            location,
+            mut expected,
+        }) if expected.iter().any(|token| token == r#"";""#) => {
+            let semicolon = Ok((location, Token::SemiColon, location));
+            let tokens = Lexer::new(&source.src).chain(std::iter::once(semicolon));
+            parser.parse(&source, tokens).map_err(|_| {
+                expected.retain(|token| token != r#"";""#);
+                ParseError::UnrecognizedEOF { location, expected }
+            })
+        }
+        result => result,
+    }
+    .map_err(|e| lalrpop_error_to_polar_error(e, source))
 }
 
 pub fn parse_query(query: &str) -> PolarResult<Term> {
@@ -119,6 +202,17 @@ mod tests {
     fn test_dot_lookups() {
         let exp = parse_term("a.b");
         assert_eq!(exp, term!(op!(Dot, term!(sym!("a")), term!("b"))));
+
+        // Chained lookups associate to the left.
+        let exp = parse_term("a.b.c");
+        assert_eq!(
+            exp,
+            term!(op!(
+                Dot,
+                term!(op!(Dot, term!(sym!("a")), term!("b"))),
+                term!("c")
+            ))
+        );
     }
 
     #[test]
@@ -182,6 +276,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_doc_strings() {
+        let rule =
+            parse_rule(r#""""Grants admins everything.""" allow(_actor, _action, _resource);"#);
+        assert_eq!(rule.doc.as_deref(), Some("Grants admins everything."));
+        assert_eq!(
+            rule,
+            rule!(
+                "allow",
+                [sym!("_actor"), sym!("_action"), sym!("_resource")]
+            )
+        );
+
+        // Lines after the first lose the indentation they share.
+        let rule = parse_rule(
+            "\"\"\"\n    Members can read\n      \"public\" documents.\n    \"\"\"\nf(x) if x;",
+        );
+        assert_eq!(
+            rule.doc.as_deref(),
+            Some("Members can read\n  \"public\" documents.")
+        );
+        assert!(parse_rule("f(x) if x;").doc.is_none());
+
+        // An empty string is still a string.
+        assert_eq!(parse_term(r#""""#), term!(""));
+        assert!(matches!(
+            super::parse_rules(r#""""unterminated"" f(x) if x;"#)
+                .unwrap_err()
+                .unwrap_parse(),
+            InvalidTokenCharacter { .. }
+        ));
+        // Doc strings only document rules.
+        assert!(super::parse_query(r#""""doc""""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_annotations() {
+        let rule = parse_rule(
+            r#""""Card data is only for billing."""
+               @tag("pci") @control("PCI-DSS", 7, true) @reviewed
+               can_read(_user, _card);"#,
+        );
+        assert_eq!(rule.doc.as_deref(), Some("Card data is only for billing."));
+        assert_eq!(
+            rule.annotations,
+            vec![
+                Annotation {
+                    name: sym!("tag"),
+                    args: vec![term!("pci")]
+                },
+                Annotation {
+                    name: sym!("control"),
+                    args: vec![term!("PCI-DSS"), term!(7), term!(true)]
+                },
+                Annotation {
+                    name: sym!("reviewed"),
+                    args: vec![]
+                },
+            ]
+        );
+
+        // Annotations take literals, not variables or expressions.
+        assert!(super::parse_rules("@tag(x) f(x) if x;").is_err());
+        assert!(super::parse_rules("@tag(1 + 1) f(x) if x;").is_err());
+        // The doc string comes first.
+        assert!(super::parse_rules(r#"@tag """doc""" f(x) if x;"#).is_err());
+    }
+
     #[test]
     fn test_parse_specializers() {
         let rule = parse_rule("f(x: 1);");
@@ -198,6 +360,259 @@ mod tests {
         assert_eq!(rule, rule!("f", ["x"; value!(instance!("y"))]));
     }
 
+    #[test]
+    fn test_parse_keyword_operators() {
+        // `not` binds tightest, then `and`, then `or`.
+        let term = parse_term("a and not b or c");
+        assert_eq!(
+            term,
+            term!(op!(
+                Or,
+                term!(op!(
+                    And,
+                    term!(sym!("a")),
+                    term!(op!(Not, term!(sym!("b"))))
+                )),
+                term!(sym!("c"))
+            ))
+        );
+        let rule = parse_rule("f(x) if g(x) and not h(x);");
+        assert_eq!(rule.to_string(), "f(x) if g(x) and not h(x);");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let term = parse_term("x in 1..10");
+        assert_eq!(
+            term,
+            term!(op!(
+                In,
+                term!(sym!("x")),
+                term!(op!(Range, term!(1), term!(10)))
+            ))
+        );
+        let rule = parse_rule("f(x, y) if x in 0..y.count and x in (y.a + 1)..5;");
+        assert_eq!(
+            rule.to_string(),
+            "f(x, y) if x in 0..y.count and x in (y.a + 1)..5;"
+        );
+    }
+
+    #[test]
+    fn test_parse_comprehension() {
+        let term = parse_term("[y for x in xs]");
+        assert_eq!(
+            term,
+            term!(op!(
+                Collect,
+                term!(sym!("y")),
+                term!(op!(
+                    And,
+                    term!(op!(In, term!(sym!("x")), term!(sym!("xs"))))
+                ))
+            ))
+        );
+        let rule = parse_rule("f(xs, ys) if ys = [x.name for x in xs if x.active and x.age > 1];");
+        assert_eq!(
+            rule.to_string(),
+            "f(xs, ys) if ys = [x.name for x in xs if x.active and x.age > 1];"
+        );
+    }
+
+    #[test]
+    fn test_parse_aggregates() {
+        let rule = parse_rule(
+            "f(u) if count(s for s in u.sessions if s.active) < 5 and sum(x for x in u.xs) = 3;",
+        );
+        assert_eq!(
+            rule.to_string(),
+            "f(u) if count(s for s in u.sessions if s.active) < 5 and sum(x for x in u.xs) = 3;"
+        );
+        assert!(matches!(
+            parse_term_error("foo(x for x in xs)"),
+            UnrecognizedToken { .. }
+        ));
+        // Calls to rules with these names are unaffected.
+        assert_eq!(
+            parse_term("count(x, y)"),
+            term!(call!("count", [sym!("x"), sym!("y")]))
+        );
+    }
+
+    #[test]
+    fn test_parse_distinct() {
+        let term = parse_term("distinct(f(x, y) and x != y)");
+        assert_eq!(term.to_string(), "distinct(f(x, y) and x != y)");
+        assert_eq!(
+            term,
+            term!(op!(
+                Distinct,
+                term!(op!(
+                    And,
+                    term!(call!("f", [sym!("x"), sym!("y")])),
+                    term!(op!(Neq, term!(sym!("x")), term!(sym!("y"))))
+                ))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_once() {
+        let rule = parse_rule("f(x) if once(x in [1, 2] or g(x)) and h(x);");
+        assert_eq!(
+            rule.to_string(),
+            "f(x) if once(x in [1, 2] or g(x)) and h(x);"
+        );
+        assert_eq!(
+            parse_term("once(g(x))"),
+            term!(op!(Once, term!(call!("g", [sym!("x")]))))
+        );
+    }
+
+    #[test]
+    fn test_parse_conditional() {
+        let term = parse_term("if a then b else c");
+        assert_eq!(
+            term,
+            term!(op!(
+                Conditional,
+                term!(sym!("a")),
+                term!(sym!("b")),
+                term!(sym!("c"))
+            ))
+        );
+        // The alternative extends as far as possible.
+        let rule = parse_rule("f(x) if (if x = 1 then g(x) else h(x) or k(x));");
+        assert_eq!(
+            rule.to_string(),
+            "f(x) if (if x = 1 then g(x) else h(x) or k(x));"
+        );
+        let rule = parse_rule("f(x) if (if x = 1 then g(x) else h(x)) and k(x);");
+        assert_eq!(
+            rule.to_string(),
+            "f(x) if (if x = 1 then g(x) else h(x)) and k(x);"
+        );
+        // `then` and `else` are still allowed as names.
+        let rule = parse_rule("f(x) if x.then = x.else;");
+        assert_eq!(rule.to_string(), "f(x) if x.then = x.else;");
+        let rule = parse_rule("f(then) if if then then else(then) else then;");
+        assert_eq!(
+            rule.to_string(),
+            "f(then) if (if then then else(then) else then);"
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        // Arithmetic binds tighter than comparisons, and `*` tighter than `+`.
+        let term = parse_term("1 + 2 * 3 > y");
+        assert_eq!(
+            term,
+            term!(op!(
+                Gt,
+                term!(op!(Add, term!(1), term!(op!(Mul, term!(2), term!(3))))),
+                term!(sym!("y"))
+            ))
+        );
+        // `not` applies to a single unification or comparison.
+        let term = parse_term("not x = 1 and y = 2");
+        assert_eq!(
+            term,
+            term!(op!(
+                And,
+                term!(op!(Not, term!(op!(Unify, term!(sym!("x")), term!(1))))),
+                term!(op!(Unify, term!(sym!("y")), term!(2)))
+            ))
+        );
+        assert_eq!(parse_term("1 - 2 - 3"), parse_term("(1 - 2) - 3"));
+        assert!(matches!(
+            parse_term_error("x in y > 0"),
+            WrongValueType { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_chained_comparisons() {
+        let term = parse_term("0 <= x < limit");
+        assert_eq!(
+            term,
+            term!(op!(
+                And,
+                term!(op!(Leq, term!(0), term!(sym!("x")))),
+                term!(op!(Lt, term!(sym!("x")), term!(sym!("limit"))))
+            ))
+        );
+        assert_eq!(
+            parse_term("a == b != c > d"),
+            parse_term("a == b and b != c and c > d")
+        );
+        // The operands of a chain bind tighter than its comparisons, and one between two
+        // comparisons is evaluated once.
+        assert_eq!(
+            parse_term("0 < x + 1 < y.z"),
+            parse_term("_chain_4 = x + 1 and 0 < _chain_4 and _chain_4 < y.z")
+        );
+        assert_eq!(
+            parse_term("0 < f(x) < 10"),
+            parse_term("f(x, _chain_4) and 0 < _chain_4 and _chain_4 < 10")
+        );
+        // `not` negates the whole chain.
+        let term = parse_term("not 0 < x < 3");
+        assert_eq!(term, parse_term("not (0 < x and x < 3)"));
+        assert_eq!(term.to_string(), "not (0 < x and x < 3)");
+        assert_eq!(parse_term("a < b or c").to_string(), "a < b or c");
+    }
+
+    #[test]
+    fn test_parse_parentheses() {
+        assert_eq!(
+            parse_term("not (x = 1 or y = 2)"),
+            term!(op!(
+                Not,
+                term!(op!(
+                    Or,
+                    term!(op!(Unify, term!(sym!("x")), term!(1))),
+                    term!(op!(Unify, term!(sym!("y")), term!(2)))
+                ))
+            ))
+        );
+        assert_eq!(
+            parse_term("not not x = 1"),
+            term!(op!(
+                Not,
+                term!(op!(Not, term!(op!(Unify, term!(sym!("x")), term!(1)))))
+            ))
+        );
+        assert_eq!(parse_term("((x)) = (1)"), parse_term("x = 1"));
+        assert_eq!(parse_term("(x.y).z"), parse_term("x.y.z"));
+        assert_eq!(parse_term("f((1 + 2))"), parse_term("f(1 + 2)"));
+        assert_eq!(parse_term("x matches (Foo)"), parse_term("x matches Foo"));
+        assert_eq!(
+            parse_term("(x > 1) and ((y < 2))"),
+            parse_term("x > 1 and y < 2")
+        );
+        // Parentheses don't make a logical expression a value.
+        assert!(matches!(
+            parse_term_error("f((x = 1))"),
+            WrongValueType { .. }
+        ));
+
+        // Formatting adds back the parentheses that are needed to parse the same term.
+        for src in [
+            "(x = 1 or y = 2) and z = 3",
+            "x = 1 or y = 2 and z = 3",
+            "not (x = 1 and y = 2)",
+            "not not x = 1",
+            "1 - (2 - 3) == (4 - 5) - 6",
+            "x = (1 + 2) * 3",
+            "(a or b) and not (c or d)",
+        ] {
+            let term = parse_term(src);
+            assert_eq!(parse_term(&term.to_string()), term, "{}", term);
+        }
+        assert_eq!(parse_term("(a and b) and c").to_string(), "a and b and c");
+    }
+
     #[test]
     fn test_parse_file() {
         let f = "a(1);b(2);c(3);";
@@ -226,6 +641,35 @@ mod tests {
             line[0],
             Line::RuleType(rule!("f", ["x"; value!(instance!("String"))]))
         );
+
+        let constant = r#"ROLES = ["admin", "owner"];"#;
+        let line = parse_lines(constant);
+        assert_eq!(
+            line[0],
+            Line::Constant {
+                name: term!(sym!("ROLES")),
+                value: term!(["admin", "owner"]),
+            }
+        );
+
+        let import = r#"import "rbac.polar";"#;
+        let line = parse_lines(import);
+        assert_eq!(line[0], Line::Import(term!("rbac.polar")));
+
+        let table = "table path;";
+        let line = parse_lines(table);
+        assert_eq!(line[0], Line::Table(term!(sym!("path"))));
+        super::parse_lines(Source::new("tabel path;")).unwrap_err();
+
+        let module = "module billing { allow(x) if x = 1; }";
+        let line = parse_lines(module);
+        assert_eq!(
+            line[0],
+            Line::Module {
+                name: term!(sym!("billing")),
+                rules: vec![rule!("allow", [sym!("x")] => op!(Unify, term!(sym!("x")), term!(1)))],
+            }
+        );
     }
 
     #[test]
@@ -249,7 +693,8 @@ mod tests {
 
         // test trailing comma
         let f = "a(x) if x = new Foo(1,);";
-        super::parse_rules(f).expect_err("parse error");
+        let results = super::parse_rules(f).unwrap();
+        assert_eq!(results[0].to_string(), "a(x) if x = new Foo(1);");
     }
 
     #[test]
@@ -277,6 +722,19 @@ mod tests {
         super::parse_rules(f).expect_err("parse error");
     }
 
+    #[test]
+    fn test_parse_new_fields() {
+        let f = "a(x) if x = new Range{min: 1, max: 10};";
+        let results = super::parse_rules(f).unwrap();
+        assert_eq!(
+            results[0].to_string(),
+            "a(x) if x = new Range(max: 10, min: 1);"
+        );
+        let f = "a(x) if x = new Range{};";
+        let results = super::parse_rules(f).unwrap();
+        assert_eq!(results[0].to_string(), "a(x) if x = new Range();");
+    }
+
     #[test]
     fn test_parse_matches() {
         let term = parse_term("{} matches {}");
@@ -363,11 +821,115 @@ mod tests {
             parse_term("{a: 1,} = [1, 2,]"),
             term!(op!(Unify, dict, list))
         );
+
+        assert_eq!(parse_term("f(1, 2,)"), parse_term("f(1, 2)"));
+        assert_eq!(parse_term("f(1, a: 2,)"), parse_term("f(1, a: 2)"));
+        assert_eq!(parse_term("x.m(1,)"), parse_term("x.m(1)"));
+        assert_eq!(parse_term("x.in(1,)"), parse_term("x.in(1)"));
+        assert_eq!(
+            parse_rule("f(x, y,) if g(x,);"),
+            parse_rule("f(x, y) if g(x);")
+        );
+        assert!(matches!(parse_term_error("f(,)"), UnrecognizedToken { .. }));
+        assert!(matches!(
+            parse_term_error("f(1,,)"),
+            UnrecognizedToken { .. }
+        ));
+    }
+
+    #[test]
+    fn optional_final_semicolon() {
+        assert_eq!(parse_lines("f(x)"), parse_lines("f(x);"));
+        assert_eq!(
+            parse_lines("f(x); g(x) if f(x)"),
+            parse_lines("f(x); g(x) if f(x);")
+        );
+        assert_eq!(
+            parse_lines("type f(x)\n# done\n"),
+            parse_lines("type f(x);")
+        );
+        assert_eq!(parse_lines("?= f(1)"), parse_lines("?= f(1);"));
+        assert_eq!(parse_lines("x = 1"), parse_lines("x = 1;"));
+
+        // Only the last line may leave it out.
+        let e = super::parse_lines(Source::new("f(x) g(x)")).unwrap_err();
+        assert!(matches!(e.unwrap_parse(), UnrecognizedToken { .. }));
+        let e = super::parse_lines(Source::new("f(x) if g(x) and")).unwrap_err();
+        assert!(matches!(e.unwrap_parse(), UnrecognizedEOF { .. }));
+        let e = super::parse_lines(Source::new("f(x);;")).unwrap_err();
+        assert!(matches!(e.unwrap_parse(), UnrecognizedToken { .. }));
+    }
+
+    #[test]
+    fn unexpected_eof_says_what_was_expected() {
+        let expected = |src: &str| match super::parse_lines(Source::new(src))
+            .unwrap_err()
+            .unwrap_parse()
+        {
+            UnrecognizedEOF { expected, .. } => expected,
+            e => panic!("unexpected error: {}", e),
+        };
+        assert_eq!(expected("f(a) if").unwrap(), "a rule body after `if`");
+        assert_eq!(
+            expected("f(x) if g(x) and").unwrap(),
+            "an expression after `and`"
+        );
+        assert_eq!(
+            expected("f(x) if x = [y for y in x if").unwrap(),
+            "an expression after `if`"
+        );
+        assert_eq!(expected("f(x) if g(x").unwrap(), "`)`");
+        assert_eq!(expected("f(x) if x = {a: [1, 2]").unwrap(), "`}`");
+        assert_eq!(expected("banana"), None);
+
+        let e = super::parse_rules("f(a)").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "hit the end of the file unexpectedly. Expected `;` at line 1, column 5:\n\t001: f(a)\n\t         ^\n"
+        );
     }
 
     #[test]
     fn duplicate_keys() {
         let q = "{a: 1, a: 2}";
         assert!(matches!(parse_term_error(q), DuplicateKey { .. }));
+        let q = r#"{a: 1, "a": 2}"#;
+        assert!(matches!(parse_term_error(q), DuplicateKey { .. }));
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let q = r#"{"content-type": "json", a: 1, "b": 2}"#;
+        let dict = term!(btreemap! {
+            sym!("content-type") => term!("json"),
+            sym!("a") => term!(1),
+            sym!("b") => term!(2),
+        });
+        assert_eq!(parse_term(q), dict);
+        // Keys that aren't identifiers stay quoted when formatted.
+        assert_eq!(dict.to_string(), r#"{a: 1, b: 2, "content-type": "json"}"#);
+        assert_eq!(
+            parse_term(r#"{"": 1, "true": 2, "2fa": 3}"#).to_string(),
+            r#"{"": 1, "2fa": 3, "true": 2}"#
+        );
+
+        let rule = parse_rule(r#"f(x: {"content-type": "json"});"#);
+        assert_eq!(rule.to_string(), r#"f(x: {"content-type": "json"});"#);
+
+        for src in [r#"x.("content-type")"#, "x.(k)", "x.y"] {
+            assert_eq!(parse_term(src).to_string(), src);
+        }
+
+        // Quotes and backslashes in keys and strings are escaped, so they parse back the same.
+        for src in [
+            r#"{"say \"hi\"": 1}"#,
+            r#"{"a\\b": "c\\d"}"#,
+            r#"x.("a\"b")"#,
+            r#""line\nbreak""#,
+        ] {
+            let term = parse_term(src);
+            assert_eq!(term.to_string(), src);
+            assert_eq!(parse_term(&term.to_string()), term);
+        }
     }
 }
@@ -78,8 +78,8 @@ impl IsaConstraintCheck {
 
         let just_vars = constraint_path.len() == 1
             && proposed_path.len() == 1
-            && matches!(&constraint.args[0].as_symbol(), Ok(Symbol(_)))
-            && matches!(&self.proposed.args[0].as_symbol(), Ok(Symbol(_)));
+            && constraint.args[0].as_symbol().is_ok()
+            && self.proposed.args[0].as_symbol().is_ok();
 
         // FIXME(gw): this logic is hard to follow!
         if just_vars {
@@ -300,7 +300,7 @@ mod test {
     use crate::events::QueryEvent;
     use crate::polar::Polar;
     use crate::query::Query;
-    use crate::terms::{Call, Dictionary, InstanceLiteral, Pattern};
+    use crate::terms::{Dictionary, InstanceLiteral, Pattern};
 
     macro_rules! assert_partial_expression {
         ($bindings:expr, $sym:expr, $right:expr) => {
@@ -353,7 +353,7 @@ mod test {
                         "Bindings: {}",
                         bindings
                             .iter()
-                            .map(|(k, v)| format!("{}: {}", k.0, v))
+                            .map(|(k, v)| format!("{}: {}", k, v))
                             .collect::<Vec<_>>()
                             .join("\n")
                     )
@@ -486,7 +486,7 @@ mod test {
         // NOTE(gj): only one permutation remains parse-able.
         p.load_str("m(x) if [_y] matches [x];")?;
         let mut q = p.new_query_from_term(term!(call!("m", [sym!("x")])), false);
-        assert_partial_expression!(next_binding(&mut q)?, "x", "__y_36 matches _this");
+        assert_partial_expression!(next_binding(&mut q)?, "x", "__y_31 matches _this");
         assert_query_done!(q);
 
         // TODO(gj): Make the below work.
@@ -517,7 +517,7 @@ mod test {
         assert_eq!(
             next[&sym!("y")],
             // TODO(gj): do something with the x <-> _x_5 cycle?
-            term!(btreemap! { sym!("x") => term!(sym!("_x0_5")) })
+            term!(btreemap! { sym!("x") => term!(sym!("_x0_4")) })
         );
         assert_query_done!(q);
 
@@ -702,8 +702,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(&*right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 QueryEvent::Done { .. } => return None,
                 _ => panic!("not bindings"),
@@ -821,8 +826,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(&*right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 e => panic!("unexpected event: {:?}", e),
             }
@@ -867,8 +877,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(&*right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 _ => panic!("not bindings"),
             }
@@ -1052,7 +1067,7 @@ mod test {
                         let last_segment = path.last().unwrap();
                         q.question_result(
                             call_id,
-                            last_segment.as_string().unwrap().to_uppercase() == class_tag.0,
+                            last_segment.as_string().unwrap().to_uppercase() == class_tag.as_str(),
                         )
                         .unwrap();
                     }
@@ -1100,7 +1115,7 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_81 in _this.b and _b_81 matches B{} and 1 = _b_81.foo"
+            "_this matches A{} and _b_67 in _this.b and _b_67 matches B{} and 1 = _b_67.foo"
         );
         assert_query_done!(q);
 
@@ -1108,7 +1123,7 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_92 in _this.b and _b_92 matches B{} and _b_92.c matches C{} and 1 = _b_92.c.bar"
+            "_this matches A{} and _b_75 in _this.b and _b_75 matches B{} and _b_75.c matches C{} and 1 = _b_75.c.bar"
         );
         assert_query_done!(q);
 
@@ -1116,7 +1131,7 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_115 in _this.b and _b_115.c matches C{} and 1 = _b_115.c.bar"
+            "_this matches A{} and _b_93 in _this.b and _b_93.c matches C{} and 1 = _b_93.c.bar"
         );
         // @TODO(sam): this result is incorrect. We *could* know
         // that `_b_104` matches B{} by checking `a.b` first
@@ -1124,7 +1139,7 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_115 in _this.b and _b_115.c matches D{} and 2 = _b_115.c.bar"
+            "_this matches A{} and _b_93 in _this.b and _b_93.c matches D{} and 2 = _b_93.c.bar"
         );
         assert_query_done!(q);
         Ok(())
@@ -1563,7 +1578,7 @@ mod test {
                 And,
                 term!(op!(
                     Neq,
-                    var!("__y_9"),
+                    var!("__y_7"),
                     term!(op!(Dot, var!("_this"), str!("foo")))
                 ))
             ))
@@ -1577,7 +1592,7 @@ mod test {
                 And,
                 term!(op!(
                     Neq,
-                    var!("__y_17"),
+                    var!("__y_12"),
                     term!(op!(
                         Dot,
                         term!(op!(Dot, var!("_this"), str!("foo"))),
@@ -1697,7 +1712,7 @@ mod test {
         let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "__y_12 in _this.values"
+            "x" => "__y_10 in _this.values"
         );
         assert_query_done!(q);
 
@@ -1710,11 +1725,11 @@ mod test {
         let mut q = p.new_query_from_term(term!(call!("h", [sym!("x")])), false);
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "_y_27 in _this.values and 1 = _y_27.bar and 2 = _y_27.baz"
+            "x" => "_y_19 in _this.values and 1 = _y_19.bar and 2 = _y_19.baz"
         );
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "_y_27 in _this.values and 3 = _y_27.bar"
+            "x" => "_y_19 in _this.values and 3 = _y_19.bar"
         );
         assert_query_done!(q);
 
@@ -1724,7 +1739,7 @@ mod test {
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("l", [sym!("x")])), false);
-        assert_partial_expressions!(next_binding(&mut q)?, "x" => "__y_36 in _this");
+        assert_partial_expressions!(next_binding(&mut q)?, "x" => "__y_27 in _this");
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("m", [sym!("x")])), false);
@@ -2348,7 +2363,7 @@ mod test {
         // well, this is semi-successful!
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "_this = _y_26.foo and 1 = _y_26.bar"
+            "x" => "_this = _y_22.foo and 1 = _y_22.bar"
         );
 
         assert_query_done!(q);
@@ -1,26 +1,58 @@
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 
+use super::builtins::{BuiltinAnswer, NativeBuiltin};
+use super::convert::ToPolarArgs;
 use super::data_filtering::{build_filter_plan, FilterPlan, PartialResults, Types};
+use super::datetime::{system_clock, Clock};
 use super::diagnostic::Diagnostic;
 use super::error::{PolarResult, RuntimeError, ValidationError};
+use super::events::QueryEvent;
 use super::filter::Filter;
+use super::imports::{order_by_imports, ParsedSource};
 use super::kb::*;
 use super::messages::*;
 use super::parser;
-use super::query::Query;
+use super::query::{ExternalHook, Query, QueryHandler, Results};
 use super::resource_block::resource_block_from_productions;
 use super::rewrites::*;
+use super::rules::*;
 use super::sources::*;
 use super::terms::*;
 use super::validations::{
-    check_ambiguous_precedence, check_no_allow_rule, check_resource_blocks_missing_has_permission,
-    check_singletons,
+    check_ambiguous_precedence, check_duplicate_rule, check_no_allow_rule,
+    check_resource_blocks_missing_has_permission, check_singletons, check_unsatisfiable_rule,
 };
+use super::warning::PolarWarning;
 
+/// A copy of a `Polar`'s knowledge base made by `Polar::snapshot`.
+pub struct Snapshot {
+    kb: KnowledgeBase,
+}
+
+/// A policy and the host's registrations, which queries run against.
+///
+/// `Polar` is `Send` and `Sync`, and only its settings need `&mut self`, so one loaded policy
+/// behind an `Arc` can answer queries from many threads at once. The knowledge base is locked for
+/// writing only by the methods that change it, such as loading a policy, adding a fact or
+/// registering something; queries only read it, and the instances they make with `new` are kept
+/// behind a lock of their own. A `Query` isn't `Send`: it runs on the thread that made it.
 pub struct Polar {
     pub kb: Arc<RwLock<KnowledgeBase>>,
     messages: MessageQueue,
     ignore_no_allow_warning: bool,
+    clock: Clock,
+    query_timeout_ms: Option<u64>,
+    step_limit: u64,
+    memory_limit: u64,
+    depth_limit: usize,
+    tabling: bool,
+    parallel_disjuncts: bool,
+    isolate_application_errors: bool,
+    batch_external_calls: bool,
+    cache_lookups: bool,
+    external_hook: Option<Arc<dyn ExternalHook>>,
 }
 
 impl Default for Polar {
@@ -41,18 +73,48 @@ impl Polar {
             kb: Arc::new(RwLock::new(KnowledgeBase::new())),
             messages: MessageQueue::new(),
             ignore_no_allow_warning,
+            clock: system_clock(),
+            query_timeout_ms: None,
+            step_limit: 0,
+            memory_limit: 0,
+            depth_limit: 0,
+            tabling: false,
+            parallel_disjuncts: false,
+            isolate_application_errors: false,
+            batch_external_calls: false,
+            cache_lookups: false,
+            external_hook: None,
         }
     }
 
     /// Load `sources` into the KB, returning compile-time diagnostics accumulated during the load.
     pub fn diagnostic_load(&self, sources: Vec<Source>) -> Vec<Diagnostic> {
+        let mut kb = self.kb.write().unwrap();
+        self.load_into(&mut kb, sources)
+    }
+
+    /// Load `sources` into `kb`, which may not be the KB of this `Polar` yet.
+    fn load_into(&self, kb: &mut KnowledgeBase, sources: Vec<Source>) -> Vec<Diagnostic> {
         // Separate function so that errors returned with `?` are captured.
-        fn load_source(source: Source, kb: &mut KnowledgeBase) -> PolarResult<Vec<Diagnostic>> {
+        fn parse_source(source: Source, kb: &mut KnowledgeBase) -> PolarResult<ParsedSource> {
             if let Some(ref filename) = source.filename {
                 kb.add_source(filename, &source.src)?;
             }
+            kb.record_source(source.clone());
+            let filename = source.filename.clone();
             // TODO(gj): we still bomb out at the first ParseError.
-            let mut lines = parser::parse_lines(source)?;
+            let lines = parser::parse_lines(source)?;
+            Ok(ParsedSource { filename, lines })
+        }
+
+        fn load_source(
+            source: ParsedSource,
+            kb: &mut KnowledgeBase,
+            loaded: &mut HashSet<u64>,
+        ) -> Vec<Diagnostic> {
+            let mut lines = source.lines;
+            // Define constants first so that rules can refer to them wherever they are declared.
+            lines.sort_by_key(|line| !matches!(line, parser::Line::Constant { .. }));
             lines.reverse();
             let mut diagnostics = vec![];
             while let Some(line) = lines.pop() {
@@ -60,12 +122,32 @@ impl Polar {
                     parser::Line::Rule(rule) => {
                         diagnostics.append(&mut check_singletons(&rule, kb));
                         diagnostics.append(&mut check_ambiguous_precedence(&rule));
+                        diagnostics.extend(check_duplicate_rule(&rule, loaded));
+                        diagnostics.extend(check_unsatisfiable_rule(&rule));
                         let rule = rewrite_rule(rule, kb);
                         kb.add_rule(rule);
                     }
                     parser::Line::Query(term) => {
                         kb.inline_queries.push(term);
                     }
+                    // Imports were resolved before loading.
+                    parser::Line::Import(_) => (),
+                    parser::Line::Table(name) => match name.as_symbol() {
+                        Ok(name) => kb.table(name.clone()),
+                        Err(e) => diagnostics.push(Diagnostic::Error(e)),
+                    },
+                    parser::Line::Constant { name, value } => {
+                        if let Err(e) = kb.add_policy_constant(&name, value) {
+                            diagnostics.push(Diagnostic::Error(e));
+                        }
+                    }
+                    parser::Line::Module { name, rules } => match name.as_symbol() {
+                        Ok(module) => {
+                            let rules = qualify_module_rules(module, rules);
+                            lines.extend(rules.into_iter().rev().map(parser::Line::Rule));
+                        }
+                        Err(e) => diagnostics.push(Diagnostic::Error(e)),
+                    },
                     parser::Line::RuleType(rule_type) => {
                         // make sure rule_type doesn't have anything that needs to be rewritten in the head
                         let rule_type = rewrite_rule(rule_type, kb);
@@ -80,7 +162,7 @@ impl Polar {
                         ) {
                             diagnostics.push(Diagnostic::Error(
                                 ValidationError::InvalidRuleType {
-                                    rule_type,
+                                    rule_type: Box::new(rule_type),
                                     msg: "Rule types cannot contain dot lookups.".into(),
                                 }
                                 .into(),
@@ -101,19 +183,31 @@ impl Polar {
                     }
                 }
             }
-            Ok(diagnostics)
+            diagnostics
         }
 
-        let mut kb = self.kb.write().unwrap();
         let mut diagnostics = vec![];
 
+        let loaded = kb
+            .sources()
+            .iter()
+            .filter_map(|source| source.filename.clone())
+            .collect::<Vec<_>>();
+        let mut parsed = vec![];
         for source in sources {
-            match load_source(source, &mut kb) {
-                Ok(mut ds) => diagnostics.append(&mut ds),
+            match parse_source(source, kb) {
+                Ok(source) => parsed.push(source),
                 Err(e) => diagnostics.push(Diagnostic::Error(e)),
             }
         }
 
+        let (parsed, errors) = order_by_imports(parsed, &loaded);
+        diagnostics.extend(errors.into_iter().map(Diagnostic::Error));
+        let mut loaded = HashSet::new();
+        for source in parsed {
+            diagnostics.append(&mut load_source(source, kb, &mut loaded));
+        }
+
         // NOTE(gj): need to bomb out before rewriting shorthand rules to avoid emitting
         // correct-but-unhelpful errors, e.g., when there's an invalid `relations` declaration that
         // will result in a second error when rewriting a shorthand rule involving the relation
@@ -146,21 +240,31 @@ impl Polar {
 
         // Perform validation checks against the whole policy
         if !self.ignore_no_allow_warning {
-            if let Some(w) = check_no_allow_rule(&kb) {
+            if let Some(w) = check_no_allow_rule(kb) {
                 diagnostics.push(w)
             }
         }
 
         // Check for has_permission calls alongside resource block definitions
-        if let Some(w) = check_resource_blocks_missing_has_permission(&kb) {
+        if let Some(w) = check_resource_blocks_missing_has_permission(kb) {
             diagnostics.push(Diagnostic::Warning(w.into()))
         };
 
         diagnostics
     }
 
-    /// Load `Source`s into the KB.
+    /// Load `Source`s into the KB. Warnings are sent as messages.
     pub fn load(&self, sources: Vec<Source>) -> PolarResult<()> {
+        let warnings = self.load_with_warnings(sources)?;
+        self.messages
+            .extend(warnings.into_iter().map(Message::warning));
+        Ok(())
+    }
+
+    /// Load `Source`s into the KB as `load` does, but return the warnings about them instead of
+    /// sending them as messages, e.g. for a policy check in CI to print or fail on. If the load
+    /// fails, returns its first error, and the warnings are sent as messages, as `load` does.
+    pub fn load_with_warnings(&self, sources: Vec<Source>) -> PolarResult<Vec<PolarWarning>> {
         if let Ok(kb) = self.kb.read() {
             if kb.has_rules() {
                 return Err(RuntimeError::MultipleLoadError.into());
@@ -175,15 +279,14 @@ impl Polar {
             }
         }
 
-        self.messages
-            .extend(warnings.into_iter().map(Message::warning));
-
         if let Some(e) = errors.into_iter().next() {
             // If we've encountered any errors, clear the KB.
             self.clear_rules();
+            self.messages
+                .extend(warnings.into_iter().map(Message::warning));
             return Err(e);
         }
-        Ok(())
+        Ok(warnings)
     }
 
     // Used in integration tests
@@ -191,7 +294,113 @@ impl Polar {
         self.load(vec![Source::new(src)])
     }
 
-    /// Clear rules from the knowledge base
+    /// Load the Polar files at `paths`, in the order they're given, as sources named by their
+    /// paths, so that errors and stack traces give the file and line of the rules they're about.
+    pub fn load_files<P: AsRef<Path>>(&self, paths: &[P]) -> PolarResult<()> {
+        let mut sources = Vec::with_capacity(paths.len());
+        for path in paths {
+            sources.push(read_source(path.as_ref())?);
+        }
+        self.load(sources)
+    }
+
+    /// Load the Polar file at `path` as `load_files` does.
+    pub fn load_file<P: AsRef<Path>>(&self, path: P) -> PolarResult<()> {
+        self.load_files(&[path])
+    }
+
+    /// Load `source` alongside the sources that are already loaded, in place of the one with the
+    /// same filename if there is one. A long-lived process can then update a single policy file
+    /// without making a new `Polar`.
+    ///
+    /// The KB is reloaded from all of its sources, and facts added with `add_fact` are added
+    /// again. Only `source`'s inline queries are left to run. If the reload fails, the policy that
+    /// was loaded before is kept.
+    pub fn add_source(&self, source: Source) -> PolarResult<()> {
+        let mut sources = self.kb.read().unwrap().sources().to_vec();
+        let same_file = sources
+            .iter()
+            .position(|s| source.filename.is_some() && s.filename == source.filename);
+        match same_file {
+            Some(i) => sources[i] = source.clone(),
+            None => sources.push(source.clone()),
+        }
+        self.reload(sources, std::slice::from_ref(&source))
+    }
+
+    /// Remove the source loaded from `filename`, along with everything it defined, and reload
+    /// the KB from the rest as `add_source` does. Returns whether there was one. Sources loaded
+    /// without a filename can only be removed with `clear_rules`.
+    pub fn remove_source(&self, filename: &str) -> PolarResult<bool> {
+        let mut sources = self.kb.read().unwrap().sources().to_vec();
+        let len = sources.len();
+        sources.retain(|s| s.filename.as_deref() != Some(filename));
+        if sources.len() == len {
+            return Ok(false);
+        }
+        self.reload(sources, &[])?;
+        Ok(true)
+    }
+
+    /// Replace the loaded policy with `sources`, keeping what the host registered: classes,
+    /// constants, MROs and unions. A long-lived process can then reload its policy without
+    /// registering them all again. Facts added with `add_fact` are added again too, and if
+    /// loading fails, the policy loaded before is kept.
+    pub fn reload_policy(&self, sources: Vec<Source>) -> PolarResult<()> {
+        self.reload(sources.clone(), &sources)
+    }
+
+    /// Load `sources` in place of the ones loaded now, keeping only the inline queries of
+    /// `added`. The new policy is loaded into a copy of the KB that replaces it only if the load
+    /// succeeds, all under one write lock, so queries see either the old policy or the new one.
+    fn reload(&self, sources: Vec<Source>, added: &[Source]) -> PolarResult<()> {
+        let mut kb = self.kb.write().unwrap();
+        let mut reloaded = kb.clone();
+        reloaded.clear_rules();
+
+        let (mut errors, mut warnings) = (vec![], vec![]);
+        for diagnostic in self.load_into(&mut reloaded, sources) {
+            match diagnostic {
+                Diagnostic::Error(e) => errors.push(e),
+                Diagnostic::Warning(w) => warnings.push(w),
+            }
+        }
+        self.messages
+            .extend(warnings.into_iter().map(Message::warning));
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
+        }
+        for fact in kb.added_facts() {
+            reloaded.add_fact(fact.clone())?;
+        }
+
+        reloaded.inline_queries.retain(|query| {
+            let source = query
+                .parsed_context()
+                .map(|context| context.source.as_ref());
+            source.is_some_and(|source| added.contains(source))
+        });
+        *kb = reloaded;
+        Ok(())
+    }
+
+    /// Take a copy of the knowledge base that `restore` can put back, so that a host can load
+    /// changes to the policy, check them with some queries and undo them if they're wrong.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            kb: self.kb.read().unwrap().clone(),
+        }
+    }
+
+    /// Put the knowledge base back the way it was when `snapshot` was taken, including its
+    /// sources, rules, constants and registered classes. The same snapshot can be restored
+    /// more than once.
+    pub fn restore(&self, snapshot: &Snapshot) {
+        *self.kb.write().unwrap() = snapshot.kb.clone();
+    }
+
+    /// Clear the rules, rule types, resource blocks and constants loaded from policies. The
+    /// classes, constants, MROs and unions registered by the host are kept.
     pub fn clear_rules(&self) {
         let mut kb = self.kb.write().unwrap();
         kb.clear_rules();
@@ -206,6 +415,18 @@ impl Polar {
         parser::parse_query(src).map(|term| self.new_query_from_term(term, trace))
     }
 
+    /// Query the rule `name` with `args`, a tuple of values like `("alice", "read", doc)`. The
+    /// arguments are passed as terms rather than parsed, so strings in them can't change the
+    /// query.
+    pub fn query_rule(&self, name: &str, args: impl ToPolarArgs) -> Query {
+        let call = Value::Call(Call {
+            name: Symbol::new(name),
+            args: args.to_polar_args(),
+            kwargs: None,
+        });
+        self.new_query_from_term(Term::from(call), false)
+    }
+
     pub fn new_query_from_term(&self, mut term: Term, trace: bool) -> Query {
         use crate::vm::{Goal, PolarVirtualMachine};
         {
@@ -213,9 +434,93 @@ impl Polar {
             term = rewrite_term(term, &kb);
         }
         let query = Goal::Query { term: term.clone() };
-        let vm =
+        let mut vm =
             PolarVirtualMachine::new(self.kb.clone(), trace, vec![query], self.messages.clone());
-        Query::new(vm, term)
+        vm.set_clock(self.clock.clone());
+        if let Some(timeout_ms) = self.query_timeout_ms {
+            vm.set_query_timeout(timeout_ms);
+        }
+        vm.set_step_limit(self.step_limit);
+        vm.set_memory_limit(self.memory_limit);
+        vm.set_depth_limit(self.depth_limit);
+        vm.set_tabling(self.tabling);
+        vm.set_parallel_disjuncts(self.parallel_disjuncts);
+        vm.set_isolate_application_errors(self.isolate_application_errors);
+        vm.set_batch_external_calls(self.batch_external_calls);
+        vm.set_cache_lookups(self.cache_lookups);
+        let mut query = Query::new(vm, term);
+        if let Some(hook) = &self.external_hook {
+            query.set_external_hook(hook.clone());
+        }
+        query
+    }
+
+    /// Parse and run `src`, answering the events that need the host with `handler`, and iterate
+    /// over the bindings of its results. The iteration ends after the first error.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn query_with<H: QueryHandler>(
+        &self,
+        src: &str,
+        mut handler: H,
+    ) -> PolarResult<Results<impl FnMut(&mut Query, QueryEvent) -> PolarResult<()>>> {
+        let query = self.new_query(src, false)?;
+        Ok(query.results(move |query, event| query.answer(&mut handler, event)))
+    }
+
+    /// Add a fact, like `session_role("alice", "admin")`, to the loaded policy without
+    /// reloading it. Clearing the rules removes added facts too.
+    pub fn add_fact(&self, fact: Term) -> PolarResult<()> {
+        let fact = fact_rule(fact)?;
+        self.kb.write().unwrap().add_fact(fact)
+    }
+
+    /// Add `rule`, e.g. one made with `builder::RuleBuilder`, to the loaded policy without
+    /// reloading it, as `add_fact` adds a fact. The rule is checked for singleton variables and
+    /// against the rule types with its name, and rewritten, as it would be if it were loaded.
+    pub fn add_rule(&self, rule: Rule) -> PolarResult<()> {
+        let mut kb = self.kb.write().unwrap();
+        let mut warnings = vec![];
+        for diagnostic in check_singletons(&rule, &kb) {
+            match diagnostic {
+                Diagnostic::Error(e) => return Err(e),
+                Diagnostic::Warning(w) => warnings.push(Message::warning(w)),
+            }
+        }
+        let rule = rewrite_rule(rule, &kb);
+        kb.add_fact(rule)?;
+        self.messages.extend(warnings);
+        Ok(())
+    }
+
+    /// Remove a fact that was added with `add_fact` or loaded as part of the policy. Returns
+    /// whether there was one. If the same fact is there more than once, the earliest one loaded
+    /// or added is removed.
+    pub fn remove_fact(&self, fact: Term) -> PolarResult<bool> {
+        let fact = fact_rule(fact)?;
+        Ok(self.kb.write().unwrap().remove_fact(&fact))
+    }
+
+    /// The loaded rules, ordered by name and then in the order they were loaded, e.g. for
+    /// tooling to list them or to check that a deployment loaded the rules it expects.
+    pub fn rules(&self) -> Vec<RuleInfo> {
+        let kb = self.kb.read().unwrap();
+        let mut generic_rules = kb.get_rules().values().collect::<Vec<_>>();
+        generic_rules.sort_by_key(|generic_rule| generic_rule.name.as_str());
+        let mut rules = vec![];
+        for generic_rule in generic_rules {
+            let mut ids = generic_rule.rules.keys().collect::<Vec<_>>();
+            ids.sort();
+            rules.extend(ids.into_iter().map(|id| generic_rule.rules[id].info()));
+        }
+        rules
+    }
+
+    /// The loaded rules with an annotation `name` whose arguments start with `args`, like the
+    /// rules annotated `@control("AC-3")` for `annotated_rules("control", &[term!("AC-3")])`.
+    pub fn annotated_rules(&self, name: &str, args: &[Term]) -> Vec<RuleInfo> {
+        let mut rules = self.rules();
+        rules.retain(|rule| rule.has_annotation(name, args));
+        rules
     }
 
     // @TODO: Direct load_rules endpoint.
@@ -238,10 +543,35 @@ impl Polar {
         self.kb.write().unwrap().add_mro(name, mro)
     }
 
+    /// Register a union specializer `name` that matches instances of any of
+    /// the registered classes in `members`.
+    pub fn register_union(&self, name: Symbol, members: Vec<Symbol>) -> PolarResult<()> {
+        self.kb.write().unwrap().register_union(name, members)
+    }
+
+    /// Register a built-in predicate `name` with `arity` arguments, which queries evaluate by
+    /// calling `f` with the arguments of a call instead of asking the host. `f` returns an error
+    /// message if the arguments aren't valid. A rule with the same name takes precedence over
+    /// it, like for the VM's own built-ins.
+    pub fn register_builtin<F>(&self, name: &str, arity: usize, f: F) -> PolarResult<()>
+    where
+        F: Fn(&[Term]) -> Result<BuiltinAnswer, String> + Send + Sync + 'static,
+    {
+        let builtin = NativeBuiltin::new(name, arity, f);
+        self.kb.write().unwrap().register_builtin(builtin)
+    }
+
+    /// The oldest message that loading a policy or running a query has sent and the host
+    /// hasn't read yet: the output of `print`, logs when `POLAR_LOG` is set, and warnings.
     pub fn next_message(&self) -> Option<Message> {
         self.messages.next()
     }
 
+    /// Take all of the messages that haven't been read yet, oldest first.
+    pub fn drain_messages(&self) -> Vec<Message> {
+        self.messages.drain()
+    }
+
     pub fn build_filter_plan(
         &self,
         types: Types,
@@ -268,6 +598,138 @@ impl Polar {
     pub fn set_ignore_no_allow_warning(&mut self, ignore: bool) {
         self.ignore_no_allow_warning = ignore;
     }
+
+    /// Call `hook` around the external calls and constructors of queries made after this call.
+    pub fn set_external_hook(&mut self, hook: Arc<dyn ExternalHook>) {
+        self.external_hook = Some(hook);
+    }
+
+    /// Replace the clock that the `now` built-in reads in queries made after this call.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Time out queries made after this call once they've run for `timeout_ms` milliseconds,
+    /// instead of the `POLAR_TIMEOUT_MS` environment variable or its default. 0 disables the
+    /// timeout.
+    pub fn set_query_timeout(&mut self, timeout_ms: u64) {
+        self.query_timeout_ms = Some(timeout_ms);
+    }
+
+    /// Fail queries made after this call once they've run `limit` goals, e.g. because of an
+    /// accidental infinite recursion. 0, the default, removes the limit.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = limit;
+    }
+
+    /// Fail queries made after this call once they use more than an estimated `limit` bytes
+    /// for their goals, choice points and bindings, instead of letting a runaway policy exhaust
+    /// the process's memory. 0, the default, removes the limit.
+    pub fn set_memory_limit(&mut self, limit: u64) {
+        self.memory_limit = limit;
+    }
+
+    /// Fail queries made after this call with a `StackOverflow` error naming the recursing rule
+    /// once rule calls are nested more than `limit` deep. Tail calls don't count, since they
+    /// return before the call they end. 0, the default, removes the limit.
+    pub fn set_depth_limit(&mut self, limit: usize) {
+        self.depth_limit = limit;
+    }
+
+    /// Table every rule in queries made after this call, as if each were declared with `table`.
+    /// Each variant of a call is then evaluated once per query, and only its distinct answers
+    /// are returned.
+    pub fn set_tabling(&mut self, tabling: bool) {
+        self.tabling = tabling;
+    }
+
+    /// Evaluate the disjuncts of queries made after this call on a thread pool when a query is
+    /// a disjunction of ones that share no unbound variables, like `f(x) or g(y)`. Results are
+    /// returned in the same order as without it, and the steps and memory used on the threads
+    /// count toward the query's limits. A disjunct that needs the host, or that fails with an
+    /// error, is evaluated on the calling thread from where its thread stopped.
+    pub fn set_parallel_disjuncts(&mut self, parallel: bool) {
+        self.parallel_disjuncts = parallel;
+    }
+
+    /// Fail only the branch that made an external call when the host answers it with
+    /// `external_call_error`, in queries made after this call, instead of ending the query with
+    /// the error. The error is sent as a warning message instead, and a policy can treat a
+    /// lookup that raised like one that found nothing.
+    pub fn set_isolate_application_errors(&mut self, isolate: bool) {
+        self.isolate_application_errors = isolate;
+    }
+
+    /// Send the host the calls that don't depend on each other's answers in one
+    /// `QueryEvent::ExternalBatch`, in queries made after this call, instead of one event for
+    /// each: the lookups of the fields of a pattern on the same instance, and the
+    /// `ExternalIsSubSpecializer` checks to sort the rules of a call. This saves round trips
+    /// where each is expensive, at the cost of looking up fields after one that doesn't match.
+    pub fn set_batch_external_calls(&mut self, batch: bool) {
+        self.batch_external_calls = batch;
+    }
+
+    /// Reuse the host's first answer to each external lookup for the rest of the query, in
+    /// queries made after this call, keyed by the instance, the attribute or method and the
+    /// arguments. Only for hosts whose lookups are pure: an attribute that changes during a
+    /// query keeps its first value. Answers that were errors aren't reused.
+    pub fn set_cache_lookups(&mut self, cache: bool) {
+        self.cache_lookups = cache;
+    }
+
+    /// Report that the host raised `error` while answering the external call or iteration
+    /// `call_id` of `query`, as `Query::external_call_error` does.
+    pub fn external_call_error(
+        &self,
+        query: &mut Query,
+        call_id: u64,
+        error: String,
+    ) -> PolarResult<()> {
+        query.external_call_error(call_id, error)
+    }
+}
+
+/// Convert a call without variables into a rule without a body.
+fn fact_rule(fact: Term) -> PolarResult<Rule> {
+    let call = fact.as_call()?;
+    let mut vars = HashSet::new();
+    fact.variables(&mut vars);
+    let rule = Rule {
+        name: call.name.clone(),
+        params: call
+            .args
+            .iter()
+            .map(|arg| Parameter {
+                parameter: arg.clone(),
+                specializer: None,
+            })
+            .collect(),
+        body: fact.clone_with_value(Value::Expression(op!(And))),
+        source_info: SourceInfo::ffi(),
+        required: false,
+        doc: None,
+        annotations: vec![],
+        program: Default::default(),
+    };
+    if call.kwargs.is_some() || !vars.is_empty() {
+        let msg = "Facts cannot contain variables or keyword arguments.".to_owned();
+        let rule = Box::new(rule);
+        return Err(ValidationError::InvalidRule { rule, msg }.into());
+    }
+    Ok(rule)
+}
+
+fn read_source(path: &Path) -> PolarResult<Source> {
+    let filename = path.to_string_lossy().into_owned();
+    match std::fs::read_to_string(path) {
+        Ok(src) => Ok(Source::new_with_name(filename, src)),
+        Err(e) => Err(ValidationError::FileLoading {
+            filename,
+            contents: String::new(),
+            msg: e.to_string(),
+        }
+        .into()),
+    }
 }
 
 #[cfg(test)]
@@ -388,10 +850,33 @@ mod tests {
         let kb = polar.kb.read().unwrap();
         let rules = kb.get_rules().values().flat_map(|g| g.rules.values());
         let has_permission_rules = rules
-            .filter(|r| r.name.0 == "has_permission")
+            .filter(|r| r.name.as_str() == "has_permission")
             .collect::<Vec<_>>();
         assert_eq!(has_permission_rules.len(), 1, "{:#?}", has_permission_rules);
         let has_permission_rule = has_permission_rules.into_iter().next().unwrap();
         assert_eq!(has_permission_rule.params[1].parameter, term!("till"));
     }
+
+    #[test]
+    fn load_files_names_the_file_of_an_error() {
+        let dir = std::env::temp_dir().join(format!("polar-load-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.polar"), dir.join("b.polar"));
+        std::fs::write(&a, "f(1);").unwrap();
+        std::fs::write(&b, "g(1);\ng(x) if y;").unwrap();
+
+        let polar = Polar::new();
+        let e = polar.load_files(&[&a, &b]).unwrap_err();
+        let position = format!("at line 2, column 3 of file {}", b.display());
+        assert!(e.to_string().contains(&position), "{}", e);
+
+        let missing = dir.join("missing.polar");
+        let e = polar.load_file(&missing).unwrap_err();
+        assert!(matches!(e.unwrap_validation(), FileLoading { .. }));
+
+        std::fs::write(&b, "g(x) if f(x);").unwrap();
+        polar.load_files(&[&a, &b]).unwrap();
+        assert_eq!(polar.kb.read().unwrap().sources().len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,322 @@
+//! Rules compiled, when they're loaded, into the goals that a call to them runs.
+//!
+//! Calling a rule gives each of its variables a fresh name, so that the call's bindings don't
+//! clash with those of other calls to it. A `Program` numbers the variables of its rule once, in
+//! the order they appear, and keeps the name that each one's temporaries share, like `_x_` for
+//! `x`. A call then only takes a number for each variable and copies the parts of the rule that
+//! have any, without building names or a map of them.
+
+use std::collections::HashMap;
+
+use crate::folder::{fold_term, Folder};
+use crate::kb::KnowledgeBase;
+use crate::rules::Rule;
+use crate::terms::{Symbol, Term, Value};
+use crate::visitor::{walk_term, Visitor};
+use crate::vm::Goal;
+
+/// A step of a call to a rule.
+#[derive(Clone, Debug)]
+enum Instruction {
+    /// Unify the argument in position `arg` with the parameter `param`.
+    Unify { arg: usize, param: Term },
+    /// Check that the argument in position `arg` matches `specializer`.
+    Isa {
+        arg: usize,
+        param: Term,
+        specializer: Term,
+    },
+    /// Query the body of the rule.
+    Query { body: Term },
+}
+
+/// A rule compiled into the instructions that a call to it runs.
+#[derive(Clone, Debug)]
+pub struct Program {
+    /// The rule's variables in the order they appear, and the temporary each is renamed to,
+    /// numbered 0.
+    vars: Vec<(Symbol, Symbol)>,
+    /// The position of each variable in `vars`.
+    slots: HashMap<Symbol, usize>,
+    /// The number of variables that appear in the rule's parameters, which come first.
+    head_vars: usize,
+    instructions: Vec<Instruction>,
+}
+
+impl Program {
+    pub fn compile(rule: &Rule) -> Self {
+        let mut slots = Slots::default();
+        for param in &rule.params {
+            slots.visit_param(param);
+        }
+        let head_vars = slots.vars.len();
+        slots.visit_term(&rule.body);
+
+        let mut instructions = vec![];
+        for (arg, param) in rule.params.iter().enumerate() {
+            instructions.push(Instruction::Unify {
+                arg,
+                param: param.parameter.clone(),
+            });
+            if let Some(specializer) = &param.specializer {
+                instructions.push(Instruction::Isa {
+                    arg,
+                    param: param.parameter.clone(),
+                    specializer: specializer.clone(),
+                });
+            }
+        }
+        instructions.push(Instruction::Query {
+            body: rule.body.clone(),
+        });
+
+        Self {
+            vars: slots
+                .vars
+                .into_iter()
+                .map(|var| {
+                    let temporary = var.temporary(0);
+                    (var, temporary)
+                })
+                .collect(),
+            slots: slots.slots,
+            head_vars,
+            instructions,
+        }
+    }
+
+    /// The goals of a call to the rule with `args`: unify them with its parameters, check them
+    /// against its specializers, and query its body.
+    pub fn call(&self, kb: &KnowledgeBase, args: &[Term]) -> Vec<Goal> {
+        let mut renamer = self.renamer(kb, self.vars.len());
+        self.instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Unify { arg, param } => Goal::Unify {
+                    left: args[*arg].clone(),
+                    right: renamer.fold_term(param.clone()),
+                },
+                Instruction::Isa {
+                    param, specializer, ..
+                } => Goal::Isa {
+                    left: renamer.fold_term(param.clone()),
+                    right: renamer.fold_term(specializer.clone()),
+                },
+                Instruction::Query { body } => Goal::Query {
+                    term: renamer.fold_term(body.clone()),
+                },
+            })
+            .collect()
+    }
+
+    /// The goals that hold if the rule applies to `args`: unify them with its parameters and
+    /// check them against its specializers. Only the variables of the parameters are renamed.
+    pub fn check(&self, kb: &KnowledgeBase, args: &[Term]) -> Vec<Goal> {
+        let mut renamer = self.renamer(kb, self.head_vars);
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Unify { arg, param } => Some(Goal::Unify {
+                    left: args[*arg].clone(),
+                    right: renamer.fold_term(param.clone()),
+                }),
+                Instruction::Isa {
+                    arg, specializer, ..
+                } => Some(Goal::Isa {
+                    left: args[*arg].clone(),
+                    right: renamer.fold_term(specializer.clone()),
+                }),
+                Instruction::Query { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Rename the first `count` variables to fresh temporaries. Constants keep their names.
+    fn renamer(&self, kb: &KnowledgeBase, count: usize) -> Renamer<'_> {
+        let renames = self.vars[..count]
+            .iter()
+            .map(|(var, temporary)| {
+                let rename = if kb.is_constant(var) {
+                    var.clone()
+                } else {
+                    temporary.renumbered(kb.next_temporary())
+                };
+                (rename, None)
+            })
+            .collect();
+        Renamer {
+            slots: &self.slots,
+            renames,
+        }
+    }
+}
+
+/// Number the variables of a rule in the order they appear.
+#[derive(Default)]
+struct Slots {
+    vars: Vec<Symbol>,
+    slots: HashMap<Symbol, usize>,
+}
+
+impl Slots {
+    fn add(&mut self, var: &Symbol) {
+        if !self.slots.contains_key(var) {
+            self.slots.insert(var.clone(), self.vars.len());
+            self.vars.push(var.clone());
+        }
+    }
+}
+
+impl Visitor for Slots {
+    fn visit_variable(&mut self, v: &Symbol) {
+        self.add(v)
+    }
+
+    fn visit_rest_variable(&mut self, r: &Symbol) {
+        self.add(r)
+    }
+
+    fn visit_term(&mut self, t: &Term) {
+        if !t.is_variable_free() {
+            walk_term(self, t)
+        }
+    }
+}
+
+/// Replace the variables of a rule with the temporaries of a call to it.
+struct Renamer<'a> {
+    slots: &'a HashMap<Symbol, usize>,
+    /// The temporary of each variable, and the value that the occurrences of it share once it's
+    /// been made.
+    renames: Vec<(Symbol, Option<Term>)>,
+}
+
+impl Renamer<'_> {
+    fn slot(&mut self, var: &Symbol) -> Option<&mut (Symbol, Option<Term>)> {
+        let slot = *self.slots.get(var)?;
+        self.renames.get_mut(slot)
+    }
+
+    fn rename(&mut self, var: Symbol) -> Symbol {
+        match self.slot(&var) {
+            Some((rename, _)) => rename.clone(),
+            None => var,
+        }
+    }
+}
+
+impl Folder for Renamer<'_> {
+    /// Share the parts of a rule without variables with the rule in the knowledge base, and the
+    /// value of each temporary between the occurrences of its variable.
+    fn fold_term(&mut self, t: Term) -> Term {
+        if t.is_variable_free() {
+            return t;
+        }
+        if let Value::Variable(var) = t.value() {
+            if let Some((rename, value)) = self.slot(var) {
+                let value = value
+                    .get_or_insert_with(|| t.clone_with_value(Value::Variable(rename.clone())));
+                return t.with_value_of(value);
+            }
+        }
+        fold_term(t, self)
+    }
+
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        self.rename(v)
+    }
+
+    fn fold_rest_variable(&mut self, r: Symbol) -> Symbol {
+        self.rename(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rewrites::{unwrap_and, Renamer as RuleRenamer};
+
+    fn parse_rule(src: &str) -> Rule {
+        crate::parser::parse_rules(src).unwrap().pop().unwrap()
+    }
+
+    fn goals(goals: Vec<Goal>) -> Vec<String> {
+        goals.iter().map(|goal| goal.to_string()).collect()
+    }
+
+    #[test]
+    fn test_call_renames_like_the_renamer() {
+        let rule = parse_rule("f(x: Integer, [y, *z], 1) if g(x, y) and w = z and x = w;");
+        let program = Program::compile(&rule);
+
+        let kb = KnowledgeBase::new();
+        let args = vec![term!(sym!("a")), term!(sym!("b")), term!(sym!("c"))];
+        let called = goals(program.call(&kb, &args));
+
+        let kb = KnowledgeBase::new();
+        let renamed = RuleRenamer::new(&kb).fold_rule(rule);
+        let mut expected = vec![];
+        for (arg, param) in args.iter().zip(&renamed.params) {
+            expected.push(Goal::Unify {
+                left: arg.clone(),
+                right: param.parameter.clone(),
+            });
+            if let Some(specializer) = &param.specializer {
+                expected.push(Goal::Isa {
+                    left: param.parameter.clone(),
+                    right: specializer.clone(),
+                });
+            }
+        }
+        expected.push(Goal::Query { term: renamed.body });
+        assert_eq!(called, goals(expected));
+        assert_eq!(
+            called[4],
+            "Query(g(_x_1, _y_2) and _w_4 = _z_3 and _x_1 = _w_4)"
+        );
+    }
+
+    #[test]
+    fn test_call_shares_the_values_of_temporaries() {
+        let rule = parse_rule("f(x) if g(x, y) and y = x;");
+        let program = Program::compile(&rule);
+        let kb = KnowledgeBase::new();
+        let goals = program.call(&kb, &[term!(1)]);
+        let (param, body) = match (&goals[0], &goals[1]) {
+            (Goal::Unify { right, .. }, Goal::Query { term }) => (right, unwrap_and(term)),
+            _ => panic!("unexpected goals"),
+        };
+        let g = body[0].as_call().unwrap();
+        let unify = body[1].as_expression().unwrap();
+        assert!(std::ptr::eq(param.value(), g.args[0].value()));
+        assert!(std::ptr::eq(param.value(), unify.args[1].value()));
+        assert!(std::ptr::eq(g.args[1].value(), unify.args[0].value()));
+        assert_eq!(unify.args[0].to_string(), "_y_2");
+    }
+
+    #[test]
+    fn test_check_renames_the_parameters() {
+        let rule = parse_rule("f(x: Integer, y) if z = x + y;");
+        let program = Program::compile(&rule);
+        let kb = KnowledgeBase::new();
+        let args = vec![term!(1), term!(2)];
+        assert_eq!(
+            goals(program.check(&kb, &args)),
+            ["Unify(1, _x_1)", "Isa(1, Integer{})", "Unify(2, _y_2)"]
+        );
+        assert_eq!(
+            goals(program.call(&kb, &args))[3],
+            "Query(_z_5 = _x_3 + _y_4)"
+        );
+    }
+
+    #[test]
+    fn test_constants_keep_their_names() {
+        let rule = parse_rule("f(x) if x = C;");
+        let program = Program::compile(&rule);
+        let mut kb = KnowledgeBase::new();
+        kb.register_constant(sym!("C"), term!(1)).unwrap();
+        assert_eq!(goals(program.call(&kb, &[term!(1)]))[1], "Query(_x_1 = C)");
+    }
+}
@@ -1,27 +1,188 @@
-use super::error::PolarResult;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::bindings::Bindings;
+use super::deserialize::from_bindings;
+use super::error::{invalid_state, PolarResult, RuntimeError};
 use super::events::*;
 use super::messages::*;
 use super::runnable::Runnable;
+use super::stats::QueryStats;
 use super::terms::*;
+use super::traces::Explanation;
 use super::vm::*;
 
+/// The direction to order results in with `Query::set_result_order`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+/// A handle that cancels the query it was taken from, from any thread. The query fails with
+/// `RuntimeError::Cancelled` at its next step, or the next time it's asked for an event.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    /// The token of the query that this one's query is part of.
+    parent: Option<Box<CancelToken>>,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(atomic::Ordering::Relaxed)
+            || matches!(&self.parent, Some(parent) if parent.is_cancelled())
+    }
+
+    /// A token for a query that's part of this one's, which is cancelled along with it but can
+    /// also be cancelled on its own.
+    pub(crate) fn child(&self) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+}
+
+/// Where a query is between two events, as `Query::state` sees it.
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryState {
+    /// The values of the variables that aren't temporary, including the renamed variables of the
+    /// rules that are running.
+    pub bindings: Bindings,
+    /// The goals left to run, the next one first.
+    pub goals: Vec<String>,
+    /// The number of alternatives left at each choice point the query can backtrack to, the
+    /// most recent first.
+    pub choices: Vec<usize>,
+}
+
+/// A query, which returns results in the order they're found unless it's asked to order them
+/// otherwise: depth first, trying the rules for a call from most to least specific and rules
+/// that are equally specific in the order they were loaded.
 pub struct Query {
     runnable_stack: Vec<(Box<dyn Runnable>, u64)>, // Tuple of Runnable + call_id.
     vm: PolarVirtualMachine,
     term: Term,
     done: bool,
+    /// The variable to order results by, and in which direction.
+    order: Option<(Symbol, Order)>,
+    /// Results held back until the query is done so they can be ordered.
+    unordered: Vec<QueryEvent>,
+    /// The ordered results, followed by `Done`.
+    ordered: VecDeque<QueryEvent>,
+    /// The number of results to return before the query pauses, if there's a limit. The VM stops
+    /// at it, unless results are ordered.
+    limit: Option<usize>,
+    /// The number of results returned so far.
+    returned: usize,
+    hook: Option<Arc<dyn ExternalHook>>,
+    /// The external calls and constructors the host hasn't finished, with the times they were
+    /// returned to it.
+    pending: Vec<(QueryEvent, Timestamp)>,
 }
 
 impl Query {
     pub fn new(vm: PolarVirtualMachine, term: Term) -> Self {
+        vm.add_instances(&term);
         Self {
             runnable_stack: vec![],
             vm,
             term,
             done: false,
+            order: None,
+            unordered: vec![],
+            ordered: VecDeque::new(),
+            limit: None,
+            returned: 0,
+            hook: None,
+            pending: vec![],
         }
     }
 
+    /// Call `hook` around each external call and constructor that the query asks the host for,
+    /// in place of the hook of the `Polar` that made it, if it has one.
+    pub fn set_external_hook(&mut self, hook: Arc<dyn ExternalHook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Return at most `limit` results, and then `Done` without looking for more, so a query
+    /// over a large domain only does the work for the results it returns. The VM keeps its
+    /// place, so `resume` can return the next page of results after them. Results that are
+    /// ordered with `set_result_order` are all found first, and then only `limit` are returned.
+    pub fn set_result_limit(&mut self, limit: usize) {
+        self.limit = Some(limit);
+        self.limit_vm();
+    }
+
+    /// Return up to `more` results after the ones already returned, carrying on from where the
+    /// query stopped at its limit.
+    pub fn resume(&mut self, more: usize) {
+        self.limit = Some(self.returned + more);
+        self.done = false;
+        self.limit_vm();
+    }
+
+    /// Pass the result limit to the VM, which stops at it without backtracking for the next
+    /// result. Ordered results must all be found first, so their limit is kept here.
+    fn limit_vm(&mut self) {
+        let limit = self.limit.filter(|_| self.order.is_none());
+        self.vm.set_result_limit(limit);
+    }
+
+    /// Whether the query stopped at its result limit rather than running out of results, so
+    /// `resume` might return more.
+    pub fn limit_reached(&self) -> bool {
+        self.limit.is_some_and(|limit| self.returned >= limit)
+    }
+
+    /// Return results ordered by the value of `var`, in `order`. Numbers come before strings
+    /// either way, and results in which `var` is bound to anything else or isn't bound come last,
+    /// in the order they were found. Results with equal values stay in the order they were
+    /// found too. No result is returned until the query is done.
+    pub fn set_result_order(&mut self, var: Symbol, order: Order) {
+        self.order = Some((var, order));
+        self.limit_vm();
+    }
+
+    /// Override the query timeout of the `Polar` that made this query, in milliseconds. 0
+    /// disables the timeout.
+    pub fn set_timeout(&mut self, timeout_ms: u64) {
+        self.vm.set_query_timeout(timeout_ms);
+    }
+
+    /// Override the step limit of the `Polar` that made this query. 0 removes the limit.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.vm.set_step_limit(limit);
+    }
+
+    /// Override the memory limit of the `Polar` that made this query, in bytes. 0 removes the
+    /// limit.
+    pub fn set_memory_limit(&mut self, limit: u64) {
+        self.vm.set_memory_limit(limit);
+    }
+
+    /// A token that cancels this query, which can be sent to another thread.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.vm.cancel_token()
+    }
+
+    /// Override the depth limit of the `Polar` that made this query. 0 removes the limit.
+    pub fn set_depth_limit(&mut self, limit: usize) {
+        self.vm.set_depth_limit(limit);
+    }
+
     #[cfg(target_arch = "wasm32")]
     pub fn set_logging_options(&mut self, rust_log: Option<String>, polar_log: Option<String>) {
         self.vm.set_logging_options(rust_log, polar_log);
@@ -35,33 +196,138 @@ impl Query {
     /// 4. When Runnable B emits a Done event, pop Runnable B off the stack and return its result as
     ///    an answer to Runnable A.
     pub fn next_event(&mut self) -> PolarResult<QueryEvent> {
-        let mut counter = self.vm.id_counter();
-        let qe = match self.top_runnable().run(Some(&mut counter)) {
-            Ok(e) => e,
-            Err(e) => self.top_runnable().handle_error(e)?,
+        self.vm.check_cancelled()?;
+        // The host has answered every call it was asked for by now, or given up on them.
+        self.finish_external(None, None);
+        loop {
+            let event = self.run()?;
+            if let QueryEvent::Result { .. } = event {
+                self.returned += 1;
+            }
+            if let Some(event) = self.start_external(event)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Call the hook before the host gets `event`, if it's a call or constructor, and return
+    /// the event for the host unless the hook answered it.
+    fn start_external(&mut self, event: QueryEvent) -> PolarResult<Option<QueryEvent>> {
+        let Some(hook) = self.hook.clone() else {
+            return Ok(Some(event));
         };
-        self.recv_event(qe)
+        match &event {
+            QueryEvent::ExternalCall { call_id, .. } => {
+                if let Some(value) = hook.before(&event) {
+                    self.call_result(*call_id, Some(value))?;
+                    return Ok(None);
+                }
+            }
+            QueryEvent::MakeExternal { .. } => {
+                hook.before(&event);
+            }
+            _ => return Ok(Some(event)),
+        }
+        self.pending.push((event.clone(), timestamp()));
+        Ok(Some(event))
+    }
+
+    /// Call the hook after the host finishes the call `call_id` with `value`, or after it's
+    /// finished all of them if there's no `call_id`.
+    fn finish_external(&mut self, call_id: Option<u64>, value: Option<&Term>) {
+        let Some(hook) = &self.hook else {
+            return;
+        };
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut i = 0;
+        while i < self.pending.len() {
+            let finished = match (&self.pending[i].0, call_id) {
+                (QueryEvent::ExternalCall { call_id: id, .. }, Some(call_id)) => *id == call_id,
+                (_, call_id) => call_id.is_none(),
+            };
+            if finished {
+                let (event, start) = self.pending.remove(i);
+                hook.after(&event, value, elapsed_since(start));
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Run the query until it has an event for the host.
+    fn run(&mut self) -> PolarResult<QueryEvent> {
+        if let Some(event) = self.next_ordered() {
+            return Ok(event);
+        }
+        // Loop rather than recurse for events the host doesn't see, since a query can start
+        // and finish any number of runnables before it returns one.
+        loop {
+            let mut counter = self.vm.id_counter();
+            let qe = match self.top_runnable().run(Some(&mut counter)) {
+                Ok(e) => e,
+                Err(e) => self.top_runnable().handle_error(e)?,
+            };
+            if let Some(ev) = self.recv_event(qe)?.and_then(|ev| self.order_event(ev)) {
+                return Ok(ev);
+            }
+        }
     }
 
-    fn recv_event(&mut self, qe: QueryEvent) -> PolarResult<QueryEvent> {
+    /// The event to return to the host for `qe`, if any.
+    fn recv_event(&mut self, qe: QueryEvent) -> PolarResult<Option<QueryEvent>> {
         match qe {
-            QueryEvent::None => self.next_event(),
+            QueryEvent::None => Ok(None),
             QueryEvent::Run { runnable, call_id } => {
                 self.push_runnable(runnable, call_id);
-                self.next_event()
+                Ok(None)
             }
             QueryEvent::Done { result } => {
                 if let Some((_, result_call_id)) = self.pop_runnable() {
                     self.top_runnable()
                         .external_question_result(result_call_id, result)?;
-                    self.next_event()
+                    Ok(None)
                 } else {
                     // VM is done.
                     assert!(self.runnable_stack.is_empty());
-                    Ok(QueryEvent::Done { result })
+                    Ok(Some(QueryEvent::Done { result }))
                 }
             }
-            ev => Ok(ev),
+            ev => Ok(Some(ev)),
+        }
+    }
+
+    /// The event to return in place of `event` if results are to be ordered: results are held
+    /// back until the query is done, and then returned in order before `Done`.
+    fn order_event(&mut self, event: QueryEvent) -> Option<QueryEvent> {
+        let Some((var, order)) = self.order.clone() else {
+            return Some(event);
+        };
+        match event {
+            QueryEvent::Result { .. } => {
+                self.unordered.push(event);
+                None
+            }
+            QueryEvent::Done { .. } => {
+                let mut results = std::mem::take(&mut self.unordered);
+                results.sort_by(|a, b| compare_results(a, b, &var, order));
+                self.ordered.extend(results);
+                self.ordered.push_back(event);
+                self.next_ordered()
+            }
+            event => Some(event),
+        }
+    }
+
+    /// The next of the ordered results, or `Done` if the limit has been reached.
+    fn next_ordered(&mut self) -> Option<QueryEvent> {
+        if self.ordered.is_empty() {
+            None
+        } else if self.limit_reached() {
+            Some(QueryEvent::Done { result: true })
+        } else {
+            self.ordered.pop_front()
         }
     }
 
@@ -81,6 +347,7 @@ impl Query {
     }
 
     pub fn call_result(&mut self, call_id: u64, value: Option<Term>) -> PolarResult<()> {
+        self.finish_external(Some(call_id), value.as_ref());
         self.top_runnable().external_call_result(call_id, value)
     }
 
@@ -89,10 +356,25 @@ impl Query {
             .external_question_result(call_id, result)
     }
 
+    /// Answer the calls of an `ExternalBatch` with `answers`, one for each call in the same
+    /// order.
+    pub fn batch_result(&mut self, answers: Vec<ExternalAnswer>) -> PolarResult<()> {
+        self.top_runnable().external_batch_result(answers)
+    }
+
     pub fn application_error(&mut self, message: String) -> PolarResult<()> {
         self.vm.external_error(message)
     }
 
+    /// Answer the external call or iteration `call_id` with `message`, an error the host raised
+    /// while looking up an attribute, calling a method or getting the next value. The query
+    /// ends with an application error, or if application errors are isolated, only the branch
+    /// that made the call fails, as `Polar::set_isolate_application_errors` configures.
+    pub fn external_call_error(&mut self, call_id: u64, message: String) -> PolarResult<()> {
+        self.finish_external(Some(call_id), None);
+        self.top_runnable().external_call_error(call_id, message)
+    }
+
     pub fn debug_command(&mut self, command: &str) -> PolarResult<()> {
         self.top_runnable().debug_command(command)
     }
@@ -101,13 +383,481 @@ impl Query {
         self.vm.messages.next()
     }
 
+    /// Take all of the messages the query has sent that haven't been read yet, oldest first.
+    pub fn drain_messages(&self) -> Vec<Message> {
+        self.vm.messages.drain()
+    }
+
+    /// Record why the query fails, for `explanation`. A query that isn't explained doesn't pay
+    /// for the bookkeeping.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.vm.set_explain(explain);
+    }
+
+    /// For each rule applied to the query, the deepest goal that failed while the rule was
+    /// tried, if the query is explained. Once the query is done, this tells an application why
+    /// nothing was allowed: which rule got furthest, and the condition it couldn't meet.
+    pub fn explanation(&self) -> Option<Explanation> {
+        self.vm.explanation().cloned()
+    }
+
+    /// Look at the query's bindings, goals and choice points, e.g. from a debugger or a test.
+    /// While the query waits on a runnable it started, like a `forall` or a negation, this is the
+    /// state of the query itself rather than the runnable's.
+    pub fn state(&self) -> QueryState {
+        QueryState {
+            bindings: self.vm.bindings(false),
+            goals: self.vm.goals.iter().map(|goal| goal.to_string()).collect(),
+            choices: self.vm.choice_alternatives(),
+        }
+    }
+
+    /// What the query has done so far.
+    pub fn stats(&self) -> QueryStats {
+        self.vm.stats()
+    }
+
+    /// The ids of the instances that `new` made in the query, or that the host made or passed
+    /// in for it, including those of the results.
+    pub fn instances(&self) -> BTreeSet<u64> {
+        self.vm.instances()
+    }
+
+    /// The ids of the instances that `new` made in the query.
+    pub fn made_instances(&self) -> BTreeSet<u64> {
+        self.vm.made_instances()
+    }
+
+    /// The ids of the instances the query made with `new` that the knowledge base doesn't refer
+    /// to in a constant or an added fact or rule, which the host can free once the query is done
+    /// and it's finished with the results. The instances the host made itself, and passed in or
+    /// returned from external calls, are left for it to free.
+    pub fn released_instances(&self) -> Vec<u64> {
+        let kept = self.vm.kb().instance_ids();
+        self.made_instances().difference(&kept).copied().collect()
+    }
+
     pub fn source_info(&self) -> String {
         self.vm.term_source(&self.term, true)
     }
 
+    /// Run the query to the end and deserialize the bindings of each of its results into a `T`,
+    /// with `deserialize::from_bindings`. The query must not need the host: any event other
+    /// than a result is an error.
+    pub fn results_as<T: DeserializeOwned>(&mut self) -> PolarResult<Vec<T>> {
+        let mut results = vec![];
+        for event in self.by_ref() {
+            match event? {
+                QueryEvent::Result { bindings, .. } => results.push(from_bindings(&bindings)?),
+                QueryEvent::Done { .. } => (),
+                event => {
+                    return invalid_state(format!(
+                        "results_as can't answer the query's {:?} event",
+                        event
+                    ))
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Run the query to the end, answering its external calls with the async function `call`,
+    /// and return the bindings of its results. `call` gets the instance, the attribute or method
+    /// name and the method's arguments, and returns the value or an error message for the
+    /// query to raise, so lookups can await a database or an HTTP request without blocking a
+    /// thread. No other event can be answered: it ends the query with an error.
+    ///
+    /// The future doesn't depend on an executor, but it holds the query, which isn't `Send`, so
+    /// on a multithreaded runtime it's run on the current thread, e.g. in a `LocalSet`.
+    pub async fn run_async<F, Fut>(&mut self, mut call: F) -> PolarResult<Vec<Bindings>>
+    where
+        F: FnMut(Term, Symbol, Option<Vec<Term>>) -> Fut,
+        Fut: Future<Output = Result<Option<Term>, String>>,
+    {
+        let mut results = vec![];
+        loop {
+            match self.next_event()? {
+                QueryEvent::Result { bindings, .. } => results.push(bindings),
+                QueryEvent::Done { .. } => return Ok(results),
+                QueryEvent::ExternalCall {
+                    kwargs: Some(_), ..
+                } => self.application_error(
+                    "run_async doesn't pass keyword arguments to calls".to_owned(),
+                )?,
+                QueryEvent::ExternalCall {
+                    call_id,
+                    instance,
+                    attribute,
+                    args,
+                    ..
+                } => match call(instance, attribute, args).await {
+                    Ok(value) => self.call_result(call_id, value)?,
+                    Err(message) => self.application_error(message)?,
+                },
+                event => {
+                    return invalid_state(format!(
+                        "run_async can't answer the query's {:?} event",
+                        event
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Bind the variable `name` in the query to `value` before it runs. A query parsed once with
+    /// `parser::parse_query` can then be made with `Polar::new_query_from_term` and bound to the
+    /// arguments of each request, instead of formatting them into a new query to parse.
     pub fn bind(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
+        self.vm.add_instances(&value);
         self.vm.bind(&name, value)
     }
+
+    /// Iterate over the bindings of each result, passing every other event the query returns to
+    /// `handler`. The handler answers the ones that need it with `call_result`,
+    /// `question_result` and so on, or returns an error to end the iteration with.
+    pub fn results<F>(self, handler: F) -> Results<F>
+    where
+        F: FnMut(&mut Query, QueryEvent) -> PolarResult<()>,
+    {
+        Results {
+            query: self,
+            handler,
+            done: false,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type Timestamp = std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+type Timestamp = f64;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn timestamp() -> Timestamp {
+    std::time::Instant::now()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn timestamp() -> Timestamp {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn elapsed_since(start: Timestamp) -> Duration {
+    start.elapsed()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn elapsed_since(start: Timestamp) -> Duration {
+    Duration::from_millis((js_sys::Date::now() - start).max(0.0) as u64)
+}
+
+/// Hooks around the round trips a query makes to the host, set with `Polar::set_external_hook`,
+/// e.g. to log slow callbacks, collect metrics or cache the results of calls.
+pub trait ExternalHook: Send + Sync {
+    /// Called before the host gets an `ExternalCall` or `MakeExternal` event. Returning a value
+    /// for a call answers it in place of the host, which never sees the event.
+    fn before(&self, event: &QueryEvent) -> Option<Term> {
+        let _ = event;
+        None
+    }
+
+    /// Called once the host has finished with `event`: with the value it answered a call with,
+    /// or with no value for a constructor, a call without a result, or a call that failed with
+    /// an application error. `elapsed` is measured by a monotonic clock, to the millisecond on
+    /// wasm.
+    fn after(&self, event: &QueryEvent, value: Option<&Term>, elapsed: Duration) {
+        let _ = (event, value, elapsed);
+    }
+}
+
+/// The host's side of a query run by `Polar::query_with`, which answers each event that needs the
+/// host with one of these methods. They return an error message for the query to raise, which
+/// is what they all do by default, so a host only implements the ones its queries need.
+pub trait QueryHandler {
+    /// Make an instance of `constructor`, which later events refer to by `instance_id`.
+    fn make_external(&mut self, instance_id: u64, constructor: Term) -> Result<(), String> {
+        let _ = instance_id;
+        Err(format!("can't make an instance of {}", constructor))
+    }
+
+    /// Look up the attribute `attribute` of `instance`, or call its method if there are `args`.
+    fn external_call(
+        &mut self,
+        instance: Term,
+        attribute: Symbol,
+        args: Option<Vec<Term>>,
+        kwargs: Option<BTreeMap<Symbol, Term>>,
+    ) -> Result<Option<Term>, String> {
+        let _ = (args, kwargs);
+        Err(format!("can't look up {} on {}", attribute, instance))
+    }
+
+    fn external_isa(&mut self, instance: Term, class_tag: Symbol) -> Result<bool, String> {
+        Err(format!(
+            "can't check whether {} is a {}",
+            instance, class_tag
+        ))
+    }
+
+    fn external_isa_with_path(
+        &mut self,
+        base_tag: Symbol,
+        path: TermList,
+        class_tag: Symbol,
+    ) -> Result<bool, String> {
+        let _ = path;
+        Err(format!(
+            "can't check whether a field of {} is a {}",
+            base_tag, class_tag
+        ))
+    }
+
+    fn external_is_subspecializer(
+        &mut self,
+        instance_id: u64,
+        left_class_tag: Symbol,
+        right_class_tag: Symbol,
+    ) -> Result<bool, String> {
+        let _ = instance_id;
+        Err(format!(
+            "can't check whether {} is more specific than {}",
+            left_class_tag, right_class_tag
+        ))
+    }
+
+    fn external_is_subclass(
+        &mut self,
+        left_class_tag: Symbol,
+        right_class_tag: Symbol,
+    ) -> Result<bool, String> {
+        Err(format!(
+            "can't check whether {} is a subclass of {}",
+            left_class_tag, right_class_tag
+        ))
+    }
+
+    fn external_op(&mut self, operator: Operator, args: TermList) -> Result<bool, String> {
+        let _ = args;
+        Err(format!("can't apply {:?} to external instances", operator))
+    }
+
+    /// The next value of `iterable`, or `None` once it's exhausted. Each iteration has its own
+    /// `call_id`.
+    fn next_external(&mut self, call_id: u64, iterable: Term) -> Result<Option<Term>, String> {
+        let _ = call_id;
+        Err(format!("can't iterate over {}", iterable))
+    }
+
+    /// The debugger command to run when the query stops at a breakpoint. By default, it
+    /// continues.
+    fn debug(&mut self, message: String) -> String {
+        let _ = message;
+        "continue".to_owned()
+    }
+}
+
+impl Query {
+    /// Answer `event` with `handler`.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn answer<H: QueryHandler>(
+        &mut self,
+        handler: &mut H,
+        event: QueryEvent,
+    ) -> PolarResult<()> {
+        let answer = match event {
+            QueryEvent::None => return Ok(()),
+            QueryEvent::Debug { message, .. } => {
+                let command = handler.debug(message);
+                return self.debug_command(&command);
+            }
+            QueryEvent::MakeExternal {
+                instance_id,
+                constructor,
+            } => {
+                // The VM doesn't check for an application error until its next external call,
+                // so a failed constructor ends the query instead.
+                return handler
+                    .make_external(instance_id, constructor.clone())
+                    .map_err(|msg| {
+                        RuntimeError::Application {
+                            msg,
+                            stack_trace: String::new(),
+                            term: Some(constructor),
+                        }
+                        .into()
+                    });
+            }
+            QueryEvent::ExternalCall {
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs,
+            } => {
+                return match handler.external_call(instance, attribute, args, kwargs) {
+                    Ok(value) => self.call_result(call_id, value),
+                    Err(message) => self.external_call_error(call_id, message),
+                }
+            }
+            QueryEvent::NextExternal { call_id, iterable } => {
+                return match handler.next_external(call_id, iterable) {
+                    Ok(value) => self.call_result(call_id, value),
+                    Err(message) => self.external_call_error(call_id, message),
+                }
+            }
+            QueryEvent::ExternalIsa {
+                call_id,
+                instance,
+                class_tag,
+            } => handler
+                .external_isa(instance, class_tag)
+                .map(|result| self.question_result(call_id, result)),
+            QueryEvent::ExternalIsaWithPath {
+                call_id,
+                base_tag,
+                path,
+                class_tag,
+            } => handler
+                .external_isa_with_path(base_tag, path, class_tag)
+                .map(|result| self.question_result(call_id, result)),
+            QueryEvent::ExternalIsSubSpecializer {
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            } => handler
+                .external_is_subspecializer(instance_id, left_class_tag, right_class_tag)
+                .map(|result| self.question_result(call_id, result)),
+            QueryEvent::ExternalIsSubclass {
+                call_id,
+                left_class_tag,
+                right_class_tag,
+            } => handler
+                .external_is_subclass(left_class_tag, right_class_tag)
+                .map(|result| self.question_result(call_id, result)),
+            QueryEvent::ExternalOp {
+                call_id,
+                operator,
+                args,
+            } => handler
+                .external_op(operator, args)
+                .map(|result| self.question_result(call_id, result)),
+            QueryEvent::ExternalBatch { calls } => {
+                let mut answers = vec![];
+                for call in calls {
+                    answers.push(match call {
+                        QueryEvent::ExternalCall {
+                            instance,
+                            attribute,
+                            args,
+                            kwargs,
+                            ..
+                        } => match handler.external_call(instance, attribute, args, kwargs) {
+                            Ok(value) => ExternalAnswer::Result(value),
+                            Err(message) => ExternalAnswer::Error(message),
+                        },
+                        QueryEvent::ExternalIsSubSpecializer {
+                            instance_id,
+                            left_class_tag,
+                            right_class_tag,
+                            ..
+                        } => match handler.external_is_subspecializer(
+                            instance_id,
+                            left_class_tag,
+                            right_class_tag,
+                        ) {
+                            Ok(result) => ExternalAnswer::Question(result),
+                            Err(message) => return self.application_error(message),
+                        },
+                        call => return invalid_state(format!("can't batch {:?}", call)),
+                    });
+                }
+                return self.batch_result(answers);
+            }
+            event => return invalid_state(format!("there's no handler for {:?}", event)),
+        };
+        match answer {
+            Ok(result) => result,
+            Err(message) => self.application_error(message),
+        }
+    }
+}
+
+/// An iterator over the results of a query, made by `Query::results`. It ends once the query
+/// is done or after the first error.
+pub struct Results<F> {
+    query: Query,
+    handler: F,
+    done: bool,
+}
+
+impl<F> Results<F> {
+    /// The query, e.g. to read its messages.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+}
+
+impl<F> Iterator for Results<F>
+where
+    F: FnMut(&mut Query, QueryEvent) -> PolarResult<()>,
+{
+    type Item = PolarResult<Bindings>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let result = match self.query.next_event() {
+                Ok(QueryEvent::Result { bindings, .. }) => return Some(Ok(bindings)),
+                Ok(QueryEvent::Done { .. }) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(event) => (self.handler)(&mut self.query, event),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = result {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+        None
+    }
+}
+
+/// Compare two results by the value of `var`, as `Query::set_result_order` describes.
+fn compare_results(a: &QueryEvent, b: &QueryEvent, var: &Symbol, order: Order) -> Ordering {
+    fn value<'a>(result: &'a QueryEvent, var: &Symbol) -> Option<&'a Value> {
+        match result {
+            QueryEvent::Result { bindings, .. } => bindings.get(var).map(Term::value),
+            _ => None,
+        }
+    }
+
+    /// Numbers, then strings, then the values that aren't ordered.
+    fn rank(value: Option<&Value>) -> u8 {
+        match value {
+            Some(Value::Number(Numeric::Float(f))) if f.is_nan() => 2,
+            Some(Value::Number(_)) => 0,
+            Some(Value::String(_)) => 1,
+            _ => 2,
+        }
+    }
+
+    let (a, b) = (value(a, var), value(b, var));
+    rank(a).cmp(&rank(b)).then_with(|| {
+        let ordering = match (a, b) {
+            (Some(Value::Number(a)), Some(Value::Number(b))) => {
+                a.partial_cmp(b).unwrap_or(Ordering::Equal)
+            }
+            (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+            _ => Ordering::Equal,
+        };
+        match order {
+            Order::Ascending => ordering,
+            Order::Descending => ordering.reverse(),
+        }
+    })
 }
 
 // Query as an iterator returns `None` after the first time `Done` is seen
@@ -27,7 +27,7 @@ pub enum Production {
 }
 
 fn validate_relation_keyword(keyword: &Term) -> PolarResult<()> {
-    if keyword.as_symbol()?.0 != "on" {
+    if keyword.as_symbol()?.as_str() != "on" {
         return Err(ValidationError::ResourceBlock {
             msg: format!(
                 "Unexpected relation keyword '{}'. Did you mean 'on'?",
@@ -48,7 +48,7 @@ pub enum ParsedDeclaration {
 }
 
 pub fn validate_parsed_declaration((name, term): (Term, Term)) -> PolarResult<ParsedDeclaration> {
-    match (name.as_symbol()?.0.as_ref(), term.value()) {
+    match (&*name.as_symbol()?.as_str(), term.value()) {
         ("roles", Value::List(_)) => Ok(ParsedDeclaration::Roles(term)),
         ("permissions", Value::List(_)) => Ok(ParsedDeclaration::Permissions(term)),
         ("relations", Value::Dictionary(_)) => Ok(ParsedDeclaration::Relations(term)),
@@ -76,7 +76,7 @@ pub fn validate_parsed_declaration((name, term): (Term, Term)) -> PolarResult<Pa
 
 pub fn block_type_from_keyword(keyword: Option<Term>, resource: &Term) -> PolarResult<BlockType> {
     if let Some(keyword) = keyword {
-        match keyword.as_symbol()?.0.as_ref() {
+        match &*keyword.as_symbol()?.as_str() {
             "actor" => Ok(BlockType::Actor),
             "resource" => Ok(BlockType::Resource),
             other => Err(ValidationError::ResourceBlock {
@@ -211,6 +211,9 @@ impl ShorthandRule {
             // Copy SourceInfo from head of shorthand rule.
             source_info: head.source_info().clone(),
             required: false,
+            doc: None,
+            annotations: vec![],
+            program: Default::default(),
         })
     }
 }
@@ -461,7 +464,8 @@ fn index_declarations(
             // `"creator" => Relation(User)` so that when we encounter a shorthand rule
             // `"admin" if "creator";` we can easily look up what type of declaration `"creator"`
             // is.
-            let stringified_relation = relation_type.clone_with_value(value!(relation.0.as_str()));
+            let stringified_relation =
+                relation_type.clone_with_value(Value::String(relation.to_string()));
             let declaration = Declaration::Relation(relation_type.clone());
 
             if let Some(existing) =
@@ -481,13 +485,13 @@ fn index_declarations(
 }
 
 fn resource_name_as_var(resource_name: &Term, related: bool) -> PolarResult<Value> {
-    let name = &resource_name.as_symbol()?.0;
+    let name = resource_name.as_symbol()?.as_str();
     let mut lowercased = name.to_lowercase();
 
     // If the resource's name is already lowercase, append "_instance" to distinguish the variable
     // name from the resource's name. In most cases, the resource name will not be lowercase (e.g.,
     // `Organization` or `RepositorySettings`).
-    if &lowercased == name {
+    if lowercased == name {
         lowercased += "_instance";
     }
 
@@ -576,7 +580,7 @@ fn shorthand_rule_body_to_rule_body(
 
 /// Turn a shorthand rule head into a trio of params that go in the head of the rewritten rule.
 fn shorthand_rule_head_to_params(head: &Term, resource: &Term) -> PolarResult<Vec<Parameter>> {
-    let resource_name = &resource.as_symbol()?.0;
+    let resource_name = resource.as_symbol()?.clone();
     let params = vec![
         Parameter {
             parameter: head.clone_with_value(value!(sym!("actor"))),
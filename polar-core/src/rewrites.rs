@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::folder::*;
 use super::kb::*;
@@ -21,13 +21,22 @@ impl<'kb> Renamer<'kb> {
 }
 
 impl<'kb> Folder for Renamer<'kb> {
+    /// Share the parts of a rule without variables with the rule in the knowledge base.
+    fn fold_term(&mut self, t: Term) -> Term {
+        if t.is_variable_free() {
+            t
+        } else {
+            fold_term(t, self)
+        }
+    }
+
     fn fold_variable(&mut self, v: Symbol) -> Symbol {
         if self.kb.is_constant(&v) {
             v
         } else if let Some(w) = self.renames.get(&v) {
             w.clone()
         } else {
-            let w = self.kb.gensym(&v.0);
+            let w = self.kb.rename(&v);
             self.renames.insert(v, w.clone());
             w
         }
@@ -37,13 +46,79 @@ impl<'kb> Folder for Renamer<'kb> {
         if let Some(s) = self.renames.get(&r) {
             s.clone()
         } else {
-            let s = self.kb.gensym(&r.0);
+            let s = self.kb.rename(&r);
             self.renames.insert(r, s.clone());
             s
         }
     }
 }
 
+/// Qualify the rules declared in a `module` block with the module's name.
+///
+/// Calls from within the module to rules it declares are qualified too, so `f(x)` in the body of
+/// a rule in `module m { ... }` refers to `m::f(x)`. Method calls (`x.f()`) and calls to rules
+/// declared elsewhere are left alone.
+pub struct Qualifier {
+    module: Symbol,
+    names: HashSet<Symbol>,
+}
+
+impl Qualifier {
+    pub fn new(module: Symbol, rules: &[Rule]) -> Self {
+        Self {
+            module,
+            names: rules.iter().map(|rule| rule.name.clone()).collect(),
+        }
+    }
+
+    fn qualify(&self, name: Symbol) -> Symbol {
+        Symbol::new(&format!("{}::{}", self.module, name))
+    }
+}
+
+impl Folder for Qualifier {
+    fn fold_rule(&mut self, rule: Rule) -> Rule {
+        let rule = fold_rule(rule, self);
+        Rule {
+            name: self.qualify(rule.name),
+            ..rule
+        }
+    }
+
+    fn fold_call(&mut self, call: Call) -> Call {
+        let call = fold_call(call, self);
+        if self.names.contains(&call.name) {
+            Call {
+                name: self.qualify(call.name),
+                ..call
+            }
+        } else {
+            call
+        }
+    }
+
+    fn fold_operation(&mut self, o: Operation) -> Operation {
+        match o.operator {
+            // The right-hand side of a lookup names a method or field, not a rule.
+            Operator::Dot => Operation {
+                operator: Operator::Dot,
+                args: o
+                    .args
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, arg)| match arg.value() {
+                        Value::Call(call) if i == 1 => {
+                            arg.clone_with_value(Value::Call(fold_call(call.clone(), self)))
+                        }
+                        _ => self.fold_term(arg),
+                    })
+                    .collect(),
+            },
+            _ => fold_operation(o, self),
+        }
+    }
+}
+
 /// Rewrite expressions, etc.
 pub struct Rewriter<'kb> {
     kb: &'kb KnowledgeBase,
@@ -70,9 +145,24 @@ impl<'kb> Rewriter<'kb> {
                 true
             }
             Operator::New if o.args.len() == 1 => true,
+            Operator::Collect if o.args.len() == 2 => true,
+            Operator::Aggregate if o.args.len() == 3 => true,
             _ => false,
         }
     }
+
+    /// Replace a predicate call used as a value, as in `f(x).y` or `y > f(x)`, with
+    /// a temp that is bound by the call `f(x, _result)`.
+    fn hoist_call(&mut self, arg: &mut Term) {
+        if let Value::Call(call) = arg.value() {
+            let temp = Value::Variable(self.kb.gensym("result"));
+            let mut call = call.clone();
+            call.args.push(Term::from(temp.clone()));
+            let call = arg.clone_with_value(Value::Call(call));
+            self.stack.last_mut().unwrap().push(call);
+            *arg = arg.clone_with_value(temp);
+        }
+    }
 }
 
 fn temp_name(o: &Operator) -> &'static str {
@@ -80,6 +170,8 @@ fn temp_name(o: &Operator) -> &'static str {
         Operator::Add | Operator::Div | Operator::Mul | Operator::Sub => "op",
         Operator::Dot => "value",
         Operator::New => "instance",
+        Operator::Collect => "list",
+        Operator::Aggregate => "aggregate",
         _ => "temp",
     }
 }
@@ -97,6 +189,9 @@ impl<'kb> Folder for Rewriter<'kb> {
             params,
             source_info,
             required,
+            doc,
+            annotations,
+            ..
         }: Rule,
     ) -> Rule {
         let mut body = self.fold_term(body);
@@ -117,6 +212,9 @@ impl<'kb> Folder for Rewriter<'kb> {
             body,
             source_info,
             required,
+            doc,
+            annotations,
+            program: Default::default(),
         }
     }
 
@@ -133,7 +231,10 @@ impl<'kb> Folder for Rewriter<'kb> {
             }
             Value::Expression(o) if self.needs_rewrite(o) => {
                 // Rewrite sub-expressions, then push a temp onto the args.
-                let mut new = fold_operation(o.clone(), self);
+                let mut new = self.fold_operation(o.clone());
+                if new.operator == Operator::Dot {
+                    self.hoist_call(&mut new.args[0]);
+                }
                 let temp = Value::Variable(self.kb.gensym(temp_name(&o.operator)));
                 new.args.push(Term::from(temp.clone()));
 
@@ -153,7 +254,7 @@ impl<'kb> Folder for Rewriter<'kb> {
     fn fold_operation(&mut self, o: Operation) -> Operation {
         use Operator::*;
         match o.operator {
-            And | Or | Not => Operation {
+            And | Or | Not | Once | Conditional => Operation {
                 operator: fold_operator(o.operator, self),
                 args: o
                     .args
@@ -209,12 +310,73 @@ impl<'kb> Folder for Rewriter<'kb> {
                 },
             },
 
+            // Record the variables a `distinct` query is deduplicated on before any temporaries
+            // are introduced, so that only the variables written in the query are compared.
+            Distinct if o.args.len() == 1 => Operation {
+                operator: Distinct,
+                args: {
+                    let query = o.args[0].clone();
+                    let mut vars = HashSet::new();
+                    query.variables(&mut vars);
+                    let mut vars = vars
+                        .into_iter()
+                        .filter(|v| !v.is_temporary_var())
+                        .collect::<Vec<_>>();
+                    vars.sort();
+                    let vars = query.clone_with_value(Value::List(
+                        vars.into_iter()
+                            .map(|v| query.clone_with_value(Value::Variable(v)))
+                            .collect(),
+                    ));
+
+                    self.stack.push(vec![]);
+                    let query = self.fold_term(query);
+                    let query_rws = self.stack.pop().unwrap();
+                    vec![vars, query_rws.into_iter().rfold(query, and_op_)]
+                },
+            },
+
+            // A call compared with a value, as in `token.expires_at > now()`, is evaluated
+            // before the comparison.
+            Lt | Leq | Gt | Geq | Eq | Neq if o.args.len() == 2 => {
+                let mut o = fold_operation(o, self);
+                for arg in o.args.iter_mut() {
+                    self.hoist_call(arg);
+                }
+                o
+            }
+
+            // Lookups in the template of a comprehension or aggregate are made once per
+            // solution of its query, after the query has bound the template's variables.
+            Collect | Aggregate => Operation {
+                operator: o.operator,
+                args: {
+                    let mut args = o.args;
+                    let query = args.pop().unwrap();
+                    let template = args.pop().unwrap();
+
+                    self.stack.push(vec![]);
+                    let template = self.fold_term(template);
+                    let template_rws = self.stack.pop().unwrap();
+
+                    self.stack.push(vec![]);
+                    let query = self.fold_term(query);
+                    let query_rws = self.stack.pop().unwrap();
+
+                    let query = query_rws.into_iter().rfold(query, and_op_);
+                    let query = template_rws.into_iter().fold(query, and_);
+                    args.push(template);
+                    args.push(query);
+                    args
+                },
+            },
+
             _ => fold_operation(o, self),
         }
     }
 
     fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
+        if v.as_str() == "_" {
             self.kb.gensym("_")
         } else {
             v
@@ -222,7 +384,7 @@ impl<'kb> Folder for Rewriter<'kb> {
     }
 
     fn fold_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
+        if v.as_str() == "_" {
             self.kb.gensym("_")
         } else {
             v
@@ -274,6 +436,12 @@ pub fn rewrite_rule(rule: Rule, kb: &KnowledgeBase) -> Rule {
     fld.fold_rule(rule)
 }
 
+/// Qualify the rules of `module` with its name.
+pub fn qualify_module_rules(module: &Symbol, rules: Vec<Rule>) -> Vec<Rule> {
+    let mut fld = Qualifier::new(module.clone(), &rules);
+    rules.into_iter().map(|rule| fld.fold_rule(rule)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -287,6 +455,20 @@ mod tests {
         crate::parser::parse_rules(src).unwrap()
     }
 
+    #[test]
+    fn rename_shares_variable_free_terms() {
+        let kb = KnowledgeBase::new();
+        let query = parse_query("x = [1, [2, 3]]");
+        let renamed = Renamer::new(&kb).fold_term(query.clone());
+        assert_eq!(renamed.to_string(), "_x_1 = [1, [2, 3]]");
+
+        let list = |t: &Term| match t.value() {
+            Value::Expression(Operation { args, .. }) => args[1].clone(),
+            _ => panic!("not an expression"),
+        };
+        assert!(std::ptr::eq(list(&query).value(), list(&renamed).value()));
+    }
+
     #[test]
     fn rewrite_anonymous_vars() {
         let kb = KnowledgeBase::new();
@@ -324,6 +506,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rewrite_call_lookups() {
+        let kb = KnowledgeBase::new();
+        let query = parse_query("current_user().name = \"alice\"");
+        assert_eq!(query.to_string(), "current_user().name = \"alice\"");
+        assert_eq!(
+            rewrite_term(query, &kb).to_string(),
+            "current_user(_result_1) and _result_1.name = _value_2 and _value_2 = \"alice\""
+        );
+    }
+
+    #[test]
+    fn qualify_module_rule_names() {
+        let rules =
+            parse_rules("allow(a) if owner(a) and a.owner() and other(a); owner(a) if a.admin;");
+        let rules = qualify_module_rules(&sym!("billing"), rules);
+        assert_eq!(
+            rules[0].to_string(),
+            "billing::allow(a) if billing::owner(a) and a.owner() and other(a);"
+        );
+        assert_eq!(rules[1].to_string(), "billing::owner(a) if a.admin;");
+    }
+
+    #[test]
+    fn rewrite_comprehensions() {
+        let kb = KnowledgeBase::new();
+        let rules = parse_rules("f(u, ys) if ys = [x.name for x in u.items if x.active];");
+        let rule = rewrite_rule(rules[0].clone(), &kb);
+        assert_eq!(
+            rule.to_string(),
+            "f(u, ys) if _list_4 = [_value_1 for u.items = _value_2 and x in _value_2 and x.active = _value_3 and _value_3 if x.name = _value_1] and ys = _list_4;"
+        );
+    }
+
     #[test]
     fn rewrite_forall_rhs_dots() {
         let kb = KnowledgeBase::new();
@@ -1,12 +1,13 @@
-use std::collections::{BTreeSet, HashMap};
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
 
 use serde::{Deserialize, Serialize};
 
-use super::sources::{Context, Source, SourceInfo};
+use super::program::Program;
+use super::sources::{Context, Source, SourceInfo, Span};
 use super::terms::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Parameter {
     pub parameter: Term,
     pub specializer: Option<Term>,
@@ -16,6 +17,57 @@ impl Parameter {
     pub fn is_ground(&self) -> bool {
         self.specializer.is_none() && self.parameter.value().is_ground()
     }
+
+    /// A quick check for whether this parameter may unify with a dereferenced argument. It's
+    /// false only if their constants or the shapes of their lists and dictionaries differ.
+    pub fn may_match(&self, arg: &Value) -> bool {
+        may_unify(self.parameter.value(), arg)
+    }
+}
+
+fn may_unify(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l == r,
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (Value::List(l), Value::List(r)) => {
+            let (l_rest, r_rest) = (has_rest_var(l), has_rest_var(r));
+            let l_len = l.len() - usize::from(l_rest);
+            let r_len = r.len() - usize::from(r_rest);
+            let lengths_match = match (l_rest, r_rest) {
+                (false, false) => l_len == r_len,
+                (true, false) => l_len <= r_len,
+                (false, true) => r_len <= l_len,
+                (true, true) => true,
+            };
+            lengths_match
+                && l.iter()
+                    .zip(r)
+                    .all(|(l, r)| may_unify(l.value(), r.value()))
+        }
+        (Value::Dictionary(l), Value::Dictionary(r)) => {
+            l.fields.len() == r.fields.len()
+                && l.fields.iter().all(|(k, l)| {
+                    r.fields
+                        .get(k)
+                        .is_some_and(|r| may_unify(l.value(), r.value()))
+                })
+        }
+        // Constants of different types never unify.
+        (
+            Value::Number(_)
+            | Value::String(_)
+            | Value::Boolean(_)
+            | Value::List(_)
+            | Value::Dictionary(_),
+            Value::Number(_)
+            | Value::String(_)
+            | Value::Boolean(_)
+            | Value::List(_)
+            | Value::Dictionary(_),
+        ) => false,
+        _ => true,
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -28,6 +80,23 @@ pub struct Rule {
     // TODO @patrickod: refactor Rule into Rule & RuleType structs
     // `required` is used exclusively with rule *types* and not normal rules.
     pub required: bool,
+    /// The doc string written before the rule, like `"""Admins can read anything."""`.
+    #[serde(default)]
+    pub doc: Option<Arc<str>>,
+    /// The annotations written before the rule, like `@tag("pci")`.
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    /// The rule compiled for calls to it, once it's added to a knowledge base.
+    #[serde(skip)]
+    pub(crate) program: OnceLock<Program>,
+}
+
+/// An annotation on a rule, like `@tag("pci")`, which tooling can find the rule by. Its
+/// arguments are strings, numbers or booleans.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Annotation {
+    pub name: Symbol,
+    pub args: Vec<Term>,
 }
 
 impl PartialEq for Rule {
@@ -39,11 +108,55 @@ impl PartialEq for Rule {
     }
 }
 
+/// A loaded rule, as `Polar::rules` lists it for tooling.
+#[derive(Clone, Debug, Serialize)]
+pub struct RuleInfo {
+    pub name: Symbol,
+    pub arity: usize,
+    /// The specializer of each parameter, like `User` in `f(u: User)`, if it has one.
+    pub specializers: Vec<Option<Term>>,
+    /// Where the rule was loaded from, with its source text. Rules that weren't parsed, like
+    /// facts added with `Polar::add_fact`, have no span.
+    pub span: Option<Span>,
+    pub doc: Option<Arc<str>>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl RuleInfo {
+    /// Whether the rule has an annotation `name` whose arguments start with `args`, so that
+    /// `("tag", [])` matches `@tag` and `@tag("pci")`, and `("tag", ["pci"])` only the second.
+    pub fn has_annotation(&self, name: &str, args: &[Term]) -> bool {
+        self.annotations
+            .iter()
+            .any(|annotation| annotation.name.as_str() == name && annotation.args.starts_with(args))
+    }
+}
+
 impl Rule {
     pub fn is_ground(&self) -> bool {
         self.params.iter().all(|p| p.is_ground())
     }
 
+    pub(crate) fn info(&self) -> RuleInfo {
+        RuleInfo {
+            name: self.name.clone(),
+            arity: self.params.len(),
+            specializers: self
+                .params
+                .iter()
+                .map(|param| param.specializer.clone())
+                .collect(),
+            span: self.parsed_context().map(Context::span),
+            doc: self.doc.clone(),
+            annotations: self.annotations.clone(),
+        }
+    }
+
+    /// The rule compiled for calls to it.
+    pub(crate) fn program(&self) -> &Program {
+        self.program.get_or_init(|| Program::compile(self))
+    }
+
     pub(crate) fn parsed_context(&self) -> Option<&Context> {
         if let SourceInfo::Parser(context) = &self.source_info {
             Some(context)
@@ -59,6 +172,9 @@ impl Rule {
             body,
             source_info: SourceInfo::Test,
             required: false,
+            doc: None,
+            annotations: vec![],
+            program: OnceLock::new(),
         }
     }
 
@@ -77,16 +193,28 @@ impl Rule {
             body,
             source_info: SourceInfo::parser(source, left, right),
             required: false,
+            doc: None,
+            annotations: vec![],
+            program: OnceLock::new(),
         }
     }
 }
 
 // TODO: should this be a Set of Rules? Do we currently check for duplicate rules?
-pub struct RuleTypes(HashMap<Symbol, Vec<Rule>>);
+#[derive(Clone)]
+pub struct RuleTypes {
+    types: HashMap<Symbol, Vec<Rule>>,
+    /// Names of default rule types that accept any arguments. Declaring a rule type with one of
+    /// these names replaces the default, so that the declared signature is actually enforced.
+    placeholders: HashSet<Symbol>,
+}
 
 impl Default for RuleTypes {
     fn default() -> Self {
-        let mut rule_types = Self(HashMap::new());
+        let mut rule_types = Self {
+            types: HashMap::new(),
+            placeholders: HashSet::new(),
+        };
         rule_types.add_default_rule_types();
         rule_types
     }
@@ -113,26 +241,34 @@ impl RuleTypes {
         ));
         // type allow_request(actor, request);"#;
         self.add(rule!("allow_request", [sym!("actor"), sym!("request")]));
+        self.placeholders = ["allow", "allow_field", "allow_request"]
+            .iter()
+            .map(|name| sym!(name))
+            .collect();
     }
 
     pub fn get(&self, name: &Symbol) -> Option<&Vec<Rule>> {
-        self.0.get(name)
+        self.types.get(name)
     }
 
     pub fn add(&mut self, rule_type: Rule) {
         let name = rule_type.name.clone();
+        if self.placeholders.remove(&name) {
+            self.types.remove(&name);
+        }
         // get rule types with this rule name
-        let rule_types = self.0.entry(name).or_insert_with(Vec::new);
+        let rule_types = self.types.entry(name).or_insert_with(Vec::new);
         rule_types.push(rule_type);
     }
 
     pub fn reset(&mut self) {
-        self.0.clear();
+        self.types.clear();
+        self.placeholders.clear();
         self.add_default_rule_types()
     }
 
     pub fn required_rule_types(&self) -> Vec<&Rule> {
-        self.0
+        self.types
             .values()
             .flatten()
             .filter(|rule_type| rule_type.required)
@@ -144,23 +280,73 @@ pub type Rules = Vec<Arc<Rule>>;
 
 type RuleSet = BTreeSet<u64>;
 
+/// What a rule's parameter is indexed by.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum IndexKey {
+    /// A ground parameter, which only matches an equal argument.
+    Value(Value),
+    /// A list pattern with variables, which only matches a list.
+    List,
+    /// A dictionary pattern with variables, which only matches a dictionary.
+    Dictionary,
+    /// A variable or specialized parameter, which may match anything.
+    Any,
+}
+
+impl IndexKey {
+    fn for_param(param: &Parameter) -> Self {
+        let value = param.parameter.value();
+        if param.is_ground() {
+            Self::Value(value.clone())
+        } else if param.specializer.is_none() {
+            Self::functor(value).unwrap_or(Self::Any)
+        } else {
+            Self::Any
+        }
+    }
+
+    /// The key of the patterns with variables that unify with values like `value`.
+    fn functor(value: &Value) -> Option<Self> {
+        match value {
+            Value::List(_) => Some(Self::List),
+            Value::Dictionary(_) => Some(Self::Dictionary),
+            _ => None,
+        }
+    }
+
+    /// Whether a parameter with this key may match an argument with the functor `functor`.
+    fn may_match(&self, functor: &Self) -> bool {
+        match self {
+            Self::Value(value) => Self::functor(value).as_ref() == Some(functor),
+            Self::Any => true,
+            key => key == functor,
+        }
+    }
+}
+
+/// What the rules that apply to a call depend on about one of its arguments, which calls with
+/// the same shapes of arguments share a cached set of applicable rules by.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ArgShape {
+    /// A ground argument equal to a ground parameter in the same position.
+    Value(Value),
+    /// Any other ground argument, with its functor if it's a list or dictionary.
+    Ground(Option<IndexKey>),
+    /// An argument with variables, with its functor if it's a list or dictionary.
+    Unground(Option<IndexKey>),
+}
+
 #[derive(Clone, Default, Debug)]
 struct RuleIndex {
     rules: RuleSet,
-    index: HashMap<Option<Value>, RuleIndex>,
+    index: HashMap<IndexKey, RuleIndex>,
 }
 
 impl RuleIndex {
     pub fn index_rule(&mut self, rule_id: u64, params: &[Parameter], i: usize) {
         if i < params.len() {
             self.index
-                .entry({
-                    if params[i].is_ground() {
-                        Some(params[i].parameter.value().clone())
-                    } else {
-                        None
-                    }
-                })
+                .entry(IndexKey::for_param(&params[i]))
                 .or_insert_with(RuleIndex::default)
                 .index_rule(rule_id, params, i + 1);
         } else {
@@ -168,35 +354,50 @@ impl RuleIndex {
         }
     }
 
-    #[allow(clippy::comparison_chain)]
+    pub fn remove_rule(&mut self, rule_id: u64, params: &[Parameter], i: usize) {
+        if i < params.len() {
+            let key = IndexKey::for_param(&params[i]);
+            if let Some(index) = self.index.get_mut(&key) {
+                index.remove_rule(rule_id, params, i + 1);
+                if index.rules.is_empty() && index.index.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        } else {
+            self.rules.remove(&rule_id);
+        }
+    }
+
     pub fn get_applicable_rules(&self, args: &[Term], i: usize) -> RuleSet {
         if i < args.len() {
             // Check this argument and recurse on the rest.
             let filter_next_args =
                 |index: &RuleIndex| -> RuleSet { index.get_applicable_rules(args, i + 1) };
             let arg = args[i].value();
+            let functor = IndexKey::functor(arg);
             if arg.is_ground() {
-                // Check the index for a ground argument.
-                let mut ruleset = self
-                    .index
-                    .get(&Some(arg.clone()))
-                    .map(|index| filter_next_args(index))
-                    .unwrap_or_else(RuleSet::default);
-
-                // Extend for a variable parameter.
-                if let Some(index) = self.index.get(&None) {
-                    ruleset.extend(filter_next_args(index));
-                }
-                ruleset
+                // Check the index for a ground argument, and for patterns and variable
+                // parameters that it may unify with.
+                let keys = [
+                    Some(IndexKey::Value(arg.clone())),
+                    functor,
+                    Some(IndexKey::Any),
+                ];
+                keys.iter()
+                    .flatten()
+                    .filter_map(|key| self.index.get(key))
+                    .flat_map(filter_next_args)
+                    .collect()
+            } else if let Some(functor) = functor {
+                // A pattern only matches parameters with the same functor.
+                self.index
+                    .iter()
+                    .filter(|(key, _)| key.may_match(&functor))
+                    .flat_map(|(_, index)| filter_next_args(index))
+                    .collect()
             } else {
                 // Accumulate all indexed arguments.
-                self.index.values().fold(
-                    RuleSet::default(),
-                    |mut result: RuleSet, index: &RuleIndex| {
-                        result.extend(filter_next_args(index).into_iter());
-                        result
-                    },
-                )
+                self.index.values().flat_map(filter_next_args).collect()
             }
         } else {
             // No more arguments.
@@ -205,20 +406,40 @@ impl RuleIndex {
     }
 }
 
-#[derive(Clone)]
 pub struct GenericRule {
     pub name: Symbol,
     pub rules: HashMap<u64, Arc<Rule>>,
     index: RuleIndex,
+    /// For each position, the number of rules with each ground parameter in it.
+    constants: Vec<HashMap<Value, usize>>,
+    /// The applicable rules for each list of argument shapes that they've been looked up for,
+    /// which is cleared whenever a rule is added or removed.
+    cache: Mutex<HashMap<Vec<ArgShape>, Rules>>,
     next_rule_id: u64,
 }
 
+impl Clone for GenericRule {
+    /// A copy of the rules without the cache of applicable rules, which can't be shared.
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            rules: self.rules.clone(),
+            index: self.index.clone(),
+            constants: self.constants.clone(),
+            cache: Default::default(),
+            next_rule_id: self.next_rule_id,
+        }
+    }
+}
+
 impl GenericRule {
     pub fn new(name: Symbol, rules: Rules) -> Self {
         let mut generic_rule = Self {
             name,
             rules: Default::default(),
             index: Default::default(),
+            constants: vec![],
+            cache: Default::default(),
             next_rule_id: 0,
         };
 
@@ -231,22 +452,81 @@ impl GenericRule {
 
     pub fn add_rule(&mut self, rule: Arc<Rule>) {
         let rule_id = self.next_rule_id();
+        rule.program();
 
         assert!(
             self.rules.insert(rule_id, rule.clone()).is_none(),
             "Rule id already used."
         );
         self.index.index_rule(rule_id, &rule.params[..], 0);
+        for (i, param) in rule.params.iter().enumerate() {
+            if let IndexKey::Value(value) = IndexKey::for_param(param) {
+                if self.constants.len() <= i {
+                    self.constants.resize_with(i + 1, HashMap::new);
+                }
+                *self.constants[i].entry(value).or_default() += 1;
+            }
+        }
+        self.cache.get_mut().unwrap().clear();
+    }
+
+    /// Remove the earliest added rule equal to `rule`. Returns whether there was one.
+    pub fn remove_rule(&mut self, rule: &Rule) -> bool {
+        let rule_id = self
+            .rules
+            .iter()
+            .filter(|(_, r)| r.as_ref() == rule)
+            .map(|(id, _)| *id)
+            .min();
+        if let Some(id) = rule_id {
+            self.rules.remove(&id);
+            self.index.remove_rule(id, &rule.params[..], 0);
+            for (i, param) in rule.params.iter().enumerate() {
+                if let IndexKey::Value(value) = IndexKey::for_param(param) {
+                    let count = self.constants[i].get_mut(&value).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        self.constants[i].remove(&value);
+                    }
+                }
+            }
+            self.cache.get_mut().unwrap().clear();
+        }
+        rule_id.is_some()
     }
 
     #[allow(clippy::ptr_arg)]
     pub fn get_applicable_rules(&self, args: &TermList) -> Rules {
-        self.index
+        let shapes = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| self.arg_shape(i, arg.value()))
+            .collect::<Vec<_>>();
+        if let Some(rules) = self.cache.lock().unwrap().get(&shapes) {
+            return rules.clone();
+        }
+        let rules: Rules = self
+            .index
             .get_applicable_rules(args, 0)
             .iter()
             .map(|id| self.rules.get(id).expect("Rule missing"))
             .cloned()
-            .collect()
+            .collect();
+        self.cache.lock().unwrap().insert(shapes, rules.clone());
+        rules
+    }
+
+    /// The shape of the argument in position `i`. The index only tells ground arguments apart
+    /// by value if there's a ground parameter equal to them, and otherwise by their functors.
+    fn arg_shape(&self, i: usize, arg: &Value) -> ArgShape {
+        let functor = IndexKey::functor(arg);
+        if !arg.is_ground() {
+            ArgShape::Unground(functor)
+        } else if self.constants.get(i).is_some_and(|c| c.contains_key(arg)) {
+            ArgShape::Value(arg.clone())
+        } else {
+            ArgShape::Ground(functor)
+        }
     }
 
     fn next_rule_id(&mut self) -> u64 {
@@ -283,42 +563,156 @@ mod tests {
         let index = &generic_rule.index;
         assert!(index.rules.is_empty());
 
-        fn keys(index: &RuleIndex) -> HashSet<Option<Value>> {
+        fn keys(index: &RuleIndex) -> HashSet<IndexKey> {
             index.index.keys().cloned().collect()
         }
 
-        let mut args = HashSet::<Option<Value>>::new();
+        let mut args = HashSet::<IndexKey>::new();
 
         args.clear();
-        args.insert(Some(value!(1)));
+        args.insert(IndexKey::Value(value!(1)));
         assert_eq!(args, keys(index));
 
         args.clear();
-        args.insert(None); // x
-        args.insert(Some(value!(1)));
-        args.insert(Some(value!(2)));
-        args.insert(Some(value!(3)));
-        let index1 = index.index.get(&Some(value!(1))).unwrap();
+        args.insert(IndexKey::Any); // x
+        args.insert(IndexKey::Value(value!(1)));
+        args.insert(IndexKey::Value(value!(2)));
+        args.insert(IndexKey::Value(value!(3)));
+        let index1 = index.index.get(&IndexKey::Value(value!(1))).unwrap();
         assert_eq!(args, keys(index1));
 
         args.clear();
-        args.insert(Some(value!("x")));
-        args.insert(Some(value!("y")));
-        let index11 = index1.index.get(&Some(value!(1))).unwrap();
+        args.insert(IndexKey::Value(value!("x")));
+        args.insert(IndexKey::Value(value!("y")));
+        let index11 = index1.index.get(&IndexKey::Value(value!(1))).unwrap();
         assert_eq!(args, keys(index11));
 
-        args.remove(&Some(value!("x")));
-        let index1_ = index1.index.get(&None).unwrap();
+        args.remove(&IndexKey::Value(value!("x")));
+        let index1_ = index1.index.get(&IndexKey::Any).unwrap();
         assert_eq!(args, keys(index1_));
 
         args.clear();
-        args.insert(Some(value!(btreemap! {sym!("b") => term!("y")})));
-        let index12 = index1.index.get(&Some(value!(2))).unwrap();
+        args.insert(IndexKey::Value(value!(btreemap! {sym!("b") => term!("y")})));
+        let index12 = index1.index.get(&IndexKey::Value(value!(2))).unwrap();
         assert_eq!(args, keys(index12));
 
         args.clear();
-        args.insert(Some(value!(btreemap! {sym!("c") => term!("z")})));
-        let index13 = index1.index.get(&Some(value!(3))).unwrap();
+        args.insert(IndexKey::Value(value!(btreemap! {sym!("c") => term!("z")})));
+        let index13 = index1.index.get(&IndexKey::Value(value!(3))).unwrap();
         assert_eq!(args, keys(index13));
     }
+
+    #[test]
+    fn test_functor_index() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"
+            g(1);
+            g([1, 2]);
+            g([x, *_rest]) if x = 1;
+            g({a: _x});
+            g(_x: Integer);
+            g(_x);
+        "#,
+            )
+            .unwrap();
+
+        let kb = polar.kb.read().unwrap();
+        let generic_rule = kb.get_generic_rule(&sym!("g")).unwrap();
+        let applicable = |arg: Term| generic_rule.get_applicable_rules(&vec![arg]);
+
+        // Patterns aren't tried against arguments they can't unify with.
+        assert_eq!(applicable(term!(1)).len(), 3);
+        assert_eq!(applicable(term!("a")).len(), 2);
+        assert_eq!(applicable(term!([1, 2])).len(), 4);
+        assert_eq!(applicable(term!([3])).len(), 3);
+        assert_eq!(
+            applicable(term!(btreemap! {sym!("a") => term!(1)})).len(),
+            3
+        );
+
+        // An argument with variables matches the parameters with the same functor.
+        assert_eq!(applicable(term!([sym!("y")])).len(), 4);
+        assert_eq!(applicable(term!(sym!("y"))).len(), 6);
+    }
+
+    #[test]
+    fn test_applicable_rules_cache() {
+        let polar = Polar::new();
+        polar.load_str("h(1, _x); h(_x, 2); h([_x], 3);").unwrap();
+        let applicable = |args: Vec<Term>| {
+            let kb = polar.kb.read().unwrap();
+            let generic_rule = kb.get_generic_rule(&sym!("h")).unwrap();
+            let rules = generic_rule.get_applicable_rules(&args).len();
+            let cached = generic_rule.cache.lock().unwrap().len();
+            (rules, cached)
+        };
+
+        assert_eq!(applicable(vec![term!(1), term!(2)]), (2, 1));
+        // Arguments that no parameter is equal to are looked up by their functors.
+        assert_eq!(applicable(vec![term!(5), term!(2)]), (1, 2));
+        assert_eq!(applicable(vec![term!(6), term!(2)]), (1, 2));
+        assert_eq!(applicable(vec![term!([4]), term!(3)]), (1, 3));
+
+        // Adding a rule clears the cache.
+        let rule = crate::parser::parse_rules("h(6, 2);").unwrap().remove(0);
+        polar.kb.write().unwrap().add_rule(rule);
+        assert_eq!(applicable(vec![term!(6), term!(2)]), (2, 1));
+        assert_eq!(applicable(vec![term!(5), term!(2)]), (1, 2));
+    }
+
+    #[test]
+    fn test_may_match() {
+        let param = |value: Value| Parameter {
+            parameter: Term::from(value),
+            specializer: None,
+        };
+        let rest = || value!(Value::RestVariable(sym!("rest")));
+        let list = param(value!([1, sym!("x"), rest()]));
+        assert!(list.may_match(&value!([1, 2])));
+        assert!(list.may_match(&value!([1, 2, 3])));
+        assert!(list.may_match(&value!([sym!("y"), sym!("z")])));
+        assert!(list.may_match(&value!(sym!("y"))));
+        assert!(!list.may_match(&value!([1])));
+        assert!(!list.may_match(&value!([2, 2])));
+        assert!(!list.may_match(&value!("a")));
+
+        let dict = param(value!(btreemap! {sym!("a") => term!(1)}));
+        assert!(dict.may_match(&value!(btreemap! {sym!("a") => term!(sym!("y"))})));
+        assert!(!dict.may_match(&value!(btreemap! {sym!("a") => term!(2)})));
+        assert!(!dict.may_match(&value!(btreemap! {sym!("b") => term!(1)})));
+        assert!(!dict.may_match(&value!(btreemap! {})));
+
+        assert!(param(value!(1)).may_match(&value!(1.0)));
+        assert!(!param(value!(1)).may_match(&value!(true)));
+    }
+
+    #[test]
+    fn test_remove_rule() {
+        let rule = rule!("f", [value!(1), value!("x")]);
+        let mut generic_rule = GenericRule::new(
+            sym!("f"),
+            vec![
+                Arc::new(rule.clone()),
+                Arc::new(rule!("f", [value!(1), value!("y")])),
+                Arc::new(rule!("f", [value!(2), value!("x")])),
+            ],
+        );
+        assert!(generic_rule.remove_rule(&rule));
+        assert!(!generic_rule.remove_rule(&rule));
+        assert!(generic_rule.remove_rule(&rule!("f", [value!(2), value!("x")])));
+
+        // Emptied branches of the index are pruned.
+        let index = &generic_rule.index;
+        assert_eq!(index.index.len(), 1);
+        let index1 = index.index.get(&IndexKey::Value(value!(1))).unwrap();
+        assert!(!index1.index.contains_key(&IndexKey::Value(value!("x"))));
+        assert_eq!(
+            generic_rule
+                .get_applicable_rules(&vec![term!(1), term!("y")])
+                .len(),
+            1
+        );
+    }
 }
@@ -1,6 +1,6 @@
 use crate::counter::Counter;
 use crate::error::{invalid_state, PolarError, PolarResult};
-use crate::events::QueryEvent;
+use crate::events::{ExternalAnswer, QueryEvent};
 use crate::terms::Term;
 
 /// Trait for something that produces query events and accepts answers.
@@ -22,6 +22,14 @@ pub trait Runnable {
         invalid_state("Unexpected external call")
     }
 
+    fn external_call_error(&mut self, _call_id: u64, _message: String) -> PolarResult<()> {
+        invalid_state("Unexpected external call error")
+    }
+
+    fn external_batch_result(&mut self, _answers: Vec<ExternalAnswer>) -> PolarResult<()> {
+        invalid_state("Unexpected external batch result")
+    }
+
     fn debug_command(&mut self, _command: &str) -> PolarResult<()> {
         invalid_state("Unexpected debug command")
     }
@@ -33,6 +33,76 @@ impl Context {
         }
     }
 
+    /// Where the context is in its source.
+    pub fn span(&self) -> Span {
+        let (line, column) = self.line_and_column(self.left);
+        let (end_line, end_column) = self.line_and_column(self.right);
+        Span {
+            filename: self.source.filename.clone(),
+            line,
+            column,
+            end_line,
+            end_column,
+            text: self
+                .source
+                .src
+                .get(self.left..self.right)
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+
+    /// The line and column of `loc`, counting from 1.
+    fn line_and_column(&self, loc: usize) -> (usize, usize) {
+        let (row, column) = loc_to_pos(&self.source.src, loc);
+        (row + 1, column + 1)
+    }
+
+    /// The line where the context starts, with the context underlined, in the style of rustc:
+    ///
+    /// ```text
+    ///  --> policy.polar:2:7
+    ///   |
+    /// 2 |   x = ;
+    ///   |       ^
+    /// ```
+    ///
+    /// A context that goes on past the line is underlined to the end of the line.
+    pub(crate) fn snippet(&self) -> String {
+        let span = self.span();
+        let line = self
+            .source
+            .src
+            .lines()
+            .nth(span.line - 1)
+            .unwrap_or_default();
+        let end_column = if span.end_line == span.line {
+            span.end_column
+        } else {
+            line.chars().count() + 1
+        };
+        let underline = end_column.saturating_sub(span.column).max(1);
+
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let mut f = String::new();
+        match span.filename {
+            Some(filename) => writeln!(f, "{gutter}--> {filename}:{}:{}", span.line, span.column),
+            None => writeln!(f, "{gutter}--> line {}, column {}", span.line, span.column),
+        }
+        .unwrap();
+        writeln!(f, "{gutter} |").unwrap();
+        writeln!(f, "{number} | {line}").unwrap();
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(span.column - 1),
+            "^".repeat(underline)
+        )
+        .unwrap();
+        f
+    }
+
     pub(crate) fn source_position(&self) -> String {
         let mut f = String::new();
         let (row, column) = loc_to_pos(&self.source.src, self.left);
@@ -44,6 +114,20 @@ impl Context {
     }
 }
 
+/// The part of a source that a `Context` covers, with lines and columns counted from 1 and
+/// columns counted in characters, for hosts that point to errors in their own way.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Span {
+    /// The file the source was loaded from, if it has one.
+    pub filename: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// The text of the source in the span, such as the offending token or rule.
+    pub text: String,
+}
+
 #[derive(Clone)]
 pub enum SourceInfo {
     // From the parser
@@ -83,7 +167,7 @@ impl SourceInfo {
 
 // TODO(gj): `Serialize` makes some `polar-wasm-api` tests easier to write. We could look into
 // https://serde.rs/remote-derive.html if we cared to preserve that while removing this impl.
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Source {
     pub filename: Option<String>,
     pub src: String,
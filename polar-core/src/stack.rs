@@ -0,0 +1,272 @@
+//! A persistent stack, used for the VM's goal, query and trace stacks.
+//!
+//! The VM snapshots those stacks at every choice point and restores them on backtracking. With
+//! a `Vec` each snapshot copies the whole stack, so a recursive rule that leaves a choice point
+//! at every level takes time quadratic in its depth. Clones of a `Stack` share their elements
+//! instead, which makes a snapshot O(1).
+//!
+//! A stack and its clones keep the nodes that they pop and no other stack shares, and push onto
+//! those before they allocate more. The VM pushes and pops goals millions of times in a long
+//! query, so it reuses the same few nodes instead of allocating and freeing one for each goal.
+//! At most `MAX_FREE` nodes are kept, and `release_free` frees them all once the query is done.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// The most popped nodes that a stack and its clones keep to push onto.
+pub const MAX_FREE: usize = 1024;
+
+pub struct Stack<T> {
+    top: Option<Rc<Node<T>>>,
+    len: usize,
+    /// The nodes to push onto, which are shared by the stack's clones.
+    free: Rc<RefCell<Vec<Rc<Node<T>>>>>,
+}
+
+struct Node<T> {
+    /// The element, which a free node doesn't have.
+    value: Option<T>,
+    next: Option<Rc<Node<T>>>,
+}
+
+impl<T> Node<T> {
+    fn value(&self) -> &T {
+        self.value.as_ref().expect("a node on a stack has a value")
+    }
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Self {
+            top: None,
+            len: 0,
+            free: Default::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, value: T) {
+        let next = self.top.take();
+        let node = match self.free.borrow_mut().pop() {
+            Some(mut node) => {
+                let free = Rc::get_mut(&mut node).expect("a free node isn't shared");
+                free.value = Some(value);
+                free.next = next;
+                node
+            }
+            None => Rc::new(Node {
+                value: Some(value),
+                next,
+            }),
+        };
+        self.top = Some(node);
+        self.len += 1;
+    }
+
+    /// The top of the stack.
+    pub fn last(&self) -> Option<&T> {
+        self.top.as_deref().map(Node::value)
+    }
+
+    /// Iterate over the stack from the top down.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.top.as_deref(),
+        }
+    }
+
+    /// The number of popped nodes kept to push onto.
+    pub fn free_len(&self) -> usize {
+        self.free.borrow().len()
+    }
+
+    /// Free the popped nodes kept by this stack and its clones.
+    pub fn release_free(&self) {
+        self.free.borrow_mut().clear();
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            top: None,
+            len: 0,
+            free: self.free.clone(),
+        };
+    }
+}
+
+impl<T: Clone> Stack<T> {
+    /// Pop the top of the stack, which is only cloned if another stack shares it.
+    pub fn pop(&mut self) -> Option<T> {
+        let mut node = self.top.take()?;
+        self.len -= 1;
+        match Rc::get_mut(&mut node) {
+            Some(unshared) => {
+                self.top = unshared.next.take();
+                let value = unshared.value.take();
+                let mut free = self.free.borrow_mut();
+                if free.len() < MAX_FREE {
+                    free.push(node);
+                }
+                value
+            }
+            None => {
+                self.top = node.next.clone();
+                Some(node.value().clone())
+            }
+        }
+    }
+}
+
+impl<T: PartialEq> Stack<T> {
+    /// Whether the bottom of this stack is `prefix`.
+    pub fn starts_with(&self, prefix: &Self) -> bool {
+        if prefix.len > self.len {
+            return false;
+        }
+        let mut ours = self.iter();
+        for _ in prefix.len..self.len {
+            ours.next();
+        }
+        let mut theirs = prefix.iter();
+        loop {
+            match (ours.next, theirs.next) {
+                (Some(a), Some(b)) if std::ptr::eq(a, b) => return true,
+                (Some(a), Some(b)) if a.value() != b.value() => return false,
+                (None, None) => return true,
+                _ => (),
+            }
+            ours.next();
+            theirs.next();
+        }
+    }
+}
+
+impl<T> Clone for Stack<T> {
+    fn clone(&self) -> Self {
+        Self {
+            top: self.top.clone(),
+            len: self.len,
+            free: self.free.clone(),
+        }
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    // Drop the nodes that aren't shared one at a time. The default drop would recurse once per
+    // node and overflow the native stack on the deep stacks this type exists for.
+    fn drop(&mut self) {
+        let mut next = self.top.take();
+        while let Some(node) = next {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => next = node.next.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<T> FromIterator<T> for Stack<T> {
+    /// Push the items in order, so the last one is on top.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stack = Self::new();
+        for item in iter {
+            stack.push(item);
+        }
+        stack
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(node.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_elements() {
+        let mut a: Stack<i32> = (1..=3).collect();
+        let mut b = a.clone();
+        assert_eq!(a.pop(), Some(3));
+        a.push(4);
+        b.push(5);
+        assert_eq!(a.iter().copied().collect::<Vec<_>>(), vec![4, 2, 1]);
+        assert_eq!(b.iter().copied().collect::<Vec<_>>(), vec![5, 3, 2, 1]);
+        assert_eq!((a.len(), b.len()), (3, 4));
+        assert_eq!(b.last(), Some(&5));
+
+        assert!(a.starts_with(&(1..=2).collect()));
+        assert!(b.starts_with(&b));
+        assert!(!b.starts_with(&a));
+        assert!(!a.starts_with(&(1..=5).collect()));
+        b.clear();
+        assert!(b.is_empty() && b.pop().is_none());
+    }
+
+    #[test]
+    fn test_reuse_popped_nodes() {
+        let mut a: Stack<i32> = (1..=3).collect();
+        let b = a.clone();
+        // The nodes are shared with `b`, so they're kept.
+        assert_eq!(a.pop(), Some(3));
+        assert!(a.free.borrow().is_empty());
+
+        a.push(4);
+        let node = a.top.as_ref().map(Rc::as_ptr);
+        assert_eq!(a.pop(), Some(4));
+        assert_eq!(a.free.borrow().len(), 1);
+        a.push(5);
+        assert_eq!(a.top.as_ref().map(Rc::as_ptr), node);
+        assert!(a.free.borrow().is_empty());
+        assert_eq!(a.iter().copied().collect::<Vec<_>>(), vec![5, 2, 1]);
+        assert_eq!(b.iter().copied().collect::<Vec<_>>(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_free_nodes_are_capped() {
+        let mut a: Stack<usize> = (0..MAX_FREE * 2).collect();
+        while a.pop().is_some() {}
+        assert_eq!(a.free_len(), MAX_FREE);
+        a.clone().release_free();
+        assert_eq!(a.free_len(), 0);
+    }
+
+    #[test]
+    fn test_drop_deep_stack() {
+        let stack: Stack<usize> = (0..1_000_000).collect();
+        let shared = stack.clone();
+        drop(stack);
+        assert_eq!(shared.len(), 1_000_000);
+    }
+}
@@ -0,0 +1,35 @@
+//! Counters of the work a query does, which `Query::stats` returns.
+
+use serde::Serialize;
+
+/// What a query has done so far, including its subqueries. Read it after the query is done for
+/// the cost of evaluating it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct QueryStats {
+    /// Goals run, which is what the step limit counts.
+    pub goals: u64,
+    pub unifications: u64,
+    /// Times the query went back to a choice point to try another alternative, or found that
+    /// there were none left.
+    pub backtracks: u64,
+    /// Events the query sent the host to answer: calls, lookups, `isa` checks, comparisons and
+    /// so on.
+    pub external_calls: u64,
+    /// External lookups answered from the query's cache instead of by the host, if lookups are
+    /// cached.
+    pub cached_lookups: u64,
+    /// The most choice points that were pending at once in the query or one of its subqueries.
+    pub peak_choice_points: u64,
+}
+
+impl QueryStats {
+    /// Count the work of a query that was run on behalf of this one.
+    pub fn add(&mut self, other: &Self) {
+        self.goals += other.goals;
+        self.unifications += other.unifications;
+        self.backtracks += other.backtracks;
+        self.external_calls += other.external_calls;
+        self.cached_lookups += other.cached_lookups;
+        self.peak_choice_points = self.peak_choice_points.max(other.peak_choice_points);
+    }
+}
@@ -0,0 +1,251 @@
+//! Tabling, which evaluates each variant of a call to a tabled rule once per query and answers
+//! later calls from a table of its results.
+//!
+//! A call with no table is evaluated to exhaustion by a `TableEvaluator` in a sub-VM. A call to
+//! the same variant while that evaluation is running, like the recursive call in
+//! `path(x, z) if path(x, y) and edge(y, z)`, gets the answers found so far instead of being
+//! evaluated again, and the evaluation is repeated until it finds no new answers. So left- and
+//! mutually recursive rules over cyclic data terminate, and each answer is found only once.
+//!
+//! Two calls are variants if they're the same up to the names of their unbound variables.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::{PolarError, PolarResult};
+use crate::events::{ExternalAnswer, QueryEvent};
+use crate::folder::{fold_term, Folder};
+use crate::runnable::Runnable;
+use crate::terms::{Symbol, Term};
+use crate::vm::PolarVirtualMachine;
+
+/// The tables of a query, which all of the VMs that run it share.
+#[derive(Debug, Default)]
+pub struct Tables {
+    tables: HashMap<Term, Table>,
+    /// The calls being evaluated, innermost last.
+    evaluating: Vec<Evaluation>,
+    /// Calls whose tables are `Incomplete`.
+    incomplete: Vec<Term>,
+    /// The number of answers in all of the tables, which tells whether an iteration of an
+    /// evaluation found any new ones.
+    answers: usize,
+}
+
+pub type SharedTables = Rc<RefCell<Tables>>;
+
+#[derive(Debug)]
+struct Evaluation {
+    call: Term,
+    /// The outermost evaluation whose answers this one used before they were complete.
+    depends_on: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    answers: Vec<Term>,
+    seen: HashSet<Term>,
+    status: Status,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Status {
+    /// Being evaluated by one of `Tables::evaluating`.
+    #[default]
+    Evaluating,
+    /// Evaluated using the answers of the evaluation at this depth, which is still running.
+    /// The table is completed along with that evaluation, or evaluated again before then if
+    /// it's called again.
+    Incomplete(usize),
+    Complete,
+}
+
+impl Tables {
+    /// The answers for `call`, a `variant` of a call to a tabled rule, or `None` if it must be
+    /// evaluated by a `TableEvaluator`, which this starts.
+    pub fn lookup(&mut self, call: &Term) -> Option<Vec<Term>> {
+        let depth = self.evaluating.iter().position(|e| &e.call == call);
+        match (self.tables.get(call).map(|table| table.status), depth) {
+            (Some(Status::Complete), _) => (),
+            (Some(Status::Evaluating), Some(depth)) => {
+                // Every evaluation since the one of `call` now depends on it.
+                for evaluation in &mut self.evaluating[depth..] {
+                    let depends_on = evaluation.depends_on.map_or(depth, |d| d.min(depth));
+                    evaluation.depends_on = Some(depends_on);
+                }
+            }
+            _ => {
+                self.incomplete.retain(|c| c != call);
+                self.tables.entry(call.clone()).or_default().status = Status::Evaluating;
+                self.evaluating.push(Evaluation {
+                    call: call.clone(),
+                    depends_on: None,
+                });
+                return None;
+            }
+        }
+        Some(self.tables[call].answers.clone())
+    }
+
+    pub fn answers(&self, call: &Term) -> Vec<Term> {
+        self.tables[call].answers.clone()
+    }
+
+    fn add_answer(&mut self, call: &Term, answer: Term) {
+        let table = self.tables.get_mut(call).expect("call has no table");
+        if table.seen.insert(answer.clone()) {
+            table.answers.push(answer);
+            self.answers += 1;
+        }
+    }
+
+    /// Whether the innermost evaluation has to be repeated after an iteration that started when
+    /// there were `answers` answers: it used answers that weren't complete, and new ones were
+    /// found since.
+    fn iterate(&self, answers: usize) -> bool {
+        let evaluation = self.evaluating.last().expect("no evaluation is running");
+        evaluation.depends_on.is_some() && self.answers != answers
+    }
+
+    /// Finish the innermost evaluation. Its table is complete unless it depends on an outer
+    /// evaluation, and so are the incomplete tables that only depend on it.
+    fn finish(&mut self) {
+        let depth = self.evaluating.len() - 1;
+        let Evaluation { call, depends_on } = self.evaluating.pop().unwrap();
+        let status = match depends_on {
+            Some(outer) if outer < depth => Status::Incomplete(outer),
+            _ => Status::Complete,
+        };
+        self.tables.get_mut(&call).unwrap().status = Status::Incomplete(depth);
+        self.incomplete.push(call);
+
+        let tables = &mut self.tables;
+        self.incomplete.retain(|call| {
+            let table = tables.get_mut(call).unwrap();
+            match table.status {
+                Status::Incomplete(d) if d >= depth => {
+                    table.status = status;
+                    status != Status::Complete
+                }
+                _ => true,
+            }
+        });
+    }
+}
+
+/// Rename the unbound variables of a dereferenced term in the order in which they appear, so
+/// that variants are equal.
+pub fn variant(term: Term) -> Term {
+    struct Variant {
+        names: HashMap<Symbol, Symbol>,
+    }
+
+    impl Variant {
+        fn rename(&mut self, v: Symbol) -> Symbol {
+            let n = self.names.len();
+            self.names
+                .entry(v)
+                .or_insert_with(|| Symbol::numbered("_", n as u64))
+                .clone()
+        }
+    }
+
+    impl Folder for Variant {
+        fn fold_variable(&mut self, v: Symbol) -> Symbol {
+            self.rename(v)
+        }
+
+        fn fold_rest_variable(&mut self, r: Symbol) -> Symbol {
+            self.rename(r)
+        }
+    }
+
+    fold_term(
+        term,
+        &mut Variant {
+            names: HashMap::new(),
+        },
+    )
+}
+
+/// Evaluates a tabled call to exhaustion using `vm`, adding the value of `template` in each
+/// result to the call's table. If the evaluation used answers that weren't complete, it's
+/// repeated until it finds no new ones.
+#[derive(Clone)]
+pub struct TableEvaluator {
+    start: PolarVirtualMachine,
+    vm: PolarVirtualMachine,
+    call: Term,
+    template: Term,
+    tables: SharedTables,
+    /// The number of answers when the current iteration started.
+    answers: usize,
+}
+
+impl TableEvaluator {
+    pub fn new(vm: PolarVirtualMachine, call: Term, template: Term, tables: SharedTables) -> Self {
+        let answers = tables.borrow().answers;
+        Self {
+            start: vm.clone(),
+            vm,
+            call,
+            template,
+            tables,
+            answers,
+        }
+    }
+}
+
+impl Runnable for TableEvaluator {
+    fn run(&mut self, _: Option<&mut Counter>) -> PolarResult<QueryEvent> {
+        loop {
+            match self.vm.run(None)? {
+                QueryEvent::Done { .. } => {
+                    let mut tables = self.tables.borrow_mut();
+                    if tables.iterate(self.answers) {
+                        self.answers = tables.answers;
+                        self.vm = self.start.clone();
+                    } else {
+                        tables.finish();
+                        return Ok(QueryEvent::Done { result: true });
+                    }
+                }
+                QueryEvent::Result { .. } => {
+                    let answer = variant(self.vm.deref(&self.template));
+                    self.tables.borrow_mut().add_answer(&self.call, answer);
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
+        self.vm.external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> PolarResult<()> {
+        self.vm.external_call_result(call_id, term)
+    }
+
+    fn external_call_error(&mut self, call_id: u64, message: String) -> PolarResult<()> {
+        self.vm.external_call_error(call_id, message)
+    }
+
+    fn external_batch_result(&mut self, answers: Vec<ExternalAnswer>) -> PolarResult<()> {
+        self.vm.external_batch_result(answers)
+    }
+
+    fn debug_command(&mut self, command: &str) -> PolarResult<()> {
+        self.vm.debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
+        self.vm.handle_error(error)
+    }
+}
@@ -1,14 +1,17 @@
 use std::{
-    collections::{hash_map::DefaultHasher, BTreeMap, HashSet},
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashSet},
     fmt,
     hash::{Hash, Hasher},
     sync::Arc,
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::error::{unexpected_value, PolarResult};
-pub use super::numerics::Numeric;
+use super::kb::KnowledgeBase;
+pub use super::numerics::{Decimal, Numeric};
 use super::resource_block::{ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
 use super::sources::{Context, Source, SourceInfo};
 use super::visitor::{walk_operation, walk_term, Visitor};
@@ -45,6 +48,38 @@ pub struct ExternalInstance {
     pub class_id: Option<u64>,
 }
 
+impl ExternalInstance {
+    /// The tag of the instance's class: the class its constructor called, or else the class the
+    /// host or the knowledge base gave it.
+    pub fn class_tag(&self) -> Option<String> {
+        match self.constructor.as_ref().map(Term::value) {
+            Some(Value::Call(Call { name, .. })) => Some(name.to_string()),
+            _ => self.class_repr.clone(),
+        }
+    }
+
+    /// Whether this is a class itself, like the constant `Role`, rather than an instance of
+    /// one. A class's instance id is its class id.
+    pub fn is_class(&self) -> bool {
+        self.class_id == Some(self.instance_id)
+    }
+
+    pub fn to_ref(&self) -> InstanceRef {
+        InstanceRef {
+            instance_id: self.instance_id,
+            class_tag: self.class_tag(),
+        }
+    }
+}
+
+/// How an external instance in a result is read by `deserialize::from_bindings`, and converted
+/// to JSON: its id, which the host maps back to its object, and its class tag if it's known.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub struct InstanceRef {
+    pub instance_id: u64,
+    pub class_tag: Option<String>,
+}
+
 pub type TermList = Vec<Term>;
 
 /// Return true if the list ends with a rest-variable.
@@ -53,24 +88,146 @@ pub fn has_rest_var(list: &TermList) -> bool {
     !list.is_empty() && matches!(list.last().unwrap().value(), Value::RestVariable(_))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct Symbol(pub String);
+/// A name, like that of a variable, rule or field.
+///
+/// Symbols share their names, so they're cheap to clone and compare. A name that ends in a
+/// number is stored as its prefix and the number, which is how the VM can make as many fresh
+/// variables as it likes from one prefix.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Symbol {
+    prefix: Arc<str>,
+    number: u64,
+}
+
+/// The `number` of a symbol whose name doesn't end in one.
+const NO_NUMBER: u64 = u64::MAX;
 
 impl Symbol {
     pub fn new(name: &str) -> Self {
-        Self(name.to_string())
+        let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let digits = &name[prefix.len()..];
+        // Only split off a number that's written the way `numbered` would write it.
+        match digits.parse() {
+            Ok(number) if number != NO_NUMBER && (digits == "0" || !digits.starts_with('0')) => {
+                Self::numbered(prefix, number)
+            }
+            _ => Self {
+                prefix: name.into(),
+                number: NO_NUMBER,
+            },
+        }
+    }
+
+    /// The symbol named `prefix` followed by `number`. `prefix` mustn't end in a digit.
+    pub fn numbered(prefix: &str, number: u64) -> Self {
+        debug_assert!(!prefix.ends_with(|c: char| c.is_ascii_digit()));
+        Self {
+            prefix: prefix.into(),
+            number,
+        }
+    }
+
+    /// The temporary variable numbered `number` that this one is renamed to, like `_x_12` for `x`.
+    pub fn temporary(&self, number: u64) -> Self {
+        Self::numbered(&KnowledgeBase::temp_prefix(&self.as_str()), number)
+    }
+
+    /// The symbol with this one's prefix followed by `number`, like `_x_12` for `_x_0`.
+    pub fn renumbered(&self, number: u64) -> Self {
+        Self {
+            prefix: self.prefix.clone(),
+            number,
+        }
+    }
+
+    /// The name of the symbol, which is only allocated if it ends in a number.
+    pub fn as_str(&self) -> Cow<'_, str> {
+        if self.number == NO_NUMBER {
+            Cow::Borrowed(&self.prefix)
+        } else {
+            Cow::Owned(format!("{}{}", self.prefix, self.number))
+        }
+    }
+
+    /// Write the name of the symbol without allocating it.
+    pub(crate) fn write_name(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        let mut buf = [0; 20];
+        w.write_str(&self.prefix)?;
+        w.write_str(std::str::from_utf8(self.digits(&mut buf)).unwrap())
+    }
+
+    /// The digits of the symbol's number, written into `buf`, or nothing if it has none.
+    fn digits<'a>(&self, buf: &'a mut [u8; 20]) -> &'a [u8] {
+        let mut start = buf.len();
+        if self.number != NO_NUMBER {
+            let mut number = self.number;
+            loop {
+                start -= 1;
+                buf[start] = b'0' + (number % 10) as u8;
+                number /= 10;
+                if number == 0 {
+                    break;
+                }
+            }
+        }
+        &buf[start..]
     }
 
     pub fn is_temporary_var(&self) -> bool {
-        self.0.starts_with('_')
+        self.prefix.starts_with('_')
     }
 
     pub fn is_namespaced_var(&self) -> bool {
-        self.0.contains("::")
+        self.prefix.contains("::")
     }
 
     pub fn is_this_var(&self) -> bool {
-        self.0 == "_this"
+        self.number == NO_NUMBER && &*self.prefix == "_this"
+    }
+}
+
+/// Symbols are ordered by name, so that e.g. the fields of a dictionary are in the same order
+/// in every process. Comparing two of them doesn't allocate their names.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (mut a, mut b) = ([0; 20], [0; 20]);
+        let (a, b) = (self.digits(&mut a), other.digits(&mut b));
+        let name = self.prefix.bytes().chain(a.iter().copied());
+        name.cmp(other.prefix.bytes().chain(b.iter().copied()))
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Symbol").field(&self.as_str()).finish()
+    }
+}
+
+// Symbols cross the API boundary as their names.
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename = "Symbol")]
+        struct Name<'a>(&'a str);
+
+        Name(&self.as_str()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Symbol")]
+        struct Name(String);
+
+        let Name(name) = Name::deserialize(deserializer)?;
+        Ok(Self::new(&name))
     }
 }
 
@@ -108,6 +265,12 @@ pub enum Operator {
     And,
     ForAll,
     Assign,
+    Conditional,
+    Range,
+    Collect,
+    Aggregate,
+    Distinct,
+    Once,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -156,6 +319,28 @@ impl Value {
     }
 }
 
+impl Value {
+    fn is_variable_free(&self) -> bool {
+        let free = Term::is_variable_free;
+        match self {
+            Value::Number(_) | Value::String(_) | Value::Boolean(_) => true,
+            Value::Variable(_) | Value::RestVariable(_) => false,
+            Value::ExternalInstance(instance) => instance.constructor.iter().all(free),
+            Value::List(terms) => terms.iter().all(free),
+            Value::Dictionary(Dictionary { fields })
+            | Value::Pattern(Pattern::Dictionary(Dictionary { fields }))
+            | Value::Pattern(Pattern::Instance(InstanceLiteral {
+                fields: Dictionary { fields },
+                ..
+            })) => fields.values().all(free),
+            Value::Call(Call { args, kwargs, .. }) => {
+                args.iter().all(free) && kwargs.iter().flat_map(BTreeMap::values).all(free)
+            }
+            Value::Expression(Operation { args, .. }) => args.iter().all(free),
+        }
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -170,12 +355,61 @@ pub struct Term {
     source_info: SourceInfo,
 
     /// The actual underlying value
-    value: Arc<Value>,
+    #[serde(with = "shared_value")]
+    value: Arc<SharedValue>,
+}
+
+/// A term's value, which is shared by its clones.
+///
+/// Values are reference counted instead of being allocated from an arena that's freed when a
+/// query is done, because the terms a query makes can outlive it: its results hold them, and so
+/// do the tables of tabled rules and the host's copies. To make fewer of them, folds share the
+/// variable-free parts of the terms they rebuild, like the ground parameters of a renamed rule,
+/// and a call to a rule makes one value for each of its temporaries.
+#[derive(Clone)]
+struct SharedValue {
+    value: Value,
+    /// Whether the value is known to have no variables in it, so that folds that only replace
+    /// variables can share it instead of rebuilding it.
+    variable_free: bool,
+}
+
+impl SharedValue {
+    fn new(value: Value) -> Self {
+        let variable_free = value.is_variable_free();
+        Self {
+            value,
+            variable_free,
+        }
+    }
+}
+
+impl fmt::Debug for SharedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+mod shared_value {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Arc<SharedValue>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<SharedValue>, D::Error> {
+        Value::deserialize(deserializer).map(|value| Arc::new(SharedValue::new(value)))
+    }
 }
 
 impl PartialEq for Term {
     fn eq(&self, other: &Self) -> bool {
-        self.value == other.value
+        self.value() == other.value()
     }
 }
 
@@ -218,11 +452,6 @@ impl From<Operation> for Value {
     }
 }
 
-impl From<TermList> for Value {
-    fn from(other: TermList) -> Self {
-        Self::List(other)
-    }
-}
 
 impl From<String> for Value {
     fn from(other: String) -> Self {
@@ -270,50 +499,50 @@ impl From<Call> for Value {
 }
 
 impl Term {
-    /// Creates a new term for a temporary variable
-    pub fn new_temporary(value: Value) -> Self {
+    fn new(source_info: SourceInfo, value: Value) -> Self {
         Self {
-            source_info: SourceInfo::TemporaryVariable,
-            value: Arc::new(value),
+            source_info,
+            value: Arc::new(SharedValue::new(value)),
         }
     }
 
+    /// Creates a new term for a temporary variable
+    pub fn new_temporary(value: Value) -> Self {
+        Self::new(SourceInfo::TemporaryVariable, value)
+    }
+
     /// Creates a new term from across the FFI boundary
     pub fn new_from_ffi(value: Value) -> Self {
-        Self {
-            source_info: SourceInfo::Ffi,
-            value: Arc::new(value),
-        }
+        Self::new(SourceInfo::Ffi, value)
     }
 
     /// Creates a new term from the parser
     pub fn new_from_parser(source: Arc<Source>, left: usize, right: usize, value: Value) -> Self {
-        Self {
-            source_info: SourceInfo::parser(source, left, right),
-            value: Arc::new(value),
-        }
+        Self::new(SourceInfo::parser(source, left, right), value)
     }
 
     /// Creates a new term from a test value
     pub fn new_from_test(value: Value) -> Self {
-        Self {
-            source_info: SourceInfo::Test,
-            value: Arc::new(value),
-        }
+        Self::new(SourceInfo::Test, value)
     }
 
     /// Create a new Term, cloning the source info of `self`
     /// but with the new `value`
     pub fn clone_with_value(&self, value: Value) -> Self {
+        Self::new(self.source_info.clone(), value)
+    }
+
+    /// A copy of this term with the value of `other`, which they share.
+    pub(crate) fn with_value_of(&self, other: &Term) -> Self {
         Self {
             source_info: self.source_info.clone(),
-            value: Arc::new(value),
+            value: other.value.clone(),
         }
     }
 
     /// Replace the `value` of self
     pub fn replace_value(&mut self, value: Value) {
-        self.value = Arc::new(value);
+        *self = Self::new(self.source_info.clone(), value);
     }
 
     pub(crate) fn source_info(&self) -> &SourceInfo {
@@ -332,7 +561,7 @@ impl Term {
 
     /// Get a reference to the underlying data of this term
     pub fn value(&self) -> &Value {
-        &self.value
+        &self.value.value
     }
 
     pub(crate) fn as_symbol(&self) -> PolarResult<&Symbol> {
@@ -393,7 +622,16 @@ impl Term {
     /// term with an Arc to the value, otherwise it will be
     /// a clone.
     pub fn mut_value(&mut self) -> &mut Value {
-        Arc::make_mut(&mut self.value)
+        let shared = Arc::make_mut(&mut self.value);
+        // The value may get variables.
+        shared.variable_free = false;
+        &mut shared.value
+    }
+
+    /// Whether the term is known to have no variables in it. This is computed from the subterms
+    /// when the term is made, and is false once its value has been mutated in place.
+    pub fn is_variable_free(&self) -> bool {
+        self.value.variable_free
     }
 
     pub fn is_ground(&self) -> bool {
@@ -421,6 +659,21 @@ impl Term {
         walk_term(&mut VariableVisitor::new(vars), self);
     }
 
+    /// Add the ids of the external instances within a term to `ids`.
+    pub fn instance_ids(&self, ids: &mut BTreeSet<u64>) {
+        struct InstanceVisitor<'set> {
+            ids: &'set mut BTreeSet<u64>,
+        }
+
+        impl<'set> Visitor for InstanceVisitor<'set> {
+            fn visit_external_instance(&mut self, e: &ExternalInstance) {
+                self.ids.insert(e.instance_id);
+            }
+        }
+
+        walk_term(&mut InstanceVisitor { ids }, self);
+    }
+
     /// Does the given variable occur in this term?
     /// Should be much faster than accumulating the set and checking.
     pub fn contains_variable(&self, var: &Symbol) -> bool {
@@ -462,11 +715,11 @@ impl Term {
     }
 
     pub fn is_actor_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == ACTOR_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.as_str() == ACTOR_UNION_NAME)
     }
 
     pub fn is_resource_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == RESOURCE_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.as_str() == RESOURCE_UNION_NAME)
     }
 }
 
@@ -501,4 +754,75 @@ mod tests {
             "b:2"
         );
     }
+
+    #[test]
+    fn test_variable_free() {
+        let parse = |src| crate::parser::parse_query(src).unwrap();
+        assert!(parse(r#"{a: [1, "b", true]}"#).is_variable_free());
+        assert!(!parse("[1, [x]]").is_variable_free());
+        assert!(!parse("f(1, k: y)").is_variable_free());
+        assert!(!parse("1 + x").is_variable_free());
+
+        let mut term = term!([1, 2]);
+        assert!(term.is_variable_free());
+        *term.mut_value() = value!([1]);
+        assert!(!term.is_variable_free());
+
+        let json = serde_json::to_string(&term!([1])).unwrap();
+        assert!(serde_json::from_str::<Term>(&json)
+            .unwrap()
+            .is_variable_free());
+    }
+
+    #[test]
+    fn test_symbol() {
+        // Names are equal however they're made.
+        assert_eq!(Symbol::new("_x_12"), Symbol::numbered("_x_", 12));
+        assert_eq!(Symbol::new("_x_12").as_str(), "_x_12");
+        for name in [
+            "x",
+            "x0",
+            "x007",
+            "",
+            "123",
+            "_x_18446744073709551615",
+            "foo::bar",
+        ] {
+            assert_eq!(Symbol::new(name).as_str(), name);
+        }
+        assert_ne!(Symbol::new("x07"), Symbol::new("x7"));
+
+        assert_eq!(sym!("x").temporary(3), Symbol::new("_x_3"));
+        assert_eq!(sym!("_").temporary(3), Symbol::new("_3"));
+        assert_eq!(Symbol::new("_x_3").temporary(4), Symbol::new("__x_3_4"));
+
+        assert!(Symbol::new("_x_1").is_temporary_var());
+        assert!(!Symbol::new("1").is_temporary_var());
+        assert!(Symbol::new("_this").is_this_var());
+        assert!(!Symbol::new("_this1").is_this_var());
+        assert!(Symbol::new("Foo::bar1").is_namespaced_var());
+
+        // Symbols are ordered by name.
+        let names = [
+            "_x_",
+            "_x_10",
+            "_x_1a",
+            "_x_9",
+            "a1",
+            "a10x",
+            "b",
+            "x18446744073709551615",
+        ];
+        let mut symbols: Vec<_> = names.iter().rev().map(|name| sym!(name)).collect();
+        symbols.sort();
+        assert_eq!(symbols, names.map(|name| sym!(name)));
+        assert_eq!(sym!("_x_10").to_string(), "_x_10");
+
+        let json = serde_json::to_string(&sym!("_x_10")).unwrap();
+        assert_eq!(json, r#""_x_10""#);
+        assert_eq!(
+            serde_json::from_str::<Symbol>(&json).unwrap(),
+            sym!("_x_10")
+        );
+    }
 }
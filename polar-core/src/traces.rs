@@ -1,5 +1,6 @@
 use super::rules::*;
 use super::terms::*;
+use super::vm::PolarVirtualMachine;
 use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 use std::sync::Arc;
@@ -26,8 +27,126 @@ impl Trace {
     }
 }
 
+impl Drop for Trace {
+    // A query is as deep in the trace as it is in the query stack, so drop the tree one node at
+    // a time instead of recursing once per level.
+    fn drop(&mut self) {
+        let mut children = std::mem::take(&mut self.children);
+        while let Some(child) = children.pop() {
+            if let Ok(mut child) = Rc::try_unwrap(child) {
+                children.append(&mut child.children);
+            }
+        }
+    }
+}
+
+impl Trace {
+    /// Add the proof of this node to `proofs`, or the proofs of its children if it's a
+    /// conjunction. `parent` is the term of the node above, which is the call for a rule.
+    pub(crate) fn add_proofs(
+        &self,
+        vm: &PolarVirtualMachine,
+        parent: Option<&Term>,
+        proofs: &mut Vec<Proof>,
+    ) {
+        let step = match &self.node {
+            Node::Term(term)
+                if matches!(
+                    term.value(),
+                    Value::Expression(Operation {
+                        operator: Operator::And,
+                        ..
+                    })
+                ) =>
+            {
+                for child in &self.children {
+                    child.add_proofs(vm, parent, proofs);
+                }
+                return;
+            }
+            Node::Term(term) => ProofStep::Goal {
+                term: resolve(vm, term),
+                source: vm.term_source(term, false),
+            },
+            Node::Rule(rule) => ProofStep::Rule {
+                name: rule.name.clone(),
+                args: parent
+                    .and_then(|term| term.as_call().ok())
+                    .map_or_else(Vec::new, |call| {
+                        call.args.iter().map(|arg| vm.deref(arg)).collect()
+                    }),
+                source: rule.to_string(),
+            },
+        };
+        let term = self.term();
+        let mut children = vec![];
+        for child in &self.children {
+            child.add_proofs(vm, term.as_ref(), &mut children);
+        }
+        proofs.push(Proof { step, children });
+    }
+}
+
+/// `term` with its variables replaced by their values, including the ones in operations, which
+/// `deref` leaves alone.
+pub(crate) fn resolve(vm: &PolarVirtualMachine, term: &Term) -> Term {
+    match term.value() {
+        Value::Expression(Operation { operator, args }) => {
+            term.clone_with_value(Value::Expression(Operation {
+                operator: *operator,
+                args: args.iter().map(|arg| resolve(vm, arg)).collect(),
+            }))
+        }
+        _ => vm.deref(term),
+    }
+}
+
+/// A step in the proof of a result, with the steps that proved it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Proof {
+    pub step: ProofStep,
+    pub children: Vec<Proof>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ProofStep {
+    /// A rule that was applied to a call, proved by the goals in its body.
+    Rule {
+        name: Symbol,
+        /// The arguments of the call, with the values they had in the result.
+        args: Vec<Term>,
+        source: String,
+    },
+    /// A goal, like a call or a unification, with its variables replaced by the values they
+    /// had in the result.
+    Goal { term: Term, source: String },
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TraceResult {
     pub trace: Rc<Trace>,
     pub formatted: String,
+    /// The proof of each conjunct of the query.
+    pub proof: Vec<Proof>,
+}
+
+/// Why a query explained with `Query::set_explain` failed, or how far each of the rules it tried
+/// got if some of them succeeded.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Explanation {
+    /// The deepest failure under each rule that was applied to the query, in the order the rules
+    /// were tried. A query for a rule that has none which apply has none.
+    pub rules: Vec<RuleFailure>,
+}
+
+/// The deepest goal that failed while a rule was being tried.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RuleFailure {
+    pub rule: Arc<Rule>,
+    /// The goal, with its variables replaced by the values they had when it failed.
+    pub goal: Term,
+    pub source: String,
+    /// How many rules deep the goal was: 1 for a goal in the rule's own body, 2 for one in the
+    /// body of a rule it called, and so on.
+    pub depth: usize,
 }
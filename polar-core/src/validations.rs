@@ -1,11 +1,16 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
+use super::builtins::Builtin;
 use super::diagnostic::Diagnostic;
 use super::error::{PolarError, ValidationError};
 use super::kb::*;
+use super::rewrites::unwrap_and;
 use super::rules::*;
 use super::terms::*;
 use super::visitor::{walk_call, walk_rule, walk_term, Visitor};
+use super::vm::compare;
 use super::warning::ValidationWarning;
 
 /// Record singleton variables and unknown specializers in a rule.
@@ -70,6 +75,61 @@ pub fn check_singletons(rule: &Rule, kb: &KnowledgeBase) -> Vec<Diagnostic> {
     visitor.warnings()
 }
 
+/// Warn about `rule` if it's the same as one of the rules in `loaded`, and add it to them.
+/// Rules are compared before they're rewritten, since rewriting names temporary variables
+/// differently in each rule. `loaded` holds a hash of each rule's name, parameters and body,
+/// since a rule's terms can't be keys themselves.
+pub fn check_duplicate_rule(rule: &Rule, loaded: &mut HashSet<u64>) -> Option<Diagnostic> {
+    let mut hasher = DefaultHasher::new();
+    (&rule.name, &rule.params, &rule.body).hash(&mut hasher);
+    if loaded.insert(hasher.finish()) {
+        return None;
+    }
+    let rule = rule.clone();
+    Some(Diagnostic::Warning(
+        ValidationWarning::DuplicateRule { rule }.into(),
+    ))
+}
+
+/// Warn about `rule` if it can never match because one of the conditions of its body is
+/// always false: `false` itself, or a comparison of literals like `1 = 2` or `"a" > "b"`.
+pub fn check_unsatisfiable_rule(rule: &Rule) -> Option<Diagnostic> {
+    fn is_literal(term: &Term) -> bool {
+        matches!(
+            term.value(),
+            Value::Boolean(_) | Value::Number(_) | Value::String(_)
+        )
+    }
+
+    fn is_always_false(term: &Term) -> bool {
+        use Operator::*;
+        match term.value() {
+            Value::Boolean(false) => true,
+            Value::Expression(Operation { operator, args })
+                if args.len() == 2 && args.iter().all(is_literal) =>
+            {
+                match operator {
+                    Unify => args[0] != args[1],
+                    Eq | Neq | Lt | Leq | Gt | Geq => {
+                        matches!(
+                            compare(*operator, &args[0], &args[1], Some(term)),
+                            Ok(false)
+                        )
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    let term = unwrap_and(&rule.body).into_iter().find(is_always_false)?;
+    let rule = rule.clone();
+    Some(Diagnostic::Warning(
+        ValidationWarning::UnsatisfiableRule { rule, term }.into(),
+    ))
+}
+
 struct AndOrPrecendenceCheck {
     unparenthesized_expr: Vec<Term>,
 }
@@ -140,7 +200,7 @@ struct ResourceBlocksMissingHasPermissionVisitor {
 
 impl Visitor for ResourceBlocksMissingHasPermissionVisitor {
     fn visit_call(&mut self, call: &Call) {
-        if call.name.0 == "has_permission" {
+        if call.name.as_str() == "has_permission" {
             self.calls_has_permission = true;
         }
         walk_call(self, call)
@@ -193,8 +253,10 @@ impl<'kb> UndefinedRuleCallVisitor<'kb> {
         self.call_terms
             .into_iter()
             .filter(|term| {
-                term.as_call()
-                    .map_or(false, |call| !self.defined_rules.contains(&call.name))
+                term.as_call().map_or(false, |call| {
+                    !self.defined_rules.contains(&call.name)
+                        && Builtin::from_name(&call.name.as_str()).is_none()
+                })
             })
             .map(|term| PolarError::from(ValidationError::UndefinedRuleCall { term }).into())
             .collect()
@@ -217,17 +279,144 @@ impl<'kb> Visitor for UndefinedRuleCallVisitor<'kb> {
 }
 
 pub fn check_undefined_rule_calls(kb: &KnowledgeBase) -> Vec<Diagnostic> {
-    let mut visitor = UndefinedRuleCallVisitor::new(kb.get_rules().keys().collect());
+    let defined = kb.get_rules().keys().chain(kb.native_builtin_names());
+    let mut visitor = UndefinedRuleCallVisitor::new(defined.collect());
     for rule in kb.get_rules().values() {
         visitor.visit_generic_rule(rule);
     }
     visitor.errors()
 }
 
+/// Whether a rule body could succeed if the rules in `productive` can, looking only at the rule
+/// calls in its conjunctions and disjunctions. Calls to rules that aren't defined are left to
+/// `check_undefined_rule_calls`.
+fn may_succeed(body: &Term, kb: &KnowledgeBase, productive: &HashSet<Symbol>) -> bool {
+    match body.value() {
+        Value::Call(call) => {
+            productive.contains(&call.name) || kb.get_generic_rule(&call.name).is_none()
+        }
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }) => args.iter().all(|arg| may_succeed(arg, kb, productive)),
+        Value::Expression(Operation {
+            operator: Operator::Or,
+            args,
+        }) => args.iter().any(|arg| may_succeed(arg, kb, productive)),
+        _ => true,
+    }
+}
+
+/// The rules called in the conjunctions and disjunctions of a rule body.
+fn called_rules(body: &Term, calls: &mut HashSet<Symbol>) {
+    match body.value() {
+        Value::Call(call) => {
+            calls.insert(call.name.clone());
+        }
+        Value::Expression(Operation {
+            operator: Operator::And | Operator::Or,
+            args,
+        }) => args.iter().for_each(|arg| called_rules(arg, calls)),
+        _ => (),
+    }
+}
+
+/// Warn about rules that call each other in a cycle with no base case, such as
+/// `f(x) if f(x);`. None of them can ever succeed, since every one of their rules calls one of
+/// them again, so a query for one either fails or never ends.
+pub fn check_unguarded_recursion(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    // The rules that can succeed, found by adding the ones with a body that can succeed using
+    // the ones found so far until there are no more.
+    let mut productive = HashSet::new();
+    loop {
+        let found = kb
+            .get_rules()
+            .iter()
+            .filter(|(name, _)| !productive.contains(*name))
+            .filter(|(_, generic_rule)| {
+                generic_rule
+                    .rules
+                    .values()
+                    .any(|rule| may_succeed(&rule.body, kb, &productive))
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            break;
+        }
+        productive.extend(found);
+    }
+
+    // The rules that can't succeed call each other, and call only other rules that can't. Each
+    // cycle of them is reported once, at its first rule.
+    let calls = kb
+        .get_rules()
+        .iter()
+        .filter(|(name, _)| !productive.contains(*name))
+        .map(|(name, generic_rule)| {
+            let mut calls = HashSet::new();
+            for rule in generic_rule.rules.values() {
+                called_rules(&rule.body, &mut calls);
+            }
+            calls.retain(|call| kb.get_generic_rule(call).is_some() && !productive.contains(call));
+            (name.clone(), calls)
+        })
+        .collect::<HashMap<_, _>>();
+    let reachable = |from: Symbol| {
+        let mut seen = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(name) = stack.pop() {
+            for call in &calls[&name] {
+                if seen.insert(call.clone()) {
+                    stack.push(call.clone());
+                }
+            }
+        }
+        seen
+    };
+    let reachable = calls
+        .keys()
+        .map(|name| (name.clone(), reachable(name.clone())))
+        .collect::<HashMap<_, _>>();
+
+    let mut cycles = reachable
+        .iter()
+        .filter(|(name, reached)| reached.contains(*name))
+        .map(|(name, reached)| {
+            let mut cycle = reached
+                .iter()
+                .filter(|other| reachable[*other].contains(name))
+                .cloned()
+                .collect::<Vec<_>>();
+            cycle.sort();
+            cycle
+        })
+        .collect::<Vec<_>>();
+    cycles.sort();
+    cycles.dedup();
+
+    cycles
+        .into_iter()
+        .map(|cycle| {
+            let rule = cycle
+                .iter()
+                .flat_map(|name| kb.get_rules()[name].rules.values())
+                .min_by_key(|rule| {
+                    rule.parsed_context()
+                        .map(|c| (c.source.filename.clone(), c.left))
+                })
+                .map(|rule| rule.as_ref().clone())
+                .expect("a rule in the cycle");
+            Diagnostic::Warning(ValidationWarning::UnguardedRecursion { rule, cycle }.into())
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::kb::KnowledgeBase;
+    use crate::warning::PolarWarning;
 
     #[test]
     fn test_check_no_allow_rule_no_allow() {
@@ -311,4 +500,51 @@ mod tests {
         kb.add_rule(rule!("defined_rule", [sym!("x")]));
         assert!(check_undefined_rule_calls(&kb).is_empty());
     }
+
+    #[test]
+    fn test_unguarded_recursion_warning() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(rule!("f", [sym!("x")] => call!("f", [sym!("x")])));
+        // `h` can't succeed either, but it's only reported as part of the cycle it calls.
+        kb.add_rule(rule!("g", [sym!("x")] => call!("h", [sym!("x")])));
+        kb.add_rule(rule!("h", [sym!("x")] => call!("i", [sym!("x")])));
+        kb.add_rule(rule!("i", [sym!("x")] => call!("h", [sym!("x")]), call!("f", [sym!("x")])));
+        kb.add_rule(rule!("i", [sym!("x")] => call!("g", [sym!("x")])));
+
+        let warnings = check_unguarded_recursion(&kb);
+        let cycles = warnings
+            .iter()
+            .map(|warning| match warning {
+                Diagnostic::Warning(PolarWarning(ValidationWarning::UnguardedRecursion {
+                    cycle,
+                    ..
+                })) => cycle
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>(),
+                _ => panic!("unexpected diagnostic: {}", warning),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cycles, vec![vec!["f"], vec!["g", "h", "i"]]);
+        assert!(warnings[0]
+            .to_string()
+            .starts_with("Every `f` rule calls `f` again"));
+    }
+
+    #[test]
+    fn test_unguarded_recursion_clean() {
+        let mut kb = KnowledgeBase::new();
+        // A base case.
+        kb.add_rule(rule!("f", [0]));
+        kb.add_rule(rule!("f", [sym!("x")] => call!("f", [sym!("x")])));
+        // A base case in another rule of the cycle.
+        kb.add_rule(rule!("g", [sym!("x")] => call!("h", [sym!("x")])));
+        kb.add_rule(rule!("h", [sym!("x")] => call!("g", [sym!("x")])));
+        kb.add_rule(rule!("h", [sym!("x")] => call!("f", [sym!("x")])));
+        // A disjunct without the recursive call.
+        kb.add_rule(rule!("i", [sym!("x")] => op!(Or, term!(call!("i", [sym!("x")])), term!(op!(Unify, term!(sym!("x")), term!(1))))));
+        // A call in a negation, which isn't followed.
+        kb.add_rule(rule!("j", [sym!("x")] => op!(Not, term!(call!("j", [sym!("x")])))));
+        assert!(check_unguarded_recursion(&kb).is_empty());
+    }
 }
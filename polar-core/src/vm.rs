@@ -1,10 +1,11 @@
-use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::rc::Rc;
 use std::string::ToString;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::time::Duration;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -12,8 +13,11 @@ use wasm_bindgen::prelude::*;
 use crate::bindings::{
     Binding, BindingManager, BindingStack, Bindings, Bsp, FollowerId, VariableState,
 };
+use crate::builtins::{Builtin, BuiltinAnswer, NativeBuiltin, Regexes};
+use crate::collector::{Aggregate, Collector};
 use crate::counter::Counter;
 use crate::data_filtering::partition_equivs;
+use crate::datetime::{format_datetime, system_clock, Clock};
 use crate::debugger::{get_binding_for_var, DebugEvent, Debugger};
 use crate::error::{invalid_state, unsupported, PolarError, PolarResult, RuntimeError};
 use crate::events::*;
@@ -22,16 +26,21 @@ use crate::inverter::Inverter;
 use crate::kb::*;
 use crate::messages::*;
 use crate::numerics::*;
+use crate::parallel;
 use crate::partial::{simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck};
+use crate::query::CancelToken;
 use crate::rewrites::Renamer;
 use crate::rules::*;
 use crate::runnable::Runnable;
 use crate::sources::Context;
+use crate::stack::{Stack, MAX_FREE};
+use crate::stats::QueryStats;
+use crate::tabling::{variant, SharedTables, TableEvaluator};
 use crate::terms::*;
 use crate::traces::*;
 use crate::visitor::{walk_term, Visitor};
 
-pub const MAX_STACK_SIZE: usize = 10_000;
+pub const MAX_STACK_SIZE: usize = 1_000_000;
 pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
 
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
@@ -99,6 +108,10 @@ pub enum Goal {
         call_id: u64,
         iterable: Term,
     },
+    /// Send the host `calls` at once, as an `ExternalBatch`.
+    ExternalBatch {
+        calls: Vec<QueryEvent>,
+    },
     CheckError,
     Noop,
     Query {
@@ -143,6 +156,40 @@ pub enum Goal {
     AddConstraintsBatch {
         add_constraints: Rc<RefCell<Bindings>>,
     },
+
+    /// Unify `term` with the list of values collected by a `Collector`, or with their aggregate.
+    UnifyCollected {
+        results: Rc<RefCell<Vec<Term>>>,
+        aggregate: Option<Aggregate>,
+        /// The comprehension or aggregate that errors point at.
+        expression: Term,
+        term: Term,
+    },
+
+    /// Unify `args` with each answer in the table of the tabled `call`.
+    UnifyAnswers {
+        call: Term,
+        args: Term,
+    },
+
+    /// Unify the variables of `disjunct` with each result that its thread streams in turn.
+    UnifyStreamed {
+        disjunct: Term,
+        stream: Rc<RefCell<parallel::Stream>>,
+    },
+
+    /// Fail the first `skip` results of a disjunct that's queried again after its thread
+    /// returned them, and stop muting messages after that.
+    SkipStreamed {
+        skip: Rc<Cell<usize>>,
+    },
+
+    /// Fail if the values of `vars` are in `seen`, and add them to it otherwise. `seen` isn't
+    /// restored on backtracking, so that it keeps the values of every earlier result.
+    CheckDistinct {
+        vars: Term,
+        seen: Rc<RefCell<HashSet<Term>>>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -153,24 +200,34 @@ pub struct Choice {
     queries: Queries,      // query stack snapshot
     trace: Vec<Rc<Trace>>, // trace snapshot
     trace_stack: TraceStack,
+    term_bytes: usize,
+    rule_depth: usize,
 }
 
 pub type Choices = Vec<Choice>;
 /// Shortcut type alias for a list of goals
 pub type Goals = Vec<Goal>;
-pub type TraceStack = Vec<Rc<Vec<Rc<Trace>>>>;
+pub type TraceStack = Stack<Rc<Vec<Rc<Trace>>>>;
 
 #[derive(Clone, Debug, Default)]
-pub struct GoalStack(Vec<Rc<Goal>>);
+pub struct GoalStack(Stack<Rc<Goal>>);
 
 impl GoalStack {
     fn new_reversed(goals: Goals) -> Self {
         Self(goals.into_iter().rev().map(Rc::new).collect())
     }
+
+    /// Push the goals of `other` so that its top is on top.
+    fn append(&mut self, other: &Self) {
+        let goals = other.iter().cloned().collect::<Vec<_>>();
+        for goal in goals.into_iter().rev() {
+            self.push(goal);
+        }
+    }
 }
 
 impl std::ops::Deref for GoalStack {
-    type Target = Vec<Rc<Goal>>;
+    type Target = Stack<Rc<Goal>>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -183,7 +240,30 @@ impl std::ops::DerefMut for GoalStack {
     }
 }
 
-pub type Queries = TermList;
+pub type Queries = Stack<Term>;
+
+/// An external lookup: the instance id, the attribute or method, and the arguments of a method
+/// call.
+type LookupKey = (
+    u64,
+    Symbol,
+    Option<Vec<Term>>,
+    Option<BTreeMap<Symbol, Term>>,
+);
+
+/// An estimate of the memory a value takes beyond the term that holds it, not counting the
+/// terms it holds, which were counted when they were bound.
+fn term_size(term: &Term) -> usize {
+    let terms = match term.value() {
+        Value::String(s) => return std::mem::size_of::<Term>() + s.len(),
+        Value::List(terms) => terms.len(),
+        Value::Dictionary(dict) => dict.fields.len() * 2,
+        Value::Call(call) => call.args.len() + call.kwargs.as_ref().map_or(0, |k| k.len() * 2),
+        Value::Expression(op) => op.args.len(),
+        _ => 0,
+    };
+    (terms + 1) * std::mem::size_of::<Term>()
+}
 
 pub fn compare(
     op: Operator,
@@ -237,12 +317,63 @@ pub struct PolarVirtualMachine {
 
     // Errors from outside the vm.
     pub external_error: Option<String>,
+    /// Whether an error the host reports for an external call only fails the branch that made
+    /// the call, instead of ending the query.
+    isolate_application_errors: bool,
+    /// Whether to send the host calls that don't depend on each other in one `ExternalBatch`.
+    batch_external_calls: bool,
+    /// The calls of the batch the host was sent last and hasn't answered.
+    batch: Vec<QueryEvent>,
+    /// The host's answers to the `ExternalIsSubSpecializer` checks it was sent in batches, by
+    /// instance id and the tags of the left and right classes.
+    subspecializers: HashMap<(u64, Symbol, Symbol), bool>,
+    /// Whether to reuse the host's first answer to an external lookup for the rest of the query.
+    cache_lookups: bool,
+    /// The host's answers to external lookups, if they're cached, shared with the VMs of
+    /// subqueries.
+    lookup_cache: Rc<RefCell<HashMap<LookupKey, Option<Term>>>>,
+    /// The lookups the host was sent whose answers are to be cached, by call id.
+    pending_lookups: HashMap<u64, LookupKey>,
 
     #[cfg(not(target_arch = "wasm32"))]
     query_start_time: Option<std::time::Instant>,
     #[cfg(target_arch = "wasm32")]
     query_start_time: Option<f64>,
     query_timeout_ms: u64,
+    /// Maximum number of goals to run, or 0 for no limit.
+    step_limit: u64,
+    /// What the query has done so far, shared with the VMs of subqueries so that their goals
+    /// count toward the step limit.
+    stats: Rc<Cell<QueryStats>>,
+    /// The ids of the instances made for the query or passed into it, shared with the VMs of
+    /// subqueries.
+    instances: Rc<RefCell<BTreeSet<u64>>>,
+    /// The ids of the instances the query made with `new`, shared with the VMs of subqueries.
+    made_instances: Rc<RefCell<BTreeSet<u64>>>,
+    /// Maximum estimated memory use in bytes, or 0 for no limit.
+    memory_limit: u64,
+    /// The estimated memory used by the VM that this one runs a subquery for, and by the values
+    /// it has collected in it, besides the bindings they share.
+    memory_base: usize,
+    /// The estimated size of the values bound since the query started.
+    term_bytes: usize,
+    /// Set by the host to stop the query, which its subqueries share.
+    cancel: CancelToken,
+    /// The steps and memory used by the threads that the query's disjuncts are evaluated on and
+    /// by the query, if it has any.
+    usage: Option<parallel::UsageShare>,
+    /// Maximum number of nested rule calls, or 0 for no limit.
+    depth_limit: usize,
+    /// The number of rule calls that the VM this one runs a subquery for is nested in.
+    depth_base: usize,
+    /// The number of rule calls the VM is nested in, counting the ones that haven't returned and
+    /// not the ones that last-call optimization has popped.
+    rule_depth: usize,
+    /// The number of results to return before the query stops looking for more, if there's a
+    /// limit. Subqueries have none.
+    result_limit: Option<usize>,
+    /// The number of results returned so far.
+    results: usize,
 
     /// Maximum size of goal stack
     stack_limit: usize,
@@ -268,9 +399,33 @@ pub struct PolarVirtualMachine {
     // Other flags.
     pub query_contains_partial: bool,
     pub inverting: bool,
+    /// Whether this VM runs a query on behalf of another VM, as for a negation.
+    pub(crate) subquery: bool,
+    /// Whether to table calls to every rule, and not only to the ones declared with `table`.
+    tabling: bool,
+    tables: SharedTables,
+    /// Whether to evaluate the independent disjuncts of a top-level disjunction in parallel.
+    parallel_disjuncts: bool,
+    /// The deepest failure under each rule applied to the query, if the query is explained.
+    explanation: Option<Explanation>,
+    /// The rule applied to the query that's being tried, if the query is explained.
+    explained_rule: Option<Arc<Rule>>,
+
+    /// Patterns compiled by the `matches_regex` built-in.
+    regexes: Regexes,
+
+    /// Source of the time returned by the `now` built-in.
+    clock: Clock,
 
     /// Output messages.
     pub messages: MessageQueue,
+    /// Whether to drop printed messages, while a disjunct is queried again for results that its
+    /// thread already returned along with them.
+    muted: Rc<Cell<bool>>,
+
+    /// Goals that have been run, and that nothing else refers to, to push new goals into
+    /// instead of allocating them.
+    free_goals: Vec<Rc<Goal>>,
 }
 
 impl Default for PolarVirtualMachine {
@@ -317,14 +472,35 @@ impl PolarVirtualMachine {
             binding_manager: BindingManager::new(),
             query_start_time: None,
             query_timeout_ms,
+            step_limit: 0,
+            stats: Rc::default(),
+            instances: Rc::default(),
+            made_instances: Rc::default(),
+            memory_limit: 0,
+            memory_base: 0,
+            term_bytes: 0,
+            cancel: CancelToken::default(),
+            usage: None,
+            depth_limit: 0,
+            depth_base: 0,
+            rule_depth: 0,
+            result_limit: None,
+            results: 0,
             stack_limit: MAX_STACK_SIZE,
             csp: Bsp::default(),
             choices: vec![],
-            queries: vec![],
+            queries: Queries::new(),
             tracing,
-            trace_stack: vec![],
+            trace_stack: TraceStack::new(),
             trace: vec![],
             external_error: None,
+            isolate_application_errors: false,
+            batch_external_calls: false,
+            batch: vec![],
+            subspecializers: HashMap::new(),
+            cache_lookups: false,
+            lookup_cache: Rc::default(),
+            pending_lookups: HashMap::new(),
             debugger: Debugger::default(),
             kb,
             call_id_symbols: HashMap::new(),
@@ -335,7 +511,17 @@ impl PolarVirtualMachine {
             polar_trace_mute: false,
             query_contains_partial: false,
             inverting: false,
+            subquery: false,
+            tabling: false,
+            tables: SharedTables::default(),
+            parallel_disjuncts: false,
+            explanation: None,
+            explained_rule: None,
+            regexes: Regexes::new(),
+            clock: system_clock(),
             messages,
+            muted: Rc::default(),
+            free_goals: vec![],
         };
         vm.bind_constants(constants);
         vm.query_contains_partial();
@@ -346,6 +532,51 @@ impl PolarVirtualMachine {
         vm
     }
 
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Set the query timeout in milliseconds, or disable it with 0.
+    pub fn set_query_timeout(&mut self, timeout_ms: u64) {
+        self.query_timeout_ms = timeout_ms;
+    }
+
+    /// Limit the number of goals the query runs, or remove the limit with 0.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = limit;
+    }
+
+    /// Limit the estimated memory the query uses to `limit` bytes, or remove the limit with 0.
+    pub fn set_memory_limit(&mut self, limit: u64) {
+        self.memory_limit = limit;
+    }
+
+    /// Limit how deeply rule calls can be nested, or remove the limit with 0.
+    pub fn set_depth_limit(&mut self, limit: usize) {
+        self.depth_limit = limit;
+    }
+
+    /// Stop once the query has returned `limit` results in all, without backtracking to look
+    /// for the next one, or remove the limit with `None`. The goals and choices are kept, so
+    /// raising the limit carries on from the last result.
+    pub fn set_result_limit(&mut self, limit: Option<usize>) {
+        self.result_limit = limit;
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Stop the query when `cancel` is, instead of when its own token is.
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    /// Count the query's steps and memory toward `usage`, which its limits then apply to.
+    pub(crate) fn share_usage(&mut self, usage: parallel::UsageShare) {
+        self.usage = Some(usage);
+    }
+
     pub fn set_logging_options(&mut self, rust_log: Option<String>, polar_log: Option<String>) {
         let polar_log = polar_log.unwrap_or_default();
         let polar_log_vars: HashSet<String> = polar_log
@@ -406,26 +637,200 @@ impl PolarVirtualMachine {
         });
     }
 
+    /// The unbound variables in `negated`, which a result of the negation must not bind if they
+    /// are also used outside of it. Whether they are is only worked out by `shared_variables`
+    /// when a result binds one of them, since that takes a walk over every running query.
+    fn negated_unbound_variables(&self, negated: &Term) -> Vec<Symbol> {
+        let mut vars = HashSet::new();
+        negated.variables(&mut vars);
+        let mut unbound = HashSet::new();
+        for var in vars {
+            self.deref(&term!(var)).variables(&mut unbound);
+        }
+        // Negations of partials are inverted into constraints instead.
+        if unbound
+            .iter()
+            .any(|v| matches!(self.variable_state(v), VariableState::Partial))
+        {
+            return vec![];
+        }
+        let mut unbound = unbound
+            .into_iter()
+            .filter(|v| matches!(self.variable_state(v), VariableState::Unbound))
+            .collect::<Vec<_>>();
+        unbound.sort();
+        unbound
+    }
+
+    /// The variables of `unbound` that are used outside of the negation `term`, in the `queries`
+    /// that were running when it started at `bsp`. If the negated query binds one of them, the
+    /// negation would fail, although it holds for other values of that variable.
+    pub(crate) fn shared_variables(
+        &self,
+        term: &Term,
+        unbound: &[Symbol],
+        queries: &Queries,
+        subquery: bool,
+        bsp: &Bsp,
+    ) -> Vec<Symbol> {
+        struct OutsideVisitor<'term> {
+            negation: &'term Term,
+            vars: HashSet<Symbol>,
+        }
+
+        impl<'term> Visitor for OutsideVisitor<'term> {
+            fn visit_term(&mut self, t: &Term) {
+                if t != self.negation {
+                    walk_term(self, t)
+                }
+            }
+
+            fn visit_variable(&mut self, v: &Symbol) {
+                self.vars.insert(v.clone());
+            }
+        }
+
+        // The negation is the last query that's running, unless it's the double negation that a
+        // `forall` is queried as. The variables of the first query are its results, so they're
+        // used outside of the negation even if it's the negation, unless a `forall` quantifies
+        // them. A subquery's results are only used by the VM that runs it.
+        let forall = queries.iter().nth(1).filter(|query| {
+            matches!(
+                query.value(),
+                Value::Expression(Operation {
+                    operator: Operator::ForAll,
+                    ..
+                })
+            )
+        });
+        let mut visitor = OutsideVisitor {
+            negation: forall.unwrap_or(term),
+            vars: HashSet::new(),
+        };
+        for query in queries.iter().skip(1) {
+            visitor.visit_term(query);
+        }
+        if let (None, false, Some(query)) = (forall, subquery, queries.iter().last()) {
+            query.variables(&mut visitor.vars);
+        }
+
+        // Dereference them as they were before the negation bound anything.
+        let mut bindings = self.binding_manager.clone();
+        bindings.backtrack(bsp);
+        let mut outside = HashSet::new();
+        for var in visitor.vars {
+            bindings.deep_deref(&term!(var)).variables(&mut outside);
+        }
+
+        unbound
+            .iter()
+            .filter(|v| outside.contains(v))
+            .cloned()
+            .collect()
+    }
+
     #[cfg(test)]
     pub fn new_test(kb: Arc<RwLock<KnowledgeBase>>, tracing: bool, goals: Goals) -> Self {
         PolarVirtualMachine::new(kb, tracing, goals, MessageQueue::new())
     }
 
     /// Clone self, replacing the goal stack and retaining only the current bindings.
-    pub fn clone_with_goals(&self, goals: Goals) -> Self {
+    pub fn clone_with_goals(&mut self, goals: Goals) -> Self {
         let mut vm = Self::new(self.kb.clone(), self.tracing, goals, self.messages.clone());
-        vm.binding_manager.clone_from(&self.binding_manager);
+        vm.binding_manager = self.binding_manager.fork();
         vm.query_contains_partial = self.query_contains_partial;
+        vm.subquery = true;
         vm.debugger = self.debugger.clone();
+        vm.clock = self.clock.clone();
+        vm.query_start_time = self.query_start_time;
+        vm.query_timeout_ms = self.query_timeout_ms;
+        vm.step_limit = self.step_limit;
+        vm.stats = self.stats.clone();
+        vm.instances = self.instances.clone();
+        vm.made_instances = self.made_instances.clone();
+        vm.memory_limit = self.memory_limit;
+        vm.memory_base = self.unshared_memory();
+        vm.cancel = self.cancel.clone();
+        vm.usage = self.usage.clone();
+        vm.muted = self.muted.clone();
+        vm.depth_limit = self.depth_limit;
+        vm.depth_base = self.depth();
+        vm.tabling = self.tabling;
+        vm.tables = self.tables.clone();
+        vm.batch_external_calls = self.batch_external_calls;
+        vm.cache_lookups = self.cache_lookups;
+        vm.lookup_cache = self.lookup_cache.clone();
         vm
     }
 
+    pub fn set_isolate_application_errors(&mut self, isolate: bool) {
+        self.isolate_application_errors = isolate;
+    }
+
+    pub fn set_batch_external_calls(&mut self, batch: bool) {
+        self.batch_external_calls = batch;
+    }
+
+    pub fn set_cache_lookups(&mut self, cache: bool) {
+        self.cache_lookups = cache;
+    }
+
+    /// Table calls to every rule, and not only to the ones declared with `table`.
+    pub fn set_tabling(&mut self, tabling: bool) {
+        self.tabling = tabling;
+    }
+
+    /// Evaluate the disjuncts of the query on a thread pool if it's a disjunction of ones that
+    /// share no unbound variables.
+    pub fn set_parallel_disjuncts(&mut self, parallel: bool) {
+        self.parallel_disjuncts = parallel;
+    }
+
+    /// Record why the query fails, for `explanation`.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explanation = explain.then(Explanation::default);
+    }
+
+    pub fn explanation(&self) -> Option<&Explanation> {
+        self.explanation.as_ref()
+    }
+
+    /// Record the goal that's failing under the rule applied to the query that's being tried,
+    /// if it's the deepest one under that rule so far.
+    fn explain_failure(&mut self) {
+        let (Some(rule), Some(goal)) = (&self.explained_rule, self.queries.last()) else {
+            return;
+        };
+        if self.rule_depth == 0 {
+            return;
+        }
+        let failure = RuleFailure {
+            rule: Arc::clone(rule),
+            goal: resolve(self, goal),
+            source: self.term_source(goal, false),
+            depth: self.rule_depth,
+        };
+        let Some(explanation) = self.explanation.as_mut() else {
+            return;
+        };
+        let recorded = explanation
+            .rules
+            .iter_mut()
+            .find(|recorded| Arc::ptr_eq(&recorded.rule, &failure.rule));
+        match recorded {
+            // The latest failure of equal depth got further through the rule's body.
+            Some(recorded) if recorded.depth <= failure.depth => *recorded = failure,
+            Some(_) => (),
+            None => explanation.rules.push(failure),
+        }
+    }
+
     #[cfg(test)]
     fn set_stack_limit(&mut self, limit: usize) {
         self.stack_limit = limit;
     }
 
-    fn kb(&self) -> RwLockReadGuard<KnowledgeBase> {
+    pub(crate) fn kb(&self) -> RwLockReadGuard<KnowledgeBase> {
         self.kb.read().unwrap()
     }
 
@@ -461,7 +866,10 @@ impl PolarVirtualMachine {
     fn next(&mut self, goal: Rc<Goal>) -> PolarResult<QueryEvent> {
         self.log(LogLevel::Trace, || goal.to_string(), &[]);
 
+        self.check_cancelled()?;
         self.check_timeout()?;
+        self.check_step_limit()?;
+        self.check_memory_limit()?;
 
         match goal.as_ref() {
             Goal::Backtrack => self.backtrack()?,
@@ -493,9 +901,13 @@ impl PolarVirtualMachine {
             Goal::NextExternal { call_id, iterable } => {
                 return self.next_external(*call_id, iterable)
             }
+            Goal::ExternalBatch { calls } => return Ok(self.external_batch(calls)),
             Goal::CheckError => return self.check_error(),
             Goal::Noop => {}
             Goal::Query { term } => {
+                if matches!(term.value(), Value::Call(_)) {
+                    self.pop_finished_frames();
+                }
                 let result = self.query(term);
                 self.maybe_break(DebugEvent::Query)?;
                 return result;
@@ -513,23 +925,25 @@ impl PolarVirtualMachine {
                 args,
             } => self.sort_rules(rules, args, *outer, *inner)?,
             Goal::TraceStackPush => {
+                if let Some(name) = self.running_rule().map(|rule| rule.name.clone()) {
+                    self.rule_depth += 1;
+                    self.check_depth_limit(name)?;
+                }
                 self.trace_stack.push(Rc::new(self.trace.clone()));
                 self.trace = vec![];
             }
             Goal::TraceStackPop => {
-                let mut children = self.trace.clone();
-                self.trace = self.trace_stack.pop().unwrap().as_ref().clone();
-                let mut trace = self.trace.pop().unwrap();
-                let trace = Rc::make_mut(&mut trace);
-                trace.children.append(&mut children);
-                self.trace.push(Rc::new(trace.clone()));
+                self.pop_trace_stack();
                 self.maybe_break(DebugEvent::Pop)?;
             }
             Goal::TraceRule { trace } => {
                 if let Node::Rule(rule) = &trace.node {
                     self.log(LogLevel::Info, || format!("RULE: {}", rule), &[]);
+                    if self.explanation.is_some() && self.rule_depth == 0 {
+                        self.explained_rule = Some(rule.clone());
+                    }
                 }
-                self.trace.push(trace.clone());
+                self.push_trace(trace.clone());
                 self.maybe_break(DebugEvent::Rule)?;
             }
             Goal::Unify { left, right } => self.unify(left, right)?,
@@ -540,7 +954,45 @@ impl PolarVirtualMachine {
                     .drain()
                     .try_for_each(|(_, constraint)| self.add_constraint(&constraint))?
             }
+            Goal::UnifyCollected {
+                results,
+                aggregate,
+                expression,
+                term,
+            } => {
+                let values = results.borrow_mut().drain(..).collect();
+                let value = match aggregate {
+                    None => Some(Value::List(values)),
+                    Some(aggregate) => self.aggregate(*aggregate, values, expression)?,
+                };
+                match value {
+                    Some(value) => self.push_goal(Goal::Unify {
+                        left: term.clone(),
+                        right: term.clone_with_value(value),
+                    })?,
+                    // There's no minimum or maximum of nothing.
+                    None => self.push_goal(Goal::Backtrack)?,
+                }
+            }
             Goal::Run { runnable } => return self.run_runnable(runnable.clone_runnable()),
+            Goal::UnifyAnswers { call, args } => {
+                let answers = self.tables.borrow().answers(call);
+                self.unify_answers(args, answers)?
+            }
+            Goal::UnifyStreamed { disjunct, stream } => self.unify_streamed(disjunct, stream)?,
+            Goal::SkipStreamed { skip } => {
+                let remaining = skip.get();
+                if remaining > 0 {
+                    skip.set(remaining - 1);
+                    self.muted.set(remaining > 1);
+                    self.backtrack()?;
+                }
+            }
+            Goal::CheckDistinct { vars, seen } => {
+                if !seen.borrow_mut().insert(self.deref(vars)) {
+                    self.backtrack()?;
+                }
+            }
         }
         Ok(QueryEvent::None)
     }
@@ -550,16 +1002,41 @@ impl PolarVirtualMachine {
         use {Goal::*, VariableState::Unbound};
         if self.goals.len() >= self.stack_limit {
             let msg = format!("Goal stack overflow! MAX_GOALS = {}", self.stack_limit);
-            Err(RuntimeError::StackOverflow { msg }.into())
+            Err(self.stack_overflow(msg))
         } else if matches!(goal, LookupExternal { call_id, ..} | NextExternal { call_id, .. } if self.variable_state(self.get_call_sym(call_id)) != Unbound)
         {
             invalid_state("The call_id result variables for LookupExternal and NextExternal goals must be unbound.")
         } else {
-            self.goals.push(Rc::new(goal));
+            let goal = match self.free_goals.pop() {
+                Some(mut free) => {
+                    *Rc::get_mut(&mut free).expect("a free goal isn't shared") = goal;
+                    free
+                }
+                None => Rc::new(goal),
+            };
+            self.goals.push(goal);
             Ok(())
         }
     }
 
+    /// Keep a goal that has been run to push another into, if nothing else refers to it.
+    fn free_goal(&mut self, mut goal: Rc<Goal>) {
+        if let Some(free) = Rc::get_mut(&mut goal) {
+            *free = Goal::Noop;
+            if self.free_goals.len() < MAX_FREE {
+                self.free_goals.push(goal);
+            }
+        }
+    }
+
+    /// Free the goals and stack nodes kept for reuse, once there's nothing left to run.
+    fn release_free(&mut self) {
+        self.goals.release_free();
+        self.queries.release_free();
+        self.trace_stack.release_free();
+        self.free_goals = vec![];
+    }
+
     /// Push a non-trivial choice onto the choice stack.
     ///
     /// Params:
@@ -583,7 +1060,7 @@ impl PolarVirtualMachine {
             .collect();
         if self.choices.len() >= self.stack_limit {
             let msg = "Too many choices.".to_owned();
-            Err(RuntimeError::StackOverflow { msg }.into())
+            Err(self.stack_overflow(msg))
         } else {
             self.choices.push(Choice {
                 alternatives,
@@ -592,7 +1069,11 @@ impl PolarVirtualMachine {
                 queries: self.queries.clone(),
                 trace: self.trace.clone(),
                 trace_stack: self.trace_stack.clone(),
+                term_bytes: self.term_bytes,
+                rule_depth: self.rule_depth,
             });
+            let choices = self.choices.len() as u64;
+            self.count(|stats| stats.peak_choice_points = stats.peak_choice_points.max(choices));
             Ok(())
         }
     }
@@ -611,7 +1092,11 @@ impl PolarVirtualMachine {
     {
         let mut alternatives_iter = alternatives.into_iter();
         if let Some(alternative) = alternatives_iter.next() {
-            self.push_choice(alternatives_iter)?;
+            // A choice without alternatives would only hold on to the state it snapshots.
+            let rest = alternatives_iter.collect::<Vec<_>>();
+            if !rest.is_empty() {
+                self.push_choice(rest)?;
+            }
             self.append_goals(alternative)
         } else {
             self.backtrack()
@@ -668,6 +1153,7 @@ impl PolarVirtualMachine {
             || format!("⇒ bind: {} ← {}", var, val),
             &[],
         );
+        self.term_bytes += term_size(&val);
         if let Some(goal) = self.binding_manager.bind(var, val)? {
             self.push_goal(goal)
         } else {
@@ -711,8 +1197,17 @@ impl PolarVirtualMachine {
             .bindings_after(include_temps, &self.csp)
     }
 
+    /// The number of alternatives left at each choice point, the most recent first.
+    pub(crate) fn choice_alternatives(&self) -> Vec<usize> {
+        self.choices
+            .iter()
+            .rev()
+            .map(|choice| choice.alternatives.len())
+            .collect()
+    }
+
     /// Retrieve internal binding stack for debugger.
-    pub fn bindings_debug(&self) -> &BindingStack {
+    pub fn bindings_debug(&self) -> BindingStack {
         self.binding_manager.bindings_debug()
     }
 
@@ -736,25 +1231,21 @@ impl PolarVirtualMachine {
     }
 
     /// Investigate the current state of a variable and return a variable state variant.
-    fn variable_state(&self, variable: &Symbol) -> VariableState {
+    pub(crate) fn variable_state(&self, variable: &Symbol) -> VariableState {
         self.binding_manager.variable_state(variable)
     }
 
     /// Recursively dereference variables in a term, including subterms, except operations.
-    fn deref(&self, term: &Term) -> Term {
+    pub(crate) fn deref(&self, term: &Term) -> Term {
         self.binding_manager.deep_deref(term)
     }
 
-    /// Generate a fresh set of variables for a rule.
-    fn rename_rule_vars(&self, rule: &Rule) -> Rule {
-        let kb = &*self.kb.read().unwrap();
-        let mut renamer = Renamer::new(kb);
-        renamer.fold_rule(rule.clone())
-    }
-
     /// Push or print a message to the output stream.
     #[cfg(not(target_arch = "wasm32"))]
     fn print<S: Into<String>>(&self, message: S) {
+        if self.muted.get() {
+            return;
+        }
         let message = message.into();
         if self.polar_log_stderr {
             eprintln!("{}", message);
@@ -766,6 +1257,9 @@ impl PolarVirtualMachine {
     /// Push or print a message to the WASM output stream.
     #[cfg(target_arch = "wasm32")]
     fn print<S: Into<String>>(&self, message: S) {
+        if self.muted.get() {
+            return;
+        }
         let message = message.into();
         if self.polar_log_stderr {
             console_error(&message);
@@ -804,7 +1298,7 @@ impl PolarVirtualMachine {
                             ", BINDINGS: {{{}}}",
                             relevant_bindings
                                 .iter()
-                                .map(|(var, val)| format!("{} => {}", var.0, val))
+                                .map(|(var, val)| format!("{} => {}", var, val))
                                 .collect::<Vec<String>>()
                                 .join(", ")
                         )
@@ -902,6 +1396,143 @@ impl PolarVirtualMachine {
         }
         Ok(())
     }
+
+    /// The number of rule calls the query is nested in, counting the ones its subqueries are
+    /// run for.
+    fn depth(&self) -> usize {
+        self.depth_base + self.rule_depth
+    }
+
+    /// Fail if calling `rule` nested rule calls deeper than the depth limit.
+    fn check_depth_limit(&self, rule: Symbol) -> PolarResult<()> {
+        let depth = self.depth();
+        if self.depth_limit != 0 && depth > self.depth_limit {
+            let msg = format!(
+                "Depth limit: Rule calls are nested more than {} deep, which exceeds the depth limit.",
+                self.depth_limit
+            );
+            let rule = Some(rule.to_string());
+            return Err(RuntimeError::StackOverflow { msg, rule, depth }.into());
+        }
+        Ok(())
+    }
+
+    /// A stack overflow error, naming the innermost rule that's running for a diagnostic.
+    fn stack_overflow(&self, msg: String) -> PolarError {
+        let rule = std::iter::once(&self.trace)
+            .chain(self.trace_stack.iter().map(|trace| trace.as_ref()))
+            .find_map(|trace| match &trace.last()?.node {
+                Node::Rule(rule) => Some(rule.name.to_string()),
+                Node::Term(_) => None,
+            });
+        let depth = self.depth();
+        RuntimeError::StackOverflow { msg, rule, depth }.into()
+    }
+
+    pub fn check_cancelled(&self) -> PolarResult<()> {
+        if self.cancel.is_cancelled() {
+            return Err(RuntimeError::Cancelled.into());
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> QueryStats {
+        self.stats.get()
+    }
+
+    /// The ids of the instances that `new` made in the query, or that the host made or passed
+    /// in for it.
+    pub fn instances(&self) -> BTreeSet<u64> {
+        self.instances.borrow().clone()
+    }
+
+    /// The ids of the instances that `new` made in the query.
+    pub fn made_instances(&self) -> BTreeSet<u64> {
+        self.made_instances.borrow().clone()
+    }
+
+    /// Add the ids of the instances in `term`, a value passed into the query, to `instances`.
+    pub fn add_instances(&self, term: &Term) {
+        term.instance_ids(&mut self.instances.borrow_mut());
+    }
+
+    /// Take `term`, an external call result, which may hold instances the host made for it
+    /// anywhere inside its lists and dictionaries. They're added to `instances`, and those whose
+    /// class tag names a registered class with an MRO that lists the class itself get its class
+    /// id, for in-core `matches` checks. The others are checked by the host.
+    fn receive_instances(&self, term: Term) -> Term {
+        struct ClassIds<'kb>(&'kb KnowledgeBase);
+
+        impl<'kb> Folder for ClassIds<'kb> {
+            fn fold_external_instance(&mut self, e: ExternalInstance) -> ExternalInstance {
+                let mut e = crate::folder::fold_external_instance(e, self);
+                if e.class_id.is_none() {
+                    e.class_id = e.class_tag().and_then(|tag| {
+                        let tag = Symbol::new(&tag);
+                        let class_id = *self.0.get_class_id_for_symbol(&tag)?;
+                        let mro = self.0.mro.get(&tag)?;
+                        mro.contains(&class_id).then_some(class_id)
+                    });
+                }
+                e
+            }
+        }
+
+        self.add_instances(&term);
+        ClassIds(&self.kb()).fold_term(term)
+    }
+
+    fn count(&self, f: impl FnOnce(&mut QueryStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /// Estimate the memory used by the query so far, less the bindings it shares with its
+    /// subqueries.
+    fn unshared_memory(&self) -> usize {
+        self.memory_base
+            + self.term_bytes
+            + self.goals.len() * std::mem::size_of::<Goal>()
+            + self.choices.len() * std::mem::size_of::<Choice>()
+    }
+
+    /// Count `term`, which a subquery collected for this VM, toward its memory use.
+    pub fn hold(&mut self, term: &Term) {
+        self.memory_base += term_size(term);
+    }
+
+    /// Fail if the estimated memory used by the query exceeds the memory limit.
+    fn check_memory_limit(&self) -> PolarResult<()> {
+        if self.memory_limit == 0 {
+            return Ok(());
+        }
+        let used =
+            self.unshared_memory() + self.binding_manager.len() * std::mem::size_of::<Binding>();
+        let used = match &self.usage {
+            Some(usage) => usage.use_memory(used),
+            None => used,
+        };
+        if used as u64 > self.memory_limit {
+            let limit = self.memory_limit;
+            return Err(RuntimeError::MemoryLimit { limit }.into());
+        }
+        Ok(())
+    }
+
+    /// Count a step, and fail if that exceeds the step limit.
+    fn check_step_limit(&self) -> PolarResult<()> {
+        self.count(|stats| stats.goals += 1);
+        let goals = match &self.usage {
+            Some(usage) => usage.step(),
+            None => self.stats.get().goals,
+        };
+        if self.step_limit != 0 && goals > self.step_limit {
+            let limit = self.step_limit;
+            return Err(RuntimeError::StepLimit { limit }.into());
+        }
+        Ok(())
+    }
 }
 
 /// Implementations of instructions.
@@ -910,6 +1541,10 @@ impl PolarVirtualMachine {
     /// next available alternative. If no choice is possible, halt.
     fn backtrack(&mut self) -> PolarResult<()> {
         self.log(LogLevel::Trace, || "BACKTRACK", &[]);
+        self.count(|stats| stats.backtracks += 1);
+        if self.explanation.is_some() {
+            self.explain_failure();
+        }
 
         loop {
             match self.choices.pop() {
@@ -921,9 +1556,13 @@ impl PolarVirtualMachine {
                     queries,
                     trace,
                     trace_stack,
+                    term_bytes,
+                    rule_depth,
                 }) => {
                     self.binding_manager.backtrack(&bsp);
-                    if let Some(mut alternative) = alternatives.pop() {
+                    self.term_bytes = term_bytes;
+                    self.rule_depth = rule_depth;
+                    if let Some(alternative) = alternatives.pop() {
                         if alternatives.is_empty() {
                             self.goals = goals;
                             self.queries = queries;
@@ -941,9 +1580,11 @@ impl PolarVirtualMachine {
                                 queries,
                                 trace,
                                 trace_stack,
+                                term_bytes,
+                                rule_depth,
                             })
                         }
-                        self.goals.append(&mut alternative);
+                        self.goals.append(&alternative);
                         break;
                     }
                 }
@@ -962,6 +1603,56 @@ impl PolarVirtualMachine {
         self.queries.pop();
     }
 
+    /// Add a node to the trace at the current level. Unless the trace is collected for the
+    /// result, only the running node of each level is kept, which is all a stack trace shows.
+    fn push_trace(&mut self, trace: Rc<Trace>) {
+        if !self.tracing {
+            self.trace.clear();
+        }
+        self.trace.push(trace);
+    }
+
+    /// The rule whose body the current level of the trace is for, if it's for one.
+    fn running_rule(&self) -> Option<&Rule> {
+        match &self.trace.last()?.node {
+            Node::Rule(rule) => Some(rule),
+            Node::Term(_) => None,
+        }
+    }
+
+    /// Return to the parent level of the trace, adding this level's nodes to its running node.
+    fn pop_trace_stack(&mut self) {
+        let children = std::mem::take(&mut self.trace);
+        self.trace = self.trace_stack.pop().unwrap().as_ref().clone();
+        if self.running_rule().is_some() {
+            self.rule_depth -= 1;
+        }
+        if self.tracing {
+            let mut trace = self.trace.pop().unwrap();
+            Rc::make_mut(&mut trace).children.extend(children);
+            self.trace.push(trace);
+        }
+    }
+
+    /// Last-call optimization. Once the only goals left above a call are the ones that pop the
+    /// frames of finished queries, pop those frames before running the call rather than after
+    /// it, so that tail-recursive rules run in constant space. Traced and debugged queries keep
+    /// their frames, since they're shown to the user, and so do explained ones, whose failures
+    /// are counted by how many rules deep they are.
+    fn pop_finished_frames(&mut self) {
+        if self.tracing || self.debugger.is_stepping() || self.explanation.is_some() {
+            return;
+        }
+        loop {
+            match self.goals.last().map(|goal| goal.as_ref()) {
+                Some(Goal::PopQuery { .. }) => self.pop_query(),
+                Some(Goal::TraceStackPop) => self.pop_trace_stack(),
+                _ => return,
+            }
+            self.goals.pop();
+        }
+    }
+
     /// Interact with the debugger.
     fn debug(&mut self, message: &str) -> QueryEvent {
         // Query start time is reset when a debug event occurs.
@@ -969,6 +1660,8 @@ impl PolarVirtualMachine {
 
         QueryEvent::Debug {
             message: message.to_string(),
+            goals: self.goals.iter().map(|g| g.to_string()).collect(),
+            bindings: self.bindings(false),
         }
     }
 
@@ -998,17 +1691,29 @@ impl PolarVirtualMachine {
             _ if self.kb.read().unwrap().is_union(left) => {
                 // A union (currently) only matches itself.
                 //
-                // TODO(gj): when we have unions beyond `Actor` and `Resource`, we'll need to be
-                // smarter about this check since UnionA is more specific than UnionB if UnionA is
-                // a member of UnionB.
-                let unions_match = (left.is_actor_union() && right.is_actor_union())
-                    || (left.is_resource_union() && right.is_resource_union());
+                // TODO(gj): UnionA should also match UnionB if UnionA is a member of UnionB.
+                let unions_match =
+                    self.kb.read().unwrap().is_union(right) && union_tag(left) == union_tag(right);
                 if !unions_match {
                     return self.push_goal(Goal::Backtrack);
                 }
             }
             _ if self.kb.read().unwrap().is_union(right) => self.isa_union(left, right)?,
 
+            // A registered class named in a pattern, like `User` in `{owner: User}`, matches its
+            // instances as `User{}` would, rather than the class itself.
+            (_, Value::Variable(class)) if self.kb().get_class_id_for_symbol(class).is_some() => {
+                self.push_goal(Goal::Isa {
+                    left: left.clone(),
+                    right: right.clone_with_value(Value::Pattern(Pattern::Instance(
+                        InstanceLiteral {
+                            tag: class.clone(),
+                            fields: Dictionary::new(),
+                        },
+                    ))),
+                })?
+            }
+
             // TODO(gj): (Var, Rest) + (Rest, Var) cases might be unreachable.
             (Value::Variable(l), Value::Variable(r))
             | (Value::Variable(l), Value::RestVariable(r))
@@ -1076,6 +1781,10 @@ impl PolarVirtualMachine {
             }
 
             (_, Value::Pattern(Pattern::Dictionary(right))) => {
+                if self.batch_lookups(left, right)? {
+                    return Ok(());
+                }
+
                 // For each field in the dict, look up the corresponding field on the instance and
                 // then isa them.
                 for (field, right_value) in right.fields.iter() {
@@ -1087,7 +1796,7 @@ impl PolarVirtualMachine {
                     let lookup = Goal::LookupExternal {
                         instance: left.clone(),
                         call_id,
-                        field: right_value.clone_with_value(Value::String(field.0.clone())),
+                        field: right_value.clone_with_value(Value::String(field.to_string())),
                     };
                     let isa = Goal::Isa {
                         left: Term::from(answer),
@@ -1108,35 +1817,37 @@ impl PolarVirtualMachine {
 
                 // attempt an in-core IsA check if we have the necessary
                 // class_id information
-                if let Value::ExternalInstance(ExternalInstance {
-                    class_id: Some(class_id),
-                    ..
-                }) = *left.value()
-                {
-                    let isa = {
+                let isa = match *left.value() {
+                    Value::ExternalInstance(ExternalInstance {
+                        class_id: Some(class_id),
+                        ..
+                    }) => {
                         let kb = self.kb.read().unwrap();
-                        let right_id = kb
-                            .get_class_id_for_symbol(&right_literal.tag)
-                            .expect("no class ID for symbol");
-                        let left_symbol = kb
-                            .get_symbol_for_class_id(&class_id)
-                            .expect("no symbol for class ID");
-                        if let Some(mro) = kb.mro.get(left_symbol) {
-                            mro.contains(right_id)
-                        } else {
-                            false
+                        match (
+                            kb.get_class_id_for_symbol(&right_literal.tag),
+                            kb.get_symbol_for_class_id(&class_id),
+                        ) {
+                            (Some(right_id), Some(left_symbol)) => Some(
+                                kb.mro
+                                    .get(left_symbol)
+                                    .is_some_and(|mro| mro.contains(right_id)),
+                            ),
+                            _ => None,
                         }
-                    };
-                    if !isa {
-                        self.push_goal(Goal::Backtrack)?;
                     }
-                // default to IsaExternal when no `class_id` information is available
-                } else {
-                    // Check class
-                    self.push_goal(Goal::IsaExternal {
-                        instance: left.clone(),
-                        literal: right_literal.clone(),
-                    })?;
+                    _ => None,
+                };
+                match isa {
+                    Some(true) => (),
+                    Some(false) => self.push_goal(Goal::Backtrack)?,
+                    // default to IsaExternal when no `class_id` information is available
+                    None => {
+                        // Check class
+                        self.push_goal(Goal::IsaExternal {
+                            instance: left.clone(),
+                            literal: right_literal.clone(),
+                        })?;
+                    }
                 }
             }
 
@@ -1182,7 +1893,7 @@ impl PolarVirtualMachine {
                 // Produce a constraint like left.field = value
                 let to_unify = |(field, value): (&Symbol, &Term)| -> Term {
                     let value = self.deref(value);
-                    let field = right.clone_with_value(value!(field.0.as_ref()));
+                    let field = right.clone_with_value(Value::String(field.to_string()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     term!(op!(Unify, left, value))
                 };
@@ -1255,7 +1966,7 @@ impl PolarVirtualMachine {
                 // Construct field constraints.
                 let field_constraints = fields.fields.iter().rev().map(|(f, v)| {
                     let v = self.deref(v);
-                    let field = right.clone_with_value(value!(f.0.as_ref()));
+                    let field = right.clone_with_value(Value::String(f.to_string()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     op!(Unify, left, v)
                 });
@@ -1292,7 +2003,7 @@ impl PolarVirtualMachine {
             let members = kb.get_union_members(union).iter();
             members
                 .map(|member| {
-                    let tag = member.as_symbol().unwrap().0.as_str();
+                    let tag = member.as_symbol().unwrap().clone();
                     member.clone_with_value(value!(pattern!(instance!(tag))))
                 })
                 .map(|pattern| {
@@ -1317,7 +2028,7 @@ impl PolarVirtualMachine {
                     // if `field` is bound, unification will only succeed for the matching key
                     // if `field` is unbound, unification will succeed for all keys
                     goals.push(Goal::Unify {
-                        left: field.clone_with_value(Value::String(k.clone().0)),
+                        left: field.clone_with_value(Value::String(k.to_string())),
                         right: field.clone(),
                     });
                     // attempt to unify dict value with result
@@ -1330,7 +2041,7 @@ impl PolarVirtualMachine {
                 self.choose(alternatives)
             }
             Value::String(field) => {
-                if let Some(retrieved) = dict.fields.get(&Symbol(field.clone())) {
+                if let Some(retrieved) = dict.fields.get(&Symbol::new(field)) {
                     self.push_goal(Goal::Unify {
                         left: retrieved.clone(),
                         right: value.clone(),
@@ -1370,7 +2081,7 @@ impl PolarVirtualMachine {
                         .collect()
                 }),
             ),
-            Value::String(field) => (Symbol(field.clone()), None, None),
+            Value::String(field) => (Symbol::new(field), None, None),
             v => {
                 return self.type_error(
                     field,
@@ -1379,6 +2090,34 @@ impl PolarVirtualMachine {
             }
         };
 
+        let instance = self.deref(instance);
+
+        // The arguments were dereferenced all the way down, so a lookup is only answered from
+        // the cache if the variables inside its arguments are bound to the same values.
+        let key = match instance.value() {
+            Value::ExternalInstance(e) if self.cache_lookups => Some((
+                e.instance_id,
+                field_name.clone(),
+                args.clone(),
+                kwargs.clone(),
+            )),
+            _ => None,
+        };
+        if let Some(key) = key {
+            if let Some(cached) = self.cached_lookup(&key) {
+                let answer = self.call_id_symbols.remove(&call_id).expect("bad call ID");
+                match cached {
+                    Some(value) => self.push_goal(Goal::Unify {
+                        left: Term::from(answer),
+                        right: value,
+                    })?,
+                    None => self.push_goal(Goal::Backtrack)?,
+                }
+                return Ok(QueryEvent::None);
+            }
+            self.pending_lookups.insert(call_id, key);
+        }
+
         // add an empty choice point; lookups return only one value
         // but we'll want to cut if we get back nothing
         self.push_choice(vec![])?;
@@ -1407,13 +2146,23 @@ impl PolarVirtualMachine {
 
         Ok(QueryEvent::ExternalCall {
             call_id,
-            instance: self.deref(instance),
+            instance,
             attribute: field_name,
             args,
             kwargs,
         })
     }
 
+    /// The host's answer to the lookup `key` earlier in the query, if lookups are cached and
+    /// it's been answered.
+    fn cached_lookup(&self, key: &LookupKey) -> Option<Option<Term>> {
+        let cached = self.lookup_cache.borrow().get(key).cloned();
+        if cached.is_some() {
+            self.count(|stats| stats.cached_lookups += 1);
+        }
+        cached
+    }
+
     fn isa_external(
         &mut self,
         instance: &Term,
@@ -1446,12 +2195,23 @@ impl PolarVirtualMachine {
     }
 
     fn make_external(&self, constructor: &Term, instance_id: u64) -> QueryEvent {
+        self.instances.borrow_mut().insert(instance_id);
+        self.made_instances.borrow_mut().insert(instance_id);
         QueryEvent::MakeExternal {
             instance_id,
             constructor: self.deref(constructor),
         }
     }
 
+    fn application_error(&self, msg: String, term: &Term) -> PolarError {
+        RuntimeError::Application {
+            msg,
+            stack_trace: self.stack_trace(),
+            term: Some(term.clone()),
+        }
+        .into()
+    }
+
     fn check_error(&mut self) -> PolarResult<QueryEvent> {
         if let Some(msg) = self.external_error.take() {
             let term = match self.trace.last().map(|t| t.node.clone()) {
@@ -1500,14 +2260,29 @@ impl PolarVirtualMachine {
 
         self.queries.push(term.clone());
         self.push_goal(Goal::PopQuery { term: term.clone() })?;
-        self.trace.push(Rc::new(Trace {
+        self.push_trace(Rc::new(Trace {
             node: Node::Term(term.clone()),
             children: vec![],
         }));
 
         match &term.value() {
             Value::Call(predicate) => {
-                self.query_for_predicate(predicate.clone())?;
+                let (builtin, native) = {
+                    let kb = self.kb();
+                    if kb.get_generic_rule(&predicate.name).is_some() {
+                        (None, None)
+                    } else {
+                        (
+                            Builtin::from_name(&predicate.name.as_str()),
+                            kb.native_builtin(&predicate.name).cloned(),
+                        )
+                    }
+                };
+                match (builtin, native) {
+                    (Some(builtin), _) => self.query_for_builtin(builtin, term)?,
+                    (None, Some(native)) => self.query_for_native_builtin(&native, term)?,
+                    (None, None) => self.query_for_predicate(term, predicate.clone())?,
+                }
             }
             Value::Expression(_) => {
                 return self.query_for_operation(term);
@@ -1551,7 +2326,7 @@ impl PolarVirtualMachine {
     /// Select applicable rules for predicate.
     /// Sort applicable rules by specificity.
     /// Create a choice over the applicable rules.
-    fn query_for_predicate(&mut self, predicate: Call) -> PolarResult<()> {
+    fn query_for_predicate(&mut self, term: &Term, predicate: Call) -> PolarResult<()> {
         if predicate.kwargs.is_some() {
             return invalid_state(format!(
                 "query_for_predicate: unexpected kwargs: {}",
@@ -1561,7 +2336,7 @@ impl PolarVirtualMachine {
         let goals = match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
             None => {
                 return Err(RuntimeError::QueryForUndefinedRule {
-                    name: predicate.name.0.clone(),
+                    name: predicate.name.to_string(),
                 }
                 .into())
             }
@@ -1585,13 +2360,142 @@ impl PolarVirtualMachine {
                     Goal::FilterRules {
                         applicable_rules: vec![],
                         unfiltered_rules: pre_filter,
-                        args: predicate.args,
+                        args: predicate.args.clone(),
                     },
                     Goal::TraceStackPop,
                 ]
             }
         };
-        self.append_goals(goals)
+        // Calls with partials aren't tabled, since constraints can't be compared as variants.
+        let tabled = self.tabling || self.kb().is_tabled(&predicate.name);
+        if tabled && !self.query_contains_partial {
+            self.query_tabled(term, predicate, goals)
+        } else {
+            self.append_goals(goals)
+        }
+    }
+
+    /// Evaluate a call to a built-in predicate, backtracking if it doesn't hold.
+    fn query_for_builtin(&mut self, builtin: Builtin, term: &Term) -> PolarResult<()> {
+        let call = term.as_call()?;
+        if call.args.len() != builtin.arity() || call.kwargs.is_some() {
+            return self.type_error(
+                term,
+                format!(
+                    "{} expects {} argument(s), got {}",
+                    builtin.name(),
+                    builtin.arity(),
+                    call.args.len()
+                ),
+            );
+        }
+        if builtin == Builtin::Now {
+            let now = format_datetime((self.clock)());
+            return self.push_goal(Goal::Unify {
+                left: call.args[0].clone(),
+                right: term.clone_with_value(Value::String(now)),
+            });
+        }
+
+        let args = call.args.iter().map(|t| self.deref(t)).collect::<Vec<_>>();
+        match builtin.holds(&args, &mut self.regexes) {
+            Ok(true) => Ok(()),
+            Ok(false) => self.push_goal(Goal::Backtrack),
+            Err(msg) => self.type_error(term, msg),
+        }
+    }
+
+    /// Evaluate a call to a built-in registered with `Polar::register_builtin`, unifying the
+    /// arguments of the call with each of its answers in turn.
+    fn query_for_native_builtin(&mut self, native: &NativeBuiltin, term: &Term) -> PolarResult<()> {
+        let call = term.as_call()?;
+        if call.args.len() != native.arity || call.kwargs.is_some() {
+            return self.type_error(
+                term,
+                format!(
+                    "{} expects {} argument(s), got {}",
+                    native.name,
+                    native.arity,
+                    call.args.len()
+                ),
+            );
+        }
+
+        let args = call.args.iter().map(|t| self.deref(t)).collect::<Vec<_>>();
+        let choices = match native.call(&args) {
+            Ok(BuiltinAnswer::No) => vec![],
+            Ok(BuiltinAnswer::Yes) => return Ok(()),
+            Ok(BuiltinAnswer::Unify(values)) => vec![values],
+            Ok(BuiltinAnswer::Choices(choices)) => choices,
+            Err(msg) => return Err(self.application_error(msg, term)),
+        };
+        if let Some(values) = choices.iter().find(|values| values.len() != native.arity) {
+            return self.type_error(
+                term,
+                format!(
+                    "{} answered with {} value(s) for {} argument(s)",
+                    native.name,
+                    values.len(),
+                    native.arity
+                ),
+            );
+        }
+        if choices.is_empty() {
+            return self.push_goal(Goal::Backtrack);
+        }
+        let alternatives = choices.into_iter().map(|values| {
+            call.args
+                .iter()
+                .zip(values)
+                .map(|(arg, value)| Goal::Unify {
+                    left: arg.clone(),
+                    right: value,
+                })
+                .collect::<Goals>()
+        });
+        self.choose(alternatives.collect::<Vec<_>>())
+    }
+
+    /// Answer a call to a tabled rule from its table. If the call has no table yet, push a goal
+    /// that evaluates the rule's `goals` into one first.
+    fn query_tabled(&mut self, term: &Term, predicate: Call, goals: Goals) -> PolarResult<()> {
+        let args = Term::from(predicate.args.clone());
+        let call = variant(self.deref(&term.clone_with_value(Value::Call(predicate))));
+        let answers = self.tables.borrow_mut().lookup(&call);
+        match answers {
+            Some(answers) => self.unify_answers(&args, answers),
+            None => {
+                let mut vm = self.clone_with_goals(goals);
+                vm.queries.push(term.clone());
+                let evaluator =
+                    TableEvaluator::new(vm, call.clone(), args.clone(), self.tables.clone());
+                self.append_goals(vec![
+                    Goal::Run {
+                        runnable: Box::new(evaluator),
+                    },
+                    Goal::UnifyAnswers { call, args },
+                ])
+            }
+        }
+    }
+
+    /// Unify `args` with each of the tabled `answers` in turn.
+    fn unify_answers(&mut self, args: &Term, answers: Vec<Term>) -> PolarResult<()> {
+        let alternatives = {
+            let kb = self.kb();
+            answers
+                .into_iter()
+                .map(|answer| {
+                    // Each use of an answer gets its own variables.
+                    let right = Renamer::new(&kb).fold_term(answer);
+                    vec![Goal::Unify {
+                        left: args.clone(),
+                        right,
+                    }]
+                })
+                .collect::<Vec<_>>()
+        };
+        self.choose(alternatives)
     }
 
     fn query_for_operation(&mut self, term: &Term) -> PolarResult<QueryEvent> {
@@ -1606,8 +2510,12 @@ impl PolarVirtualMachine {
                 self.push_goal(Goal::TraceStackPush)?;
             }
             Operator::Or => {
-                // Make an alternative Query for each disjunct.
-                self.choose(args.into_iter().map(|term| vec![Goal::Query { term }]))?;
+                if let Some(alternatives) = self.parallel_disjuncts(&args) {
+                    self.choose(alternatives)?;
+                } else {
+                    // Make an alternative Query for each disjunct.
+                    self.choose(args.into_iter().map(|term| vec![Goal::Query { term }]))?;
+                }
             }
             Operator::Not => {
                 // Query in a sub-VM and invert the results.
@@ -1615,13 +2523,20 @@ impl PolarVirtualMachine {
                     return wrong_arity();
                 }
 
-                let term = args.pop().unwrap();
+                let negated = args.pop().unwrap();
+                let unbound = if self.query_contains_partial {
+                    vec![]
+                } else {
+                    self.negated_unbound_variables(&negated)
+                };
                 let add_constraints = Rc::new(RefCell::new(Bindings::new()));
                 let inverter = Box::new(Inverter::new(
                     self,
-                    vec![Goal::Query { term }],
+                    vec![Goal::Query { term: negated }],
                     add_constraints.clone(),
                     self.bsp(),
+                    term.clone(),
+                    unbound,
                 ));
                 self.choose_conditional(
                     vec![Goal::Run { runnable: inverter }],
@@ -1686,9 +2601,74 @@ impl PolarVirtualMachine {
             }
 
             Operator::In => {
+                if let [item, range] = &args[..] {
+                    if let Value::Expression(Operation {
+                        operator: Operator::Range,
+                        ..
+                    }) = range.value()
+                    {
+                        return self.in_range_helper(item, range);
+                    }
+                }
                 return self.query_op_helper(term, Self::in_op_helper, false, true);
             }
 
+            Operator::Collect => {
+                if args.len() != 3 {
+                    return wrong_arity();
+                }
+                let result = args.pop().unwrap();
+                let query = args.pop().unwrap();
+                let template = args.pop().unwrap();
+                self.collect(term, template, query, None, result)?;
+            }
+
+            Operator::Aggregate => {
+                if args.len() != 4 {
+                    return wrong_arity();
+                }
+                let aggregate = match args[0].value() {
+                    Value::String(name) => Aggregate::from_name(name),
+                    _ => None,
+                };
+                let aggregate = match aggregate {
+                    Some(aggregate) => aggregate,
+                    None => return invalid_state(format!("unknown aggregate: {}", term)),
+                };
+                let result = args.pop().unwrap();
+                // Errors point at the aggregate as it was written, without its result variable.
+                let expression = term.clone_with_value(Value::Expression(Operation {
+                    operator: Operator::Aggregate,
+                    args: args.clone(),
+                }));
+                let query = args.pop().unwrap();
+                let template = args.pop().unwrap();
+                self.collect(&expression, template, query, Some(aggregate), result)?;
+            }
+
+            Operator::Distinct => {
+                if self.query_contains_partial {
+                    return unsupported("cannot use distinct with partial evaluation", term);
+                }
+                if args.len() != 2 {
+                    return wrong_arity();
+                }
+                // Fail each result of the query whose variables have the values of an earlier one.
+                let query = args.pop().unwrap();
+                let vars = args.pop().unwrap();
+                self.append_goals(vec![
+                    Goal::Query { term: query },
+                    Goal::CheckDistinct {
+                        vars,
+                        seen: Default::default(),
+                    },
+                ])?;
+            }
+
+            Operator::Range => {
+                return unsupported("a range can only be used on the right of `in`", term);
+            }
+
             Operator::Debug => {
                 let message = self.debugger.break_msg(self).unwrap_or_else(|| {
                     format!(
@@ -1721,7 +2701,7 @@ impl PolarVirtualMachine {
 
                 let class = &constructor.as_call()?.name;
                 let class_repr = if self.kb().is_constant(class) {
-                    Some(class.0.clone())
+                    Some(class.to_string())
                 } else {
                     None
                 };
@@ -1755,10 +2735,12 @@ impl PolarVirtualMachine {
                 // Remove all choices created before this cut that are in the
                 // current rule body.
                 let mut choice_index = self.choices.len();
+                // Comparison excludes the rule body & cut operator (the last two elements of self.queries)
+                let mut prefix = self.queries.clone();
+                prefix.pop();
+                prefix.pop();
                 for choice in self.choices.iter().rev() {
-                    // Comparison excludes the rule body & cut operator (the last two elements of self.queries)
-                    let prefix = &self.queries[..(self.queries.len() - 2)];
-                    if choice.queries.starts_with(prefix) {
+                    if choice.queries.starts_with(&prefix) {
                         // If the choice has the same query stack as the current
                         // query stack, remove it.
                         choice_index -= 1;
@@ -1769,6 +2751,22 @@ impl PolarVirtualMachine {
 
                 self.push_goal(Goal::Cut { choice_index })?;
             }
+            Operator::Once => {
+                if self.query_contains_partial {
+                    return unsupported("cannot use once with partial evaluation", term);
+                }
+                if args.len() != 1 {
+                    return wrong_arity();
+                }
+                // Cut any choices left by the query after its first solution.
+                let choice_index = self.choices.len();
+                self.append_goals(vec![
+                    Goal::Query {
+                        term: args.pop().unwrap(),
+                    },
+                    Goal::Cut { choice_index },
+                ])?;
+            }
             Operator::Isa => {
                 // TODO (dhatch): Use query op helper.
                 if args.len() != 2 {
@@ -1803,6 +2801,29 @@ impl PolarVirtualMachine {
                     term: double_negation,
                 })?;
             }
+            Operator::Conditional => {
+                if self.query_contains_partial {
+                    return unsupported("cannot use if-then-else with partial evaluation", term);
+                }
+                if args.len() != 3 {
+                    return wrong_arity();
+                }
+                let alternative = args.pop().unwrap();
+                let consequent = args.pop().unwrap();
+                let condition = args.pop().unwrap();
+
+                // Commit to the first solution of the condition, keeping its bindings, by
+                // cutting back to (and including) the choice point for the alternative.
+                let choice_index = self.choices.len();
+                self.choose(vec![
+                    vec![
+                        Goal::Query { term: condition },
+                        Goal::Cut { choice_index },
+                        Goal::Query { term: consequent },
+                    ],
+                    vec![Goal::Query { term: alternative }],
+                ])?;
+            }
         }
         Ok(QueryEvent::None)
     }
@@ -2119,7 +3140,7 @@ impl PolarVirtualMachine {
                     .iter()
                     .map(|(k, v)| {
                         iterable.clone_with_value(Value::List(vec![
-                            v.clone_with_value(Value::String(k.0.clone())),
+                            v.clone_with_value(Value::String(k.to_string())),
                             v.clone(),
                         ]))
                     })
@@ -2184,6 +3205,136 @@ impl PolarVirtualMachine {
         Ok(QueryEvent::None)
     }
 
+    /// Run `query` to exhaustion in a sub-VM, then unify `result` with the list of values of
+    /// `template` in each solution, or with their `aggregate`.
+    fn collect(
+        &mut self,
+        term: &Term,
+        template: Term,
+        query: Term,
+        aggregate: Option<Aggregate>,
+        result: Term,
+    ) -> PolarResult<()> {
+        if self.query_contains_partial {
+            let msg = match aggregate {
+                None => "cannot use list comprehensions with partial evaluation",
+                Some(_) => "cannot use aggregates with partial evaluation",
+            };
+            return unsupported(msg, term);
+        }
+        let results = Rc::new(RefCell::new(vec![]));
+        let collector = Box::new(Collector::new(
+            self,
+            vec![Goal::Query { term: query }],
+            template,
+            results.clone(),
+        ));
+        self.append_goals(vec![
+            Goal::Run {
+                runnable: collector,
+            },
+            Goal::UnifyCollected {
+                results,
+                aggregate,
+                expression: term.clone(),
+                term: result,
+            },
+        ])
+    }
+
+    /// Apply `aggregate` to the collected `values`. Returns `None` for the minimum or maximum
+    /// of no values.
+    fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        values: Vec<Term>,
+        term: &Term,
+    ) -> PolarResult<Option<Value>> {
+        let mut values = values.into_iter();
+        match aggregate {
+            Aggregate::Count => Ok(Some(Value::Number(Numeric::Integer(values.len() as i64)))),
+            Aggregate::Sum => values
+                .try_fold(Numeric::Integer(0), |sum, value| match value.value() {
+                    Value::Number(n) => (sum + *n)
+                        .ok_or_else(|| RuntimeError::ArithmeticError { term: term.clone() }.into()),
+                    _ => self.type_error(&value, format!("can only sum numbers, got {}", value)),
+                })
+                .map(|sum| Some(Value::Number(sum))),
+            Aggregate::Min | Aggregate::Max => {
+                let op = if aggregate == Aggregate::Min {
+                    Operator::Lt
+                } else {
+                    Operator::Gt
+                };
+                let first = match values.next() {
+                    Some(first) => first,
+                    None => return Ok(None),
+                };
+                values
+                    .try_fold(first, |best, value| {
+                        match compare(op, &value, &best, Some(term)) {
+                            Ok(true) => Ok(value),
+                            Ok(false) => Ok(best),
+                            Err(_) => self
+                                .type_error(term, format!("cannot order {} and {}", best, value)),
+                        }
+                    })
+                    .map(|best| Some(best.value().clone()))
+            }
+        }
+    }
+
+    /// Unify `item` with each integer in the half-open range `low..high`, in order.
+    fn in_range_helper(&mut self, item: &Term, range: &Term) -> PolarResult<QueryEvent> {
+        let bounds = &range.as_expression().unwrap().args;
+        if bounds.len() != 2 {
+            return invalid_state(format!("in_range_helper: wrong arity: {}", range));
+        }
+        let (low, high) = match (
+            self.deref(&bounds[0]).value(),
+            self.deref(&bounds[1]).value(),
+        ) {
+            (Value::Number(Numeric::Integer(low)), Value::Number(Numeric::Integer(high))) => {
+                (*low, *high)
+            }
+            _ => {
+                return self.type_error(
+                    range,
+                    format!("range bounds must be integers, got {}", range),
+                )
+            }
+        };
+
+        let item = self.deref(item);
+        match item.value() {
+            // Check ground items against the bounds instead of enumerating the range.
+            Value::Number(Numeric::Integer(i)) if (low..high).contains(i) => (),
+            _ if item.is_ground() || low >= high => self.push_goal(Goal::Backtrack)?,
+            _ => {
+                let rest = Operation {
+                    operator: Operator::Range,
+                    args: vec![
+                        bounds[0].clone_with_value(Value::Number(Numeric::Integer(low + 1))),
+                        bounds[1].clone(),
+                    ],
+                };
+                let rest = op!(
+                    In,
+                    item.clone(),
+                    range.clone_with_value(Value::Expression(rest))
+                );
+                self.choose(vec![
+                    vec![Goal::Unify {
+                        left: item,
+                        right: bounds[0].clone_with_value(Value::Number(Numeric::Integer(low))),
+                    }],
+                    vec![Goal::Query { term: rest.into() }],
+                ])?;
+            }
+        }
+        Ok(QueryEvent::None)
+    }
+
     /// Unify `left` and `right` terms.
     ///
     /// Outcomes of a unification are:
@@ -2191,6 +3342,7 @@ impl PolarVirtualMachine {
     ///  - Recursive unification => more `Unify` goals are pushed onto the stack
     ///  - Failure => backtrack
     fn unify(&mut self, left: &Term, right: &Term) -> PolarResult<()> {
+        self.count(|stats| stats.unifications += 1);
         match (left.value(), right.value()) {
             (Value::Expression(op), other) | (other, Value::Expression(op)) => {
                 match op {
@@ -2460,7 +3612,8 @@ impl PolarVirtualMachine {
     {
         let n = rest_list.len() - 1;
         if list.len() >= n {
-            let rest = unify((&rest_list[n].clone(), &Term::from(list[n..].to_vec())));
+            let tail = self.list_tail(&list[n..])?;
+            let rest = unify((&rest_list[n].clone(), &tail));
             self.append_goals(
                 rest_list
                     .iter()
@@ -2474,6 +3627,26 @@ impl PolarVirtualMachine {
         }
     }
 
+    /// The tail `elements` of a list, for a rest-variable to match. Rather than a copy of the
+    /// elements, it's a chain of one-element lists joined by rest-variables, which are bound
+    /// here. Matching `[_, *tail]` against a chain then binds `tail` to the next link, so a rule
+    /// that walks a list this way takes constant space per element instead of copying the rest
+    /// of the list at every step.
+    fn list_tail(&mut self, elements: &[Term]) -> PolarResult<Term> {
+        let mut elements = elements.iter().rev();
+        let mut tail = Term::from(
+            elements
+                .next()
+                .map_or_else(Vec::new, |last| vec![last.clone()]),
+        );
+        for element in elements {
+            let link = self.kb().gensym("rest");
+            self.bind(&link, tail)?;
+            tail = Term::from(vec![element.clone(), term!(Value::RestVariable(link))]);
+        }
+        Ok(tail)
+    }
+
     /// Filter rules to just those applicable to a list of arguments,
     /// then sort them by specificity.
     #[allow(clippy::ptr_arg)]
@@ -2483,34 +3656,32 @@ impl PolarVirtualMachine {
         unfiltered_rules: &Rules,
         args: &TermList,
     ) -> PolarResult<()> {
-        if unfiltered_rules.is_empty() {
-            // The rules have been filtered. Sort them.
-
-            if applicable_rules.is_empty() {
-                self.log(LogLevel::Info, || "No matching rules found", &[]);
+        // Rules of the wrong arity, ground rules, which the prefilter already checked, and
+        // rules whose parameters can't match the arguments' constants are filtered here
+        // without unification. The rest are checked one at a time.
+        let derefed_args: TermList = args.iter().map(|arg| self.deref(arg)).collect();
+        let mut applicable_rules = applicable_rules.clone();
+        let mut unfiltered_rules = unfiltered_rules.clone();
+        while let Some(rule) = unfiltered_rules.pop() {
+            if rule.params.len() != args.len()
+                || !rule
+                    .params
+                    .iter()
+                    .zip(&derefed_args)
+                    .all(|(param, arg)| param.may_match(arg.value()))
+            {
+                continue;
+            }
+            if rule.is_ground() {
+                applicable_rules.push(rule);
+                continue;
             }
-
-            self.push_goal(Goal::SortRules {
-                rules: applicable_rules.iter().rev().cloned().collect(),
-                args: args.clone(),
-                outer: 1,
-                inner: 1,
-            })
-        } else {
-            // Check one rule for applicability.
-            let mut unfiltered_rules = unfiltered_rules.clone();
-            let rule = unfiltered_rules.pop().unwrap();
 
             let inapplicable = Goal::FilterRules {
                 args: args.clone(),
                 applicable_rules: applicable_rules.clone(),
                 unfiltered_rules: unfiltered_rules.clone(),
             };
-            if rule.params.len() != args.len() {
-                return self.push_goal(inapplicable); // wrong arity
-            }
-
-            let mut applicable_rules = applicable_rules.clone();
             applicable_rules.push(rule.clone());
             let applicable = Goal::FilterRules {
                 args: args.clone(),
@@ -2518,30 +3689,27 @@ impl PolarVirtualMachine {
                 unfiltered_rules,
             };
 
-            // The prefilter already checks applicability for ground rules.
-            if rule.is_ground() {
-                return self.push_goal(applicable);
-            }
-
-            // Rename the variables in the rule (but not the args).
+            // Rename the variables in the rule's parameters (but not the args).
             // This avoids clashes between arg vars and rule vars.
-            let Rule { params, .. } = self.rename_rule_vars(&rule);
-            let mut check_applicability = vec![];
-            for (arg, param) in args.iter().zip(params.iter()) {
-                check_applicability.push(Goal::Unify {
-                    left: arg.clone(),
-                    right: param.parameter.clone(),
-                });
-                if let Some(specializer) = &param.specializer {
-                    check_applicability.push(Goal::Isa {
-                        left: arg.clone(),
-                        right: specializer.clone(),
-                    });
-                }
-            }
-            self.choose_conditional(check_applicability, vec![applicable], vec![inapplicable])?;
-            Ok(())
+            let check_applicability = rule.program().check(&self.kb(), args);
+            return self.choose_conditional(
+                check_applicability,
+                vec![applicable],
+                vec![inapplicable],
+            );
+        }
+
+        // The rules have been filtered. Sort them.
+        if applicable_rules.is_empty() {
+            self.log(LogLevel::Info, || "No matching rules found", &[]);
         }
+
+        self.push_goal(Goal::SortRules {
+            rules: applicable_rules.into_iter().rev().collect(),
+            args: args.clone(),
+            outer: 1,
+            inner: 1,
+        })
     }
 
     /// Sort a list of rules with respect to a list of arguments
@@ -2570,6 +3738,30 @@ impl PolarVirtualMachine {
             return invalid_state("bad insertion sort state");
         }
 
+        // Sort as far as the comparisons that don't need the host go, without a goal or a
+        // choice point for each step.
+        let mut rules = rules.clone();
+        let (mut outer, mut inner) = (outer, inner);
+        while outer < rules.len() {
+            if inner == 0 {
+                outer += 1;
+                inner = outer;
+                continue;
+            }
+            match self.compare_specializers(&rules[inner], &rules[inner - 1]) {
+                Ok(true) => {
+                    rules.swap(inner - 1, inner);
+                    inner -= 1;
+                }
+                Ok(false) => {
+                    outer += 1;
+                    inner = outer;
+                }
+                Err(_) => break,
+            }
+        }
+        let rules = &rules;
+
         let next_outer = Goal::SortRules {
             rules: rules.clone(),
             args: args.clone(),
@@ -2580,6 +3772,20 @@ impl PolarVirtualMachine {
         // fails and we jump down to the evaluation of that lone rule.
         if outer < rules.len() {
             if inner > 0 {
+                let calls = self.subspecializer_calls(rules, args);
+                if !calls.is_empty() {
+                    // Sort again once the host has answered them all.
+                    return self.append_goals(vec![
+                        Goal::ExternalBatch { calls },
+                        Goal::SortRules {
+                            rules: rules.clone(),
+                            args: args.clone(),
+                            outer,
+                            inner,
+                        },
+                    ]);
+                }
+
                 let compare = Goal::IsMoreSpecific {
                     left: rules[inner].clone(),
                     right: rules[inner - 1].clone(),
@@ -2635,24 +3841,9 @@ impl PolarVirtualMachine {
                     }),
                 });
                 goals.push(Goal::TraceStackPush);
-                let Rule { body, params, .. } = self.rename_rule_vars(rule);
-
-                // Unify the arguments with the formal parameters.
-                for (arg, param) in args.iter().zip(params.iter()) {
-                    goals.push(Goal::Unify {
-                        left: arg.clone(),
-                        right: param.parameter.clone(),
-                    });
-                    if let Some(specializer) = &param.specializer {
-                        goals.push(Goal::Isa {
-                            left: param.parameter.clone(),
-                            right: specializer.clone(),
-                        });
-                    }
-                }
-
-                // Query for the body clauses.
-                goals.push(Goal::Query { term: body.clone() });
+                // Unify the arguments with the formal parameters, and query for the body clauses
+                // with a fresh set of the rule's variables.
+                goals.extend(rule.program().call(&self.kb(), args));
                 goals.push(Goal::TraceStackPop);
 
                 alternatives.push(goals)
@@ -2667,8 +3858,156 @@ impl PolarVirtualMachine {
     /// Succeed if `left` is more specific than `right` with respect to `args`.
     #[allow(clippy::ptr_arg, clippy::wrong_self_convention)]
     fn is_more_specific(&mut self, left: &Rule, right: &Rule, args: &TermList) -> PolarResult<()> {
-        let zipped = left.params.iter().zip(right.params.iter()).zip(args.iter());
-        for ((left_param, right_param), arg) in zipped {
+        match self.compare_specializers(left, right) {
+            Ok(true) => Ok(()),
+            Ok(false) => self.push_goal(Goal::Backtrack),
+            Err(i) => {
+                let answer = self.kb.read().unwrap().gensym("is_subspecializer");
+                // Bind answer to false as a starting point in case is subspecializer doesn't
+                // bind any result.
+                // This is done here for safety to avoid a bug where `answer` is unbound by
+                // `IsSubspecializer` and the `Unify` Goal just assigns it to `true` instead
+                // of checking that is is equal to `true`.
+                self.bind(&answer, Term::from(false)).unwrap();
+
+                self.append_goals(vec![
+                    Goal::IsSubspecializer {
+                        answer: answer.clone(),
+                        left: left.params[i].specializer.clone().unwrap(),
+                        right: right.params[i].specializer.clone().unwrap(),
+                        arg: args[i].clone(),
+                    },
+                    Goal::Unify {
+                        left: Term::from(answer),
+                        right: Term::from(true),
+                    },
+                ])
+            }
+        }
+    }
+
+    /// If the disjunction of `args` is the whole query and its disjuncts are independent,
+    /// evaluate them in parallel and make an alternative that unifies with the results of each.
+    fn parallel_disjuncts(&mut self, args: &[Term]) -> Option<Vec<Goals>> {
+        // There are no threads to evaluate disjuncts on in wasm.
+        if cfg!(target_arch = "wasm32")
+            || !self.parallel_disjuncts
+            || self.subquery
+            || self.tracing
+            || self.query_contains_partial
+            || self.queries.len() != 1
+            || self.usage.is_some()
+        {
+            return None;
+        }
+        let disjuncts = args.iter().map(|arg| self.deref(arg)).collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        for disjunct in &disjuncts {
+            let mut vars = HashSet::new();
+            disjunct.variables(&mut vars);
+            if !seen.is_disjoint(&vars) {
+                return None;
+            }
+            seen.extend(vars);
+        }
+
+        let usage = Arc::new(parallel::Usage::new(self.stats.get().goals));
+        self.share_usage(parallel::UsageShare::new(usage.clone()));
+        let query_timeout_ms = if self.is_query_timeout_disabled() {
+            0
+        } else {
+            // A timeout of 0 would disable it.
+            self.query_timeout_ms
+                .saturating_sub(self.query_duration())
+                .max(1)
+        };
+        let config = parallel::Config {
+            kb: self.kb.clone(),
+            clock: self.clock.clone(),
+            query_timeout_ms,
+            step_limit: self.step_limit,
+            memory_limit: self.memory_limit,
+            depth_limit: self.depth_limit,
+            tabling: self.tabling,
+            cancel: self.cancel.clone(),
+            usage,
+        };
+        let streams = parallel::evaluate(config, disjuncts.clone());
+        let alternatives = disjuncts
+            .into_iter()
+            .zip(streams)
+            .map(|(disjunct, stream)| {
+                vec![Goal::UnifyStreamed {
+                    disjunct,
+                    stream: Rc::new(RefCell::new(stream)),
+                }]
+            })
+            .collect();
+        Some(alternatives)
+    }
+
+    /// Unify the variables of `disjunct` with the next result from the thread it's evaluated on,
+    /// and leave a choice to unify them with the ones after. If the thread couldn't evaluate all
+    /// of it, query it here instead and skip the results the thread already returned.
+    fn unify_streamed(
+        &mut self,
+        disjunct: &Term,
+        stream: &Rc<RefCell<parallel::Stream>>,
+    ) -> PolarResult<()> {
+        let update = loop {
+            if let Some(update) = stream.borrow_mut().next(Duration::from_millis(10)) {
+                break update;
+            }
+            self.check_cancelled()?;
+            self.check_timeout()?;
+        };
+        match update {
+            parallel::Update::Result { bindings, messages } => {
+                self.messages.extend(messages);
+                let unify = bindings
+                    .into_iter()
+                    .map(|(var, value)| Goal::Unify {
+                        left: Term::from(var),
+                        right: value,
+                    })
+                    .collect();
+                let next = vec![Goal::UnifyStreamed {
+                    disjunct: disjunct.clone(),
+                    stream: stream.clone(),
+                }];
+                self.choose(vec![unify, next])
+            }
+            parallel::Update::Done(done) => {
+                self.messages.extend(done.messages);
+                self.count(|stats| stats.add(&done.stats));
+                self.instances.borrow_mut().extend(done.instances);
+                self.made_instances.borrow_mut().extend(done.made_instances);
+                let query = Goal::Query {
+                    term: disjunct.clone(),
+                };
+                match stream.borrow().results() {
+                    _ if done.exhausted => self.backtrack(),
+                    0 => self.push_goal(query),
+                    // The messages sent before the results that are skipped were already sent.
+                    skip => {
+                        self.muted.set(true);
+                        let skip = Goal::SkipStreamed {
+                            skip: Rc::new(Cell::new(skip)),
+                        };
+                        self.append_goals(vec![query, skip])
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `left` is more specific than `right` regardless of the arguments, or else the
+    /// position of the specializers that decide it, which the host compares with respect to the
+    /// argument there.
+    fn compare_specializers(&self, left: &Rule, right: &Rule) -> Result<bool, usize> {
+        let kb = self.kb.read().unwrap();
+        let zipped = left.params.iter().zip(right.params.iter()).enumerate();
+        for (i, (left_param, right_param)) in zipped {
             match (&left_param.specializer, &right_param.specializer) {
                 // If both specs are unions, they have the same specificity regardless of whether
                 // they're the same or different unions.
@@ -2677,17 +4016,11 @@ impl PolarVirtualMachine {
                 // smarter about this check since UnionA is more specific than UnionB if UnionA is
                 // a member of UnionB.
                 (Some(left_spec), Some(right_spec))
-                    if self.kb.read().unwrap().is_union(left_spec)
-                        && self.kb.read().unwrap().is_union(right_spec) => {}
-                // If left is a union and right is not, left cannot be more specific, so we
-                // backtrack.
-                (Some(left_spec), Some(_)) if self.kb.read().unwrap().is_union(left_spec) => {
-                    return self.push_goal(Goal::Backtrack)
-                }
-                // If right is a union and left is not, left IS more specific, so we return.
-                (Some(_), Some(right_spec)) if self.kb.read().unwrap().is_union(right_spec) => {
-                    return Ok(())
-                }
+                    if kb.is_union(left_spec) && kb.is_union(right_spec) => {}
+                // If left is a union and right is not, left cannot be more specific.
+                (Some(left_spec), Some(_)) if kb.is_union(left_spec) => return Ok(false),
+                // If right is a union and left is not, left IS more specific.
+                (Some(_), Some(right_spec)) if kb.is_union(right_spec) => return Ok(true),
 
                 (Some(left_spec), Some(right_spec)) => {
                     // If you find two non-equal specializers, that comparison determines the relative
@@ -2695,40 +4028,18 @@ impl PolarVirtualMachine {
                     // that aren't the same and you can compare them and ask which one is more specific
                     // to the relevant argument, you're done.
                     if left_spec != right_spec {
-                        let answer = self.kb.read().unwrap().gensym("is_subspecializer");
-                        // Bind answer to false as a starting point in case is subspecializer doesn't
-                        // bind any result.
-                        // This is done here for safety to avoid a bug where `answer` is unbound by
-                        // `IsSubspecializer` and the `Unify` Goal just assigns it to `true` instead
-                        // of checking that is is equal to `true`.
-                        self.bind(&answer, Term::from(false)).unwrap();
-
-                        return self.append_goals(vec![
-                            Goal::IsSubspecializer {
-                                answer: answer.clone(),
-                                left: left_spec.clone(),
-                                right: right_spec.clone(),
-                                arg: arg.clone(),
-                            },
-                            Goal::Unify {
-                                left: Term::from(answer),
-                                right: Term::from(true),
-                            },
-                        ]);
+                        return Err(i);
                     }
                 }
-                // If the left rule has no specializer and the right does, it is NOT more specific,
-                // so we Backtrack (fail)
-                (None, Some(_)) => return self.push_goal(Goal::Backtrack),
-                // If the left rule has a specializer and the right does not, the left IS more specific,
-                // so we return
-                (Some(_), None) => return Ok(()),
+                // If the left rule has no specializer and the right does, it is NOT more specific.
+                (None, Some(_)) => return Ok(false),
+                // If the left rule has a specializer and the right does not, the left IS more specific.
+                (Some(_), None) => return Ok(true),
                 // If neither has a specializer, neither is more specific, so we continue to the next argument.
                 (None, None) => (),
             }
         }
-        // Fail on any of the above branches that do not return
-        self.push_goal(Goal::Backtrack)
+        Ok(false)
     }
 
     /// Determine if `left` is a more specific specializer ("subspecializer") than `right`
@@ -2747,7 +4058,6 @@ impl PolarVirtualMachine {
                 Value::Pattern(Pattern::Instance(left_lit)),
                 Value::Pattern(Pattern::Instance(right_lit)),
             ) => {
-                let call_id = self.new_call_id(answer);
                 let instance_id = instance.instance_id;
                 if left_lit.tag == right_lit.tag
                     && !(left_lit.fields.fields.is_empty() && right_lit.fields.fields.is_empty())
@@ -2760,10 +4070,16 @@ impl PolarVirtualMachine {
                         right: right.clone_with_value(Value::Pattern(Pattern::Dictionary(
                             right_lit.fields.clone(),
                         ))),
-                        arg,
+                        arg: arg.clone(),
                     })?;
                 }
+                let key = (instance_id, left_lit.tag.clone(), right_lit.tag.clone());
+                if let Some(&batched) = self.subspecializers.get(&key) {
+                    self.rebind_external_answer(answer, Term::from(batched));
+                    return Ok(QueryEvent::None);
+                }
                 // check ordering based on the classes
+                let call_id = self.new_call_id(answer);
                 Ok(QueryEvent::ExternalIsSubSpecializer {
                     call_id,
                     instance_id,
@@ -2801,6 +4117,124 @@ impl PolarVirtualMachine {
         }
     }
 
+    /// The `ExternalIsSubSpecializer` checks the host hasn't answered that sorting `rules` with
+    /// respect to `args` needs, if calls are batched.
+    fn subspecializer_calls(&mut self, rules: &Rules, args: &TermList) -> Vec<QueryEvent> {
+        if !self.batch_external_calls {
+            return vec![];
+        }
+        let mut keys = vec![];
+        for left in rules {
+            for right in rules {
+                let Err(i) = self.compare_specializers(left, right) else {
+                    continue;
+                };
+                let arg = self.deref(&args[i]);
+                let (left_spec, right_spec) = (
+                    left.params[i].specializer.as_ref().unwrap(),
+                    right.params[i].specializer.as_ref().unwrap(),
+                );
+                if let (
+                    Value::ExternalInstance(instance),
+                    Value::Pattern(Pattern::Instance(left_lit)),
+                    Value::Pattern(Pattern::Instance(right_lit)),
+                ) = (arg.value(), left_spec.value(), right_spec.value())
+                {
+                    let key = (
+                        instance.instance_id,
+                        left_lit.tag.clone(),
+                        right_lit.tag.clone(),
+                    );
+                    if !self.subspecializers.contains_key(&key) && !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+        keys.into_iter()
+            .map(|(instance_id, left_class_tag, right_class_tag)| {
+                QueryEvent::ExternalIsSubSpecializer {
+                    call_id: self.new_id(),
+                    instance_id,
+                    left_class_tag,
+                    right_class_tag,
+                }
+            })
+            .collect()
+    }
+
+    /// Look up the fields of the dictionary pattern `right` on the external instance `left` in
+    /// one batch, and then isa them, if calls are batched and there's more than one. Returns
+    /// whether it did.
+    fn batch_lookups(&mut self, left: &Term, right: &Dictionary) -> PolarResult<bool> {
+        if !self.batch_external_calls || right.fields.len() < 2 {
+            return Ok(false);
+        }
+        let instance_id = match left.value() {
+            Value::ExternalInstance(instance) => instance.instance_id,
+            _ => return Ok(false),
+        };
+
+        // Fail without asking the host if a field is known to have no value.
+        let mut cached = vec![];
+        for field in right.fields.keys() {
+            let value = self
+                .cache_lookups
+                .then(|| self.cached_lookup(&(instance_id, field.clone(), None, None)))
+                .flatten();
+            if let Some(None) = value {
+                return self.push_goal(Goal::Backtrack).map(|_| true);
+            }
+            cached.push(value.flatten());
+        }
+
+        let mut calls = vec![];
+        let mut isas = vec![];
+        for ((field, right_value), cached) in right.fields.iter().zip(cached) {
+            if let Some(value) = cached {
+                isas.push(Goal::Isa {
+                    left: value,
+                    right: right_value.clone(),
+                });
+                continue;
+            }
+            let answer = self.kb.read().unwrap().gensym("isa_value");
+            let call_id = self.new_call_id(&answer);
+            if self.cache_lookups {
+                self.pending_lookups
+                    .insert(call_id, (instance_id, field.clone(), None, None));
+            }
+            calls.push(QueryEvent::ExternalCall {
+                call_id,
+                instance: left.clone(),
+                attribute: field.clone(),
+                args: None,
+                kwargs: None,
+            });
+            isas.push(Goal::Isa {
+                left: Term::from(answer),
+                right: right_value.clone(),
+            });
+        }
+        if !calls.is_empty() {
+            isas.insert(0, Goal::ExternalBatch { calls });
+        }
+        self.append_goals(isas)?;
+        Ok(true)
+    }
+
+    fn external_batch(&mut self, calls: &[QueryEvent]) -> QueryEvent {
+        self.log(
+            LogLevel::Trace,
+            || format!("BATCH: {} external calls", calls.len()),
+            &[],
+        );
+        self.batch = calls.to_vec();
+        QueryEvent::ExternalBatch {
+            calls: calls.to_vec(),
+        }
+    }
+
     pub fn term_source(&self, term: &Term, include_info: bool) -> String {
         let source_info = term.parsed_context();
 
@@ -2860,8 +4294,13 @@ impl Runnable for PolarVirtualMachine {
             self.query_start_time = query_start_time;
         }
 
+        if self.result_limit.is_some_and(|limit| self.results >= limit) {
+            return Ok(QueryEvent::Done { result: true });
+        }
+
         if self.goals.is_empty() {
             if self.choices.is_empty() {
+                self.release_free();
                 return Ok(QueryEvent::Done { result: true });
             } else {
                 self.backtrack()?;
@@ -2872,11 +4311,30 @@ impl Runnable for PolarVirtualMachine {
             match self.next(goal.clone())? {
                 QueryEvent::None => (),
                 event => {
+                    if matches!(
+                        event,
+                        QueryEvent::ExternalCall { .. }
+                            | QueryEvent::ExternalIsa { .. }
+                            | QueryEvent::ExternalIsSubSpecializer { .. }
+                            | QueryEvent::ExternalIsSubclass { .. }
+                            | QueryEvent::ExternalOp { .. }
+                            | QueryEvent::NextExternal { .. }
+                    ) {
+                        self.count(|stats| stats.external_calls += 1);
+                    }
+                    if let QueryEvent::ExternalBatch { calls } = &event {
+                        self.count(|stats| stats.external_calls += calls.len() as u64);
+                    }
                     self.external_error = None;
+                    self.free_goal(goal);
+                    if matches!(event, QueryEvent::Done { .. }) {
+                        self.release_free();
+                    }
                     return Ok(event);
                 }
             }
             self.maybe_break(DebugEvent::Goal(goal.clone()))?;
+            self.free_goal(goal);
         }
 
         if self.tracing {
@@ -2887,15 +4345,20 @@ impl Runnable for PolarVirtualMachine {
 
         let trace = if self.tracing {
             let trace = self.trace.first().cloned();
-            trace.map(|trace| TraceResult {
-                formatted: trace.draw(self),
-                trace,
+            trace.map(|trace| {
+                let mut proof = vec![];
+                trace.add_proofs(self, None, &mut proof);
+                TraceResult {
+                    formatted: trace.draw(self),
+                    trace,
+                    proof,
+                }
             })
         } else {
             None
         };
 
-        let mut bindings = self.bindings(true);
+        let mut bindings = self.binding_manager.result_bindings(&self.csp);
         if !self.inverting {
             match simplify_bindings_opt(bindings, false) {
                 Ok(Some(bs)) => {
@@ -2910,7 +4373,8 @@ impl Runnable for PolarVirtualMachine {
 
                 Err(RuntimeError::UnhandledPartial { term, ref var }) => {
                     // use the debugger to get the nicest possible version of this binding
-                    let Binding(original_var_name, simplified) = get_binding_for_var(&var.0, self);
+                    let Binding(original_var_name, simplified) =
+                        get_binding_for_var(&var.as_str(), self);
 
                     // TODO(gj): `t` is a partial constructed in the VM, so we don't have any
                     // source context for it. We make a best effort to track down some relevant
@@ -2984,6 +4448,7 @@ impl Runnable for PolarVirtualMachine {
             &[],
         );
 
+        self.results += 1;
         Ok(QueryEvent::Result { bindings, trace })
     }
 
@@ -3013,9 +4478,10 @@ impl Runnable for PolarVirtualMachine {
     /// value is `None` then the external has no (more) results, so we
     /// backtrack to the choice point left by `Goal::LookupExternal`.
     fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> PolarResult<()> {
-        // TODO: Open question if we need to pass errors back down to rust.
-        // For example what happens if the call asked for a field that doesn't exist?
-
+        let term = term.map(|value| self.receive_instances(value));
+        if let Some(key) = self.pending_lookups.remove(&call_id) {
+            self.lookup_cache.borrow_mut().insert(key, term.clone());
+        }
         if let Some(value) = term {
             self.log(LogLevel::Trace, || format!("=> {}", value), &[]);
 
@@ -3051,6 +4517,104 @@ impl Runnable for PolarVirtualMachine {
         Ok(())
     }
 
+    /// Fail the external call `call_id` as if it had no results, and raise `message`, the error
+    /// the host had answering it, before anything else runs. If application errors are
+    /// isolated, the error is sent as a warning instead, and only the call fails.
+    fn external_call_error(&mut self, call_id: u64, message: String) -> PolarResult<()> {
+        self.pending_lookups.remove(&call_id);
+        self.external_call_result(call_id, None)?;
+        if self.isolate_application_errors {
+            let warning = format!(
+                "An external call failed with an application error: {}",
+                message
+            );
+            self.messages.push(MessageKind::Warning, warning);
+            Ok(())
+        } else {
+            self.external_error = Some(message);
+            self.push_goal(Goal::CheckError)
+        }
+    }
+
+    /// Answer the calls of the last `ExternalBatch` with `answers`, in the same order. The branch
+    /// fails if any lookup has no result or raised an error, and the query ends with the error
+    /// unless application errors are isolated.
+    fn external_batch_result(&mut self, answers: Vec<ExternalAnswer>) -> PolarResult<()> {
+        let calls = std::mem::take(&mut self.batch);
+        if calls.len() != answers.len() {
+            return invalid_state(format!(
+                "expected {} answers to an external batch, got {}",
+                calls.len(),
+                answers.len()
+            ));
+        }
+        let (mut failed, mut error) = (false, None);
+        for (call, answer) in calls.into_iter().zip(answers) {
+            let answer = match answer {
+                ExternalAnswer::Result(value) => {
+                    ExternalAnswer::Result(value.map(|value| self.receive_instances(value)))
+                }
+                answer => answer,
+            };
+            if let (QueryEvent::ExternalCall { call_id, .. }, answer) = (&call, &answer) {
+                let key = self.pending_lookups.remove(call_id);
+                if let (Some(key), ExternalAnswer::Result(value)) = (key, answer) {
+                    self.lookup_cache.borrow_mut().insert(key, value.clone());
+                }
+            }
+            match (call, answer) {
+                (QueryEvent::ExternalCall { call_id, .. }, ExternalAnswer::Result(Some(value))) => {
+                    self.log(LogLevel::Trace, || format!("=> {}", value), &[]);
+                    let sym = self.call_id_symbols.remove(&call_id).expect("bad call ID");
+                    self.push_goal(Goal::Unify {
+                        left: Term::from(sym),
+                        right: value,
+                    })?;
+                }
+                (QueryEvent::ExternalCall { call_id, .. }, ExternalAnswer::Result(None)) => {
+                    self.call_id_symbols.remove(&call_id).expect("bad call ID");
+                    failed = true;
+                }
+                (QueryEvent::ExternalCall { call_id, .. }, ExternalAnswer::Error(message)) => {
+                    self.call_id_symbols.remove(&call_id).expect("bad call ID");
+                    failed = true;
+                    if self.isolate_application_errors {
+                        let warning = format!(
+                            "An external call failed with an application error: {}",
+                            message
+                        );
+                        self.messages.push(MessageKind::Warning, warning);
+                    } else {
+                        error.get_or_insert(message);
+                    }
+                }
+                (
+                    QueryEvent::ExternalIsSubSpecializer {
+                        instance_id,
+                        left_class_tag,
+                        right_class_tag,
+                        ..
+                    },
+                    ExternalAnswer::Question(answer),
+                ) => {
+                    self.subspecializers
+                        .insert((instance_id, left_class_tag, right_class_tag), answer);
+                }
+                (call, answer) => {
+                    return invalid_state(format!("{:?} doesn't answer {:?}", answer, call))
+                }
+            }
+        }
+        if failed {
+            self.push_goal(Goal::Backtrack)?;
+        }
+        if let Some(message) = error {
+            self.external_error = Some(message);
+            self.push_goal(Goal::CheckError)?;
+        }
+        Ok(())
+    }
+
     /// Drive debugger.
     fn debug_command(&mut self, command: &str) -> PolarResult<()> {
         let mut debugger = self.debugger.clone();
@@ -3552,7 +5116,7 @@ mod tests {
         );
         assert!(matches!(
             vm.run(None).unwrap(),
-            QueryEvent::Debug { message } if &message[..] == "Hello"
+            QueryEvent::Debug { message, .. } if &message[..] == "Hello"
         ));
     }
 
@@ -3632,7 +5196,7 @@ mod tests {
 
     #[test]
     fn test_gen_var() {
-        let vm = PolarVirtualMachine::default();
+        let kb = KnowledgeBase::new();
 
         let rule = Rule::new_from_test(
             Symbol::new("foo"),
@@ -3641,27 +5205,30 @@ mod tests {
                 operator: Operator::And,
                 args: vec![
                     term!(1),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
                     Term::new_from_test(Value::List(vec![Term::new_from_test(Value::Variable(
-                        Symbol("y".to_string()),
+                        Symbol::new("y"),
                     ))])),
                 ],
             })),
         );
 
-        let renamed_rule = vm.rename_rule_vars(&rule);
-        let renamed_terms = unwrap_and(&renamed_rule.body);
+        let renamed_body = match rule.program().call(&kb, &[]).pop() {
+            Some(Goal::Query { term }) => term,
+            _ => panic!("expected a query"),
+        };
+        let renamed_terms = unwrap_and(&renamed_body);
         assert_eq!(renamed_terms[1].value(), renamed_terms[2].value());
         let x_value = match &renamed_terms[1].value() {
-            Value::Variable(sym) => Some(sym.0.clone()),
+            Value::Variable(sym) => Some(sym.to_string()),
             _ => None,
         };
         assert_eq!(x_value.unwrap(), "_x_1");
 
         let y_value = match &renamed_terms[3].value() {
             Value::List(terms) => match &terms[0].value() {
-                Value::Variable(sym) => Some(sym.0.clone()),
+                Value::Variable(sym) => Some(sym.to_string()),
                 _ => None,
             },
             _ => None,
@@ -3704,7 +5271,7 @@ mod tests {
                 } => {
                     external_isas.push(class_tag.clone());
                     // Return `true` if the specified `class_tag` is `"a"`.
-                    vm.external_question_result(call_id, class_tag.0 == "a")
+                    vm.external_question_result(call_id, class_tag.as_str() == "a")
                         .unwrap()
                 }
                 QueryEvent::ExternalOp { .. }
@@ -3817,6 +5384,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compare_specializers() {
+        let vm = PolarVirtualMachine::default();
+        let a = rule!("f", ["_"; instance!("a"), sym!("_y")]);
+        let b = rule!("f", ["_"; instance!("b"), sym!("_y")]);
+        let any = rule!("f", [sym!("_x"), sym!("_y")]);
+        assert_eq!(vm.compare_specializers(&a, &any), Ok(true));
+        assert_eq!(vm.compare_specializers(&any, &a), Ok(false));
+        assert_eq!(vm.compare_specializers(&any, &any), Ok(false));
+        // Which of two classes is more specific is up to the host.
+        assert_eq!(vm.compare_specializers(&a, &b), Err(0));
+    }
+
     #[test]
     fn test_is_subspecializer() {
         let mut vm = PolarVirtualMachine::default();
@@ -3928,6 +5508,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_filter_rules_without_unification() {
+        let polar = crate::polar::Polar::new();
+        polar
+            .load_str(
+                r#"bar([1, x]) if x = 3;
+                   bar([1, *_rest]);
+                   bar([2, _x]);
+                   bar([_x]);
+                   bar(_x, _y);"#,
+            )
+            .unwrap();
+
+        let mut vm = PolarVirtualMachine::new_test(polar.kb.clone(), false, vec![]);
+        let _ = vm.run(None);
+        vm.push_goal(query!(call!("bar", [value!([1, 3])])))
+            .unwrap();
+        loop {
+            let goal = vm.goals.pop().unwrap();
+            let filter_rules = matches!(goal.as_ref(), Goal::FilterRules { .. });
+            let _ = vm.next(goal).unwrap();
+            if filter_rules {
+                break;
+            }
+        }
+        // The last two rules are filtered out without a choice point. The conditional that
+        // checks `bar([1, *_rest])` leaves the first rule unfiltered.
+        assert_eq!(vm.choices.len(), 2);
+        assert!(matches!(
+            vm.goals.last().unwrap().as_ref(),
+            Goal::Unify { .. }
+        ));
+        let remaining = vm.choices[0].alternatives.iter().find_map(|goals| {
+            goals.iter().find_map(|goal| match goal.as_ref() {
+                Goal::FilterRules {
+                    unfiltered_rules, ..
+                } => Some(unfiltered_rules.len()),
+                _ => None,
+            })
+        });
+        assert_eq!(remaining, Some(1));
+        assert_query_events!(vm, [QueryEvent::Result { hashmap! {} }, QueryEvent::Result { hashmap! {} }, QueryEvent::Done { .. }]);
+    }
+
+    #[test]
+    fn test_last_call_optimization() {
+        let polar = crate::polar::Polar::new();
+        polar
+            .load_str(
+                r#"walk([]);
+                   walk([_, *t]) if walk(t);
+                   even([]);
+                   even([_, *t]) if odd(t);
+                   odd([_, *t]) if even(t);"#,
+            )
+            .unwrap();
+        let list = Value::List((0..200).map(|i| term!(i)).collect());
+
+        for name in ["walk", "even"] {
+            let query = query!(call!(name, [list.clone()]));
+            let mut vm = PolarVirtualMachine::new_test(polar.kb.clone(), false, vec![query]);
+            // Without the optimization, every element would add goals to the stack.
+            vm.set_stack_limit(50);
+            assert_query_events!(vm, [QueryEvent::Result { hashmap! {} }, QueryEvent::Done { .. }]);
+        }
+
+        // Traced queries keep their frames.
+        let query = query!(call!("walk", [list]));
+        let mut vm = PolarVirtualMachine::new_test(polar.kb.clone(), true, vec![query]);
+        vm.set_stack_limit(50);
+        assert!(matches!(
+            vm.run(None).unwrap_err().0,
+            ErrorKind::Runtime(RuntimeError::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_release_free_goals() {
+        let polar = crate::polar::Polar::new();
+        polar
+            .load_str("deep(0); deep(n) if n > 0 and deep(n - 1) and true;")
+            .unwrap();
+        let query = query!(call!("deep", [3000]));
+        let mut vm = PolarVirtualMachine::new_test(polar.kb.clone(), false, vec![query]);
+        assert!(matches!(vm.run(None).unwrap(), QueryEvent::Result { .. }));
+        assert!(vm.goals.free_len() <= MAX_FREE && vm.free_goals.len() <= MAX_FREE);
+        assert!(vm.goals.free_len() > 0);
+
+        // The kept goals and nodes are freed once the query is done.
+        assert!(matches!(vm.run(None).unwrap(), QueryEvent::Done { .. }));
+        assert_eq!(vm.goals.free_len(), 0);
+        assert_eq!(vm.queries.free_len(), 0);
+        assert!(vm.free_goals.is_empty());
+    }
+
     #[test]
     fn choose_conditional() {
         let mut vm = PolarVirtualMachine::new_test(
@@ -3950,7 +5625,7 @@ mod tests {
         )
         .unwrap();
         assert_query_events!(vm, [
-            QueryEvent::Debug { message } if &message[..] == "consequent" && vm.is_halted(),
+            QueryEvent::Debug { message, .. } if &message[..] == "consequent" && vm.is_halted(),
             QueryEvent::Done { result: true }
         ]);
 
@@ -3962,7 +5637,7 @@ mod tests {
         )
         .unwrap();
         assert_query_events!(vm, [
-            QueryEvent::Debug { message } if &message[..] == "alternative" && vm.is_halted(),
+            QueryEvent::Debug { message, .. } if &message[..] == "alternative" && vm.is_halted(),
             QueryEvent::Done { result: true }
         ]);
 
@@ -3980,7 +5655,7 @@ mod tests {
         )
         .unwrap();
         assert_query_events!(vm, [
-            QueryEvent::Debug { message } if &message[..] == "consequent" && vm.bindings(true).is_empty() && vm.is_halted(),
+            QueryEvent::Debug { message, .. } if &message[..] == "consequent" && vm.bindings(true).is_empty() && vm.is_halted(),
             QueryEvent::Done { result: true }
         ]);
     }
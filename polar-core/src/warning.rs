@@ -3,6 +3,7 @@ use std::fmt;
 use indoc::indoc;
 use strum_macros::AsRefStr;
 
+use super::rules::Rule;
 use super::sources::Context;
 use super::terms::{InstanceLiteral, Pattern, Symbol, Term, Value};
 
@@ -18,8 +19,11 @@ impl PolarWarning {
         use ValidationWarning::*;
 
         match &self.0 {
-            AmbiguousPrecedence { term } | UnknownSpecializer { term, .. } => {
-                term.parsed_context().cloned()
+            AmbiguousPrecedence { term }
+            | UnknownSpecializer { term, .. }
+            | UnsatisfiableRule { term, .. } => term.parsed_context().cloned(),
+            UnguardedRecursion { rule, .. } | DuplicateRule { rule } => {
+                rule.parsed_context().cloned()
             }
             MissingAllowRule | MissingHasPermissionRule => None,
         }
@@ -48,6 +52,12 @@ pub enum ValidationWarning {
     // TODO(gj): won't need `sym` once we have an easier, infallible way of going from `Term` ->
     // `Pattern` -> `InstanceLiteral` -> `tag` (`Symbol`).
     UnknownSpecializer { term: Term, sym: Symbol },
+    // Category: general
+    UnguardedRecursion { rule: Rule, cycle: Vec<Symbol> },
+    // Category: general
+    DuplicateRule { rule: Rule },
+    // Category: general
+    UnsatisfiableRule { rule: Rule, term: Term },
 }
 
 impl From<ValidationWarning> for PolarWarning {
@@ -89,7 +99,7 @@ const MISSING_HAS_PERMISSION_RULE_MSG: &str = indoc! {"
 
 fn common_specializer_misspellings(term: &Term) -> Option<&str> {
     if let Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) = term.value() {
-        let misspelled_type = match tag.0.as_ref() {
+        let misspelled_type = match &*tag.as_str() {
             "integer" => "Integer",
             "int" => "Integer",
             "i32" => "Integer",
@@ -139,6 +149,20 @@ impl fmt::Display for ValidationWarning {
                     write!(f, ", did you mean {}?", suggestion)?;
                 }
             }
+            UnguardedRecursion { cycle, .. } => {
+                let names = cycle
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if cycle.len() == 1 {
+                    write!(f, "Every {} rule calls {} again, so it has no base case. A query for it will never succeed, and may never end.", names, names)?;
+                } else {
+                    write!(f, "Every rule for {} calls one of them again, so they have no base case. A query for any of them will never succeed, and may never end.", names)?;
+                }
+            }
+            DuplicateRule { rule } => write!(f, "This {} rule is the same as one loaded before it, so it only repeats that rule's results.", rule.name)?,
+            UnsatisfiableRule { rule, term } => write!(f, "This {} rule can never match, since `{}` is always false.", rule.name, term)?,
         }
 
         Ok(())
@@ -4,16 +4,20 @@ use indoc::indoc;
 use maplit::btreemap;
 use permute::permute;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, HashMap};
 
-use mock_externals::MockExternal;
+use mock_externals::{external_instance, MockExternal, MockHandler};
 use polar_core::{
+    builtins::BuiltinAnswer,
+    call,
     error::{ParseErrorKind::*, RuntimeError::*, ValidationError::*, *},
     events::*,
     messages::*,
     polar::Polar,
-    query::Query,
+    pred,
+    query::{ExternalHook, Order, Query, QueryHandler},
+    sources::Source,
     sym, term,
     terms::*,
     traces::*,
@@ -145,7 +149,7 @@ where
                     ),
                 )
                 .unwrap(),
-            QueryEvent::Debug { ref message } => {
+            QueryEvent::Debug { ref message, .. } => {
                 query.debug_command(&debug_handler(message)).unwrap();
             }
             QueryEvent::ExternalOp {
@@ -337,7 +341,7 @@ fn vars(p: &Polar, query_str: &str, vars: &[&str]) -> Vec<Vec<Value>> {
         .iter()
         .map(|bindings| {
             vars.iter()
-                .map(|&var| bindings.0.get(&Symbol(var.to_string())).unwrap().clone())
+                .map(|&var| bindings.0.get(&Symbol::new(var)).unwrap().clone())
                 .collect()
         })
         .collect()
@@ -502,6 +506,108 @@ fn test_trace() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_trace_proof() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"permit(user, action) if role(user, role) and can(role, action);
+           role("alice", "admin");
+           can("admin", action) if action = "read";"#,
+    )?;
+    let q = p.new_query(r#"permit("alice", x)"#, true)?;
+    let results = query_results!(q);
+    let proof = &results[0].1.as_ref().unwrap().proof;
+
+    /// Each step as a line, indented under the step it proves.
+    fn draw(proofs: &[Proof], depth: usize, lines: &mut Vec<String>) {
+        for proof in proofs {
+            let step = match &proof.step {
+                ProofStep::Rule { name, args, .. } => format!(
+                    "rule {}({})",
+                    name,
+                    args.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                ProofStep::Goal { term, .. } => format!("goal {}", term),
+            };
+            lines.push("  ".repeat(depth) + &step);
+            draw(&proof.children, depth + 1, lines);
+        }
+    }
+    let mut lines = vec![];
+    draw(proof, 0, &mut lines);
+    assert_eq!(
+        lines,
+        vec![
+            r#"goal permit("alice", "read")"#,
+            r#"  rule permit("alice", "read")"#,
+            r#"    goal role("alice", "admin")"#,
+            r#"      rule role("alice", "admin")"#,
+            r#"    goal can("admin", "read")"#,
+            r#"      rule can("admin", "read")"#,
+            r#"        goal "read" = "read""#,
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_explanation() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"can(user, "read", doc) if user.role = "reader" and doc.public = true;
+           can(user, "read", doc) if owns(user, doc);
+           owns(user, doc) if doc.owner = user.name;"#,
+    )?;
+    let mut q = p.new_query(
+        r#"can({role: "reader", name: "bob"}, "read", {public: false, owner: "alice"})"#,
+        false,
+    )?;
+    q.set_explain(true);
+    assert!(matches!(q.next_event()?, QueryEvent::Done { .. }));
+
+    let explanation = q.explanation().unwrap();
+    let failures = explanation
+        .rules
+        .iter()
+        .map(|failure| {
+            (
+                failure.source.clone(),
+                failure.goal.to_string(),
+                failure.depth,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        failures,
+        vec![
+            (
+                "doc.public".to_owned(),
+                r#"{owner: "alice", public: false}.public = true"#.to_owned(),
+                1
+            ),
+            (
+                "user.name".to_owned(),
+                r#"{name: "bob", role: "reader"}.name = "alice""#.to_owned(),
+                2
+            )
+        ]
+    );
+    // Each failure is under the `can` rule that was applied to the query.
+    assert!(explanation
+        .rules
+        .iter()
+        .all(|failure| failure.rule.name == sym!("can")));
+
+    // A query that isn't explained doesn't record anything.
+    let mut q = p.new_query(r#"can({}, "read", {})"#, false)?;
+    let _ = q.next_event();
+    assert!(q.explanation().is_none());
+    Ok(())
+}
+
 #[test]
 fn test_nested_rule() -> TestResult {
     let p = polar();
@@ -736,6 +842,33 @@ fn test_not() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_not_unbound() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"g(1);
+           f() if not g(x) and x = 2;
+           h(l) if not (x in l and g(x));"#,
+    )?;
+    // Negating a query that binds a variable used outside of the negation is an error, because
+    // the negation may be true for other values of the variable.
+    qruntime!(&p, "not g(x)", Unsupported { .. });
+    qruntime!(&p, "f()", Unsupported { .. });
+    qruntime!("not x > 1", Unsupported { .. });
+    qvar(&p, "x = 2 and not g(x)", "x", values![2]);
+    qnull(&p, "x = 1 and not g(x)");
+
+    // Failing negated queries don't bind anything.
+    qeval(&p, "not (x = 1 and x = 2)");
+    qeval(&p, "not x in []");
+
+    // Variables that only appear in the negation are fine.
+    qeval(&p, "h([2, 3])");
+    qnull(&p, "h([1, 2])");
+    qeval(&p, "forall(x in [2, 3], not g(x))");
+    Ok(())
+}
+
 #[test]
 fn test_and() -> TestResult {
     let p = polar();
@@ -760,6 +893,60 @@ fn test_lookup() {
     qeval(&polar(), "{x: 1}.x = 1");
 }
 
+#[test]
+fn test_chained_lookup() -> TestResult {
+    let p = polar();
+    qeval(&p, "{a: {b: {c: 1}}}.a.b.c = 1");
+    qvar(&p, "x = {a: {b: 2}} and x.a.b = res", "res", values![2]);
+    qnull(&p, "{a: {b: {c: 1}}}.a.b.c = 2");
+
+    // Each segment of an external lookup is a separate call, made left to right.
+    let instance = |instance_id| external_instance(instance_id, None);
+    p.register_constant(sym!("user"), instance(1))?;
+    let mut lookups = vec![];
+    let q = p.new_query("user.profile.organization.id = 3", false)?;
+    let results = query_results!(q, |_, receiver: Term, attribute: Symbol, _, _| {
+        let receiver = match receiver.value() {
+            Value::ExternalInstance(ExternalInstance { instance_id, .. }) => *instance_id,
+            _ => panic!("expected an external instance"),
+        };
+        lookups.push((receiver, attribute.to_string()));
+        match &*attribute.as_str() {
+            "profile" => Some(instance(2)),
+            "organization" => Some(instance(3)),
+            "id" => Some(term!(3)),
+            _ => None,
+        }
+    });
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        lookups,
+        vec![
+            (1, "profile".to_string()),
+            (2, "organization".to_string()),
+            (3, "id".to_string())
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_lookup_on_call() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"current_user(u) if u = {name: "alice", org: {id: 1}};
+           users(1, {name: "alice"});
+           users(1, {name: "bob"});
+           is_alice() if current_user().name = "alice";"#,
+    )?;
+    qeval(&p, r#"current_user().name = "alice""#);
+    qnull(&p, r#"current_user().name = "bob""#);
+    qvar(&p, "current_user().org.id = x", "x", values![1]);
+    qeval(&p, "is_alice()");
+    qvar(&p, "users(1).name = x", "x", values!["alice", "bob"]);
+    Ok(())
+}
+
 #[test]
 fn test_instance_lookup() {
     // Q: Not sure if this should be allowed? I can't get (new a{x: 1}).x to parse, but that might
@@ -904,6 +1091,194 @@ fn test_dict_specializers() -> TestResult {
     Ok(())
 }
 
+/// Applicable rules are tried most specific first, regardless of source order.
+#[test]
+fn test_dict_specializer_ordering() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(_x, "bare");
+           f(_: {}, "empty");
+           f(_: {a: 1}, "a");
+           f(_: {a: 1, b: 2}, "ab");"#,
+    )?;
+    qvar(
+        &p,
+        "f({a: 1, b: 2}, x)",
+        "x",
+        values!["ab", "a", "empty", "bare"],
+    );
+    qvar(&p, "f({a: 1}, x)", "x", values!["a", "empty", "bare"]);
+    qvar(&p, "f({b: 2}, x)", "x", values!["empty", "bare"]);
+    Ok(())
+}
+
+#[test]
+fn test_union_specializers() -> TestResult {
+    let p = polar();
+    p.register_constant(sym!("Circle"), term!(true))?;
+    p.register_constant(sym!("Square"), term!(true))?;
+    p.register_constant(sym!("Triangle"), term!(true))?;
+    p.register_union(sym!("Shape"), vec![sym!("Circle"), sym!("Square")])?;
+    p.load_str(
+        r#"kind(_: Shape, "shape");
+           kind(_: Circle, "circle");"#,
+    )?;
+
+    let kinds = |query: &str| -> Vec<Value> {
+        let (results, _externals) =
+            query_results_with_externals(p.new_query(query, false).unwrap());
+        results.iter().map(|(r, _)| r[&sym!("x")].clone()).collect()
+    };
+    assert_eq!(kinds("kind(new Circle(), x)"), values!["circle", "shape"]);
+    assert_eq!(kinds("kind(new Square(), x)"), values!["shape"]);
+    assert!(kinds("kind(new Triangle(), x)").is_empty());
+    assert!(kinds("kind(1, x)").is_empty());
+
+    assert!(matches!(
+        p.register_union(sym!("Circle"), vec![sym!("Square")]),
+        Err(PolarError(ErrorKind::Runtime(InvalidRegistration { .. })))
+    ));
+    assert!(matches!(
+        p.register_union(sym!("Polygon"), vec![sym!("Hexagon")]),
+        Err(PolarError(ErrorKind::Validation(UnregisteredClass { .. })))
+    ));
+    assert!(matches!(
+        p.register_constant(sym!("Shape"), term!(true)),
+        Err(PolarError(ErrorKind::Runtime(InvalidRegistration { .. })))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_policy_constants() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"admin(role) if role in ADMIN_ROLES;
+           limit(LIMIT);
+           ADMIN_ROLES = ["admin", "owner"];
+           LIMIT = 10;
+           DEFAULTS = {role: "guest", active: true};"#,
+    )?;
+    qeval(&p, r#"admin("owner")"#);
+    qnull(&p, r#"admin("guest")"#);
+    qvar(&p, "limit(x)", "x", values![10]);
+    qvar(&p, "x = DEFAULTS.role", "x", values!["guest"]);
+
+    // Policy constants are cleared with the rules, so they can be redefined.
+    p.clear_rules();
+    p.load_str("LIMIT = 20;")?;
+    qvar(&p, "x = LIMIT", "x", values![20]);
+    p.clear_rules();
+
+    let err = p.load_str("X = 1; X = 2;").unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Validation(InvalidConstant { .. })
+    ));
+    p.register_constant(sym!("Y"), term!(1))?;
+    let err = p.load_str("Y = 2;").unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Validation(InvalidConstant { .. })
+    ));
+    let err = p.load_str("Z = [1, y];").unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Validation(InvalidConstant { .. })
+    ));
+    let err = p.load_str("Z = 1 + 2;").unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Validation(InvalidConstant { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_modules() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"module billing {
+             allow(user) if admin(user);
+             admin("alice");
+           }
+           module docs {
+             allow(user) if billing::allow(user) or user = "bob";
+           }
+           admin("carol");"#,
+    )?;
+    qeval(&p, r#"billing::allow("alice")"#);
+    qnull(&p, r#"billing::allow("carol")"#);
+    qeval(&p, r#"docs::allow("alice")"#);
+    qeval(&p, r#"docs::allow("bob")"#);
+    qnull(&p, r#"admin("alice")"#);
+    qeval(&p, r#"billing::admin("alice")"#);
+    Ok(())
+}
+
+#[test]
+fn test_imports() -> TestResult {
+    let p = polar();
+    p.load(vec![
+        Source::new_with_name(
+            "policies/main.polar",
+            r#"import "shared/rbac.polar";
+               admin(user) if has_role(user, ADMIN);"#,
+        ),
+        Source::new_with_name(
+            "policies/shared/rbac.polar",
+            r#"ADMIN = "admin";
+               has_role("alice", "admin");"#,
+        ),
+    ])?;
+    qeval(&p, r#"admin("alice")"#);
+    qnull(&p, r#"admin("bob")"#);
+
+    // A file can import one that an earlier load loaded.
+    let p = polar();
+    p.load(vec![Source::new_with_name(
+        "policies/roles.polar",
+        r#"ADMIN = "admin";"#,
+    )])?;
+    p.load(vec![Source::new_with_name(
+        "policies/billing.polar",
+        r#"import "./roles.polar";
+           billing_admin(user) if user = ADMIN;"#,
+    )])?;
+    qeval(&p, r#"billing_admin("admin")"#);
+
+    let e = polar()
+        .load(vec![Source::new_with_name(
+            "main.polar",
+            r#"import "rbac.polar";"#,
+        )])
+        .unwrap_err();
+    assert!(matches!(e.0, ErrorKind::Validation(InvalidImport { .. })));
+    assert!(
+        e.to_string()
+            .contains("no file named rbac.polar is being loaded or has been loaded"),
+        "{}",
+        e
+    );
+    assert!(e.to_string().contains("of file main.polar"), "{}", e);
+
+    let e = polar()
+        .load(vec![
+            Source::new_with_name("a.polar", r#"import "b.polar";"#),
+            Source::new_with_name("b.polar", r#"import "a.polar";"#),
+        ])
+        .unwrap_err();
+    assert!(matches!(e.0, ErrorKind::Validation(InvalidImport { .. })));
+    assert!(
+        e.to_string()
+            .contains("import cycle a.polar -> b.polar -> a.polar"),
+        "{}",
+        e
+    );
+    assert!(e.to_string().contains("of file b.polar"), "{}", e);
+    Ok(())
+}
+
 #[test]
 fn test_non_instance_specializers() -> TestResult {
     let p = polar();
@@ -1094,6 +1469,40 @@ fn test_make_external() -> TestResult {
     Ok(())
 }
 
+/// Test using a field-style constructor and calling a method on the result.
+#[test]
+fn test_make_external_fields() -> TestResult {
+    let q = polar().new_query(
+        "x = new Range{min: 1, max: 10} and x.contains(5) = true",
+        false,
+    )?;
+    let mut made = None;
+    let mock_make_range = |id, constructor: Term| match constructor.value() {
+        Value::Call(Call {
+            name,
+            args,
+            kwargs: Some(kwargs),
+        }) if name == &sym!("Range")
+            && args.is_empty()
+            && kwargs == &btreemap! {sym!("min") => term!(1), sym!("max") => term!(10)} =>
+        {
+            made = Some(id)
+        }
+        _ => panic!("Expected call with kwargs"),
+    };
+    let mut calls = vec![term!(true)];
+    let mock_contains = |_, instance: Term, attribute: Symbol, args: Option<Vec<Term>>, _| {
+        assert!(matches!(instance.value(), Value::ExternalInstance(_)));
+        assert_eq!(attribute, sym!("contains"));
+        assert_eq!(args, Some(vec![term!(5)]));
+        calls.pop()
+    };
+    let results = query_results!(q, mock_contains, mock_make_range, no_debug);
+    assert_eq!(results.len(), 1);
+    assert!(made.is_some());
+    Ok(())
+}
+
 /// Test external call with positional + kwargs.
 #[test]
 fn test_external_call() -> TestResult {
@@ -1127,6 +1536,334 @@ fn test_infinite_loop() {
     qeval(&p, "f(1)");
 }
 
+#[test]
+fn test_deep_recursion() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"count(0);
+           count(n) if n > 0 and count(n - 1);
+           sum(0, 0);
+           sum(n, s) if n > 0 and sum(n - 1, t) and s = t + n;
+           range(0, []);
+           range(n, [n, *t]) if n > 0 and range(n - 1, t);
+           positive(0);
+           positive(n) if not n <= 0 and positive(n - 1);"#,
+    )?;
+    qeval(&p, "count(100000)");
+    qeval(&p, "positive(10000)");
+    qvar(&p, "sum(10000, s)", "s", vec![value!(50005000)]);
+
+    // The list is a chain of 10000 rest variables until the result dereferences it.
+    let list = var(&p, "range(10000, l)", "l");
+    let expected = (1..=10000).rev().map(|n| term!(n)).collect::<Vec<_>>();
+    assert_eq!(list, vec![Value::List(expected)]);
+    Ok(())
+}
+
+#[test]
+fn test_query_limits() -> TestResult {
+    let mut p = polar();
+    p.load_str(
+        r#"loop(x) if loop(x);
+           count(0);
+           count(n) if n > 0 and count(n - 1);"#,
+    )?;
+    p.set_step_limit(1000);
+    qruntime!(&p, "loop(1)", StepLimit { limit: 1000 });
+    qruntime!(&p, "x = [y for y in [1] if loop(y)]", StepLimit { .. });
+    qeval(&p, "count(10)");
+
+    // A query's limits override the ones it was made with.
+    let mut q = p.new_query("loop(1)", false)?;
+    q.set_step_limit(0);
+    q.set_timeout(50);
+    let err = q.next_event().unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Runtime(RuntimeError::QueryTimeout { timeout: 50, .. })
+    ));
+
+    p.set_step_limit(0);
+    p.set_query_timeout(50);
+    qruntime!(&p, "loop(1)", QueryTimeout { timeout: 50, .. });
+    Ok(())
+}
+
+#[test]
+fn test_memory_limit() -> TestResult {
+    let mut p = polar();
+    p.load_str(
+        r#"grow(n) if grow(n + 1);
+           count(0);
+           count(n) if n > 0 and count(n - 1);"#,
+    )?;
+    p.set_memory_limit(1_000_000);
+    qruntime!(&p, "grow(1)", MemoryLimit { limit: 1_000_000 });
+    qruntime!(&p, "x = [y for y in [1] if grow(y)]", MemoryLimit { .. });
+    qeval(&p, "count(1000)");
+
+    // A query's limit overrides the one it was made with.
+    let mut q = p.new_query("grow(1)", false)?;
+    q.set_memory_limit(0);
+    q.set_step_limit(100_000);
+    let err = q.next_event().unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Runtime(RuntimeError::StepLimit { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_tabling() -> TestResult {
+    let mut p = polar();
+    p.load_str(
+        r#"table path;
+           edge(1, 2);
+           edge(2, 3);
+           edge(3, 1);
+           edge(3, 4);
+           path(x, y) if edge(x, y);
+           path(x, z) if path(x, y) and edge(y, z);
+
+           table a;
+           table b;
+           a(x, y) if edge(x, y);
+           a(x, y) if b(x, z) and edge(z, y);
+           b(x, y) if a(x, y);
+
+           table fib;
+           fib(0, 0);
+           fib(1, 1);
+           fib(n, f) if n > 1 and fib(n - 1, a) and fib(n - 2, b) and f = a + b;
+
+           table twice;
+           twice(1);
+           twice(1);
+           reach(x, y) if edge(x, y);
+           reach(x, z) if reach(x, y) and edge(y, z);
+           f(table) if table = 1;"#,
+    )?;
+
+    // Left recursion over a cycle terminates, and each answer is returned once.
+    qvar(&p, "path(1, y)", "y", values![2, 3, 1, 4]);
+    assert_eq!(var(&p, "path(x, y)", "y").len(), 12);
+    qnull(&p, "path(4, _)");
+    qvar(&p, "b(2, y)", "y", values![3, 1, 4, 2]);
+    qvar(&p, "twice(x)", "x", values![1]);
+
+    // Each subgoal is evaluated once, so this doesn't take exponential time.
+    qvar(&p, "fib(50, f)", "f", values![12586269025i64]);
+
+    // Tabling every rule makes untabled left recursion terminate too.
+    p.set_tabling(true);
+    qvar(&p, "reach(4, y) or reach(2, y)", "y", values![3, 1, 4, 2]);
+    qeval(&p, "f(1)");
+    Ok(())
+}
+
+#[test]
+fn test_parallel_disjuncts() -> TestResult {
+    let mut p = polar();
+    p.load_str(
+        r#"f(1);
+           f(2);
+           g(3);
+           slow(0);
+           slow(n) if n > 0 and slow(n - 1);
+           h(x) if print("h1") and x = 1;
+           h(x) if print("h2") and x = new Foo().bar;
+           nat(0);
+           nat(n) if nat(m) and n = m + 1;"#,
+    )?;
+
+    // The disjunct that calls the host is evaluated in its turn, and results come in the same
+    // order either way.
+    let query = "f(x) or (slow(100) and y = 1) or new Foo().bar = z or g(w) or f(v)";
+    let results = |p: &Polar| {
+        let q = p.new_query(query, false).unwrap();
+        query_results!(q, |_, _, _, _, _| Some(term!(5)))
+    };
+    let sequential = results(&p);
+    assert_eq!(sequential.len(), 7);
+    p.set_parallel_disjuncts(true);
+    assert_eq!(results(&p), sequential);
+
+    // Messages from disjuncts evaluated in parallel are in order too.
+    let mut messages = vec![];
+    let q = p.new_query("print(1) or print(2)", false)?;
+    let results = query_results!(q, @msgs |msg: &Message| messages.push(msg.msg.clone()));
+    assert_eq!(results.len(), 2);
+    assert_eq!(messages, vec!["1", "2"]);
+
+    // A disjunct that needs the host after its first results is queried again for the rest,
+    // without sending the messages of the results it had twice.
+    let results = |p: &Polar| {
+        let mut messages = vec![];
+        let q = p.new_query("h(x) or f(y)", false).unwrap();
+        let results = query_results(
+            q,
+            |_, _, _, _, _| Some(term!(5)),
+            no_externals,
+            no_isa,
+            no_is_subspecializer,
+            no_debug,
+            |msg: &Message| messages.push(msg.msg.clone()),
+            no_error_handler,
+        );
+        (results, messages)
+    };
+    p.set_parallel_disjuncts(false);
+    let sequential = results(&p);
+    assert_eq!(sequential.0.len(), 4);
+    assert_eq!(sequential.1, vec![r#""h1""#, r#""h2""#]);
+    p.set_parallel_disjuncts(true);
+    assert_eq!(results(&p), sequential);
+
+    // A disjunct with no end of results returns the first ones.
+    let xs = p
+        .new_query("nat(x) or f(y)", false)?
+        .results(|_, event| panic!("unexpected event {:?}", event))
+        .map(|bindings| bindings.map(|bindings| bindings[&sym!("x")].clone()))
+        .take(3)
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(xs, vec![term!(0), term!(1), term!(2)]);
+
+    // The steps of all of the disjuncts count toward the query's step limit.
+    let mut q = p.new_query("slow(100)", false)?;
+    for event in q.by_ref() {
+        if let QueryEvent::Done { .. } = event? {
+            break;
+        }
+    }
+    p.set_step_limit(q.stats().goals * 3 / 2);
+    let e = p
+        .new_query("slow(100) or slow(100)", false)?
+        .results(|_, event| panic!("unexpected event {:?}", event))
+        .collect::<PolarResult<Vec<_>>>()
+        .unwrap_err();
+    assert!(matches!(e.0, ErrorKind::Runtime(StepLimit { .. })));
+    p.set_step_limit(0);
+
+    // Disjuncts that share a variable are evaluated one after another.
+    qvar(&p, "f(x) or g(x)", "x", values![1, 2, 3]);
+    qvar(&p, "x = 1 and (y = x or y = 2)", "y", values![1, 2]);
+    Ok(())
+}
+
+#[test]
+fn test_query_results() -> TestResult {
+    let p = polar();
+    p.load_str("f(1); f(2); f(3); g(x) if x = new Foo().bar;")?;
+
+    let xs = p
+        .new_query("f(x)", false)?
+        .results(|_, event| panic!("unexpected event {:?}", event))
+        .map(|bindings| bindings.map(|bindings| bindings[&sym!("x")].clone()))
+        .take(2)
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(xs, vec![term!(1), term!(2)]);
+
+    // The handler answers the query's questions for the host.
+    let results = p
+        .new_query("g(x)", false)?
+        .results(|query, event| match event {
+            QueryEvent::MakeExternal { .. } => Ok(()),
+            QueryEvent::ExternalCall { call_id, .. } => query.call_result(call_id, Some(term!(5))),
+            event => panic!("unexpected event {:?}", event),
+        })
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0][&sym!("x")], term!(5));
+
+    // An error ends the iteration.
+    let mut results = p
+        .new_query("f(x) and (x = 1 or x in 1..y)", false)?
+        .results(|_, event| panic!("unexpected event {:?}", event));
+    assert!(results.next().unwrap().is_ok());
+    assert!(results.next().unwrap().is_err());
+    assert!(results.next().is_none());
+    Ok(())
+}
+
+#[test]
+fn test_result_order() -> TestResult {
+    let p = polar();
+    p.load_str(r#"f(3); f("b"); f(1.5); f(true); f(2); f("a"); f(1);"#)?;
+    let ordered = |query: &str, order| {
+        let mut q = p.new_query(query, false).unwrap();
+        q.set_result_order(sym!("x"), order);
+        query_results!(q)
+            .into_iter()
+            .map(|(r, _)| r.get(&sym!("x")).cloned())
+            .collect::<Vec<_>>()
+    };
+
+    // Without an order, results come in the order the rules were loaded.
+    qvar(&p, "f(x)", "x", values![3, "b", 1.5, true, 2, "a", 1]);
+    assert_eq!(
+        ordered("f(x)", Order::Ascending),
+        values![1, 1.5, 2, 3, "a", "b", true]
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        ordered("f(x)", Order::Descending),
+        values![3, 2, 1.5, 1, "b", "a", true]
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>()
+    );
+
+    // Results without the variable come last, in the order they were found.
+    assert_eq!(
+        ordered("x = 2 or y = 1 or x = 1 or z = 1", Order::Ascending),
+        vec![Some(value!(1)), Some(value!(2)), None, None]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_query_stats() -> TestResult {
+    let p = polar();
+    p.load_str("f(1); f(2); f(3); g(x) if x = new Foo().bar;")?;
+
+    let mut results = p
+        .new_query("f(x) and x > 1", false)?
+        .results(|_, event| panic!("unexpected event {:?}", event));
+    assert_eq!(results.by_ref().count(), 2);
+    let stats = results.query().stats();
+    assert!(stats.goals > stats.unifications);
+    assert_eq!(stats.unifications, 3);
+    // Once for `1 > 1` and once to look for more results after `f(2)`.
+    assert_eq!(stats.backtracks, 2);
+    assert_eq!(stats.peak_choice_points, 1);
+    assert_eq!(stats.external_calls, 0);
+
+    let mut results = p
+        .new_query("g(x)", false)?
+        .results(|query, event| match event {
+            QueryEvent::MakeExternal { .. } => Ok(()),
+            QueryEvent::ExternalCall { call_id, .. } => query.call_result(call_id, Some(term!(5))),
+            event => panic!("unexpected event {:?}", event),
+        });
+    assert_eq!(results.by_ref().count(), 1);
+    assert_eq!(results.query().stats().external_calls, 1);
+
+    // Subqueries count toward the query's stats.
+    let stats = |query: &str| {
+        let mut results = p
+            .new_query(query, false)
+            .unwrap()
+            .results(|_, event| panic!("unexpected event {:?}", event));
+        results.by_ref().count();
+        results.query().stats()
+    };
+    assert!(stats("not (f(x) and x > 3)").unifications >= 3);
+    Ok(())
+}
+
 #[test]
 fn test_comparisons() -> TestResult {
     let p = polar();
@@ -1237,6 +1974,37 @@ fn test_comparisons() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_chained_comparisons() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"within_quota(user) if 0 <= user.usage < user.limit;
+           between(x, lo, hi) if lo < x <= hi;
+           size(x) if x in [5, 20, 1];"#,
+    )?;
+    qeval(&p, "within_quota({usage: 0, limit: 10})");
+    qeval(&p, "within_quota({usage: 9, limit: 10})");
+    qnull(&p, "within_quota({usage: 10, limit: 10})");
+    qnull(&p, "within_quota({usage: -1, limit: 10})");
+    qvar(
+        &p,
+        "x in [1, 2, 3, 4] and between(x, 1, 3)",
+        "x",
+        values![2, 3],
+    );
+    qeval(&p, "1 < 2 < 3 < 4");
+    qnull(&p, "1 < 3 < 2 < 4");
+    qeval(&p, "1 == 1.0 != 2");
+    qnull(&p, "not 1 < 2 < 3");
+
+    // A call between two comparisons is made once, and each of its values is compared.
+    assert_eq!(
+        query_results!(p.new_query("0 < size() < 10", false)?).len(),
+        2
+    );
+    Ok(())
+}
+
 #[test]
 fn test_modulo_and_remainder() {
     let p = polar();
@@ -1411,6 +2179,25 @@ fn test_debug_temp_var() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_debug_event_goals_and_bindings() -> TestResult {
+    let p = polar();
+    let mut q = p.new_query("x = 1 and debug() and y = 2", false)?;
+    match q.next_event()? {
+        QueryEvent::Debug {
+            goals, bindings, ..
+        } => {
+            assert_eq!(bindings.get(&sym!("x")), Some(&term!(1)));
+            assert!(!bindings.contains_key(&sym!("y")));
+            assert!(goals.iter().any(|g| g == "Query(y = 2)"), "{:#?}", goals);
+        }
+        e => panic!("expected debug event, got {:?}", e),
+    }
+    q.debug_command("continue")?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    Ok(())
+}
+
 #[test]
 fn test_debug() -> TestResult {
     let p = polar();
@@ -1731,6 +2518,53 @@ has_role(user: User, "owner", repository: Repository) if
     Ok(())
 }
 
+#[test]
+fn test_load_with_warnings() -> TestResult {
+    let p = polar();
+    let warnings = p.load_with_warnings(vec![
+        Source::new("f(1);\nf(x) if x.y = 1;\nf(2);"),
+        Source::new("f(1);\nf(x) if x.y = 1;\nf(x) if x.y = 2;"),
+    ])?;
+    let kinds = warnings
+        .iter()
+        .map(|warning| warning.kind())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, vec!["ValidationWarning::DuplicateRule"; 2]);
+    assert!(warnings[0]
+        .to_string()
+        .starts_with("This f rule is the same as one loaded before it"));
+    // They aren't sent as messages too.
+    assert!(p.next_message().is_none());
+    // Duplicates still repeat results.
+    qvar(&p, "f(1) and x = 1", "x", values![1, 1]);
+
+    p.clear_rules();
+    let warnings = p.load_with_warnings(vec![Source::new(indoc! {r#"
+        g(x) if x = 1 and 1 = 2;
+        g(x) if x = 1 and "b" < "a";
+        g(_x) if false;
+        g(x) if x = 1 and 1 == 1.0 and 1 < 2;
+    "#})])?;
+    let kinds = warnings
+        .iter()
+        .map(|warning| warning.kind())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, vec!["ValidationWarning::UnsatisfiableRule"; 3]);
+    assert!(warnings[0]
+        .to_string()
+        .starts_with("This g rule can never match, since `1 = 2` is always false."));
+
+    // The warnings of a load that fails are sent as messages.
+    p.clear_rules();
+    assert!(p
+        .load_with_warnings(vec![Source::new("f(1);\nf(1);\ng(x);")])
+        .is_err());
+    let message = p.next_message().unwrap();
+    assert!(matches!(message.kind, MessageKind::Warning));
+    assert!(message.msg.contains("same as one loaded before it"));
+    Ok(())
+}
+
 #[test]
 fn test_and_or_warning() -> TestResult {
     let p = polar();
@@ -1808,6 +2642,27 @@ fn test_print() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_drain_messages() -> TestResult {
+    let p = polar();
+    p.load_str("f(s: string) if print(s);")?;
+    let warnings = p.drain_messages();
+    assert_eq!(warnings.len(), 1);
+    assert!(matches!(warnings[0].kind, MessageKind::Warning));
+    assert!(p.drain_messages().is_empty());
+
+    let mut q = p.new_query("print(1) and print(2, 3)", false)?;
+    while !matches!(q.next_event()?, QueryEvent::Done { .. }) {}
+    let printed = q
+        .drain_messages()
+        .into_iter()
+        .map(|message| message.msg)
+        .collect::<Vec<_>>();
+    assert_eq!(printed, vec!["1", "2, 3"]);
+    assert!(q.next_message().is_none());
+    Ok(())
+}
+
 #[test]
 fn test_unknown_specializer_suggestions() -> TestResult {
     let p = polar();
@@ -1867,6 +2722,38 @@ fn test_dict_destructuring() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_quoted_dict_keys() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"json(request) if request.headers.("content-type") = "application/json";
+           header(request, name, value) if value = request.headers.(name);
+           json_pattern(_headers: {"content-type": "application/json"});"#,
+    )?;
+    let request = r#"{headers: {"content-type": "application/json", "x-id": 1}}"#;
+    qeval(&p, &format!("json({})", request));
+    qnull(&p, r#"json({headers: {"content-type": "text/plain"}})"#);
+    qvar(
+        &p,
+        &format!(r#"header({}, "x-id", value)"#, request),
+        "value",
+        values![1],
+    );
+    qvar(
+        &p,
+        &format!("header({}, name, 1)", request),
+        "name",
+        values!["x-id"],
+    );
+    qeval(
+        &p,
+        r#"json_pattern({"content-type": "application/json", a: 1})"#,
+    );
+    qnull(&p, r#"json_pattern({content_type: "application/json"})"#);
+    qvar(&p, r#"x = {"a b": 1}.("a b")"#, "x", values![1]);
+    Ok(())
+}
+
 #[ignore]
 #[test]
 fn test_dict_destructuring_broken() -> TestResult {
@@ -1989,7 +2876,7 @@ fn test_in_op() -> TestResult {
     assert_eq!(results.len(), 3);
     assert!(results[0].0.is_empty());
     assert_eq!(
-        results[1].0.get(&Symbol("x".to_string())).unwrap().clone(),
+        results[1].0.get(&Symbol::new("x")).unwrap().clone(),
         value!(1)
     );
     assert!(results[2].0.is_empty());
@@ -2049,13 +2936,66 @@ fn test_matches() {
     qnull(&p, "x = {foo: 1} and x matches {foo: 1, bar: 2}");
     qnull(&p, "x = {foo: 1} and x matches {foo: 2}");
     qeval(&p, "x matches Integer and x = 1");
+    qeval(&p, "x = {a: {b: 1}} and x.a matches {b: 1}");
 }
 
 #[test]
-fn test_keyword_call() {
-    qparse!("cut(a) if a;", ReservedWord { .. });
-    qparse!("debug(a) if a;", ReservedWord { .. });
+fn test_matches_in_rule_body() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"admin(user) if user matches {role: "admin"};
+           member(x) if x matches {org: {id: 1}} or x matches {org: {id: 2}};"#,
+    )?;
+    qeval(&p, r#"admin({role: "admin", name: "alice"})"#);
+    qnull(&p, r#"admin({role: "guest"})"#);
+    qnull(&p, "admin(1)");
+    qeval(&p, "member({org: {id: 2}})");
+    qnull(&p, "member({org: {id: 3}})");
+    qnull(&p, "member({org: 1})");
+    Ok(())
+}
+
+#[test]
+fn test_keyword_call() {
+    qparse!("cut(a) if a;", ReservedWord { .. });
+    qparse!("debug(a) if a;", ReservedWord { .. });
     qparse!("foo(debug) if debug = 1;", UnrecognizedToken { .. });
+    qparse!("f(x) if x else x;", UnrecognizedToken { .. });
+    let e = polar()
+        .load_str("f(x) if x = new distinct(1);")
+        .unwrap_err();
+    assert!(
+        e.to_string()
+            .starts_with("distinct is a reserved Polar word and cannot be used here"),
+        "{}",
+        e
+    );
+}
+
+#[test]
+fn test_contextual_keywords() -> TestResult {
+    // Newer keywords are still names wherever the keyword can't appear, like `table`.
+    let p = polar();
+    p.load_str(
+        r#"
+        for(then, else) if then = else;
+        module(import) if import = 1;
+        import(module) if module(module);
+        f(distinct, once) if distinct = once and x = {for: distinct} and x.for = 2;
+        distinct(x) if x = 3;
+        g(x) if x = [y for y in [1, 2]];
+        table module;
+        "#,
+    )?;
+    qeval(&p, "for(1, 1)");
+    qnull(&p, "for(1, 2)");
+    qeval(&p, "import(1)");
+    qeval(&p, "f(2, 2)");
+    qvar(&p, "g(x)", "x", vec![value!([1, 2])]);
+    // A call to `distinct` or `once` is still the operator.
+    qvar(&p, "distinct(x = 1 or x = 1)", "x", vec![value!(1)]);
+    qvar(&p, "once(x = 1 or x = 2)", "x", vec![value!(1)]);
+    Ok(())
 }
 
 #[test]
@@ -2070,7 +3010,7 @@ fn test_keyword_dot() -> TestResult {
     )?;
     qeval(
         &p,
-        "x = {debug: 1, new: 2, type: 3} and x.debug + x.new = x.type",
+        "x = {debug: 1, new: 2, type: 3, for: 0} and x.debug + x.new + x.for = x.type",
     );
     Ok(())
 }
@@ -2100,6 +3040,1980 @@ fn test_unify_rule_head() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_conditional() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"level(user, level) if
+             if user.role = "admin" then level = 2
+             else if user.role = "member" then level = 1
+             else level = 0;
+           first(x, y) if if x in [1, 2, 3] then y = x else y = 0;"#,
+    )?;
+    qvar(&p, r#"level({role: "admin"}, x)"#, "x", values![2]);
+    qvar(&p, r#"level({role: "member"}, x)"#, "x", values![1]);
+    qvar(&p, r#"level({role: "guest"}, x)"#, "x", values![0]);
+    qvar(&p, "level({}, x)", "x", values![0]);
+    qnull(&p, r#"level({role: "admin"}, 0)"#);
+
+    // The consequent sees the bindings from the first solution of the condition only.
+    qvar(&p, "first(x, y)", "y", values![1]);
+    qvar(&p, "first(4, y)", "y", values![0]);
+
+    // Alternatives within the branches are not cut.
+    qvar(
+        &p,
+        "if true then x in [1, 2] else x = 3",
+        "x",
+        values![1, 2],
+    );
+    qvar(
+        &p,
+        "if false then x = 1 else x in [2, 3]",
+        "x",
+        values![2, 3],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ranges() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"page(n, size, i) if i in (n * size)..((n + 1) * size);
+           under_quota(user) if user.sessions in 0..user.quota;"#,
+    )?;
+    qvar(&p, "x in 1..5", "x", values![1, 2, 3, 4]);
+    qnull(&p, "x in 5..5");
+    qnull(&p, "x in 5..1");
+    qvar(&p, "page(1, 3, i)", "i", values![3, 4, 5]);
+
+    // Ground items are checked against the bounds.
+    qeval(&p, "3 in 1..5");
+    qnull(&p, "5 in 1..5");
+    qnull(&p, r#""a" in 1..5"#);
+    qeval(&p, "under_quota({sessions: 2, quota: 5})");
+    qnull(&p, "under_quota({sessions: 5, quota: 5})");
+
+    // Ranges are lazy.
+    qvar(
+        &p,
+        "x in 0..9223372036854775807 and x > 2 and cut",
+        "x",
+        values![3],
+    );
+
+    qruntime!("x in 1..y", TypeError { .. });
+    qruntime!("x in 1.5..3", TypeError { .. });
+    Ok(())
+}
+
+#[test]
+fn test_comprehensions() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"active_names(user, names) if
+             names = [s.name for s in user.sessions if s.active];
+           double(x, y) if y = x * 2;
+           doubled(xs, ys) if ys = [y for x in xs if double(x, y)];
+           products(ps) if ps = [x * y for x in 1..3 if y in [10, 20]];"#,
+    )?;
+    qvar(
+        &p,
+        r#"active_names({sessions: [{name: "a", active: true}, {name: "b", active: false}, {name: "c", active: true}]}, names)"#,
+        "names",
+        vec![value!(["a", "c"])],
+    );
+    qvar(&p, "doubled([1, 2, 3], ys)", "ys", vec![value!([2, 4, 6])]);
+    qvar(&p, "doubled([], ys)", "ys", vec![value!([])]);
+    qvar(&p, "products(ps)", "ps", vec![value!([10, 20, 20, 40])]);
+
+    // The comprehension's variables are not bound outside of it.
+    qvar(&p, "xs = [x for x in [1, 2]] and x = 3", "x", values![3]);
+    qnull(&p, "[x for x in [1, 2]] = [2, 1]");
+    qeval(&p, "[x + 1 for x in [1, 2]] = [2, 3]");
+    Ok(())
+}
+
+#[test]
+fn test_aggregates() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"can_log_in(user) if count(s for s in user.sessions if s.active) < 2;
+           total(xs, n) if n = sum(x for x in xs);
+           oldest(people, age) if age = max(p.age for p in people);
+           first_name(names, name) if name = min(n for n in names);"#,
+    )?;
+    qeval(
+        &p,
+        "can_log_in({sessions: [{active: true}, {active: false}]})",
+    );
+    qnull(
+        &p,
+        "can_log_in({sessions: [{active: true}, {active: true}]})",
+    );
+    qeval(&p, "can_log_in({sessions: []})");
+
+    qvar(&p, "total([1, 2, 3], n)", "n", values![6]);
+    qvar(&p, "total([1, 2.5], n)", "n", values![3.5]);
+    qvar(&p, "total([], n)", "n", values![0]);
+    qvar(
+        &p,
+        "oldest([{age: 30}, {age: 50}, {age: 40}], age)",
+        "age",
+        values![50],
+    );
+    qvar(
+        &p,
+        r#"first_name(["bob", "alice", "carol"], name)"#,
+        "name",
+        values!["alice"],
+    );
+    qnull(&p, "oldest([], age)");
+    qvar(
+        &p,
+        "n = count(x for x in 1..10 if x mod 3 = 0)",
+        "n",
+        values![3],
+    );
+
+    qruntime!(r#"n = sum(x for x in [1, "a"])"#, TypeError { .. });
+    let e = p
+        .new_query(r#"first_name([3, "a"], name)"#, false)?
+        .next_event()
+        .unwrap_err();
+    assert!(
+        e.to_string()
+            .contains(r#"Type error: cannot order 3 and "a" at line 4, column 46"#),
+        "{}",
+        e
+    );
+    qruntime!(
+        "n = sum(x for x in [9223372036854775807, 1])",
+        ArithmeticError { .. }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_distinct() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"loves("vincent", "mia");
+           loves("marcellus", "mia");
+           loves("vincent", "honey bunny");
+           loves("marcellus", "honey bunny");
+           jealous(a, b) if loves(a, c) and loves(b, c) and a != b;
+           lover(x) if distinct(loves(x, _));"#,
+    )?;
+    // Each pair is jealous via two witnesses.
+    assert_eq!(vars(&p, "jealous(a, b)", &["a", "b"]).len(), 4);
+    qvars(
+        &p,
+        "distinct(jealous(a, b))",
+        &["a", "b"],
+        values![["vincent", "marcellus"], ["marcellus", "vincent"]],
+    );
+    // Anonymous & underscored variables aren't part of an answer.
+    qvar(&p, "lover(x)", "x", values!["vincent", "marcellus"]);
+    qvar(
+        &p,
+        "distinct(loves(x, _who))",
+        "x",
+        values!["vincent", "marcellus"],
+    );
+    qvar(&p, "distinct(x in [1, 2, 1, 3, 2])", "x", values![1, 2, 3]);
+    qvar(&p, "x = 1 and distinct(x in [1, 1])", "x", values![1]);
+    qnull(&p, "distinct(x in [])");
+    qvar(
+        &p,
+        "distinct(y in [{a: 1}, {a: 2}, {a: 1}] and x = y.a)",
+        "x",
+        values![1, 2],
+    );
+    qvar(
+        &p,
+        "distinct(_x in 0..1000 and y = _x mod 5)",
+        "y",
+        values![0, 1, 2, 3, 4],
+    );
+
+    // Each new result is returned before the query looks for the next.
+    let mut q = p.new_query("distinct(x in [1, 1, 2] and print(x))", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    let printed: Vec<_> = std::iter::from_fn(|| q.next_message())
+        .map(|message| message.msg)
+        .collect();
+    assert_eq!(printed, ["1"]);
+    Ok(())
+}
+
+#[test]
+fn test_once() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"member(x, [x, *_]);
+           member(x, [_, *rest]) if member(x, rest);
+           f(x) if once(member(x, [1, 2, 3]));
+           f(4);
+           g(x, y) if once(x in [1, 2]) and y in [1, 2];"#,
+    )?;
+    qvar(&p, "f(x)", "x", values![1, 4]);
+    qvars(&p, "g(x, y)", &["x", "y"], values![[1, 1], [1, 2]]);
+    qeval(&p, "once(member(2, [1, 2, 2]))");
+    qnull(&p, "once(member(4, [1, 2, 3]))");
+    qvar(&p, "once(x in [1, 2] and x > 1)", "x", values![2]);
+    qvar(
+        &p,
+        "once(y in [{a: 1}, {a: 2}] and x = y.a)",
+        "x",
+        values![1],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_boundness_builtins() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"digit(x) if is_unbound(x) and x in 0..3;
+           digit(x) if is_bound(x) and x >= 0 and x < 3;"#,
+    )?;
+    qvar(&p, "digit(x)", "x", values![0, 1, 2]);
+    qeval(&p, "digit(2)");
+    qnull(&p, "digit(5)");
+
+    qeval(&p, "is_bound(1)");
+    qeval(&p, "is_bound([x])");
+    qeval(&p, "x = 1 and is_bound(x)");
+    qnull(&p, "is_bound(x)");
+    qeval(&p, "is_unbound(x)");
+    qeval(&p, "x = y and is_unbound(x)");
+    qnull(&p, "x = y and y = 1 and is_unbound(x)");
+    qruntime!("is_bound(x, y)", TypeError { .. });
+
+    // Rules with the same name take precedence over the built-in.
+    p.clear_rules();
+    p.load_str(r#"is_bound("custom");"#)?;
+    qnull(&p, "is_bound(1)");
+    qeval(&p, r#"is_bound("custom")"#);
+    Ok(())
+}
+
+#[test]
+fn test_type_check_builtins() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"owner(resource, name) if is_dict(resource) and is_string(resource.owner) and name = resource.owner;
+           size(x, n) if is_list(x) and n = count(_y for _y in x);
+           size(x, n) if is_integer(x) and n = x;"#,
+    )?;
+    qvar(
+        &p,
+        r#"owner({owner: "alice"}, name)"#,
+        "name",
+        values!["alice"],
+    );
+    qnull(&p, "owner({owner: 1}, name)");
+    qnull(&p, r#"owner(["alice"], name)"#);
+    qvar(&p, "size([1, 2], n)", "n", values![2]);
+    qvar(&p, "size(3, n)", "n", values![3]);
+    qnull(&p, "size(3.0, n)");
+
+    qeval(&p, r#"is_string("a")"#);
+    qnull(&p, "is_string(1)");
+    qeval(&p, "is_integer(-1)");
+    qnull(&p, r#"is_integer("1")"#);
+    qeval(&p, "is_list([])");
+    qnull(&p, "is_list({})");
+    qeval(&p, "is_dict({a: [1]})");
+    qnull(&p, "is_dict([])");
+    // Unbound variables aren't of any type.
+    qnull(&p, "is_string(x)");
+    qeval(&p, r#"x = "a" and is_string(x)"#);
+    Ok(())
+}
+
+#[test]
+fn test_matches_regex() -> TestResult {
+    let p = polar();
+    p.load_str(r#"valid_email(email) if matches_regex(email, "^[a-z.]+@example\\.com$");"#)?;
+    qeval(&p, r#"valid_email("alice@example.com")"#);
+    qnull(&p, r#"valid_email("alice@example.org")"#);
+    qnull(&p, r#"valid_email("alice@exampleXcom")"#);
+    qvar(
+        &p,
+        r#"x in ["ab", "b", "abc"] and matches_regex(x, "^ab")"#,
+        "x",
+        values!["ab", "abc"],
+    );
+
+    qruntime!("matches_regex(1, \"a\")", TypeError { .. });
+    qruntime!("matches_regex(x, \"a\")", TypeError { .. });
+    qruntime!(r#"matches_regex("a", "(")"#, TypeError { .. });
+    Ok(())
+}
+
+#[test]
+fn test_register_builtin() -> TestResult {
+    let p = polar();
+    p.register_builtin("has_prefix", 2, |args| {
+        match (args[0].value(), args[1].value()) {
+            (Value::String(s), Value::String(prefix)) if s.starts_with(prefix.as_str()) => {
+                Ok(BuiltinAnswer::Yes)
+            }
+            (Value::String(_), Value::String(_)) => Ok(BuiltinAnswer::No),
+            _ => Err(format!(
+                "has_prefix expects strings, got {} and {}",
+                args[0], args[1]
+            )),
+        }
+    })?;
+    p.register_builtin("between", 3, |args| {
+        let (Value::Number(Numeric::Integer(lo)), Value::Number(Numeric::Integer(hi))) =
+            (args[0].value(), args[1].value())
+        else {
+            return Err("between expects integer bounds".to_owned());
+        };
+        let choices = (*lo..*hi)
+            .map(|i| vec![args[0].clone(), args[1].clone(), term!(i)])
+            .collect();
+        Ok(BuiltinAnswer::Choices(choices))
+    })?;
+    p.register_builtin("double", 2, |args| match args[0].value() {
+        Value::Number(Numeric::Integer(i)) => {
+            Ok(BuiltinAnswer::Unify(vec![args[0].clone(), term!(i * 2)]))
+        }
+        _ => Ok(BuiltinAnswer::Unify(vec![])),
+    })?;
+
+    // Rules can call registered built-ins without them being undefined rule calls.
+    p.load_str(r#"internal(ip) if has_prefix(ip, "10.");"#)?;
+    qeval(&p, r#"internal("10.0.0.1")"#);
+    qnull(&p, r#"internal("192.168.0.1")"#);
+    qruntime!(
+        &p,
+        r#"internal(1)"#,
+        Application { msg, .. },
+        msg.contains("expects strings")
+    );
+    qruntime!(
+        &p,
+        r#"has_prefix("a")"#,
+        TypeError { msg, .. },
+        msg.ends_with("got 1")
+    );
+
+    qvar(&p, "between(1, 4, x)", "x", values![1, 2, 3]);
+    qnull(&p, "between(1, 1, x)");
+    qvar(&p, "between(1, 4, x) and x > 1", "x", values![2, 3]);
+    qeval(&p, "between(1, 4, 2)");
+    qnull(&p, "between(1, 4, 7)");
+    qvar(&p, "x = 3 and double(x, y)", "y", values![6]);
+    // The answer has to have a value for each argument.
+    qruntime!(&p, "double(1.5, y)", TypeError { .. });
+
+    assert!(p
+        .register_builtin("has_prefix", 2, |_| Ok(BuiltinAnswer::No))
+        .is_err());
+    assert!(p
+        .register_builtin("is_bound", 1, |_| Ok(BuiltinAnswer::No))
+        .is_err());
+
+    // Rules with the same name take precedence over the built-in.
+    p.clear_rules();
+    p.load_str(r#"has_prefix("custom", _);"#)?;
+    qnull(&p, r#"has_prefix("10.0.0.1", "10.")"#);
+    qeval(&p, r#"has_prefix("custom", "x")"#);
+    Ok(())
+}
+
+#[test]
+fn test_datetimes() -> TestResult {
+    let mut p = polar();
+    p.set_clock(std::sync::Arc::new(|| 1646137800000));
+    p.load_str(
+        r#"valid(token) if now(t) and time_after(token.expires_at, t);
+           issued_after(token, t) if not time_before(token.issued_at, t);"#,
+    )?;
+    qeval(&p, r#"valid({expires_at: "2022-03-01T12:30:00.001Z"})"#);
+    qnull(&p, r#"valid({expires_at: "2022-03-01T12:30:00Z"})"#);
+    // Offsets and fractional digits don't affect the order.
+    qeval(&p, r#"valid({expires_at: "2022-03-01T13:31:00+01:00"})"#);
+    qnull(
+        &p,
+        r#"valid({expires_at: "2022-03-01T13:29:00.999999+01:00"})"#,
+    );
+    qeval(
+        &p,
+        r#"issued_after({issued_at: "2022-03-01T06:00:00-06:00"}, "2022-03-01T12:00:00.000Z")"#,
+    );
+    qvar(&p, "now(t)", "t", values!["2022-03-01T12:30:00.000Z"]);
+    qeval(&p, r#"now("2022-03-01T12:30:00.000Z")"#);
+    qvar(
+        &p,
+        "x = [t for _ in [1] if now(t)]",
+        "x",
+        vec![value!(["2022-03-01T12:30:00.000Z"])],
+    );
+    qruntime!(
+        r#"time_before("2022-03-01", "2022-03-01T00:00:00Z")"#,
+        TypeError { .. }
+    );
+    qruntime!(r#"time_after(1, "2022-03-01T00:00:00Z")"#, TypeError { .. });
+    // Comparison operators compare the strings, even if they're datetimes.
+    qeval(
+        &p,
+        r#""2022-03-01T13:00:00+01:00" > "2022-03-01T12:30:00Z""#,
+    );
+    qnull(
+        &p,
+        r#""2022-03-01T12:30:00Z" == "2022-03-01T12:30:00.000Z""#,
+    );
+    Ok(())
+}
+
+#[test]
+fn test_add_and_remove_facts() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"session_role("bob", "member");
+           can_edit(user) if session_role(user, "admin");"#,
+    )?;
+    let alice_admin = term!(call!("session_role", ["alice", "admin"]));
+    qnull(&p, r#"can_edit("alice")"#);
+    p.add_fact(alice_admin.clone())?;
+    qeval(&p, r#"can_edit("alice")"#);
+    qvar(&p, "session_role(user, _)", "user", values!["bob", "alice"]);
+
+    // Duplicate facts are removed one at a time.
+    p.add_fact(alice_admin.clone())?;
+    assert!(p.remove_fact(alice_admin.clone())?);
+    qeval(&p, r#"can_edit("alice")"#);
+    assert!(p.remove_fact(alice_admin.clone())?);
+    assert!(!p.remove_fact(alice_admin)?);
+    qnull(&p, r#"can_edit("alice")"#);
+
+    // Loaded facts can be removed too.
+    assert!(p.remove_fact(term!(call!("session_role", ["bob", "member"])))?);
+    qnull(&p, "session_role(_user, _role)");
+
+    // Facts can be added for new rule names.
+    p.add_fact(term!(call!("flag", [1])))?;
+    qeval(&p, "flag(1)");
+
+    // Of duplicate facts, the earliest is removed, here and after a reload.
+    for x in [1, 2, 1] {
+        p.add_fact(term!(call!("rank", [x])))?;
+    }
+    assert!(p.remove_fact(term!(call!("rank", [1])))?);
+    qvar(&p, "rank(x)", "x", values![2, 1]);
+    let sources = p.kb.read().unwrap().sources().to_vec();
+    p.reload_policy(sources)?;
+    qvar(&p, "rank(x)", "x", values![2, 1]);
+
+    let err = p
+        .add_fact(term!(call!("session_role", [sym!("user"), "admin"])))
+        .unwrap_err();
+    assert!(matches!(err.0, ErrorKind::Validation(InvalidRule { .. })));
+    assert!(p.add_fact(term!("session_role")).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_add_and_remove_sources() -> TestResult {
+    let p = polar();
+    let source = |filename: &str, src: &str| Source {
+        filename: Some(filename.into()),
+        src: src.into(),
+    };
+    p.load(vec![source("a.polar", "f(1);"), source("b.polar", "g(1);")])?;
+    p.add_fact(term!(call!("f", [2])))?;
+
+    p.add_source(source("c.polar", "h(1); ?= f(1);"))?;
+    qvar(&p, "f(x)", "x", values![1, 2]);
+    qeval(&p, "h(1)");
+    assert!(p.next_inline_query(false).is_some());
+    assert!(p.next_inline_query(false).is_none());
+
+    // A source replaces the one with the same filename, and facts that were added stay.
+    p.add_source(source("a.polar", "f(3);"))?;
+    qvar(&p, "f(x)", "x", values![3, 2]);
+    assert!(p.next_inline_query(false).is_none());
+
+    // If the reload fails, the policy stays as it was.
+    assert!(p.add_source(source("b.polar", "g(1) if;")).is_err());
+    qeval(&p, "g(1)");
+    qvar(&p, "f(x)", "x", values![3, 2]);
+
+    assert!(p.remove_source("b.polar")?);
+    assert!(!p.remove_source("b.polar")?);
+    qruntime!(&p, "g(1)", QueryForUndefinedRule { .. });
+    qeval(&p, "h(1)");
+    Ok(())
+}
+
+#[test]
+fn test_reload_policy() -> TestResult {
+    let p = polar();
+    p.register_constant(sym!("c"), term!(1))?;
+    p.load_str("X = 2; f(x) if x = c or x = X;")?;
+    qvar(&p, "f(x)", "x", values![1, 2]);
+
+    // Registered constants are kept and policy constants aren't.
+    p.reload_policy(vec![Source::new("X = 3; f(x) if x = c or x = X;")])?;
+    qvar(&p, "f(x)", "x", values![1, 3]);
+    p.clear_rules();
+    p.load_str("f(x) if x = c;")?;
+    qvar(&p, "f(x)", "x", values![1]);
+
+    // If the new policy fails to load, the old one stays.
+    assert!(p.reload_policy(vec![Source::new("f(x) if x = ;")]).is_err());
+    qvar(&p, "f(x)", "x", values![1]);
+    Ok(())
+}
+
+#[test]
+fn test_query_from_pred() -> TestResult {
+    let p = polar();
+    p.load_str(r#"allow(actor, "read", doc) if doc = "public" and actor != "mallory";"#)?;
+    let results = |term| query_results!(p.new_query_from_term(term, false));
+
+    assert_eq!(
+        results(pred!("allow", ["alice", "read", "public"])).len(),
+        1
+    );
+    assert!(results(pred!("allow", ["mallory", "read", "public"])).is_empty());
+    // An argument is only ever a string, however much it looks like Polar.
+    let doc = r#"secret") or ("public"#;
+    assert!(results(pred!("allow", ["alice", "read", doc])).is_empty());
+    // Arguments can be terms, including variables.
+    let action = term!(sym!("action"));
+    let results = results(pred!("allow", ["alice", action, "public"]));
+    assert_eq!(results[0].0[&sym!("action")], value!("read"));
+    Ok(())
+}
+
+#[test]
+fn test_bind_parsed_query() -> TestResult {
+    let p = polar();
+    p.load_str(r#"allow(actor, "read", doc) if doc = "public" or actor = "admin";"#)?;
+    let query = polar_core::parser::parse_query("allow(actor, action, resource)")?;
+
+    let allowed = |actor: &str, resource: &str| -> PolarResult<Vec<Term>> {
+        let mut q = p.new_query_from_term(query.clone(), false);
+        q.bind(sym!("actor"), term!(actor))?;
+        q.bind(sym!("resource"), term!(resource))?;
+        q.results(|_, event| panic!("unexpected event: {:?}", event))
+            .map(|bindings| bindings.map(|bindings| bindings[&sym!("action")].clone()))
+            .collect()
+    };
+    assert_eq!(allowed("alice", "public")?, vec![term!("read")]);
+    assert_eq!(allowed("alice", "secret")?, vec![]);
+    assert_eq!(allowed("admin", "secret")?, vec![term!("read")]);
+    Ok(())
+}
+
+#[test]
+fn test_error_spans() -> TestResult {
+    let p = polar();
+    let e = p.load_str("f(x) if\n  x = ;").unwrap_err();
+    let span = e.span().unwrap();
+    assert_eq!((span.line, span.column), (2, 7));
+    assert_eq!(span.text, ";");
+    assert_eq!(span.filename, None);
+
+    let source = Source::new_with_name("singleton.polar", "g(x, y) if\n  y = 1;");
+    let e = p.load(vec![source]).unwrap_err();
+    assert!(matches!(
+        e.0,
+        ErrorKind::Validation(SingletonVariable { .. })
+    ));
+    let span = e.span().unwrap();
+    assert_eq!(span.filename.as_deref(), Some("singleton.polar"));
+    assert_eq!((span.line, span.column), (1, 3));
+    assert_eq!(span.text, "x");
+
+    let error = FormattedPolarError::from(e);
+    let json = serde_json::to_value(&error).unwrap();
+    assert_eq!(json["span"]["line"], 1);
+    assert_eq!(json["span"]["end_column"], 4);
+
+    p.load_str("h(x) if x + 1 = 2;")?;
+    let e = _qruntime(&p, "h(\"one\")");
+    assert!(matches!(e.0, ErrorKind::Runtime(Unsupported { .. })));
+    let span = e.span().unwrap();
+    assert_eq!((span.line, span.column), (1, 9));
+    assert_eq!(span.text, "x + 1");
+    Ok(())
+}
+
+#[test]
+fn test_formatted_errors() -> TestResult {
+    let p = polar();
+    let source = Source::new_with_name("policy.polar", "f(x) if\n  x = ;");
+    let e = p.load(vec![source]).unwrap_err();
+    let expected = indoc! {"
+        error: did not expect to find the token ';'
+         --> policy.polar:2:7
+          |
+        2 |   x = ;
+          |       ^"};
+    assert_eq!(e.to_formatted(), expected);
+    assert_eq!(format!("{:#}", e), expected);
+
+    let e = p.load_str("g() if missing(1);").unwrap_err();
+    let expected = indoc! {"
+        error: Call to undefined rule: missing(1)
+         --> line 1, column 8
+          |
+        1 | g() if missing(1);
+          |        ^^^^^^^^^^
+          = hint: check the spelling of the rule's name, or define the rule"};
+    assert_eq!(e.to_formatted(), expected);
+
+    p.load_str("h(x) if x + 1 = 2;")?;
+    let e = _qruntime(&p, "h(\"one\")");
+    assert!(e
+        .to_formatted()
+        .ends_with("1 | h(x) if x + 1 = 2;\n  |         ^^^^^"));
+
+    // Errors with no context are only the message.
+    let e = _qruntime(&p, "undefined()");
+    assert_eq!(
+        e.to_formatted(),
+        "error: Query for undefined rule `undefined`"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_released_instances() -> TestResult {
+    use polar_core::builder::{op, var, RuleBuilder};
+
+    let p = polar();
+    p.register_constant(sym!("User"), external_instance(1001, Some("User")))?;
+    p.register_constant(sym!("ADMIN"), external_instance(1002, None))?;
+    // Answer a query, leaving the instances it makes to the host.
+    let results = |query: &mut Query| {
+        let mut results = vec![];
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Result { bindings, .. } => results.push(bindings),
+                QueryEvent::MakeExternal { .. } => (),
+                _ => return results,
+            }
+        }
+    };
+
+    let mut query = p.new_query(r#"x = new User("carol") and y = ADMIN"#, false)?;
+    let bindings = results(&mut query).remove(0);
+    let carol_id = match bindings[&sym!("x")].value() {
+        Value::ExternalInstance(ExternalInstance { instance_id, .. }) => *instance_id,
+        _ => unreachable!(),
+    };
+
+    // The constant outlives the query, but the instance it made doesn't.
+    assert!(query.instances().contains(&carol_id));
+    assert_eq!(query.released_instances(), vec![carol_id]);
+
+    // Instances the host binds are the host's to free.
+    let mut query = p.new_query("x = y", false)?;
+    query.bind(sym!("y"), external_instance(1003, None))?;
+    assert_eq!(results(&mut query).len(), 1);
+    assert_eq!(query.instances().len(), 1);
+    assert!(query.released_instances().is_empty());
+
+    // Instances in the body of an added rule outlive the query that made them.
+    let mut query = p.new_query(r#"x = new User("dave")"#, false)?;
+    let dave = results(&mut query).remove(0)[&sym!("x")].clone();
+    p.add_rule(
+        RuleBuilder::new("dave")
+            .param(var("u"))
+            .body(op(Operator::Unify, vec![var("u"), dave]))
+            .build(),
+    )?;
+    assert!(query.released_instances().is_empty());
+    assert_eq!(query.made_instances().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_wide_numbers() -> TestResult {
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Order {
+        next_id: u64,
+        total: String,
+    }
+
+    let p = polar();
+    p.load_str(indoc! {r#"
+        order(id, price, next_id, total) if
+            id > 9223372036854775807 and
+            next_id = id + 1 and
+            total = price * 3 and
+            total > 59 and total < 60;
+    "#})?;
+    let mut query = p.new_query("order(id, price, next_id, total)", false)?;
+    query.bind(sym!("id"), Term::from(u64::MAX - 1))?;
+    query.bind(sym!("price"), Term::from(Decimal::parse("19.99").unwrap()))?;
+    assert_eq!(
+        query.results_as::<Order>()?,
+        vec![Order {
+            next_id: u64::MAX,
+            total: "59.97".to_owned(),
+        }]
+    );
+
+    // Wide numbers compare with other numbers by value, and a decimal with the exact value of a
+    // float, which 59.97 is only close to.
+    let mut query = p.new_query(
+        "x = y and x < 18446744073709551616.0 and x != 18446744073709551616.0 and \
+         z != 59.97 and z > 59.96 and z < 59.98",
+        false,
+    )?;
+    query.bind(sym!("x"), Term::from(u64::MAX))?;
+    query.bind(sym!("y"), Term::from(u64::MAX as i128))?;
+    query.bind(sym!("z"), Term::from(Decimal::parse("59.970").unwrap()))?;
+    let results = query.results_as::<BTreeMap<String, serde_json::Value>>()?;
+    assert_eq!(results[0]["x"], serde_json::json!(u64::MAX));
+    assert_eq!(results[0]["z"], serde_json::json!("59.97"));
+    Ok(())
+}
+
+#[test]
+fn test_results_as() -> TestResult {
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Edge {
+        from: String,
+        to: String,
+        weight: Option<f64>,
+    }
+
+    let p = polar();
+    p.load_str(indoc! {r#"
+        edge("a", "b", 1.5);
+        edge("b", "c", _);
+    "#})?;
+    let mut query = p.new_query("edge(from, to, weight)", false)?;
+    let edges: Vec<Edge> = query.results_as()?;
+    assert_eq!(
+        edges,
+        vec![
+            Edge {
+                from: "a".to_owned(),
+                to: "b".to_owned(),
+                weight: Some(1.5),
+            },
+            Edge {
+                from: "b".to_owned(),
+                to: "c".to_owned(),
+                weight: None,
+            },
+        ]
+    );
+
+    // Values that don't fit the type are errors.
+    let mut query = p.new_query("from = 1", false)?;
+    assert!(query.results_as::<Edge>().is_err());
+
+    // So are events for the host.
+    let mut query = p.new_query("x = new Foo()", false)?;
+    assert!(query.results_as::<Edge>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_instances_in_results() -> TestResult {
+    #[derive(Debug, serde::Deserialize)]
+    struct Owned {
+        owner: InstanceRef,
+        resource: InstanceRef,
+    }
+
+    let p = polar();
+    let instance = |instance_id, class_tag: &str| {
+        term!(Value::ExternalInstance(ExternalInstance {
+            instance_id,
+            constructor: None,
+            repr: None,
+            class_repr: Some(class_tag.to_owned()),
+            class_id: None,
+        }))
+    };
+    p.register_constant(sym!("alice"), instance(101, "User"))?;
+    p.register_constant(sym!("repo"), instance(100, "Repository"))?;
+    p.load_str(r#"owns(owner, resource) if owner = alice and resource = repo;"#)?;
+
+    // The instances in a result have their ids and class tags, so the host can find its objects.
+    let mut query = p.new_query("owns(owner, resource)", false)?;
+    let results: Vec<Owned> = query.results_as()?;
+    assert_eq!(results[0].owner.class_tag.as_deref(), Some("User"));
+    assert_eq!(
+        results[0].resource,
+        InstanceRef {
+            instance_id: 100,
+            class_tag: Some("Repository".to_owned()),
+        }
+    );
+
+    let mut query = p.new_query("owns(owner, resource)", false)?;
+    let QueryEvent::Result { bindings, .. } = query.next_event()? else {
+        panic!("expected a result");
+    };
+    let owner = InstanceRef::try_from(&bindings[&sym!("owner")])?;
+    assert_eq!(owner, results[0].owner);
+    assert_eq!(
+        serde_json::Value::try_from(&bindings[&sym!("resource")])?,
+        serde_json::json!({"instance_id": 100, "class_tag": "Repository"})
+    );
+    assert!(InstanceRef::try_from(&term!("repo")).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_queries() -> TestResult {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Polar>();
+
+    let p = std::sync::Arc::new(polar());
+    p.load_str(indoc! {"
+        count(n, n) if n >= 200;
+        count(n, m) if n < 200 and count(n + 1, m);
+    "})?;
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let p = p.clone();
+            std::thread::spawn(move || {
+                let query = p.new_query(&format!("count({}, m)", i), false).unwrap();
+                query.count()
+            })
+        })
+        .collect();
+    for thread in threads {
+        // Each query has a result and then is done.
+        assert_eq!(thread.join().unwrap(), 2);
+    }
+    Ok(())
+}
+
+/// Poll `future` on this thread until it's ready.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+    impl Wake for Unpark {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(std::sync::Arc::new(Unpark(std::thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// A future that's pending once, like a lookup waiting on I/O.
+async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|context| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            context.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+#[test]
+fn test_run_async() -> TestResult {
+    let p = polar();
+    p.load_str(r#"can(user, "read") if user.roles.contains("reader");"#)?;
+    p.register_constant(sym!("alice"), external_instance(1, None))?;
+    let roles = |instance: Term, attribute: Symbol, args: Option<Vec<Term>>| async move {
+        yield_now().await;
+        match (&*attribute.as_str(), args) {
+            ("roles", None) => Ok(Some(instance)),
+            ("contains", Some(args)) => Ok(Some(term!(args == vec![term!("reader")]))),
+            _ => Err(format!("no attribute {}", attribute)),
+        }
+    };
+
+    let mut q = p.new_query(r#"can(alice, action)"#, false)?;
+    let results = block_on(q.run_async(roles))?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0][&sym!("action")], term!("read"));
+
+    let mut q = p.new_query("alice.name = _", false)?;
+    let error = block_on(q.run_async(roles)).unwrap_err();
+    assert!(
+        matches!(&error.0, ErrorKind::Runtime(Application { msg, .. }) if msg == "no attribute name"),
+        "{}",
+        error
+    );
+
+    let mut q = p.new_query("new Foo() = _", false)?;
+    assert!(block_on(q.run_async(roles)).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_query_with() -> TestResult {
+    /// Users made with `new User(name)`.
+    #[derive(Default)]
+    struct Users(HashMap<u64, Term>);
+
+    impl QueryHandler for Users {
+        fn make_external(&mut self, instance_id: u64, constructor: Term) -> Result<(), String> {
+            match constructor.value() {
+                Value::Call(Call { name, args, .. }) if &*name.as_str() == "User" => {
+                    self.0.insert(instance_id, args[0].clone());
+                    Ok(())
+                }
+                _ => Err(format!("can't make {}", constructor)),
+            }
+        }
+
+        fn external_call(
+            &mut self,
+            instance: Term,
+            attribute: Symbol,
+            _: Option<Vec<Term>>,
+            _: Option<BTreeMap<Symbol, Term>>,
+        ) -> Result<Option<Term>, String> {
+            match (instance.value(), &*attribute.as_str()) {
+                (Value::ExternalInstance(ExternalInstance { instance_id, .. }), "name") => {
+                    Ok(self.0.get(instance_id).cloned())
+                }
+                _ => Err(format!("no attribute {}", attribute)),
+            }
+        }
+
+        fn external_isa(&mut self, instance: Term, class_tag: Symbol) -> Result<bool, String> {
+            Ok(&*class_tag.as_str() == "User"
+                && matches!(instance.value(), Value::ExternalInstance(_)))
+        }
+    }
+
+    let p = polar();
+    p.register_constant(sym!("User"), external_instance(p.get_external_id(), None))?;
+    p.load_str(r#"named(u: User, name) if name = u.name;"#)?;
+
+    let results = p
+        .query_with(r#"named(new User("alice"), name)"#, Users::default())?
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0][&sym!("name")], term!("alice"));
+
+    let mut results = p.query_with(r#"new User("bob").age = _"#, Users::default())?;
+    assert!(matches!(
+        results.next(),
+        Some(Err(PolarError(ErrorKind::Runtime(Application { msg, .. }), ..))) if msg == "no attribute age"
+    ));
+    assert!(results.next().is_none());
+
+    // The methods a handler doesn't implement can't answer their events.
+    struct Nothing;
+    impl QueryHandler for Nothing {}
+    let mut results = p.query_with(r#"new User("carol") = _"#, Nothing)?;
+    assert!(results.next().unwrap().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_unify_host_instances() -> TestResult {
+    // Records whose instances are equal if they have the same key, like two handles to one row.
+    let keys = HashMap::from([(1, 10), (2, 10), (3, 30)]);
+    let key = |term: &Term| match term.value() {
+        Value::ExternalInstance(ExternalInstance { instance_id, .. }) => keys.get(instance_id),
+        _ => None,
+    };
+    let records = || {
+        MockHandler::default().on_op(|operator, args| {
+            let equal = key(&args[0]).is_some() && key(&args[0]) == key(&args[1]);
+            match operator {
+                Operator::Eq => Ok(equal),
+                Operator::Neq => Ok(!equal),
+                _ => Err(format!("can't compare records with {:?}", operator)),
+            }
+        })
+    };
+
+    let p = polar();
+    for (name, instance_id) in [("a", 1), ("b", 2), ("c", 3)] {
+        p.register_constant(sym!(name), external_instance(instance_id, None))?;
+    }
+    p.load_str("same(x, x);")?;
+    let count = |src: &str| -> PolarResult<usize> {
+        Ok(p.query_with(src, records())?
+            .collect::<PolarResult<Vec<_>>>()?
+            .len())
+    };
+
+    // Unifying two different instances asks the host whether they're equal.
+    assert_eq!(count("a = b")?, 1);
+    assert_eq!(count("a = c")?, 0);
+    assert_eq!(count("same(a, b)")?, 1);
+    assert_eq!(count("[a, c] = [b, c]")?, 1);
+    assert_eq!(count("x = a and x = b and x = c")?, 0);
+    assert_eq!(count("a != c")?, 1);
+    // The same instance is equal to itself without asking.
+    assert_eq!(count("c = c")?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_compare_host_instances() -> TestResult {
+    // Versions, by instance id, that the host orders. Plain integers are major versions.
+    let versions = HashMap::from([(1, (1, 5)), (2, (1, 2)), (3, (2, 0))]);
+    let version = |term: &Term| match term.value() {
+        Value::ExternalInstance(ExternalInstance { instance_id, .. }) => {
+            versions.get(instance_id).copied().ok_or("unknown version")
+        }
+        Value::Number(Numeric::Integer(major)) => Ok((*major, 0)),
+        _ => Err("not a version"),
+    };
+    let versions = || {
+        MockHandler::default().on_op(|operator, args| {
+            let (left, right) = (version(&args[0])?, version(&args[1])?);
+            Ok(match operator {
+                Operator::Lt => left < right,
+                Operator::Leq => left <= right,
+                Operator::Gt => left > right,
+                Operator::Geq => left >= right,
+                Operator::Eq => left == right,
+                Operator::Neq => left != right,
+                _ => return Err(format!("can't compare versions with {:?}", operator)),
+            })
+        })
+    };
+
+    let p = polar();
+    for (name, instance_id) in [("min_version", 1), ("v1_2", 2), ("v2_0", 3)] {
+        p.register_constant(sym!(name), external_instance(instance_id, None))?;
+    }
+    p.load_str("supported(v) if v >= min_version and v < 3;")?;
+    let holds = |src: &str| -> PolarResult<bool> {
+        Ok(p.query_with(src, versions())?.next().transpose()?.is_some())
+    };
+
+    // Comparisons with a host instance on either side are answered by the host.
+    assert!(holds("supported(v2_0)")?);
+    assert!(!holds("supported(v1_2)")?);
+    assert!(holds("v1_2 < min_version and 1 <= v1_2")?);
+    assert!(!holds("v2_0 > 2")?);
+    Ok(())
+}
+
+#[test]
+fn test_lazy_host_iteration() -> TestResult {
+    let p = polar();
+    p.register_constant(sym!("naturals"), external_instance(1, None))?;
+    p.load_str("big(x) if x in naturals and x > 2;")?;
+
+    // The host produces elements one at a time, only as many as the results need, so even an
+    // endless iterable can be searched.
+    let pulled = Cell::new(0);
+    let mut positions = HashMap::new();
+    let naturals = MockHandler::default().on_next(|call_id| {
+        let position = positions.entry(call_id).or_insert(0);
+        *position += 1;
+        pulled.set(pulled.get() + 1);
+        Ok(Some(term!(*position)))
+    });
+    let mut results = p.query_with("big(x)", naturals)?;
+    assert_eq!(results.next().transpose()?.unwrap()[&sym!("x")], term!(3));
+    assert_eq!(pulled.get(), 3);
+    assert_eq!(results.next().transpose()?.unwrap()[&sym!("x")], term!(4));
+    assert_eq!(pulled.get(), 4);
+    Ok(())
+}
+
+#[test]
+fn test_make_external_arguments() -> TestResult {
+    use std::rc::Rc;
+
+    // Accounts made with `new Account(name, plan: plan)`, by instance id.
+    let accounts = || {
+        let made = Rc::new(RefCell::new(HashMap::<u64, (Term, Term)>::new()));
+        let lookups = made.clone();
+        MockHandler::default()
+            .on_make(move |instance_id, constructor| {
+                let Value::Call(Call { args, kwargs, .. }) = constructor.value() else {
+                    return Err(format!("can't make {}", constructor));
+                };
+                let plan = kwargs
+                    .as_ref()
+                    .and_then(|kwargs| kwargs.get(&sym!("plan")).cloned())
+                    .unwrap_or_else(|| term!("free"));
+                made.borrow_mut()
+                    .insert(instance_id, (args[0].clone(), plan));
+                Ok(())
+            })
+            .on_call(move |instance_id, attribute, _, _| {
+                let (name, plan) = &lookups.borrow()[&instance_id];
+                match attribute {
+                    "name" => Ok(Some(name.clone())),
+                    "plan" => Ok(Some(plan.clone())),
+                    _ => Err(format!("no attribute {}", attribute)),
+                }
+            })
+    };
+
+    let p = polar();
+    p.register_constant(
+        sym!("Account"),
+        external_instance(p.get_external_id(), None),
+    )?;
+    let query = |src: &str| -> PolarResult<Vec<HashMap<Symbol, Term>>> {
+        p.query_with(src, accounts())?.collect()
+    };
+
+    // The constructor has the positional and keyword arguments of `new`, and later lookups on
+    // the new instance use the id it was made with.
+    let results =
+        query(r#"a = new Account("acme", plan: "pro") and name = a.name and plan = a.plan"#)?;
+    assert_eq!(results[0][&sym!("name")], term!("acme"));
+    assert_eq!(results[0][&sym!("plan")], term!("pro"));
+    let results = query(r#"plan = new Account("initech").plan"#)?;
+    assert_eq!(results[0][&sym!("plan")], term!("free"));
+    Ok(())
+}
+
+#[test]
+fn test_external_call_arguments() -> TestResult {
+    /// The instance hosts register as `nil`, like the Rust library's `None`.
+    fn nil() -> Term {
+        term!(Value::ExternalInstance(ExternalInstance {
+            instance_id: 2,
+            constructor: None,
+            repr: Some("None".to_owned()),
+            class_repr: None,
+            class_id: None,
+        }))
+    }
+
+    // A repository whose `role_for(user)` method knows one member, and returns nil for users
+    // with no role and no result for unknown users.
+    let repository = || {
+        MockHandler::default().on_call(|_, attribute, args, _| {
+            match (attribute, args.first().map(Term::value)) {
+                ("role_for", Some(Value::String(user))) => Ok(match user.as_str() {
+                    "alice" => Some(term!("maintainer")),
+                    "bob" => Some(nil()),
+                    _ => None,
+                }),
+                _ => Err(format!("can't call {} with {:?}", attribute, args)),
+            }
+        })
+    };
+
+    let p = polar();
+    p.register_constant(sym!("repo"), external_instance(1, None))?;
+    p.register_constant(sym!("nil"), nil())?;
+    p.load_str("role(user, role) if role = repo.role_for(user);")?;
+    let roles = |src: &str| -> PolarResult<Vec<Term>> {
+        p.query_with(src, repository())?
+            .map(|result| result.map(|bindings| bindings[&sym!("role")].clone()))
+            .collect()
+    };
+
+    // Arguments are passed with their values, not as the variables bound to them.
+    assert_eq!(
+        roles(r#"user = "alice" and role(user, role)"#)?,
+        vec![term!("maintainer")]
+    );
+    // A nil result is a value, and no result fails the lookup.
+    assert_eq!(roles(r#"role("bob", role)"#)?, vec![nil()]);
+    assert!(roles(r#"role("carol", role)"#)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_external_call_kwargs() -> TestResult {
+    // A directory whose `members(org, role: role)` method filters by the role it's called with.
+    let directory = || {
+        MockHandler::default().on_call(|_, attribute, args, kwargs| {
+            if attribute != "members" || args.len() != 1 {
+                return Err(format!("can't call {}", attribute));
+            }
+            Ok(Some(match kwargs.get(&sym!("role")).map(Term::value) {
+                Some(Value::String(role)) if role == "admin" => term!(["alice"]),
+                Some(_) => term!([]),
+                None => term!(["alice", "bob"]),
+            }))
+        })
+    };
+
+    let p = polar();
+    p.register_constant(sym!("directory"), external_instance(1, None))?;
+    let members = |src: &str| -> PolarResult<Term> {
+        let mut results = p.query_with(src, directory())?;
+        Ok(results.next().transpose()?.unwrap()[&sym!("members")].clone())
+    };
+
+    // Keyword arguments reach the host by name, with their values.
+    assert_eq!(
+        members(r#"role = "admin" and members = directory.members("acme", role: role)"#)?,
+        term!(["alice"])
+    );
+    assert_eq!(
+        members(r#"members = directory.members("acme", role: "guest")"#)?,
+        term!([])
+    );
+    assert_eq!(
+        members(r#"members = directory.members("acme")"#)?,
+        term!(["alice", "bob"])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_class_patterns_in_dictionaries() -> TestResult {
+    // Instance 1 is a user with id 1, and instance 2 is a team.
+    let directory = || {
+        MockHandler::default()
+            .on_call(|_, attribute, _, _| {
+                assert_eq!(attribute, "id");
+                Ok(Some(term!(1)))
+            })
+            .on_isa(|instance_id, class_tag| {
+                matches!(
+                    (instance_id, class_tag),
+                    (Some(1), "User") | (Some(2), "Team")
+                )
+            })
+    };
+
+    let p = polar();
+    for (name, id) in [("User", 100), ("Team", 101)] {
+        p.register_constant(sym!(name), external_instance(id, Some(name)))?;
+    }
+    for (name, id) in [("alice", 1), ("eng", 2)] {
+        p.register_constant(sym!(name), external_instance(id, None))?;
+    }
+    p.load_str(indoc! {r#"
+        owned_by_user(_: {owner: User});
+        owned_by_user_1(_: {owner: User{id: 1}});
+        owned_by_users(repo) if repo matches {owners: [User, User]};
+    "#})?;
+
+    // A class named in a pattern matches its instances, with the host's `isa` checks.
+    let count = |src: &str| -> PolarResult<usize> {
+        p.query_with(src, directory())?
+            .collect::<PolarResult<Vec<_>>>()
+            .map(|r| r.len())
+    };
+    assert_eq!(count("owned_by_user({owner: alice})")?, 1);
+    assert_eq!(count("owned_by_user({owner: eng})")?, 0);
+    assert_eq!(count("owned_by_user({owner: User})")?, 0);
+    assert_eq!(count("owned_by_user_1({owner: alice})")?, 1);
+    assert_eq!(count("owned_by_users({owners: [alice, alice]})")?, 1);
+    assert_eq!(count("owned_by_users({owners: [alice, eng]})")?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_nested_instances_in_call_results() -> TestResult {
+    // Instance 1 is an org whose teams are instances 10 and 11, which the host makes when
+    // they're looked up.
+    let orgs = || {
+        MockHandler::default().on_call(|instance_id, attribute, _, _| {
+            let team = |instance_id| InstanceRef {
+                instance_id,
+                class_tag: Some("Team".to_owned()),
+            };
+            Ok(Some(match (instance_id, attribute) {
+                (1, "teams") => Term::from(vec![team(10), team(11)]),
+                (1, "owner") => Term::from(btreemap! { "lead".to_owned() => team(11) }),
+                (10, "name") => term!("eng"),
+                (11, "name") => term!("sales"),
+                _ => return Ok(None),
+            }))
+        })
+    };
+
+    let p = polar();
+    p.register_constant(sym!("Team"), external_instance(100, Some("Team")))?;
+    p.register_mro(sym!("Team"), vec![100])?;
+    p.register_constant(sym!("ORG"), external_instance(1, None))?;
+    p.load_str(indoc! {r#"
+        team_name(org, name) if team in org.teams and team matches Team and team.name = name;
+        lead_name(org, name) if org.owner.lead matches Team and org.owner.lead.name = name;
+    "#})?;
+    // The host's `isa` checks aren't needed for instances of registered classes, so a handler
+    // without them answers the query.
+    let mut results = p.query_with("team_name(ORG, x)", orgs())?;
+    let names = results
+        .by_ref()
+        .map(|r| r.map(|bindings| bindings[&sym!("x")].clone()))
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(names, vec![term!("eng"), term!("sales")]);
+    assert!(results.query().instances().is_superset(&[10, 11].into()));
+
+    let names = p
+        .query_with("lead_name(ORG, x)", orgs())?
+        .map(|r| r.map(|bindings| bindings[&sym!("x")].clone()))
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(names, vec![term!("sales")]);
+
+    // Without a class id, a class is checked by the host instead of failing the check.
+    let mut query = p.new_query("Team matches Missing", false)?;
+    assert!(matches!(
+        query.next_event()?,
+        QueryEvent::ExternalIsa { class_tag, .. } if class_tag == sym!("Missing")
+    ));
+
+    // The Rust host registers an empty MRO for each class, which can't answer `matches`, so the
+    // host checks that the teams are teams.
+    p.register_mro(sym!("Team"), vec![])?;
+    let host_teams = orgs().on_isa(|_, class_tag| class_tag == "Team");
+    let names = p
+        .query_with("team_name(ORG, x)", host_teams)?
+        .map(|r| r.map(|bindings| bindings[&sym!("x")].clone()))
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(names, vec![term!("eng"), term!("sales")]);
+    Ok(())
+}
+
+#[test]
+fn test_external_call_error() -> TestResult {
+    // An account service whose lookups fail for the account with id 2.
+    let accounts = || {
+        MockHandler::default().on_call(|_, _, args, _| match args.first().map(Term::value) {
+            Some(Value::Number(Numeric::Integer(2))) => Err("account 2 is locked".to_owned()),
+            Some(Value::Number(Numeric::Integer(id))) => Ok(Some(term!(id * 100))),
+            _ => Err("missing account id".to_owned()),
+        })
+    };
+
+    let p = polar();
+    p.register_constant(sym!("accounts"), external_instance(1, None))?;
+    let src = "x in [1, 2, 3] and balance = accounts.balance(x)";
+
+    // By default, the error ends the query after the results before it.
+    let mut results = p.query_with(src, accounts())?;
+    assert_eq!(
+        results.next().transpose()?.unwrap()[&sym!("balance")],
+        term!(100)
+    );
+    let error = results.next().unwrap().unwrap_err();
+    assert!(matches!(
+        &error.0,
+        ErrorKind::Runtime(RuntimeError::Application { msg, .. }) if msg == "account 2 is locked"
+    ));
+    assert!(results.next().is_none());
+
+    // Isolated, the error only fails the branch that made the call, and is sent as a warning.
+    let mut p = p;
+    p.set_isolate_application_errors(true);
+    let mut results = p.query_with(src, accounts())?;
+    let balances = results
+        .by_ref()
+        .map(|bindings| Ok(bindings?[&sym!("balance")].clone()))
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(balances, vec![term!(100), term!(300)]);
+    let warning = results.query().next_message().unwrap();
+    assert!(matches!(warning.kind, MessageKind::Warning));
+    assert!(warning.msg.contains("account 2 is locked"));
+    Ok(())
+}
+
+#[test]
+fn test_batch_external_calls() -> TestResult {
+    let mut p = polar();
+    p.set_batch_external_calls(true);
+    p.register_constant(sym!("user"), external_instance(1, None))?;
+    p.load_str(indoc! {r#"
+        kind(_: Person, "person");
+        kind(_: Admin, "admin");
+        kind(_: Owner, "owner");
+    "#})?;
+
+    // An owner is an admin, who is a person. Count the events the host gets.
+    let rank = |tag: &Symbol| {
+        ["Owner", "Admin", "Person"]
+            .iter()
+            .position(|t| Symbol::new(t) == *tag)
+    };
+    let run = |query: &mut Query, fields: &[(&str, Option<Term>)]| -> PolarResult<_> {
+        let (mut results, mut batches) = (vec![], 0);
+        loop {
+            match query.next_event()? {
+                QueryEvent::Done { .. } => return Ok((results, batches)),
+                QueryEvent::Result { bindings, .. } => results.push(bindings),
+                QueryEvent::ExternalIsa { call_id, .. } => query.question_result(call_id, true)?,
+                QueryEvent::ExternalBatch { calls } => {
+                    batches += 1;
+                    let answers = calls
+                        .iter()
+                        .map(|call| match call {
+                            QueryEvent::ExternalIsSubSpecializer {
+                                left_class_tag,
+                                right_class_tag,
+                                ..
+                            } => ExternalAnswer::Question(
+                                rank(left_class_tag) < rank(right_class_tag),
+                            ),
+                            QueryEvent::ExternalCall { attribute, .. } => {
+                                let (_, value) = fields
+                                    .iter()
+                                    .find(|(f, _)| Symbol::new(f) == *attribute)
+                                    .unwrap();
+                                ExternalAnswer::Result(value.clone())
+                            }
+                            call => panic!("unexpected call: {:?}", call),
+                        })
+                        .collect();
+                    query.batch_result(answers)?;
+                }
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+    };
+
+    // The rules are sorted with one batch of specializer checks.
+    let mut query = p.new_query("kind(user, k)", false)?;
+    let (results, batches) = run(&mut query, &[])?;
+    let kinds: Vec<_> = results.iter().map(|r| r[&sym!("k")].clone()).collect();
+    assert_eq!(kinds, vec![term!("owner"), term!("admin"), term!("person")]);
+    assert_eq!(batches, 1);
+
+    // The fields of a pattern are looked up together.
+    let src = r#"user matches {name: "alice", age: 30}"#;
+    let alice = [("name", Some(term!("alice"))), ("age", Some(term!(30)))];
+    let (results, batches) = run(&mut p.new_query(src, false)?, &alice)?;
+    assert_eq!((results.len(), batches), (1, 1));
+    let bob = [("name", Some(term!("bob"))), ("age", Some(term!(30)))];
+    assert!(run(&mut p.new_query(src, false)?, &bob)?.0.is_empty());
+    let ageless = [("name", Some(term!("alice"))), ("age", None)];
+    assert!(run(&mut p.new_query(src, false)?, &ageless)?.0.is_empty());
+
+    // An error ends the query unless application errors are isolated.
+    let mut query = p.new_query(src, false)?;
+    let Ok(QueryEvent::ExternalBatch { calls }) = query.next_event() else {
+        panic!("expected a batch");
+    };
+    assert_eq!(calls.len(), 2);
+    query.batch_result(vec![
+        ExternalAnswer::Result(Some(term!("alice"))),
+        ExternalAnswer::Error("no age".to_owned()),
+    ])?;
+    let error = query.next_event().unwrap_err();
+    assert!(matches!(
+        &error.0,
+        ErrorKind::Runtime(RuntimeError::Application { msg, .. }) if msg == "no age"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_cache_lookups() -> TestResult {
+    // A user whose role is "admin" and whose score for `x` is `x * 10`, or `x * 100` for `[x]`.
+    let users = || {
+        MockHandler::default().on_call(|_, attribute, args, _| {
+            let integer = |x: &Term| match x.value() {
+                Value::Number(Numeric::Integer(x)) => Some(*x),
+                _ => None,
+            };
+            Ok(match (attribute, args.as_slice()) {
+                ("role", []) => Some(term!("admin")),
+                ("score", [x]) => match x.value() {
+                    Value::List(xs) if xs.len() == 1 => integer(&xs[0]).map(|x| term!(x * 100)),
+                    _ => integer(x).map(|x| term!(x * 10)),
+                },
+                _ => None,
+            })
+        })
+    };
+
+    let mut p = polar();
+    p.register_constant(sym!("user"), external_instance(1, None))?;
+    let src = r#"x in [1, 2, 1] and user.role = "admin" and s = user.score(x) and user.missing"#;
+    let stats = |p: &Polar| -> PolarResult<_> {
+        let mut results = p.query_with(src, users())?;
+        assert!(results.next().transpose()?.is_none());
+        Ok(results.query().stats())
+    };
+
+    // Each branch asks the host again.
+    let uncached = stats(&p)?;
+    assert_eq!((uncached.external_calls, uncached.cached_lookups), (9, 0));
+
+    // With the cache, the host is asked once for each attribute and set of arguments.
+    p.set_cache_lookups(true);
+    let cached = stats(&p)?;
+    assert_eq!((cached.external_calls, cached.cached_lookups), (4, 5));
+
+    // Arguments are compared with the variables inside them bound.
+    let scores = p
+        .query_with("y = [x] and x in [1, 2] and s = user.score(y)", users())?
+        .map(|r| r.map(|bindings| bindings[&sym!("s")].clone()))
+        .collect::<PolarResult<Vec<_>>>()?;
+    assert_eq!(scores, vec![term!(100), term!(200)]);
+    Ok(())
+}
+
+#[test]
+fn test_rules() -> TestResult {
+    let p = polar();
+    p.load(vec![Source::new_with_name(
+        "policy.polar",
+        "g(x) if f(x, _);\nf(_x: Integer, {id: 1});\nf(_x, _y);",
+    )])?;
+    p.add_fact(term!(call!("h", [1])))?;
+
+    let rules = p.rules();
+    let names = rules
+        .iter()
+        .map(|rule| (rule.name.to_string(), rule.arity))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            ("f".to_owned(), 2),
+            ("f".to_owned(), 2),
+            ("g".to_owned(), 1),
+            ("h".to_owned(), 1)
+        ]
+    );
+
+    let specializers = rules[0]
+        .specializers
+        .iter()
+        .map(|specializer| specializer.as_ref().map(ToString::to_string))
+        .collect::<Vec<_>>();
+    // A ground parameter like `{id: 1}` is matched by value, not specialized.
+    assert_eq!(specializers, vec![Some("Integer{}".to_owned()), None]);
+    assert_eq!(rules[1].specializers, vec![None, None]);
+
+    let span = rules[0].span.as_ref().unwrap();
+    assert_eq!(span.filename.as_deref(), Some("policy.polar"));
+    assert_eq!((span.line, span.column), (2, 1));
+    assert_eq!(span.text, "f(_x: Integer, {id: 1})");
+    assert!(rules[3].span.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_rule_docs() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#""""Admins can do anything."""
+           can("admin", _action, _resource);
+           can(actor, "read", resource) if resource.owner = actor;"#,
+    )?;
+    let docs = p
+        .rules()
+        .into_iter()
+        .map(|rule| rule.doc.map(|doc| doc.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(docs, vec![Some("Admins can do anything.".to_owned()), None]);
+    // The doc string isn't part of the rule.
+    assert_eq!(
+        p.rules()[0].span.as_ref().unwrap().text,
+        r#"can("admin", _action, _resource)"#
+    );
+    qeval(&p, r#"can("admin", "delete", {})"#);
+    Ok(())
+}
+
+#[test]
+fn test_annotated_rules() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"@tag("pci") @control("PCI-DSS", "7.1")
+           can(_actor, "read", "card");
+           @tag("pci")
+           can(_actor, "refund", "card");
+           @tag("gdpr")
+           can(actor, "delete", actor);
+           module billing {
+               @tag("pci") invoice(_id);
+           }"#,
+    )?;
+    let rules = p.annotated_rules("tag", &[term!("pci")]);
+    let names = rules
+        .iter()
+        .map(|rule| (rule.name.to_string(), rule.span.as_ref().unwrap().line))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            ("billing::invoice".to_owned(), 8),
+            ("can".to_owned(), 2),
+            ("can".to_owned(), 4)
+        ]
+    );
+    assert_eq!(p.annotated_rules("tag", &[]).len(), 4);
+    assert_eq!(p.annotated_rules("control", &[term!("PCI-DSS")]).len(), 1);
+    assert!(p.annotated_rules("control", &[term!("SOX")]).is_empty());
+    assert!(p.annotated_rules("reviewed", &[]).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_add_rule() -> TestResult {
+    use polar_core::builder::{call, class, op, var, RuleBuilder};
+
+    let p = polar();
+    p.load_str("named(x, name) if x.name = name;")?;
+    // name_of(x: Dictionary, name) if named(x, name) and x.name = name;
+    let rule = RuleBuilder::new("name_of")
+        .param_with_specializer(var("x"), class("Dictionary"))
+        .param(var("name"))
+        .body(call("named", vec![var("x"), var("name")]))
+        .body(op(
+            Operator::Unify,
+            vec![
+                op(Operator::Dot, vec![var("x"), term!("name")]),
+                var("name"),
+            ],
+        ))
+        .build();
+    p.add_rule(rule)?;
+    qvar(
+        &p,
+        r#"name_of({name: "alice"}, name)"#,
+        "name",
+        values!["alice"],
+    );
+    qnull(&p, r#"name_of("alice", _)"#);
+
+    // A fact is a rule without a body.
+    p.add_rule(RuleBuilder::new("user").param(term!("alice")).build())?;
+    qeval(&p, r#"user("alice")"#);
+
+    let singleton = RuleBuilder::new("f").param(var("x")).build();
+    assert!(matches!(
+        p.add_rule(singleton).unwrap_err().0,
+        ErrorKind::Validation(SingletonVariable { .. })
+    ));
+    let wrong_arity = RuleBuilder::new("allow").param(term!(1)).build();
+    assert!(matches!(
+        p.add_rule(wrong_arity).unwrap_err().0,
+        ErrorKind::Validation(InvalidRule { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_query_rule() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"can(actor, "read", resource) if resource.owner = actor;
+           can("admin", _action, _resource);
+           owners(names) if names = ["alice"];"#,
+    )?;
+    let doc = term!(btreemap! { sym!("owner") => term!("alice") });
+    let results = query_results!(p.query_rule("can", ("alice", "read", doc.clone())));
+    assert_eq!(results.len(), 1);
+    assert!(query_results!(p.query_rule("can", ("bob", "read", doc.clone()))).is_empty());
+    assert_eq!(
+        query_results!(p.query_rule("can", ("admin", 1, 2))).len(),
+        1
+    );
+
+    // Strings are passed as values rather than parsed into the query.
+    let injected = r#"bob" or true or "x"#;
+    assert!(query_results!(p.query_rule("can", (injected, "read", doc))).is_empty());
+
+    let results = query_results!(p.query_rule("owners", (term!(sym!("names")),)));
+    assert_eq!(results[0].0[&sym!("names")], value!(["alice"]));
+    assert_eq!(
+        query_results!(p.query_rule("owners", vec![vec!["alice"]])).len(),
+        1
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_arguments() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"admin(claims) if "admin" in claims.roles and claims.org = "acme";
+           profile(claims, profile) if profile = {name: claims.sub, manager: claims.manager};"#,
+    )?;
+    let claims = serde_json::json!({"sub": "alice", "org": "acme", "roles": ["admin"]});
+    let claims = Term::try_from(claims)?;
+    assert_eq!(query_results!(p.query_rule("admin", (claims,))).len(), 1);
+
+    let claims = Term::try_from(serde_json::json!({"sub": "bob", "manager": "alice"}))?;
+    let results = query_results!(p.query_rule("profile", (claims, term!(sym!("profile")))));
+    let profile = serde_json::Value::try_from(&Term::from(results[0].0[&sym!("profile")].clone()))?;
+    assert_eq!(
+        profile,
+        serde_json::json!({"name": "bob", "manager": "alice"})
+    );
+
+    // Polar has no null, so claims with nulls are rejected. Were nulls a shared variable, a null
+    // org and a null manager would be the same value, and the org could be bound to "acme".
+    let claims =
+        serde_json::json!({"sub": "bob", "org": null, "manager": null, "roles": ["admin"]});
+    assert!(Term::try_from(claims).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_result_limit() -> TestResult {
+    let p = polar();
+    p.load_str("f(x) if x in [1, 2, 3, 4, 5] and print(x);")?;
+    let mut q = p.new_query("f(x)", false)?;
+    q.set_result_limit(2);
+
+    let page = |q: &mut Query| -> PolarResult<(Vec<Value>, Vec<String>)> {
+        let mut results = vec![];
+        for event in q.by_ref() {
+            if let QueryEvent::Result { bindings, .. } = event? {
+                results.push(bindings[&sym!("x")].value().clone());
+            }
+        }
+        let printed = q.drain_messages().into_iter().map(|m| m.msg).collect();
+        Ok((results, printed))
+    };
+
+    // The query stops at the limit, without finding the next result.
+    assert_eq!(page(&mut q)?, (values![1, 2], vec!["1".into(), "2".into()]));
+    assert!(q.limit_reached());
+    q.resume(2);
+    assert_eq!(page(&mut q)?, (values![3, 4], vec!["3".into(), "4".into()]));
+    q.resume(10);
+    assert_eq!(page(&mut q)?, (values![5], vec!["5".into()]));
+    assert!(!q.limit_reached());
+
+    // Ordered results are limited once they're ordered.
+    let mut q = p.new_query("f(x)", false)?;
+    q.set_result_order(sym!("x"), Order::Descending);
+    q.set_result_limit(2);
+    assert_eq!(page(&mut q)?.0, values![5, 4]);
+
+    // The host isn't called for anything past the limit.
+    p.register_constant(sym!("ext"), external_instance(1, None))?;
+    let mut q = p.new_query("x in [1, 2, 3] and ext.allowed(x)", false)?;
+    q.set_result_limit(1);
+    let mut calls = vec![];
+    loop {
+        match q.next_event()? {
+            QueryEvent::ExternalCall { call_id, args, .. } => {
+                calls.push(args.unwrap()[0].clone());
+                q.call_result(call_id, Some(term!(true)))?;
+            }
+            QueryEvent::Result { .. } => (),
+            QueryEvent::Done { .. } => break,
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+    assert_eq!(calls, vec![term!(1)]);
+    assert!(q.limit_reached());
+    Ok(())
+}
+
+#[test]
+fn test_query_state() -> TestResult {
+    let p = polar();
+    p.load_str("f(1); f(2); f(3);")?;
+    p.register_constant(sym!("ext"), external_instance(1, None))?;
+    let mut q = p.new_query("f(x) and y = ext.name", false)?;
+    let QueryEvent::ExternalCall { call_id, .. } = q.next_event()? else {
+        panic!("expected an external call");
+    };
+
+    // The query waits on the lookup after matching the first of the three rules, with the
+    // other two left to try.
+    let state = q.state();
+    assert_eq!(state.bindings[&sym!("x")], term!(1));
+    assert!(matches!(
+        state.bindings.get(&sym!("y")).map(Term::value),
+        None | Some(Value::Variable(_))
+    ));
+    assert!(state.choices.contains(&2));
+    assert!(!state.goals.is_empty());
+
+    q.call_result(call_id, Some(term!("alice")))?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    assert_eq!(q.state().bindings[&sym!("y")], term!("alice"));
+    Ok(())
+}
+
+#[test]
+fn test_external_hook() -> TestResult {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Answers lookups of `cached` itself and records how long the host took with the rest.
+    #[derive(Default)]
+    struct Hook(Mutex<Vec<(String, Option<Term>, Duration)>>);
+
+    impl ExternalHook for Hook {
+        fn before(&self, event: &QueryEvent) -> Option<Term> {
+            match event {
+                QueryEvent::ExternalCall { attribute, .. } if &*attribute.as_str() == "cached" => {
+                    Some(term!("hit"))
+                }
+                _ => None,
+            }
+        }
+
+        fn after(&self, event: &QueryEvent, value: Option<&Term>, elapsed: Duration) {
+            let name = match event {
+                QueryEvent::ExternalCall { attribute, .. } => attribute.to_string(),
+                QueryEvent::MakeExternal { .. } => "new".to_owned(),
+                _ => panic!("unexpected event {:?}", event),
+            };
+            self.0.lock().unwrap().push((name, value.cloned(), elapsed));
+        }
+    }
+
+    let mut p = polar();
+    // Timing doesn't go by the clock that `now` reads.
+    p.set_clock(Arc::new(|| 0));
+    let hook = Arc::new(Hook::default());
+    p.set_external_hook(hook.clone());
+    p.register_constant(sym!("ext"), external_instance(1, None))?;
+
+    let mut q = p.new_query("x = ext.slow and y = ext.cached and _ = new Foo()", false)?;
+    let mut seen = vec![];
+    loop {
+        match q.next_event()? {
+            QueryEvent::ExternalCall {
+                call_id, attribute, ..
+            } => {
+                seen.push(attribute.to_string());
+                std::thread::sleep(Duration::from_millis(5));
+                q.call_result(call_id, Some(term!("value")))?;
+            }
+            QueryEvent::MakeExternal { .. } => seen.push("new".to_owned()),
+            QueryEvent::Result { bindings, .. } => {
+                assert_eq!(bindings[&sym!("y")], term!("hit"));
+            }
+            QueryEvent::Done { .. } => break,
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+    assert_eq!(seen, vec!["slow", "new"]);
+    let finished = hook.0.lock().unwrap();
+    assert_eq!(
+        finished
+            .iter()
+            .map(|(name, value, _)| (name.as_str(), value.clone()))
+            .collect::<Vec<_>>(),
+        vec![("slow", Some(term!("value"))), ("new", None)]
+    );
+    assert!(finished[0].2 >= Duration::from_millis(5));
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_and_restore() -> TestResult {
+    let p = polar();
+    p.load_str("f(1); g(x) if f(x);")?;
+    let snapshot = p.snapshot();
+
+    p.add_source(Source::new("f(2); h(1);"))?;
+    p.register_constant(sym!("c"), term!(1))?;
+    qvar(&p, "g(x)", "x", values![1, 2]);
+    qeval(&p, "h(c)");
+
+    p.restore(&snapshot);
+    qvar(&p, "g(x)", "x", values![1]);
+    qruntime!(&p, "h(1)", QueryForUndefinedRule { .. });
+    // `c` is a variable again.
+    qvar(&p, "f(c)", "c", values![1]);
+
+    // A snapshot can be restored more than once.
+    p.clear_rules();
+    p.restore(&snapshot);
+    qvar(&p, "g(x)", "x", values![1]);
+    Ok(())
+}
+
+#[test]
+fn test_cancel_token() -> TestResult {
+    let p = polar();
+    p.load_str("loop() if loop(); f(1); f(2);")?;
+
+    let mut q = p.new_query("loop()", false)?;
+    let token = q.cancel_token();
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        token.cancel();
+    });
+    let error = q.next_event().unwrap_err();
+    canceller.join().unwrap();
+    assert!(
+        matches!(error.0, ErrorKind::Runtime(RuntimeError::Cancelled)),
+        "{}",
+        error
+    );
+    // The query stays cancelled.
+    assert!(q.next_event().is_err());
+
+    // A query that's cancelled between results returns no more of them.
+    let mut q = p.new_query("f(x)", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    q.cancel_token().cancel();
+    assert!(q.next_event().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_depth_limit() -> TestResult {
+    let mut p = polar();
+    p.load_str(
+        r#"count(0);
+           count(n) if n > 0 and count(n - 1) and true;
+           tail(0);
+           tail(n) if n > 0 and tail(n - 1);
+           sub(0);
+           sub(n) if n > 0 and forall(true, sub(n - 1));"#,
+    )?;
+    p.set_depth_limit(100);
+
+    qeval(&p, "count(50)");
+    qeval(&p, "count(50) and count(50) and count(50)");
+    qeval(&p, "tail(500)");
+    qruntime!(
+        &p,
+        "count(200)",
+        StackOverflow {
+            rule: Some(rule),
+            depth: 101,
+            ..
+        },
+        rule == "count"
+    );
+    // Subqueries count the calls they're nested in.
+    qruntime!(
+        &p,
+        "sub(200)",
+        StackOverflow {
+            rule: Some(rule),
+            depth: 101,
+            ..
+        },
+        rule == "sub"
+    );
+
+    let error = p.new_query("count(200)", false)?.next_event().unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("The innermost rule running was `count`, 101 calls deep."));
+
+    let mut q = p.new_query("count(200)", false)?;
+    q.set_depth_limit(0);
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    Ok(())
+}
+
 /// Test that cut commits to all choice points before the cut, not just the last.
 #[test]
 fn test_cut() -> TestResult {
@@ -1,7 +1,136 @@
 /// Utils for mocking externals in tests.
 use std::collections::{BTreeMap, HashMap};
 
-use polar_core::terms::{ExternalInstance, Pattern, Symbol, Term, Value};
+use polar_core::query::QueryHandler;
+use polar_core::terms::{ExternalInstance, Operator, Pattern, Symbol, Term, TermList, Value};
+
+/// A host instance with the id `instance_id`, or a class if it has a `class_repr`, which is
+/// then its own class id too.
+pub fn external_instance(instance_id: u64, class_repr: Option<&str>) -> Term {
+    Term::from(Value::ExternalInstance(ExternalInstance {
+        instance_id,
+        constructor: None,
+        repr: None,
+        class_repr: class_repr.map(str::to_owned),
+        class_id: class_repr.map(|_| instance_id),
+    }))
+}
+
+type OnMake<'a> = Box<dyn FnMut(u64, Term) -> Result<(), String> + 'a>;
+type OnCall<'a> = Box<
+    dyn FnMut(u64, &str, Vec<Term>, BTreeMap<Symbol, Term>) -> Result<Option<Term>, String> + 'a,
+>;
+type OnIsa<'a> = Box<dyn FnMut(Option<u64>, &str) -> bool + 'a>;
+type OnOp<'a> = Box<dyn FnMut(Operator, TermList) -> Result<bool, String> + 'a>;
+type OnNext<'a> = Box<dyn FnMut(u64) -> Result<Option<Term>, String> + 'a>;
+
+/// A `QueryHandler` that answers events with closures, for tests of hosts that only answer a
+/// few kinds of events. The events it has no closure for get the default answers.
+#[derive(Default)]
+pub struct MockHandler<'a> {
+    on_make: Option<OnMake<'a>>,
+    on_call: Option<OnCall<'a>>,
+    on_isa: Option<OnIsa<'a>>,
+    on_op: Option<OnOp<'a>>,
+    on_next: Option<OnNext<'a>>,
+}
+
+/// The default answers to every event.
+struct NoHandler;
+
+impl QueryHandler for NoHandler {}
+
+impl<'a> MockHandler<'a> {
+    /// Make instances with `f(instance_id, constructor)`.
+    pub fn on_make(mut self, f: impl FnMut(u64, Term) -> Result<(), String> + 'a) -> Self {
+        self.on_make = Some(Box::new(f));
+        self
+    }
+
+    /// Answer calls with `f(instance_id, attribute, args, kwargs)`, with no args for a lookup.
+    pub fn on_call(
+        mut self,
+        f: impl FnMut(u64, &str, Vec<Term>, BTreeMap<Symbol, Term>) -> Result<Option<Term>, String> + 'a,
+    ) -> Self {
+        self.on_call = Some(Box::new(f));
+        self
+    }
+
+    /// Check classes with `f(instance_id, class_tag)`, with no id for a value that isn't a host
+    /// instance.
+    pub fn on_isa(mut self, f: impl FnMut(Option<u64>, &str) -> bool + 'a) -> Self {
+        self.on_isa = Some(Box::new(f));
+        self
+    }
+
+    /// Apply operators to host instances with `f(operator, args)`.
+    pub fn on_op(mut self, f: impl FnMut(Operator, TermList) -> Result<bool, String> + 'a) -> Self {
+        self.on_op = Some(Box::new(f));
+        self
+    }
+
+    /// Produce the elements of host iterables with `f(call_id)`.
+    pub fn on_next(mut self, f: impl FnMut(u64) -> Result<Option<Term>, String> + 'a) -> Self {
+        self.on_next = Some(Box::new(f));
+        self
+    }
+}
+
+fn instance_id(term: &Term) -> Option<u64> {
+    match term.value() {
+        Value::ExternalInstance(ExternalInstance { instance_id, .. }) => Some(*instance_id),
+        _ => None,
+    }
+}
+
+impl QueryHandler for MockHandler<'_> {
+    fn make_external(&mut self, instance_id: u64, constructor: Term) -> Result<(), String> {
+        match &mut self.on_make {
+            Some(f) => f(instance_id, constructor),
+            None => NoHandler.make_external(instance_id, constructor),
+        }
+    }
+
+    fn external_call(
+        &mut self,
+        instance: Term,
+        attribute: Symbol,
+        args: Option<Vec<Term>>,
+        kwargs: Option<BTreeMap<Symbol, Term>>,
+    ) -> Result<Option<Term>, String> {
+        match (&mut self.on_call, instance_id(&instance)) {
+            (Some(f), Some(id)) => f(
+                id,
+                &attribute.as_str(),
+                args.unwrap_or_default(),
+                kwargs.unwrap_or_default(),
+            ),
+            (Some(_), None) => Err(format!("{} isn't a host instance", instance)),
+            (None, _) => NoHandler.external_call(instance, attribute, args, kwargs),
+        }
+    }
+
+    fn external_isa(&mut self, instance: Term, class_tag: Symbol) -> Result<bool, String> {
+        match &mut self.on_isa {
+            Some(f) => Ok(f(instance_id(&instance), &class_tag.as_str())),
+            None => NoHandler.external_isa(instance, class_tag),
+        }
+    }
+
+    fn external_op(&mut self, operator: Operator, args: TermList) -> Result<bool, String> {
+        match &mut self.on_op {
+            Some(f) => f(operator, args),
+            None => NoHandler.external_op(operator, args),
+        }
+    }
+
+    fn next_external(&mut self, call_id: u64, iterable: Term) -> Result<Option<Term>, String> {
+        match &mut self.on_next {
+            Some(f) => f(call_id),
+            None => NoHandler.next_external(call_id, iterable),
+        }
+    }
+}
 
 #[derive(Default)]
 /// Mock external that keeps track of instance literals and allows
@@ -402,6 +402,11 @@ impl PolarLanguageServer {
                     }
                 }
                 Line::RuleType(_) => event.policy_stats.rule_types += 1,
+                Line::Constant { .. } | Line::Import(_) | Line::Table(_) => (),
+                Line::Module { rules, .. } => {
+                    event.policy_stats.longhand_rules += rules.len();
+                    event.policy_stats.total_rules += rules.len();
+                }
                 Line::Rule(_) => {
                     event.policy_stats.longhand_rules += 1;
                     event.policy_stats.total_rules += 1;
@@ -570,9 +575,9 @@ mod tests {
     }
 
     #[track_caller]
-    fn doc_with_missing_semicolon(path: &str) -> TextDocumentItem {
+    fn doc_with_missing_body(path: &str) -> TextDocumentItem {
         let file_name = path.split('/').last().unwrap();
-        polar_doc(path, format!("{}()", file_name))
+        polar_doc(path, format!("{}() if", file_name))
     }
 
     #[track_caller]
@@ -583,11 +588,8 @@ mod tests {
     }
 
     #[track_caller]
-    fn add_doc_with_missing_semicolon(
-        pls: &mut PolarLanguageServer,
-        path: &str,
-    ) -> TextDocumentItem {
-        let doc = doc_with_missing_semicolon(path);
+    fn add_doc_with_missing_body(pls: &mut PolarLanguageServer, path: &str) -> TextDocumentItem {
+        let doc = doc_with_missing_body(path);
         assert!(pls.upsert_document(doc.clone()).is_none());
         doc
     }
@@ -598,7 +600,7 @@ mod tests {
     }
 
     #[track_caller]
-    fn assert_missing_semicolon_error(diagnostics: &Diagnostics, docs: Vec<&TextDocumentItem>) {
+    fn assert_missing_body_error(diagnostics: &Diagnostics, docs: Vec<&TextDocumentItem>) {
         for doc in docs {
             let params = diagnostics.get(&doc.uri).unwrap();
             assert_eq!(params.uri, doc.uri);
@@ -607,7 +609,7 @@ mod tests {
             let diagnostic = params.diagnostics.get(0).unwrap();
             assert_eq!(
                 diagnostic.message,
-                "hit the end of the file unexpectedly. Did you forget a semi-colon"
+                "hit the end of the file unexpectedly. Expected a rule body after `if`"
             );
         }
     }
@@ -644,8 +646,8 @@ mod tests {
 
         let a = doc_with_no_errors("apple");
         let b = doc_with_no_errors("banana");
-        let c = doc_with_missing_semicolon("canteloupe");
-        let d = doc_with_missing_semicolon("date");
+        let c = doc_with_missing_body("canteloupe");
+        let d = doc_with_missing_body("date");
         let e = doc_with_no_errors("elderberry");
 
         // Load a single doc w/ no errors.
@@ -664,19 +666,19 @@ mod tests {
         // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
         // that check.
         assert_no_errors(&diagnostics, vec![&a, &b]);
-        assert_missing_semicolon_error(&diagnostics, vec![&c]);
+        assert_missing_body_error(&diagnostics, vec![&c]);
 
         // Load a fourth doc w/ errors.
         let diagnostics = pls.on_did_open_text_document(d.clone());
         assert_eq!(diagnostics.len(), 4);
         assert_no_errors(&diagnostics, vec![&a, &b]);
-        assert_missing_semicolon_error(&diagnostics, vec![&c, &d]);
+        assert_missing_body_error(&diagnostics, vec![&c, &d]);
 
         // Load a fifth doc w/ no errors.
         let diagnostics = pls.on_did_open_text_document(e.clone());
         assert_eq!(diagnostics.len(), 5);
         assert_no_errors(&diagnostics, vec![&a, &b, &e]);
-        assert_missing_semicolon_error(&diagnostics, vec![&c, &d]);
+        assert_missing_body_error(&diagnostics, vec![&c, &d]);
     }
 
     #[wasm_bindgen_test]
@@ -696,16 +698,16 @@ mod tests {
         assert_missing_allow_rule_warning(&diagnostics1, vec![&a1]);
 
         // Change tracked doc, introducing an error.
-        let a2 = update_text(a1, "pie()");
+        let a2 = update_text(a1, "pie() if");
         let diagnostics2 = pls.on_did_change_text_document(a2.clone());
         assert_eq!(diagnostics2.len(), 1);
-        assert_missing_semicolon_error(&diagnostics2, vec![&a2]);
+        assert_missing_body_error(&diagnostics2, vec![&a2]);
 
         // 'Change' untracked doc, introducing a second error.
-        let b3 = doc_with_missing_semicolon("banana");
+        let b3 = doc_with_missing_body("banana");
         let diagnostics3 = pls.on_did_change_text_document(b3.clone());
         assert_eq!(diagnostics3.len(), 2);
-        assert_missing_semicolon_error(&diagnostics3, vec![&a2, &b3]);
+        assert_missing_body_error(&diagnostics3, vec![&a2, &b3]);
 
         // Change tracked doc, fixing an error.
         let a4 = update_text(a2, "pie();");
@@ -714,7 +716,7 @@ mod tests {
         // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
         // that check.
         assert_no_errors(&diagnostics4, vec![&a4]);
-        assert_missing_semicolon_error(&diagnostics4, vec![&b3]);
+        assert_missing_body_error(&diagnostics4, vec![&b3]);
 
         // Change tracked doc, fixing the last error.
         let b5 = update_text(b3, "split();");
@@ -747,7 +749,7 @@ mod tests {
         assert!(pls.documents.is_empty());
 
         // Deleting tracked doc w/ error.
-        let a3 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let a3 = add_doc_with_missing_body(&mut pls, "apple");
         let events3 = vec![a3.uri.clone()];
         let diagnostics3 = pls.on_did_change_watched_files(events3);
         assert_eq!(diagnostics3.len(), 1);
@@ -766,7 +768,7 @@ mod tests {
         assert!(pls.documents.is_empty());
 
         // Deleting tracked doc w/ error; doc w/o error remains.
-        let a5 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let a5 = add_doc_with_missing_body(&mut pls, "apple");
         let b5 = add_doc_with_no_errors(&mut pls, "banana");
         let events5 = vec![a5.uri.clone()];
         let diagnostics5 = pls.on_did_change_watched_files(events5);
@@ -778,30 +780,30 @@ mod tests {
 
         // Deleting tracked doc w/o error; doc w/ error remains.
         let a6 = add_doc_with_no_errors(&mut pls, "apple");
-        let b6 = add_doc_with_missing_semicolon(&mut pls, "banana");
+        let b6 = add_doc_with_missing_body(&mut pls, "banana");
         let events6 = vec![a6.uri.clone()];
         let diagnostics6 = pls.on_did_change_watched_files(events6);
         assert_eq!(diagnostics6.len(), 2);
         assert_no_errors(&diagnostics6, vec![&a6]);
-        assert_missing_semicolon_error(&diagnostics6, vec![&b6]);
+        assert_missing_body_error(&diagnostics6, vec![&b6]);
         assert!(pls.remove_document(&b6.uri).is_some());
         assert!(pls.documents.is_empty());
 
         // Deleting tracked doc w/ error; doc w/ error remains.
-        let a7 = add_doc_with_missing_semicolon(&mut pls, "apple");
-        let b7 = add_doc_with_missing_semicolon(&mut pls, "banana");
+        let a7 = add_doc_with_missing_body(&mut pls, "apple");
+        let b7 = add_doc_with_missing_body(&mut pls, "banana");
         let events7 = vec![a7.uri.clone()];
         let diagnostics7 = pls.on_did_change_watched_files(events7);
         assert_eq!(diagnostics7.len(), 2);
         assert_no_errors(&diagnostics7, vec![&a7]);
-        assert_missing_semicolon_error(&diagnostics7, vec![&b7]);
+        assert_missing_body_error(&diagnostics7, vec![&b7]);
         assert!(pls.remove_document(&b7.uri).is_some());
         assert!(pls.documents.is_empty());
 
         // Deleting multiple docs at once.
-        let a8 = add_doc_with_missing_semicolon(&mut pls, "apple");
-        let b8 = add_doc_with_missing_semicolon(&mut pls, "banana");
-        let c8 = add_doc_with_missing_semicolon(&mut pls, "canteloupe");
+        let a8 = add_doc_with_missing_body(&mut pls, "apple");
+        let b8 = add_doc_with_missing_body(&mut pls, "banana");
+        let c8 = add_doc_with_missing_body(&mut pls, "canteloupe");
         let d8 = add_doc_with_no_errors(&mut pls, "date");
         let e8 = add_doc_with_no_errors(&mut pls, "elderberry");
         let f8 = add_doc_with_no_errors(&mut pls, "fig");
@@ -816,13 +818,13 @@ mod tests {
         // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
         // that check.
         assert_no_errors(&diagnostics8, vec![&a8, &b8, &d8, &e8, &f8]);
-        assert_missing_semicolon_error(&diagnostics8, vec![&c8]);
+        assert_missing_body_error(&diagnostics8, vec![&c8]);
         assert!(pls.remove_document(&c8.uri).is_some());
         assert!(pls.remove_document(&f8.uri).is_some());
         assert!(pls.documents.is_empty());
 
         // Deleting directories containing Polar files.
-        let a9 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let a9 = add_doc_with_missing_body(&mut pls, "apple");
         let b9 = add_doc_with_no_errors(&mut pls, "a/b/banana");
         let ca9a = add_doc_with_no_errors(&mut pls, "a/b/c/ca/calabash");
         let ca9b = add_doc_with_no_errors(&mut pls, "a/b/c/ca/canteloupe");
@@ -837,7 +839,7 @@ mod tests {
         assert_eq!(pls.documents.len(), 8);
         let diagnostics9a = pls.on_did_delete_files(events9a).unwrap();
         assert_eq!(diagnostics9a.len(), 8);
-        assert_missing_semicolon_error(&diagnostics9a, vec![&a9]);
+        assert_missing_body_error(&diagnostics9a, vec![&a9]);
         // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
         // that check.
         assert_no_errors(
@@ -855,7 +857,7 @@ mod tests {
         assert_eq!(pls.documents.len(), 5);
         let diagnostics9b = pls.on_did_delete_files(events9b).unwrap();
         assert_eq!(diagnostics9b.len(), 5);
-        assert_missing_semicolon_error(&diagnostics9b, vec![&a9]);
+        assert_missing_body_error(&diagnostics9b, vec![&a9]);
         // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
         // that check.
         assert_no_errors(&diagnostics9b, vec![&b9, &ca9a, &ca9b, &ch9]);
@@ -868,7 +870,7 @@ mod tests {
         assert_eq!(pls.documents.len(), 2);
         let diagnostics9c = pls.on_did_delete_files(events9c).unwrap();
         assert_eq!(diagnostics9c.len(), 2);
-        assert_missing_semicolon_error(&diagnostics9c, vec![&a9]);
+        assert_missing_body_error(&diagnostics9c, vec![&a9]);
         // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
         // that check.
         assert_no_errors(&diagnostics9c, vec![&b9]);
@@ -137,7 +137,7 @@ fn new_query_from_term_succeeds() {
     polar.wasm_load(sources).unwrap();
 
     let term = Term::from(Value::Call(Call {
-        name: Symbol("x".into()),
+        name: Symbol::new("x"),
         args: vec![Term::from(2)],
         kwargs: None,
     }));
@@ -147,7 +147,7 @@ fn new_query_from_term_succeeds() {
     assert!(is_done_event(event));
 
     let term = Term::from(Value::Call(Call {
-        name: Symbol("x".into()),
+        name: Symbol::new("x"),
         args: vec![Term::from(1)],
         kwargs: None,
     }));
@@ -53,13 +53,13 @@ class Test {
     // Test that a custom error type is thrown.
     boolean throwsException = false;
     try {
-      o.loadStr("missingSemicolon()");
+      o.loadStr("unfinishedRule() if");
     } catch (Exceptions.UnrecognizedEOF e) {
       throwsException = true;
       assert e.getMessage()
           .startsWith(
-              "hit the end of the file unexpectedly. Did you forget a semi-colon at line 1, column"
-                  + " 19");
+              "hit the end of the file unexpectedly. Expected a rule body after `if` at line 1,"
+                  + " column 20");
     }
     assert throwsException;
 
@@ -49,11 +49,11 @@ oso.register_class(E)
 # Test that a custom error type is thrown.
 exception_thrown = False
 try:
-    oso.load_str("missingSemicolon()")
+    oso.load_str("unfinishedRule() if")
 except UnrecognizedEOF as e:
     exception_thrown = True
     assert str(e).startswith(
-        "hit the end of the file unexpectedly. Did you forget a semi-colon at line 1, column 19"
+        "hit the end of the file unexpectedly. Expected a rule body after `if` at line 1, column 20"
     )
 assert exception_thrown
 
@@ -47,7 +47,7 @@ suite('Diagnostics', () => {
     ok(diagnostic.range.end.isEqual(new Position(0, 6)));
     strictEqual(
       diagnostic.message,
-      'hit the end of the file unexpectedly. Did you forget a semi-colon'
+      'hit the end of the file unexpectedly'
     );
   });
 });